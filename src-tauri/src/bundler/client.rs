@@ -0,0 +1,92 @@
+// ERC-4337 Bundler Client
+// JSON-RPC methods of an ERC-4337 bundler, sharing the transport and failover
+// of the chain RPC client
+
+use alloy_primitives::{Address, B256, U256, U64};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::chain::rpc::{Log, RpcClient, RpcError};
+use crate::chain::TransactionReceipt;
+use crate::eth::UserOperation;
+
+/// Result of eth_estimateUserOperationGas
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationGasEstimate {
+    pub pre_verification_gas: U256,
+    pub verification_gas_limit: U256,
+    pub call_gas_limit: U256,
+    #[serde(default)]
+    pub paymaster_verification_gas_limit: Option<U256>,
+    #[serde(default)]
+    pub paymaster_post_op_gas_limit: Option<U256>,
+}
+
+/// Result of eth_getUserOperationReceipt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationReceipt {
+    pub user_op_hash: B256,
+    pub entry_point: Address,
+    pub sender: Address,
+    pub nonce: U256,
+    #[serde(default)]
+    pub paymaster: Option<Address>,
+    pub actual_gas_cost: U256,
+    pub actual_gas_used: U256,
+    pub success: bool,
+    /// Revert reason when the account call failed
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+    /// Receipt of the bundle transaction that included the UserOperation
+    pub receipt: TransactionReceipt,
+}
+
+/// Result of eth_getUserOperationByHash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationByHash {
+    pub user_operation: UserOperation,
+    pub entry_point: Address,
+    #[serde(default)]
+    pub block_number: Option<U64>,
+    #[serde(default)]
+    pub block_hash: Option<B256>,
+    #[serde(default)]
+    pub transaction_hash: Option<B256>,
+}
+
+/// JSON-RPC client for an ERC-4337 bundler
+pub struct BundlerClient {
+    rpc: RpcClient,
+}
+
+impl BundlerClient {
+    /// Create a client; endpoints are tried in order when one is unreachable
+    pub fn new(urls: Vec<String>) -> Self {
+        Self { rpc: RpcClient::new(urls) }
+    }
+
+    pub async fn send_user_operation(&self, user_op: &UserOperation, entry_point: Address) -> Result<B256, RpcError> {
+        self.rpc.request("eth_sendUserOperation", json!([user_op, entry_point])).await
+    }
+
+    pub async fn estimate_user_operation_gas(&self, user_op: &UserOperation, entry_point: Address) -> Result<UserOperationGasEstimate, RpcError> {
+        self.rpc.request("eth_estimateUserOperationGas", json!([user_op, entry_point])).await
+    }
+
+    pub async fn get_user_operation_receipt(&self, user_op_hash: B256) -> Result<Option<UserOperationReceipt>, RpcError> {
+        self.rpc.request("eth_getUserOperationReceipt", json!([user_op_hash])).await
+    }
+
+    pub async fn get_user_operation_by_hash(&self, user_op_hash: B256) -> Result<Option<UserOperationByHash>, RpcError> {
+        self.rpc.request("eth_getUserOperationByHash", json!([user_op_hash])).await
+    }
+
+    pub async fn supported_entry_points(&self) -> Result<Vec<Address>, RpcError> {
+        self.rpc.request("eth_supportedEntryPoints", json!([])).await
+    }
+}
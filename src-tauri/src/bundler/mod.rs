@@ -0,0 +1,628 @@
+// Bundler Module
+// Submits ERC-4337 UserOperations: nonce from the EntryPoint, fee and gas estimation,
+// optional paymaster sponsorship, signing through the TEE adapter, submission to the
+// chain's bundler, and status events (userop-submitted, userop-confirmed, userop-failed)
+// until inclusion.
+
+pub mod client;
+
+pub use client::BundlerClient;
+
+use alloy_primitives::aliases::U192;
+use alloy_primitives::{Address, Bytes, B256, U256};
+use alloy_sol_types::{sol, SolCall};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::chain::rpc::CallRequest;
+use crate::chain::{ChainConfig, RpcClient};
+use crate::eth::{EntryPointVersion, TxFees, UserOperation, UserOperationRequest, ENTRY_POINT_V07};
+use crate::history;
+use crate::paymaster::{self, PaymasterClient, Sponsorship};
+use crate::policy::step_up;
+use crate::sender::{self, EventSink, SendOptions, TeeSigner};
+use crate::tee::{self, TeeError, TeeOperation};
+
+// Event names
+pub const EVENT_USEROP_SUBMITTED: &str = "userop-submitted";
+pub const EVENT_USEROP_CONFIRMED: &str = "userop-confirmed";
+pub const EVENT_USEROP_FAILED: &str = "userop-failed";
+
+// Well-formed ECDSA signature used while estimating gas, before the real one exists
+const DUMMY_SIGNATURE: [u8; 65] = {
+    let mut signature = [0xff; 65];
+    signature[64] = 0x1c;
+    signature
+};
+
+sol! {
+    interface IEntryPoint {
+        function getNonce(address sender, uint192 key) external view returns (uint256 nonce);
+    }
+}
+
+/// Signs UserOperations for the smart account owner
+#[async_trait]
+pub trait UserOperationSigner: Send + Sync {
+    /// Signature for `UserOperationRequest` JSON
+    async fn sign_user_operation(&self, request: String) -> Result<Bytes, TeeError>;
+
+    /// Have the user confirm `op` before it is signed; signers that need no confirmation
+    /// return right away
+    async fn confirm(&self, _op: &TeeOperation, _events: &EventSink) -> Result<(), TeeError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserOperationSigner for TeeSigner {
+    async fn sign_user_operation(&self, request: String) -> Result<Bytes, TeeError> {
+        let result = tee::perform_tee_operation(TeeOperation::SignUserOperation(request)).await?;
+        let data = sender::tee_result_data(result)?;
+        serde_json::from_value(data["signature"].clone())
+            .map_err(|e| TeeError::OperationFailed(format!("Invalid signing result: {}", e)))
+    }
+
+    async fn confirm(&self, op: &TeeOperation, events: &EventSink) -> Result<(), TeeError> {
+        step_up::confirm(op, events).await
+    }
+}
+
+/// Bundler client for a configured chain
+pub fn client_for(chain: &ChainConfig) -> Result<BundlerClient, String> {
+    if chain.bundler_urls.is_empty() {
+        return Err(format!("No bundler configured for chain {}", chain.chain_id));
+    }
+    Ok(BundlerClient::new(chain.bundler_urls.clone()))
+}
+
+/// Submitted UserOperation, returned to the caller and sent as userop-submitted
+#[derive(Debug, Clone, Serialize)]
+pub struct SubmittedUserOperation {
+    pub user_op_hash: B256,
+    pub chain_id: u64,
+    pub entry_point: Address,
+    pub sender: Address,
+    pub nonce: U256,
+    pub sponsorship: Option<Sponsorship>,
+}
+
+// Failure before submission, with the step that failed
+struct SubmitError {
+    stage: &'static str,
+    message: String,
+}
+
+impl SubmitError {
+    fn new(stage: &'static str, message: impl ToString) -> Self {
+        Self { stage, message: message.to_string() }
+    }
+}
+
+/// Complete, sign and submit a UserOperation, then watch for its inclusion in the background
+///
+/// The nonce is read from the EntryPoint using the key in the high bits of `user_op.nonce`.
+/// Fees and gas limits left at zero are estimated; the signature is always replaced.
+/// With a `sponsor`, the chain's paymaster service of that name pays for gas.
+pub async fn send_user_operation(
+    chain: &ChainConfig,
+    signer: &dyn UserOperationSigner,
+    user_op: UserOperation,
+    entry_point: Option<Address>,
+    sponsor: Option<&str>,
+    events: EventSink,
+    options: SendOptions,
+) -> Result<SubmittedUserOperation, String> {
+    let result = match client_for(chain) {
+        Ok(bundler) => {
+            let bundler = Arc::new(bundler);
+            submit(chain, &bundler, signer, user_op, entry_point, sponsor, &events).await
+                .map(|submitted| (bundler, submitted))
+        },
+        Err(e) => Err(SubmitError::new("prepare", e)),
+    };
+
+    match result {
+        Ok((bundler, submitted)) => {
+            println!("COS72-Tauri: UserOperation {} submitted on chain {}", submitted.user_op_hash, chain.chain_id);
+            history::mark_submitted(submitted.user_op_hash);
+            events(EVENT_USEROP_SUBMITTED, json!(submitted));
+            tokio::spawn(watch_inclusion(bundler, chain.clone(), submitted.clone(), events, options));
+            Ok(submitted)
+        },
+        Err(error) => {
+            println!("COS72-Tauri: UserOperation failed at {}: {}", error.stage, error.message);
+            events(EVENT_USEROP_FAILED, json!({
+                "chain_id": chain.chain_id,
+                "stage": error.stage,
+                "error": error.message,
+            }));
+            Err(error.message)
+        }
+    }
+}
+
+async fn submit(
+    chain: &ChainConfig,
+    bundler: &BundlerClient,
+    signer: &dyn UserOperationSigner,
+    mut user_op: UserOperation,
+    entry_point: Option<Address>,
+    sponsor: Option<&str>,
+    events: &EventSink,
+) -> Result<SubmittedUserOperation, SubmitError> {
+    // Only the canonical EntryPoints have a known UserOperation format
+    let entry_point = entry_point.unwrap_or(ENTRY_POINT_V07);
+    let version = EntryPointVersion::of(entry_point)
+        .ok_or_else(|| SubmitError::new("prepare", format!("Unsupported EntryPoint {}", entry_point)))?;
+    user_op.normalize_for(version).map_err(|e| SubmitError::new("prepare", e))?;
+    let paymaster_config = match sponsor {
+        Some(name) => Some(chain.paymaster(name).ok_or_else(|| SubmitError::new(
+            "prepare", format!("No paymaster named {} on chain {}", name, chain.chain_id)
+        ))?),
+        None => None,
+    };
+
+    let supported = bundler.supported_entry_points().await.map_err(|e| SubmitError::new("prepare", e))?;
+    if !supported.contains(&entry_point) {
+        return Err(SubmitError::new("prepare", format!("Bundler does not support EntryPoint {}", entry_point)));
+    }
+
+    let rpc = RpcClient::new(chain.rpc_urls.clone());
+    let key: U192 = (user_op.nonce >> 64usize).to();
+    let call = IEntryPoint::getNonceCall { sender: user_op.sender, key };
+    let nonce = rpc.call(&CallRequest {
+        to: Some(entry_point),
+        data: Some(call.abi_encode().into()),
+        ..Default::default()
+    }, "latest").await.map_err(|e| SubmitError::new("prepare", e))?;
+    user_op.nonce = IEntryPoint::getNonceCall::abi_decode_returns(&nonce)
+        .map_err(|e| SubmitError::new("prepare", format!("Invalid getNonce result: {}", e)))?;
+
+    if user_op.max_fee_per_gas.is_zero() {
+        let (max_fee_per_gas, max_priority_fee_per_gas) = match sender::fees::estimate_fees(&rpc).await
+            .map_err(|e| SubmitError::new("fees", e))?
+        {
+            TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => (max_fee_per_gas, max_priority_fee_per_gas),
+            TxFees::Legacy { gas_price } => (gas_price, gas_price),
+        };
+        user_op.max_fee_per_gas = U256::from(max_fee_per_gas);
+        user_op.max_priority_fee_per_gas = U256::from(max_priority_fee_per_gas);
+    }
+
+    user_op.signature = Bytes::from_static(&DUMMY_SIGNATURE);
+
+    // Paymaster stub data lets the bundler estimate gas for the sponsored operation
+    let paymaster_client = paymaster_config.map(PaymasterClient::new);
+    let mut sponsor_info = None;
+    let mut paymaster_final = false;
+    if let Some(client) = &paymaster_client {
+        let stub = client.get_paymaster_stub_data(&user_op, entry_point, chain.chain_id).await
+            .map_err(|e| SubmitError::new("paymaster", e))?;
+        stub.apply(&mut user_op, version).map_err(|e| SubmitError::new("paymaster", e))?;
+        paymaster_final = stub.is_final;
+        sponsor_info = stub.sponsor;
+    }
+
+    if user_op.call_gas_limit.is_zero() || user_op.verification_gas_limit.is_zero() || user_op.pre_verification_gas.is_zero() {
+        let estimate = bundler.estimate_user_operation_gas(&user_op, entry_point).await
+            .map_err(|e| SubmitError::new("estimate_gas", e))?;
+        user_op.call_gas_limit = estimate.call_gas_limit;
+        user_op.verification_gas_limit = estimate.verification_gas_limit;
+        user_op.pre_verification_gas = estimate.pre_verification_gas;
+        if user_op.paymaster.is_some() {
+            user_op.paymaster_verification_gas_limit = estimate.paymaster_verification_gas_limit
+                .or(user_op.paymaster_verification_gas_limit);
+            user_op.paymaster_post_op_gas_limit = estimate.paymaster_post_op_gas_limit
+                .or(user_op.paymaster_post_op_gas_limit);
+        }
+    }
+
+    if let Some(client) = &paymaster_client {
+        if !paymaster_final {
+            let data = client.get_paymaster_data(&user_op, entry_point, chain.chain_id).await
+                .map_err(|e| SubmitError::new("paymaster", e))?;
+            data.apply(&mut user_op, version).map_err(|e| SubmitError::new("paymaster", e))?;
+            sponsor_info = data.sponsor.or(sponsor_info);
+        }
+    }
+    let sponsorship = match paymaster_config {
+        Some(config) => {
+            if let Some(expected_signer) = config.verifying_signer {
+                let now = chrono::Utc::now().timestamp().max(0) as u64;
+                paymaster::verify_sponsorship(&user_op, version, chain.chain_id, expected_signer, now)
+                    .map_err(|e| SubmitError::new("paymaster", e))?;
+            }
+            Some(Sponsorship::new(config, &user_op, version, sponsor_info.as_ref())
+                .ok_or_else(|| SubmitError::new("paymaster", "Paymaster service returned no paymaster"))?)
+        },
+        None => None,
+    };
+
+    // The user confirms the final UserOperation, paymaster data included, then the TEE checks
+    // the account's call against the transaction policy before signing
+    let request = UserOperationRequest { user_op, entry_point, chain_id: chain.chain_id };
+    let request_json = serde_json::to_string(&request).map_err(|e| SubmitError::new("sign", e))?;
+    signer.confirm(&TeeOperation::SignUserOperation(request_json.clone()), events).await
+        .map_err(|e| SubmitError::new("step_up", e))?;
+    let signature = signer.sign_user_operation(request_json).await.map_err(|e| {
+        let stage = match e {
+            TeeError::PolicyDenied(_) | TeeError::StepUpRequired(_) => "policy",
+            _ => "sign",
+        };
+        SubmitError::new(stage, e)
+    })?;
+    let local_hash = request.user_op_hash();
+    let mut user_op = request.user_op;
+    user_op.signature = signature;
+
+    let user_op_hash = bundler.send_user_operation(&user_op, entry_point).await.map_err(|e| {
+        history::mark_failed(local_hash, &e.to_string());
+        SubmitError::new("submit", e)
+    })?;
+    if user_op_hash != local_hash {
+        println!("COS72-Tauri: Bundler returned userOpHash {} for signed hash {}", user_op_hash, local_hash);
+    }
+
+    Ok(SubmittedUserOperation {
+        user_op_hash,
+        chain_id: chain.chain_id,
+        entry_point,
+        sender: user_op.sender,
+        nonce: user_op.nonce,
+        sponsorship,
+    })
+}
+
+// Poll the bundler for the UserOperation receipt and report the outcome
+async fn watch_inclusion(
+    bundler: Arc<BundlerClient>,
+    chain: ChainConfig,
+    submitted: SubmittedUserOperation,
+    events: EventSink,
+    options: SendOptions,
+) {
+    let started = Instant::now();
+
+    loop {
+        match bundler.get_user_operation_receipt(submitted.user_op_hash).await {
+            Ok(Some(receipt)) => {
+                let tx_hash = receipt.receipt.transaction_hash.to_string();
+                let mut payload = json!({
+                    "user_op_hash": submitted.user_op_hash,
+                    "chain_id": submitted.chain_id,
+                    "transaction_hash": tx_hash,
+                    "block_number": receipt.receipt.block_number,
+                    "actual_gas_cost": receipt.actual_gas_cost,
+                    "actual_gas_used": receipt.actual_gas_used,
+                    "paymaster": receipt.paymaster,
+                    "sponsorship": submitted.sponsorship,
+                    "explorer_url": chain.explorer_tx_url(&tx_hash),
+                });
+                let error = (!receipt.success).then(|| receipt.reason.clone().unwrap_or_else(|| "UserOperation reverted".to_string()));
+                history::mark_included(
+                    submitted.user_op_hash, receipt.receipt.transaction_hash,
+                    receipt.receipt.block_number.map(|block| block.to()), error,
+                );
+                // The sponsor pays for gas whether or not the account call succeeded
+                if let Some(sponsorship) = &submitted.sponsorship {
+                    paymaster::record_sponsorship(
+                        sponsorship, submitted.chain_id, submitted.user_op_hash,
+                        receipt.receipt.transaction_hash, receipt.actual_gas_cost,
+                    );
+                }
+                if receipt.success {
+                    println!("COS72-Tauri: UserOperation {} included in {}", submitted.user_op_hash, tx_hash);
+                    events(EVENT_USEROP_CONFIRMED, payload);
+                } else {
+                    println!("COS72-Tauri: UserOperation {} reverted", submitted.user_op_hash);
+                    payload["stage"] = json!("execution");
+                    payload["error"] = json!(receipt.reason.unwrap_or_else(|| "UserOperation reverted".to_string()));
+                    events(EVENT_USEROP_FAILED, payload);
+                }
+                return;
+            },
+            Ok(None) => {},
+            // Transient RPC errors do not end the watch
+            Err(e) => println!("COS72-Tauri: Receipt poll for {} failed: {}", submitted.user_op_hash, e),
+        }
+
+        if started.elapsed() >= options.confirmation_timeout {
+            events(EVENT_USEROP_FAILED, json!({
+                "user_op_hash": submitted.user_op_hash,
+                "chain_id": submitted.chain_id,
+                "stage": "confirmation",
+                "error": "Timed out waiting for the UserOperation receipt",
+            }));
+            return;
+        }
+        tokio::time::sleep(options.poll_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_node::TestNode;
+    use crate::chain::PaymasterConfig;
+    use crate::eth::ENTRY_POINT_V06;
+    use crate::paymaster::verifying::{sponsorship_hash, VerifyingPaymasterData};
+    use crate::tee::wallet::WalletKey;
+    use alloy_primitives::{eip191_hash_message, Signature};
+    use k256::ecdsa::SigningKey;
+    use serde_json::Value;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[async_trait]
+    impl UserOperationSigner for WalletKey {
+        async fn sign_user_operation(&self, request: String) -> Result<Bytes, TeeError> {
+            let signed = WalletKey::sign_user_operation(self, &request)?;
+            Ok(serde_json::from_value(signed["signature"].clone()).unwrap())
+        }
+    }
+
+    type Recorded = Arc<Mutex<Vec<(String, Value)>>>;
+
+    fn recorder() -> (EventSink, Recorded) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink_events = events.clone();
+        let sink: EventSink = Arc::new(move |event, payload| {
+            sink_events.lock().unwrap().push((event.to_string(), payload));
+        });
+        (sink, events)
+    }
+
+    fn fast_options() -> SendOptions {
+        SendOptions { poll_interval: Duration::from_millis(10), confirmation_timeout: Duration::from_millis(500) }
+    }
+
+    // Bundler that accepts UserOperations and includes them on the next receipt poll
+    fn script_bundler(node: &TestNode, success: bool) -> Arc<Mutex<Vec<UserOperation>>> {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        node.on("eth_supportedEntryPoints", |_| Ok(json!([ENTRY_POINT_V06, ENTRY_POINT_V07])));
+        // EntryPoint.getNonce
+        node.on("eth_call", |_| Ok(json!(B256::from(U256::from(7)))));
+        node.on("eth_estimateUserOperationGas", |_| Ok(json!({
+            "preVerificationGas": "0xc350",
+            "verificationGasLimit": "0x186a0",
+            "callGasLimit": "0x9c40",
+        })));
+        let sent_ops = sent.clone();
+        node.on("eth_sendUserOperation", move |params| {
+            let user_op: UserOperation = serde_json::from_value(params[0].clone()).map_err(|e| (-32602, e.to_string()))?;
+            let entry_point: Address = serde_json::from_value(params[1].clone()).map_err(|e| (-32602, e.to_string()))?;
+            let hash = user_op.hash(entry_point, 31337);
+            sent_ops.lock().unwrap().push(user_op);
+            Ok(json!(hash))
+        });
+        node.on("eth_getUserOperationReceipt", move |params| Ok(json!({
+            "userOpHash": params[0],
+            "entryPoint": ENTRY_POINT_V07,
+            "sender": Address::repeat_byte(0x11),
+            "nonce": "0x7",
+            "actualGasCost": "0x1000",
+            "actualGasUsed": "0x100",
+            "success": success,
+            "reason": if success { Value::Null } else { json!("AA23 reverted") },
+            "logs": [],
+            "receipt": {
+                "transactionHash": B256::repeat_byte(0x22),
+                "blockNumber": "0x2",
+                "status": "0x1",
+                "gasUsed": "0x100",
+                "logs": [],
+            },
+        })));
+        sent
+    }
+
+    // Verifying paymaster service that signs with `signer` and reports `sponsor`
+    fn script_paymaster(node: &TestNode, signer: SigningKey, paymaster: Address) {
+        let stub_data = VerifyingPaymasterData { valid_until: 0, valid_after: 0, signature: Bytes::from_static(&DUMMY_SIGNATURE) };
+        let stub_response = move |version: EntryPointVersion, data: &VerifyingPaymasterData| match version {
+            EntryPointVersion::V06 => json!({ "paymasterAndData": format!("{}{}", paymaster, hex::encode(data.encode())) }),
+            EntryPointVersion::V07 => json!({
+                "paymaster": paymaster,
+                "paymasterData": data.encode(),
+                "paymasterVerificationGasLimit": "0x7530",
+                "paymasterPostOpGasLimit": "0x2710",
+            }),
+        };
+        let entry_point_version = |params: &Value| {
+            let entry_point: Address = serde_json::from_value(params[1].clone()).unwrap();
+            EntryPointVersion::of(entry_point).unwrap()
+        };
+
+        node.on("pm_getPaymasterStubData", move |params| {
+            assert_eq!(params[2], json!("0x7a69"));
+            assert_eq!(params[3], json!({ "policy": "community" }));
+            Ok(stub_response(entry_point_version(params), &stub_data))
+        });
+        node.on("pm_getPaymasterData", move |params| {
+            let version = entry_point_version(params);
+            let user_op: UserOperation = serde_json::from_value(params[0].clone()).unwrap();
+            let hash = sponsorship_hash(&user_op, version, paymaster, 31337, 0, 0);
+            let signature = Signature::from(signer.sign_prehash_recoverable(eip191_hash_message(hash).as_slice()).unwrap());
+            let data = VerifyingPaymasterData { valid_until: 0, valid_after: 0, signature: signature.as_bytes().into() };
+            let mut response = stub_response(version, &data);
+            response["sponsor"] = json!({ "name": "AAStar Community" });
+            Ok(response)
+        });
+    }
+
+    async fn wait_for_events(events: &Mutex<Vec<(String, Value)>>, count: usize) {
+        for _ in 0..100 {
+            if events.lock().unwrap().len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_user_operation_fills_signs_and_confirms() {
+        let node = TestNode::spawn().await;
+        let sent = script_bundler(&node, true);
+        let owner = WalletKey::from_private_key_hex(&format!("0x{}", "44".repeat(32))).unwrap();
+        let (sink, events) = recorder();
+
+        let user_op = UserOperation {
+            sender: Address::repeat_byte(0x11),
+            call_data: Bytes::from_static(&[0xb6, 0x1d, 0x27, 0xf6]),
+            ..Default::default()
+        };
+        let submitted = send_user_operation(&node.chain_config(), &owner, user_op, None, None, sink, fast_options()).await.unwrap();
+        assert_eq!(submitted.nonce, U256::from(7));
+
+        let sent_op = sent.lock().unwrap()[0].clone();
+        assert_eq!(sent_op.call_gas_limit, U256::from(0x9c40));
+        assert!(!sent_op.max_fee_per_gas.is_zero());
+        assert_eq!(sent_op.hash(ENTRY_POINT_V07, 31337), submitted.user_op_hash);
+        let signature = Signature::try_from(sent_op.signature.as_ref()).unwrap();
+        assert_eq!(signature.recover_address_from_msg(submitted.user_op_hash).unwrap(), owner.address());
+
+        wait_for_events(&events, 2).await;
+        let events_seen = events.lock().unwrap().clone();
+        assert_eq!(events_seen[0].0, EVENT_USEROP_SUBMITTED);
+        assert_eq!(events_seen[1].0, EVENT_USEROP_CONFIRMED);
+        assert_eq!(events_seen[1].1["transaction_hash"], json!(B256::repeat_byte(0x22)));
+    }
+
+    #[tokio::test]
+    async fn test_send_user_operation_failures() {
+        let node = TestNode::spawn().await;
+        script_bundler(&node, false);
+        let owner = WalletKey::from_private_key_hex(&format!("0x{}", "55".repeat(32))).unwrap();
+        let user_op = UserOperation { sender: Address::repeat_byte(0x11), ..Default::default() };
+
+        // Reverted account call
+        let (sink, events) = recorder();
+        send_user_operation(&node.chain_config(), &owner, user_op.clone(), None, None, sink, fast_options()).await.unwrap();
+        wait_for_events(&events, 2).await;
+        let failed = events.lock().unwrap()[1].clone();
+        assert_eq!(failed.0, EVENT_USEROP_FAILED);
+        assert_eq!(failed.1["error"], json!("AA23 reverted"));
+
+        // EntryPoint the bundler does not serve
+        let (sink, events) = recorder();
+        let error = send_user_operation(&node.chain_config(), &owner, user_op.clone(), Some(Address::repeat_byte(1)), None, sink, fast_options())
+            .await.unwrap_err();
+        assert!(error.contains("EntryPoint"), "{}", error);
+        assert_eq!(events.lock().unwrap()[0].1["stage"], json!("prepare"));
+
+        // Chain without a bundler
+        let mut chain = node.chain_config();
+        chain.bundler_urls.clear();
+        let (sink, _) = recorder();
+        assert!(send_user_operation(&chain, &owner, user_op, None, None, sink, fast_options()).await.is_err());
+    }
+
+    // Signer whose user declines to confirm, recording what was shown
+    struct DecliningSigner(Mutex<Option<String>>);
+
+    #[async_trait]
+    impl UserOperationSigner for DecliningSigner {
+        async fn sign_user_operation(&self, _request: String) -> Result<Bytes, TeeError> {
+            panic!("signed without confirmation");
+        }
+
+        async fn confirm(&self, op: &TeeOperation, _events: &EventSink) -> Result<(), TeeError> {
+            if let TeeOperation::SignUserOperation(request) = op {
+                *self.0.lock().unwrap() = Some(request.clone());
+            }
+            Err(TeeError::StepUpRequired("Passkey confirmation timed out".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_user_operation_is_confirmed_before_signing() {
+        let node = TestNode::spawn().await;
+        let sent = script_bundler(&node, true);
+        let signer = DecliningSigner(Mutex::new(None));
+        let (sink, events) = recorder();
+
+        let user_op = UserOperation { sender: Address::repeat_byte(0x11), ..Default::default() };
+        let error = send_user_operation(&node.chain_config(), &signer, user_op, None, None, sink, fast_options())
+            .await.unwrap_err();
+        assert!(error.contains("timed out"), "{}", error);
+        assert_eq!(events.lock().unwrap()[0].1["stage"], json!("step_up"));
+        assert!(sent.lock().unwrap().is_empty());
+
+        // The user was shown the final operation, with nonce and gas filled in
+        let request = signer.0.lock().unwrap().clone().expect("no UserOperation confirmed");
+        let request = UserOperationRequest::from_json(&request).unwrap();
+        assert_eq!(request.user_op.nonce, U256::from(7));
+        assert_eq!(request.user_op.call_gas_limit, U256::from(0x9c40));
+    }
+
+    #[tokio::test]
+    async fn test_sponsored_user_operation() {
+        let node = TestNode::spawn().await;
+        let sent = script_bundler(&node, true);
+        let paymaster_key = SigningKey::from_slice(&[0x66; 32]).unwrap();
+        let paymaster = Address::repeat_byte(0x99);
+        script_paymaster(&node, paymaster_key.clone(), paymaster);
+        let owner = WalletKey::from_private_key_hex(&format!("0x{}", "44".repeat(32))).unwrap();
+
+        let mut chain = node.chain_config();
+        chain.paymasters.push(PaymasterConfig {
+            name: "community".to_string(),
+            url: node.url(),
+            context: Some(json!({ "policy": "community" })),
+            verifying_signer: Some(Address::from_private_key(&paymaster_key)),
+        });
+
+        for entry_point in [ENTRY_POINT_V06, ENTRY_POINT_V07] {
+            let version = EntryPointVersion::of(entry_point).unwrap();
+            let user_op = UserOperation { sender: Address::repeat_byte(0x11), ..Default::default() };
+            let (sink, events) = recorder();
+            let submitted = send_user_operation(&chain, &owner, user_op, Some(entry_point), Some("community"), sink, fast_options())
+                .await.unwrap();
+            let sponsorship = submitted.sponsorship.clone().unwrap();
+            assert_eq!(sponsorship.sponsor, "AAStar Community");
+            assert_eq!(sponsorship.paymaster, paymaster);
+
+            // The owner signed over the final paymaster data
+            let sent_op = sent.lock().unwrap().last().unwrap().clone();
+            assert_eq!(sent_op.hash(entry_point, 31337), submitted.user_op_hash);
+            assert!(paymaster::verify_sponsorship(&sent_op, version, 31337, Address::from_private_key(&paymaster_key), 0).is_ok());
+
+            wait_for_events(&events, 2).await;
+            let confirmed = events.lock().unwrap()[1].clone();
+            assert_eq!(confirmed.0, EVENT_USEROP_CONFIRMED);
+            assert_eq!(confirmed.1["sponsorship"]["sponsor"], json!("AAStar Community"));
+            let recorded = paymaster::list_sponsorships().unwrap();
+            assert!(recorded.iter().any(|record| record["user_op_hash"] == json!(submitted.user_op_hash)));
+        }
+
+        // Paymaster data signed by someone other than the configured signer
+        chain.paymasters[0].verifying_signer = Some(Address::repeat_byte(0x01));
+        let (sink, events) = recorder();
+        let user_op = UserOperation { sender: Address::repeat_byte(0x11), ..Default::default() };
+        let error = send_user_operation(&chain, &owner, user_op.clone(), None, Some("community"), sink, fast_options())
+            .await.unwrap_err();
+        assert!(error.contains("signed by"), "{}", error);
+        assert_eq!(events.lock().unwrap()[0].1["stage"], json!("paymaster"));
+
+        let (sink, _) = recorder();
+        assert!(send_user_operation(&chain, &owner, user_op, None, Some("unknown"), sink, fast_options()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bundler_lookups() {
+        let node = TestNode::spawn().await;
+        script_bundler(&node, true);
+        node.on("eth_getUserOperationByHash", |_| Ok(Value::Null));
+        let bundler = client_for(&node.chain_config()).unwrap();
+
+        assert_eq!(bundler.supported_entry_points().await.unwrap(), vec![ENTRY_POINT_V06, ENTRY_POINT_V07]);
+        let receipt = bundler.get_user_operation_receipt(B256::repeat_byte(3)).await.unwrap().unwrap();
+        assert!(receipt.success);
+        assert_eq!(receipt.user_op_hash, B256::repeat_byte(3));
+        assert_eq!(bundler.get_user_operation_by_hash(B256::repeat_byte(3)).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,92 @@
+// Chain Module
+// EVM chain registry and JSON-RPC access. Built-in chains can be overridden or
+// extended per community through chains.json in the app data directory.
+
+pub mod registry;
+pub mod rpc;
+#[cfg(test)]
+pub(crate) mod test_node;
+
+pub use registry::{ChainConfig, ChainRegistry, PaymasterConfig};
+pub use rpc::{RpcClient, TransactionReceipt};
+
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+use crate::storage;
+
+// Constants
+const CHAINS_FILE: &str = "chains.json";
+
+struct ChainState {
+    // Community overrides persisted in CHAINS_FILE
+    overrides: Vec<ChainConfig>,
+    registry: ChainRegistry,
+}
+
+static CHAIN_STATE: Lazy<Mutex<ChainState>> = Lazy::new(|| {
+    let overrides = match storage::load_json::<Vec<ChainConfig>>(CHAINS_FILE) {
+        Ok(overrides) => overrides.unwrap_or_default(),
+        Err(e) => {
+            println!("COS72-Tauri: Failed to load chain configuration, using built-in chains: {}", e);
+            Vec::new()
+        }
+    };
+    let registry = ChainRegistry::with_overrides(overrides.clone());
+    Mutex::new(ChainState { overrides, registry })
+});
+
+fn lock_state() -> std::sync::MutexGuard<'static, ChainState> {
+    CHAIN_STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// All configured chains
+pub fn list_chains() -> Vec<ChainConfig> {
+    lock_state().registry.chains().to_vec()
+}
+
+/// Configuration of one chain
+pub fn get_chain(chain_id: u64) -> Result<ChainConfig, String> {
+    lock_state().registry.get(chain_id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown chain {}", chain_id))
+}
+
+/// Add or replace a chain configuration and persist it
+pub fn save_chain(config: ChainConfig) -> Result<(), String> {
+    config.validate()?;
+
+    let mut state = lock_state();
+    let mut overrides = state.overrides.clone();
+    overrides.retain(|chain| chain.chain_id != config.chain_id);
+    overrides.push(config.clone());
+
+    storage::save_json(CHAINS_FILE, &overrides)
+        .map_err(|e| format!("Failed to save chain configuration: {}", e))?;
+    state.overrides = overrides;
+    state.registry.upsert(config);
+    Ok(())
+}
+
+/// Remove a community chain; built-in chains revert to their defaults
+pub fn remove_chain(chain_id: u64) -> Result<bool, String> {
+    let mut state = lock_state();
+    let mut overrides = state.overrides.clone();
+    overrides.retain(|chain| chain.chain_id != chain_id);
+    if overrides.len() == state.overrides.len() {
+        return Ok(false);
+    }
+
+    storage::save_json(CHAINS_FILE, &overrides)
+        .map_err(|e| format!("Failed to save chain configuration: {}", e))?;
+    state.registry = ChainRegistry::with_overrides(overrides.clone());
+    state.overrides = overrides;
+    Ok(true)
+}
+
+/// RPC client for a configured chain
+pub fn client(chain_id: u64) -> Result<(ChainConfig, RpcClient), String> {
+    let config = get_chain(chain_id)?;
+    let client = RpcClient::new(config.rpc_urls.clone());
+    Ok((config, client))
+}
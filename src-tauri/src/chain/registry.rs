@@ -0,0 +1,185 @@
+// Chain Registry
+// Known EVM chains with their RPC endpoints, explorer and native currency.
+// Built-in chains can be overridden or extended by the community's chains file.
+
+use serde::{Deserialize, Serialize};
+
+/// Native currency of a chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NativeCurrency {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// Community paymaster service that sponsors gas through ERC-7677
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymasterConfig {
+    /// Community sponsor paying for the gas
+    pub name: String,
+    pub url: String,
+    /// Service-specific context passed to pm_* calls (sponsorship policy ID, ...)
+    #[serde(default)]
+    pub context: Option<serde_json::Value>,
+    /// Signer of a verifying paymaster; its signature is checked before the user signs
+    #[serde(default)]
+    pub verifying_signer: Option<alloy_primitives::Address>,
+}
+
+/// Configuration of one EVM chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    pub name: String,
+    /// RPC endpoints, tried in order
+    pub rpc_urls: Vec<String>,
+    /// ERC-4337 bundler endpoints, tried in order
+    #[serde(default)]
+    pub bundler_urls: Vec<String>,
+    /// Paymaster services that sponsor UserOperations
+    #[serde(default)]
+    pub paymasters: Vec<PaymasterConfig>,
+    #[serde(default)]
+    pub explorer_url: Option<String>,
+    pub native_currency: NativeCurrency,
+    #[serde(default)]
+    pub testnet: bool,
+}
+
+impl ChainConfig {
+    /// Check that the configuration can be used
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chain_id == 0 {
+            return Err("chain_id must not be 0".to_string());
+        }
+        if self.rpc_urls.is_empty() {
+            return Err(format!("Chain {} has no RPC URLs", self.chain_id));
+        }
+        let paymaster_urls = self.paymasters.iter().map(|paymaster| &paymaster.url);
+        for rpc_url in self.rpc_urls.iter().chain(&self.bundler_urls).chain(paymaster_urls) {
+            let parsed = url::Url::parse(rpc_url).map_err(|e| format!("Invalid RPC URL {}: {}", rpc_url, e))?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err(format!("RPC URL {} must use http or https", rpc_url));
+            }
+        }
+        Ok(())
+    }
+
+    /// Paymaster service of the named sponsor
+    pub fn paymaster(&self, name: &str) -> Option<&PaymasterConfig> {
+        self.paymasters.iter().find(|paymaster| paymaster.name == name)
+    }
+
+    /// Explorer link for a transaction, if an explorer is configured
+    pub fn explorer_tx_url(&self, tx_hash: &str) -> Option<String> {
+        self.explorer_url.as_ref().map(|url| format!("{}/tx/{}", url.trim_end_matches('/'), tx_hash))
+    }
+}
+
+/// Set of chains available to the app
+#[derive(Debug, Clone)]
+pub struct ChainRegistry {
+    chains: Vec<ChainConfig>,
+}
+
+impl ChainRegistry {
+    /// Registry with the built-in chains plus the given overrides (matched by chain ID)
+    pub fn with_overrides(overrides: Vec<ChainConfig>) -> Self {
+        let mut registry = Self { chains: builtin_chains() };
+        for chain in overrides {
+            registry.upsert(chain);
+        }
+        registry
+    }
+
+    pub fn get(&self, chain_id: u64) -> Option<&ChainConfig> {
+        self.chains.iter().find(|chain| chain.chain_id == chain_id)
+    }
+
+    pub fn chains(&self) -> &[ChainConfig] {
+        &self.chains
+    }
+
+    /// Add a chain or replace the existing one with the same chain ID
+    pub fn upsert(&mut self, chain: ChainConfig) {
+        match self.chains.iter_mut().find(|existing| existing.chain_id == chain.chain_id) {
+            Some(existing) => *existing = chain,
+            None => self.chains.push(chain),
+        }
+    }
+}
+
+fn chain(chain_id: u64, name: &str, rpc_url: &str, explorer_url: Option<&str>, symbol: &str, testnet: bool) -> ChainConfig {
+    ChainConfig {
+        chain_id,
+        name: name.to_string(),
+        rpc_urls: vec![rpc_url.to_string()],
+        bundler_urls: Vec::new(),
+        paymasters: Vec::new(),
+        explorer_url: explorer_url.map(str::to_string),
+        native_currency: NativeCurrency {
+            name: if symbol == "ETH" { "Ether".to_string() } else { symbol.to_string() },
+            symbol: symbol.to_string(),
+            decimals: 18,
+        },
+        testnet,
+    }
+}
+
+// Built-in chains
+fn builtin_chains() -> Vec<ChainConfig> {
+    // Local bundlers (Alto, Rundler, ...) listen on 4337 by convention
+    let mut local = chain(31337, "Local Anvil", "http://127.0.0.1:8545", None, "ETH", true);
+    local.bundler_urls = vec!["http://127.0.0.1:4337".to_string()];
+
+    vec![
+        chain(1, "Ethereum", "https://ethereum-rpc.publicnode.com", Some("https://etherscan.io"), "ETH", false),
+        chain(11155111, "Sepolia", "https://ethereum-sepolia-rpc.publicnode.com", Some("https://sepolia.etherscan.io"), "ETH", true),
+        chain(10, "OP Mainnet", "https://mainnet.optimism.io", Some("https://optimistic.etherscan.io"), "ETH", false),
+        chain(11155420, "OP Sepolia", "https://sepolia.optimism.io", Some("https://sepolia-optimism.etherscan.io"), "ETH", true),
+        chain(8453, "Base", "https://mainnet.base.org", Some("https://basescan.org"), "ETH", false),
+        local,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_replace_and_extend_builtin_chains() {
+        let mut sepolia = ChainRegistry::with_overrides(Vec::new()).get(11155111).unwrap().clone();
+        sepolia.rpc_urls = vec!["https://rpc.community.example/sepolia".to_string()];
+        let community = chain(42069, "Community L2", "https://rpc.community.example", None, "CMT", true);
+
+        let registry = ChainRegistry::with_overrides(vec![sepolia.clone(), community]);
+        assert_eq!(registry.get(11155111), Some(&sepolia));
+        assert_eq!(registry.get(42069).unwrap().native_currency.symbol, "CMT");
+        assert_eq!(
+            registry.get(1).unwrap().explorer_tx_url("0xabc").as_deref(),
+            Some("https://etherscan.io/tx/0xabc")
+        );
+    }
+
+    #[test]
+    fn test_validate_chain_config() {
+        let mut config = chain(42069, "Community L2", "https://rpc.community.example", None, "CMT", true);
+        assert!(config.validate().is_ok());
+
+        config.bundler_urls = vec!["ftp://bundler.community.example".to_string()];
+        assert!(config.validate().is_err());
+        config.bundler_urls.clear();
+        config.paymasters.push(PaymasterConfig {
+            name: "Community".to_string(),
+            url: "not a url".to_string(),
+            context: None,
+            verifying_signer: None,
+        });
+        assert!(config.validate().is_err());
+        config.paymasters.clear();
+        config.rpc_urls = vec!["ws://rpc.community.example".to_string()];
+        assert!(config.validate().is_err());
+        config.rpc_urls.clear();
+        assert!(config.validate().is_err());
+    }
+}
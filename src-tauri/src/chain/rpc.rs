@@ -0,0 +1,304 @@
+// EVM JSON-RPC Client
+// Async client for the eth_* methods used by the wallet, with request batching
+// and failover across the chain's RPC endpoints
+
+use alloy_primitives::{Address, Bytes, B256, U256, U64};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Constants
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// JSON-RPC client errors
+#[derive(Debug)]
+pub enum RpcError {
+    Transport(String),                                        // Network or HTTP failure
+    Rpc { code: i64, message: String, data: Option<Value> },  // Error object returned by the node
+    InvalidResponse(String),                                  // Response could not be parsed
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::Transport(msg) => write!(f, "RPC transport error: {}", msg),
+            RpcError::Rpc { code, message, .. } => write!(f, "RPC error {}: {}", code, message),
+            RpcError::InvalidResponse(msg) => write!(f, "Invalid RPC response: {}", msg),
+        }
+    }
+}
+
+/// Transaction fields for eth_call and eth_estimateGas
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Bytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas: Option<U64>,
+}
+
+/// Result of eth_feeHistory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeHistory {
+    pub oldest_block: U64,
+    pub base_fee_per_gas: Vec<U256>,
+    pub gas_used_ratio: Vec<f64>,
+    #[serde(default)]
+    pub reward: Option<Vec<Vec<U256>>>,
+}
+
+/// Log entry of a transaction receipt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<B256>,
+    pub data: Bytes,
+}
+
+/// Result of eth_getTransactionReceipt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReceipt {
+    pub transaction_hash: B256,
+    pub block_number: Option<U64>,
+    pub block_hash: Option<B256>,
+    /// 1 for success, 0 for failure
+    pub status: Option<U64>,
+    pub gas_used: U256,
+    #[serde(default)]
+    pub effective_gas_price: Option<U256>,
+    #[serde(default)]
+    pub contract_address: Option<Address>,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+}
+
+impl TransactionReceipt {
+    pub fn succeeded(&self) -> bool {
+        self.status.map(|status| status == U64::from(1)).unwrap_or(true)
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    id: Option<Value>,
+    result: Option<Value>,
+    error: Option<RpcErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcResponse {
+    fn into_result(self) -> Result<Value, RpcError> {
+        match (self.result, self.error) {
+            (_, Some(error)) => Err(RpcError::Rpc { code: error.code, message: error.message, data: error.data }),
+            (Some(result), None) => Ok(result),
+            (None, None) => Ok(Value::Null),
+        }
+    }
+}
+
+/// JSON-RPC client for one chain
+pub struct RpcClient {
+    urls: Vec<String>,
+    http: reqwest::Client,
+    next_id: AtomicU64,
+}
+
+impl RpcClient {
+    /// Create a client; endpoints are tried in order when one is unreachable
+    pub fn new(urls: Vec<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        Self { urls, http, next_id: AtomicU64::new(1) }
+    }
+
+    /// Send a single request and deserialize its result
+    pub async fn request<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, RpcError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let body = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+
+        let response: RpcResponse = serde_json::from_value(self.post(&body).await?)
+            .map_err(|e| RpcError::InvalidResponse(format!("{}: {}", method, e)))?;
+        let result = response.into_result()?;
+        serde_json::from_value(result)
+            .map_err(|e| RpcError::InvalidResponse(format!("{}: {}", method, e)))
+    }
+
+    /// Send several requests in one batch; results are returned in request order
+    pub async fn batch(&self, calls: &[(&str, Value)]) -> Result<Vec<Result<Value, RpcError>>, RpcError> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let first_id = self.next_id.fetch_add(calls.len() as u64, Ordering::Relaxed);
+        let body = Value::Array(calls.iter().enumerate()
+            .map(|(i, (method, params))| json!({
+                "jsonrpc": "2.0", "id": first_id + i as u64, "method": method, "params": params
+            }))
+            .collect());
+
+        let responses: Vec<RpcResponse> = serde_json::from_value(self.post(&body).await?)
+            .map_err(|e| RpcError::InvalidResponse(format!("batch: {}", e)))?;
+
+        // Nodes may answer a batch in any order; match responses by id
+        let mut results: Vec<Option<Result<Value, RpcError>>> = calls.iter().map(|_| None).collect();
+        for response in responses {
+            let index = response.id.as_ref()
+                .and_then(Value::as_u64)
+                .and_then(|id| id.checked_sub(first_id))
+                .filter(|index| (*index as usize) < calls.len())
+                .ok_or_else(|| RpcError::InvalidResponse("batch response with unknown id".to_string()))?;
+            results[index as usize] = Some(response.into_result());
+        }
+
+        Ok(results.into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(RpcError::InvalidResponse("missing batch response".to_string()))))
+            .collect())
+    }
+
+    async fn post(&self, body: &Value) -> Result<Value, RpcError> {
+        let mut last_error = RpcError::Transport("no RPC URLs configured".to_string());
+
+        for url in &self.urls {
+            let response = match self.http.post(url).json(body).send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    println!("COS72-Tauri: RPC endpoint {} failed: {}", url, e);
+                    last_error = RpcError::Transport(e.to_string());
+                    continue;
+                }
+            };
+
+            let status = response.status();
+            if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                println!("COS72-Tauri: RPC endpoint {} returned HTTP {}", url, status);
+                last_error = RpcError::Transport(format!("HTTP {} from {}", status, url));
+                continue;
+            }
+
+            return response.json::<Value>().await
+                .map_err(|e| RpcError::InvalidResponse(e.to_string()));
+        }
+
+        Err(last_error)
+    }
+
+    pub async fn chain_id(&self) -> Result<u64, RpcError> {
+        self.request::<U64>("eth_chainId", json!([])).await.map(|id| id.to())
+    }
+
+    pub async fn get_balance(&self, address: Address, block: &str) -> Result<U256, RpcError> {
+        self.request("eth_getBalance", json!([address, block])).await
+    }
+
+    pub async fn get_transaction_count(&self, address: Address, block: &str) -> Result<u64, RpcError> {
+        self.request::<U64>("eth_getTransactionCount", json!([address, block])).await.map(|count| count.to())
+    }
+
+    pub async fn get_code(&self, address: Address, block: &str) -> Result<Bytes, RpcError> {
+        self.request("eth_getCode", json!([address, block])).await
+    }
+
+    pub async fn call(&self, call: &CallRequest, block: &str) -> Result<Bytes, RpcError> {
+        self.request("eth_call", json!([call, block])).await
+    }
+
+    pub async fn estimate_gas(&self, call: &CallRequest) -> Result<u64, RpcError> {
+        self.request::<U64>("eth_estimateGas", json!([call])).await.map(|gas| gas.to())
+    }
+
+    pub async fn fee_history(&self, block_count: u64, newest_block: &str, reward_percentiles: &[f64]) -> Result<FeeHistory, RpcError> {
+        self.request("eth_feeHistory", json!([U64::from(block_count), newest_block, reward_percentiles])).await
+    }
+
+    pub async fn send_raw_transaction(&self, raw_tx: &[u8]) -> Result<B256, RpcError> {
+        self.request("eth_sendRawTransaction", json!([Bytes::copy_from_slice(raw_tx)])).await
+    }
+
+    pub async fn get_transaction_receipt(&self, tx_hash: B256) -> Result<Option<TransactionReceipt>, RpcError> {
+        self.request("eth_getTransactionReceipt", json!([tx_hash])).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_node::TestNode;
+
+    const ALICE: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+    #[tokio::test]
+    async fn test_basic_methods_against_test_node() {
+        let node = TestNode::spawn().await;
+        let alice: Address = ALICE.parse().unwrap();
+        node.set_balance(alice, U256::from(10).pow(U256::from(18)));
+        let client = RpcClient::new(vec![node.url()]);
+
+        assert_eq!(client.chain_id().await.unwrap(), 31337);
+        assert_eq!(client.get_balance(alice, "latest").await.unwrap(), U256::from(10).pow(U256::from(18)));
+        node.set_nonce(alice, 5);
+        assert_eq!(client.get_transaction_count(alice, "pending").await.unwrap(), 5);
+        assert_eq!(client.estimate_gas(&CallRequest { to: Some(alice), ..Default::default() }).await.unwrap(), 21000);
+
+        let history = client.fee_history(2, "latest", &[50.0]).await.unwrap();
+        assert_eq!(history.base_fee_per_gas.len(), 3);
+        assert!(history.reward.is_some());
+
+        let tx_hash = client.send_raw_transaction(&[0x02, 0xc0]).await.unwrap();
+        assert_eq!(node.raw_transactions(), vec![Bytes::from_static(&[0x02, 0xc0])]);
+        let receipt = client.get_transaction_receipt(tx_hash).await.unwrap().unwrap();
+        assert!(receipt.succeeded());
+        assert_eq!(client.get_transaction_receipt(B256::ZERO).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_batch_and_errors() {
+        let node = TestNode::spawn().await;
+        node.on("web3_clientVersion", |_| Ok(json!("anvil/v1.0.0")));
+        let client = RpcClient::new(vec![node.url()]);
+
+        let results = client.batch(&[
+            ("eth_chainId", json!([])),
+            ("eth_unknownMethod", json!([])),
+            ("web3_clientVersion", json!([])),
+        ]).await.unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &json!("0x7a69"));
+        assert!(matches!(results[1], Err(RpcError::Rpc { code: -32601, .. })));
+        assert_eq!(results[2].as_ref().unwrap(), &json!("anvil/v1.0.0"));
+
+        // Reverted eth_call surfaces the node's error with its revert data
+        let error = client.call(&CallRequest { data: Some(Bytes::from_static(b"fail")), ..Default::default() }, "latest")
+            .await.unwrap_err();
+        assert!(matches!(error, RpcError::Rpc { code: 3, data: Some(_), .. }));
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_next_endpoint() {
+        let node = TestNode::spawn().await;
+        // Nothing listens on port 9 locally, so the first endpoint fails to connect
+        let client = RpcClient::new(vec!["http://127.0.0.1:9".to_string(), node.url()]);
+        assert_eq!(client.chain_id().await.unwrap(), 31337);
+
+        let unreachable = RpcClient::new(vec!["http://127.0.0.1:9".to_string()]);
+        assert!(matches!(unreachable.chain_id().await, Err(RpcError::Transport(_))));
+    }
+}
@@ -0,0 +1,249 @@
+// Test Node
+// Minimal anvil-style JSON-RPC stand-in served over local HTTP for tests.
+// Answers the eth_* methods the wallet uses from in-memory state; other
+// methods can be scripted per test with `TestNode::on`.
+
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use alloy_sol_types::{Revert, SolError};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::chain::registry::NativeCurrency;
+use crate::chain::ChainConfig;
+
+pub const TEST_CHAIN_ID: u64 = 31337;
+pub const TEST_BASE_FEE: u64 = 1_000_000_000;
+pub const TEST_PRIORITY_FEE: u64 = 100_000_000;
+
+// Calldata that makes eth_call and eth_estimateGas revert
+pub const REVERT_CALLDATA: &[u8] = b"fail";
+
+type Handler = Box<dyn Fn(&Value) -> Result<Value, (i64, String)> + Send + Sync>;
+
+#[derive(Default)]
+struct NodeState {
+    block_number: u64,
+    balances: HashMap<Address, U256>,
+    nonces: HashMap<Address, u64>,
+    codes: HashMap<Address, Bytes>,
+    raw_transactions: Vec<Bytes>,
+    receipts: HashMap<B256, Value>,
+    handlers: HashMap<String, Handler>,
+}
+
+/// Local JSON-RPC node for tests; stops when dropped
+pub struct TestNode {
+    url: String,
+    state: Arc<Mutex<NodeState>>,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl TestNode {
+    pub async fn spawn() -> Self {
+        // Send flows journal their results; keep them out of the user's data directory
+        crate::storage::use_test_data_dir();
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind test node");
+        let url = format!("http://{}", listener.local_addr().expect("test node address"));
+        let state = Arc::new(Mutex::new(NodeState { block_number: 1, ..Default::default() }));
+
+        let server_state = state.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, server_state.clone()));
+            }
+        });
+
+        Self { url, state, server }
+    }
+
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Chain configuration using this node for both RPC and bundler requests
+    pub fn chain_config(&self) -> ChainConfig {
+        ChainConfig {
+            chain_id: TEST_CHAIN_ID,
+            name: "Test".to_string(),
+            rpc_urls: vec![self.url()],
+            bundler_urls: vec![self.url()],
+            paymasters: Vec::new(),
+            explorer_url: Some("https://explorer.example".to_string()),
+            native_currency: NativeCurrency { name: "Ether".to_string(), symbol: "ETH".to_string(), decimals: 18 },
+            testnet: true,
+        }
+    }
+
+    pub fn set_balance(&self, address: Address, balance: U256) {
+        self.state.lock().unwrap().balances.insert(address, balance);
+    }
+
+    pub fn set_nonce(&self, address: Address, nonce: u64) {
+        self.state.lock().unwrap().nonces.insert(address, nonce);
+    }
+
+    /// Deploy `code` at `address` as far as eth_getCode is concerned
+    pub fn set_code(&self, address: Address, code: Bytes) {
+        self.state.lock().unwrap().codes.insert(address, code);
+    }
+
+    /// Raw transactions received through eth_sendRawTransaction
+    pub fn raw_transactions(&self) -> Vec<Bytes> {
+        self.state.lock().unwrap().raw_transactions.clone()
+    }
+
+    /// Script the response of a method; the handler receives the request params
+    pub fn on(&self, method: &str, handler: impl Fn(&Value) -> Result<Value, (i64, String)> + Send + Sync + 'static) {
+        self.state.lock().unwrap().handlers.insert(method.to_string(), Box::new(handler));
+    }
+}
+
+impl Drop for TestNode {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn serve_connection(mut stream: TcpStream, state: Arc<Mutex<NodeState>>) {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    // Read headers, then the body announced by Content-Length
+    let (header_end, content_length) = loop {
+        let Ok(read) = stream.read(&mut chunk).await else { return };
+        if read == 0 {
+            return;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&buffer[..position]).to_lowercase();
+            let content_length = headers.lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            break (position + 4, content_length);
+        }
+    };
+    while buffer.len() < header_end + content_length {
+        let Ok(read) = stream.read(&mut chunk).await else { return };
+        if read == 0 {
+            return;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let request: Value = serde_json::from_slice(&buffer[header_end..header_end + content_length]).unwrap_or(Value::Null);
+    let response = match request {
+        Value::Array(requests) => Value::Array(requests.iter().map(|request| handle_request(&state, request)).collect()),
+        request => handle_request(&state, &request),
+    };
+
+    let body = response.to_string();
+    let http_response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(), body
+    );
+    let _ = stream.write_all(http_response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+fn handle_request(state: &Mutex<NodeState>, request: &Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+    let params = request.get("params").cloned().unwrap_or(json!([]));
+
+    let mut state = state.lock().unwrap();
+    let result = match state.handlers.get(method) {
+        Some(handler) => handler(&params).map_err(|(code, message)| json!({ "code": code, "message": message })),
+        None => dispatch(&mut state, method, &params),
+    };
+
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+fn quantity(value: u64) -> Value {
+    json!(format!("{:#x}", value))
+}
+
+fn param_address(params: &Value, index: usize) -> Address {
+    params[index].as_str().and_then(|s| s.parse().ok()).unwrap_or_default()
+}
+
+fn reverts(params: &Value) -> bool {
+    let data = params[0].get("data").or_else(|| params[0].get("input")).and_then(Value::as_str).unwrap_or("0x");
+    data.parse::<Bytes>().map(|data| data.as_ref() == REVERT_CALLDATA).unwrap_or(false)
+}
+
+fn revert_error() -> Value {
+    json!({
+        "code": 3,
+        "message": "execution reverted: fail",
+        "data": Bytes::from(Revert::from("fail").abi_encode()),
+    })
+}
+
+fn dispatch(state: &mut NodeState, method: &str, params: &Value) -> Result<Value, Value> {
+    match method {
+        "eth_chainId" => Ok(quantity(TEST_CHAIN_ID)),
+        "eth_blockNumber" => Ok(quantity(state.block_number)),
+        "eth_getBalance" => {
+            let balance = state.balances.get(&param_address(params, 0)).copied().unwrap_or_default();
+            Ok(json!(balance))
+        },
+        "eth_getTransactionCount" => Ok(quantity(state.nonces.get(&param_address(params, 0)).copied().unwrap_or(0))),
+        "eth_getCode" => Ok(json!(state.codes.get(&param_address(params, 0)).cloned().unwrap_or_default())),
+        "eth_call" => {
+            if reverts(params) { Err(revert_error()) } else { Ok(json!("0x")) }
+        },
+        "eth_estimateGas" => {
+            let has_data = params[0].get("data").and_then(Value::as_str).is_some_and(|data| data.len() > 2);
+            if reverts(params) {
+                Err(revert_error())
+            } else {
+                Ok(quantity(if has_data { 50_000 } else { 21_000 }))
+            }
+        },
+        "eth_maxPriorityFeePerGas" => Ok(quantity(TEST_PRIORITY_FEE)),
+        "eth_feeHistory" => {
+            let count = params[0].as_str()
+                .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+                .or_else(|| params[0].as_u64())
+                .unwrap_or(1);
+            let percentiles = params[2].as_array().map(Vec::len).unwrap_or(0);
+            Ok(json!({
+                "oldestBlock": quantity(state.block_number.saturating_sub(count - 1)),
+                "baseFeePerGas": vec![quantity(TEST_BASE_FEE); count as usize + 1],
+                "gasUsedRatio": vec![0.5; count as usize],
+                "reward": vec![vec![quantity(TEST_PRIORITY_FEE); percentiles]; count as usize],
+            }))
+        },
+        "eth_sendRawTransaction" => {
+            let raw: Bytes = params[0].as_str().and_then(|s| s.parse().ok()).unwrap_or_default();
+            let hash = keccak256(&raw);
+            state.block_number += 1;
+            state.raw_transactions.push(raw);
+            state.receipts.insert(hash, json!({
+                "transactionHash": hash,
+                "blockNumber": quantity(state.block_number),
+                "blockHash": B256::repeat_byte(0x11),
+                "status": "0x1",
+                "gasUsed": quantity(21_000),
+                "effectiveGasPrice": quantity(TEST_BASE_FEE + TEST_PRIORITY_FEE),
+                "logs": [],
+            }));
+            Ok(json!(hash))
+        },
+        "eth_getTransactionReceipt" => {
+            let hash: B256 = params[0].as_str().and_then(|s| s.parse().ok()).unwrap_or_default();
+            Ok(state.receipts.get(&hash).cloned().unwrap_or(Value::Null))
+        },
+        _ => Err(json!({ "code": -32601, "message": format!("Method not found: {}", method) })),
+    }
+}
@@ -0,0 +1,383 @@
+// Contract ABI
+// Parses JSON ABIs and human-readable signatures, encodes function calls and
+// decodes return data, revert reasons and event logs. Values cross the Tauri
+// boundary as JSON: integers as decimal strings, bytes as 0x-prefixed hex.
+
+use alloy_dyn_abi::{DynSolType, DynSolValue, ErrorExt, EventExt, FunctionExt, JsonAbiExt, Specifier};
+use alloy_json_abi::{Function, JsonAbi, Param};
+use alloy_primitives::{Bytes, B256};
+use alloy_sol_types::{Panic, Revert, SolError};
+use serde_json::{json, Map, Value};
+
+/// Parsed contract ABI
+#[derive(Debug, Clone)]
+pub struct ContractAbi {
+    abi: JsonAbi,
+}
+
+impl ContractAbi {
+    /// Parse an ABI from one of:
+    /// - a JSON ABI array, or a compiler artifact with an `abi` field
+    /// - a JSON array of human-readable signatures
+    /// - human-readable signatures separated by newlines or `;`,
+    ///   e.g. `function balanceOf(address owner) view returns (uint256)`
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let trimmed = source.trim();
+        if trimmed.starts_with('[') || trimmed.starts_with('{') {
+            let value: Value = serde_json::from_str(trimmed)
+                .map_err(|e| format!("Invalid ABI JSON: {}", e))?;
+            let value = match value {
+                Value::Object(mut artifact) => artifact.remove("abi")
+                    .ok_or_else(|| "ABI JSON object has no abi field".to_string())?,
+                other => other,
+            };
+
+            if let Some(signatures) = value.as_array().and_then(|items| items.iter().map(Value::as_str).collect::<Option<Vec<_>>>()) {
+                return Self::parse_signatures(signatures);
+            }
+            let abi = serde_json::from_value(value).map_err(|e| format!("Invalid ABI JSON: {}", e))?;
+            return Ok(Self { abi });
+        }
+
+        Self::parse_signatures(trimmed.split(['\n', ';']).map(str::trim).filter(|line| !line.is_empty()))
+    }
+
+    fn parse_signatures<'a>(signatures: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        // Bare signatures such as "transfer(address,uint256)" are treated as functions
+        let normalized = signatures.into_iter()
+            .map(|signature| {
+                let keyword = signature.split_whitespace().next().unwrap_or("");
+                if ["function", "event", "error", "constructor", "fallback", "receive"].contains(&keyword) {
+                    signature.to_string()
+                } else {
+                    format!("function {}", signature)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let abi = JsonAbi::parse(normalized.iter().map(String::as_str))
+            .map_err(|e| format!("Invalid ABI signature: {}", e))?;
+        Ok(Self { abi })
+    }
+
+    /// Find a function by name, or by full signature when it is overloaded
+    pub fn function(&self, name: &str, arg_count: Option<usize>) -> Result<&Function, String> {
+        if name.contains('(') {
+            let signature = name.replace(' ', "");
+            return self.abi.functions()
+                .find(|function| function.signature() == signature)
+                .ok_or_else(|| format!("Function {} not found in ABI", name));
+        }
+
+        let overloads = self.abi.function(name)
+            .ok_or_else(|| format!("Function {} not found in ABI", name))?;
+        let mut candidates = overloads.iter()
+            .filter(|function| arg_count.is_none_or(|count| function.inputs.len() == count));
+        match (candidates.next(), candidates.next()) {
+            (Some(function), None) => Ok(function),
+            (None, _) => Err(format!("No overload of {} takes {} arguments", name, arg_count.unwrap_or(0))),
+            (Some(_), Some(_)) => Err(format!("Function {} is overloaded; use its full signature", name)),
+        }
+    }
+
+    /// Encode a function call (selector followed by the ABI-encoded arguments)
+    pub fn encode_call(&self, name: &str, args: &[Value]) -> Result<Bytes, String> {
+        let function = self.function(name, Some(args.len()))?;
+        let values = function.inputs.iter()
+            .zip(args)
+            .map(|(param, arg)| json_to_param_value(param, arg))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        function.abi_encode_input(&values)
+            .map(Bytes::from)
+            .map_err(|e| format!("Failed to encode {}: {}", function.signature(), e))
+    }
+
+    /// Decode calldata by matching its selector against the ABI's functions
+    pub fn decode_call(&self, data: &[u8]) -> Result<Value, String> {
+        let selector = data.get(..4).ok_or_else(|| "Calldata is shorter than a selector".to_string())?;
+        let function = self.abi.functions()
+            .find(|function| function.selector().as_slice() == selector)
+            .ok_or_else(|| format!("No function with selector 0x{} in ABI", hex::encode(selector)))?;
+
+        let values = function.abi_decode_input(&data[4..])
+            .map_err(|e| format!("Failed to decode {} calldata: {}", function.name, e))?;
+        Ok(json!({
+            "function": function.name,
+            "signature": function.signature(),
+            "args": named_values(&function.inputs, &values),
+        }))
+    }
+
+    /// Decode the return data of a function call
+    pub fn decode_output(&self, name: &str, data: &[u8]) -> Result<Value, String> {
+        let function = self.function(name, None)?;
+        let values = function.abi_decode_output(data)
+            .map_err(|e| format!("Failed to decode {} output: {}", function.name, e))?;
+        Ok(named_values(&function.outputs, &values))
+    }
+
+    /// Decode an event log by matching its first topic against the ABI's events
+    pub fn decode_log(&self, topics: &[B256], data: &[u8]) -> Result<Value, String> {
+        let topic0 = topics.first().ok_or_else(|| "Log has no topics".to_string())?;
+        let event = self.abi.events()
+            .find(|event| !event.anonymous && event.selector() == *topic0)
+            .ok_or_else(|| format!("No event with topic {} in ABI", topic0))?;
+
+        let decoded = event.decode_log_parts(topics.iter().copied(), data)
+            .map_err(|e| format!("Failed to decode {} log: {}", event.name, e))?;
+
+        // Re-assemble arguments in declaration order from the indexed and body values
+        let mut indexed = decoded.indexed.iter();
+        let mut body = decoded.body.iter();
+        let args = event.inputs.iter()
+            .map(|input| {
+                let value = if input.indexed { indexed.next() } else { body.next() };
+                json!({
+                    "name": input.name,
+                    "type": input.ty,
+                    "indexed": input.indexed,
+                    "value": value.map(value_to_json),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({
+            "event": event.name,
+            "signature": event.signature(),
+            "args": args,
+        }))
+    }
+}
+
+/// Decode revert data: `Error(string)`, `Panic(uint256)` or a custom error from the ABI
+pub fn decode_revert_with(abi: Option<&ContractAbi>, data: &[u8]) -> Value {
+    let Some(selector) = data.get(..4) else {
+        return json!({ "kind": "empty", "message": "Execution reverted without a reason" });
+    };
+
+    if selector == Revert::SELECTOR {
+        if let Ok(revert) = Revert::abi_decode(data) {
+            return json!({ "kind": "error", "message": revert.reason });
+        }
+    } else if selector == Panic::SELECTOR {
+        if let Ok(panic) = Panic::abi_decode(data) {
+            let message = panic.kind()
+                .map(|kind| kind.to_string())
+                .unwrap_or_else(|| format!("unknown panic code {}", panic.code));
+            return json!({ "kind": "panic", "code": panic.code.to_string(), "message": message });
+        }
+    } else if let Some(abi) = abi {
+        for error in abi.abi.errors().filter(|error| error.selector().as_slice() == selector) {
+            if let Ok(decoded) = error.decode_error(data) {
+                return json!({
+                    "kind": "custom",
+                    "error": error.name,
+                    "signature": error.signature(),
+                    "message": format!("{}(...)", error.name),
+                    "args": named_values(&error.inputs, &decoded.body),
+                });
+            }
+        }
+    }
+
+    json!({
+        "kind": "unknown",
+        "selector": format!("0x{}", hex::encode(selector)),
+        "message": format!("Execution reverted with unknown error 0x{}", hex::encode(selector)),
+        "data": format!("0x{}", hex::encode(data)),
+    })
+}
+
+// Convert a JSON argument into an ABI value for the given parameter
+fn json_to_param_value(param: &Param, value: &Value) -> Result<DynSolValue, String> {
+    let ty = param.resolve().map_err(|e| format!("Unsupported type {}: {}", param.ty, e))?;
+    json_to_value(&ty, &param.components, value)
+        .map_err(|e| format!("Invalid value for {} {}: {}", param.ty, param.name, e))
+}
+
+fn json_to_value(ty: &DynSolType, components: &[Param], value: &Value) -> Result<DynSolValue, String> {
+    match (ty, value) {
+        (DynSolType::Array(inner), Value::Array(items)) => items.iter()
+            .map(|item| json_to_value(inner, components, item))
+            .collect::<Result<Vec<_>, _>>()
+            .map(DynSolValue::Array),
+        (DynSolType::FixedArray(inner, size), Value::Array(items)) => {
+            if items.len() != *size {
+                return Err(format!("expected {} elements, got {}", size, items.len()));
+            }
+            items.iter()
+                .map(|item| json_to_value(inner, components, item))
+                .collect::<Result<Vec<_>, _>>()
+                .map(DynSolValue::FixedArray)
+        },
+        (DynSolType::Tuple(types), Value::Array(items)) => {
+            if items.len() != types.len() {
+                return Err(format!("expected {} tuple fields, got {}", types.len(), items.len()));
+            }
+            types.iter().zip(items).enumerate()
+                .map(|(i, (ty, item))| {
+                    let nested = components.get(i).map(|c| c.components.as_slice()).unwrap_or(&[]);
+                    json_to_value(ty, nested, item)
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(DynSolValue::Tuple)
+        },
+        // Tuples may also be given as objects keyed by component name
+        (DynSolType::Tuple(types), Value::Object(fields)) => {
+            if components.len() != types.len() {
+                return Err("tuple components are unnamed; pass an array".to_string());
+            }
+            types.iter().zip(components)
+                .map(|(ty, component)| {
+                    let field = fields.get(&component.name)
+                        .ok_or_else(|| format!("missing tuple field {}", component.name))?;
+                    json_to_value(ty, &component.components, field)
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(DynSolValue::Tuple)
+        },
+        (_, Value::String(s)) => ty.coerce_str(s).map_err(|e| e.to_string()),
+        (_, Value::Number(n)) => ty.coerce_str(&n.to_string()).map_err(|e| e.to_string()),
+        (_, Value::Bool(b)) => ty.coerce_str(&b.to_string()).map_err(|e| e.to_string()),
+        (_, other) => Err(format!("unexpected JSON value {}", other)),
+    }
+}
+
+/// Convert an ABI value into JSON
+pub fn value_to_json(value: &DynSolValue) -> Value {
+    match value {
+        DynSolValue::Bool(b) => json!(b),
+        DynSolValue::Int(i, _) => json!(i.to_string()),
+        DynSolValue::Uint(u, _) => json!(u.to_string()),
+        DynSolValue::FixedBytes(word, size) => json!(format!("0x{}", hex::encode(&word[..*size]))),
+        DynSolValue::Address(address) => json!(address.to_checksum(None)),
+        DynSolValue::Function(function) => json!(format!("0x{}", hex::encode(function.as_slice()))),
+        DynSolValue::Bytes(bytes) => json!(format!("0x{}", hex::encode(bytes))),
+        DynSolValue::String(s) => json!(s),
+        DynSolValue::Array(items) | DynSolValue::FixedArray(items) | DynSolValue::Tuple(items) => {
+            Value::Array(items.iter().map(value_to_json).collect())
+        },
+    }
+}
+
+// Pair decoded values with their parameter names and types
+fn named_values(params: &[Param], values: &[DynSolValue]) -> Value {
+    Value::Array(params.iter().zip(values).enumerate()
+        .map(|(i, (param, value))| {
+            let mut entry = Map::new();
+            entry.insert("name".to_string(), json!(if param.name.is_empty() { i.to_string() } else { param.name.clone() }));
+            entry.insert("type".to_string(), json!(param.selector_type()));
+            entry.insert("value".to_string(), value_to_json(value));
+            Value::Object(entry)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{Address, U256};
+    use alloy_sol_types::SolValue;
+
+    const ERC20_JSON_ABI: &str = r#"[
+        {"type": "function", "name": "transfer", "stateMutability": "nonpayable",
+         "inputs": [{"name": "to", "type": "address"}, {"name": "amount", "type": "uint256"}],
+         "outputs": [{"name": "", "type": "bool"}]},
+        {"type": "event", "name": "Transfer", "anonymous": false,
+         "inputs": [{"name": "from", "type": "address", "indexed": true},
+                    {"name": "to", "type": "address", "indexed": true},
+                    {"name": "value", "type": "uint256", "indexed": false}]},
+        {"type": "error", "name": "InsufficientBalance",
+         "inputs": [{"name": "available", "type": "uint256"}, {"name": "required", "type": "uint256"}]}
+    ]"#;
+    const ALICE: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+    #[test]
+    fn test_encode_call_from_json_abi() {
+        let abi = ContractAbi::parse(ERC20_JSON_ABI).unwrap();
+        let data = abi.encode_call("transfer", &[json!(ALICE), json!("1000")]).unwrap();
+        assert_eq!(hex::encode(&data[..4]), "a9059cbb");
+        assert_eq!(data.len(), 4 + 64);
+
+        let decoded = abi.decode_call(&data).unwrap();
+        assert_eq!(decoded["function"], "transfer");
+        assert_eq!(decoded["args"][0]["value"], ALICE);
+        assert_eq!(decoded["args"][1]["value"], "1000");
+
+        assert!(abi.encode_call("transfer", &[json!(ALICE)]).is_err());
+        assert!(abi.encode_call("transfer", &[json!("0x1234"), json!(1)]).is_err());
+    }
+
+    #[test]
+    fn test_human_readable_tuples_and_arrays() {
+        let abi = ContractAbi::parse(
+            "submitTask((uint256,string,address[]) task, bytes proof)\n\
+             function points(address user) view returns (uint256 total, bytes32[] badges)"
+        ).unwrap();
+
+        let task = json!([7, "Write docs", [ALICE]]);
+        let data = abi.encode_call("submitTask", &[task, json!("0xdeadbeef")]).unwrap();
+        let decoded = abi.decode_call(&data).unwrap();
+        assert_eq!(decoded["signature"], "submitTask((uint256,string,address[]),bytes)");
+        assert_eq!(decoded["args"][0]["value"], json!(["7", "Write docs", [ALICE]]));
+        assert_eq!(decoded["args"][1]["value"], "0xdeadbeef");
+
+        let output = (U256::from(42), vec![B256::repeat_byte(1)]).abi_encode_params();
+        let decoded = abi.decode_output("points", &output).unwrap();
+        assert_eq!(decoded[0]["name"], "total");
+        assert_eq!(decoded[0]["value"], "42");
+        assert_eq!(decoded[1]["value"][0], format!("0x{}", "01".repeat(32)));
+    }
+
+    #[test]
+    fn test_tuple_arguments_as_objects() {
+        let abi = ContractAbi::parse(r#"[{"type": "function", "name": "submitTask", "stateMutability": "nonpayable",
+            "inputs": [{"name": "task", "type": "tuple[]", "components": [
+                {"name": "id", "type": "uint256"}, {"name": "reviewers", "type": "address[]"}]}],
+            "outputs": []}]"#).unwrap();
+
+        let tasks = json!([{ "id": "1", "reviewers": [ALICE] }, { "id": 2, "reviewers": [] }]);
+        let from_objects = abi.encode_call("submitTask", &[tasks]).unwrap();
+        let from_arrays = abi.encode_call("submitTask", &[json!([["1", [ALICE]], [2, []]])]).unwrap();
+        assert_eq!(from_objects, from_arrays);
+
+        assert!(abi.encode_call("submitTask", &[json!([{ "id": 1 }])]).is_err());
+    }
+
+    #[test]
+    fn test_decode_revert_reasons() {
+        let abi = ContractAbi::parse(ERC20_JSON_ABI).unwrap();
+
+        let revert = Revert::from("not owner").abi_encode();
+        assert_eq!(decode_revert_with(Some(&abi), &revert)["message"], "not owner");
+
+        let panic = Panic { code: U256::from(0x11) }.abi_encode();
+        assert_eq!(decode_revert_with(Some(&abi), &panic)["kind"], "panic");
+
+        let error = abi.abi.errors().next().unwrap();
+        let custom = error.abi_encode_input(&[DynSolValue::from(U256::from(1)), DynSolValue::from(U256::from(5))]).unwrap();
+        let decoded = decode_revert_with(Some(&abi), &custom);
+        assert_eq!(decoded["error"], "InsufficientBalance");
+        assert_eq!(decoded["args"][1]["value"], "5");
+
+        assert_eq!(decode_revert_with(None, &[])["kind"], "empty");
+        assert_eq!(decode_revert_with(None, &custom)["kind"], "unknown");
+    }
+
+    #[test]
+    fn test_decode_event_log() {
+        let abi = ContractAbi::parse(ERC20_JSON_ABI).unwrap();
+        let event = abi.abi.events().next().unwrap();
+        let alice: Address = ALICE.parse().unwrap();
+
+        let topics = vec![event.selector(), alice.into_word(), Address::ZERO.into_word()];
+        let data = U256::from(500).abi_encode();
+        let decoded = abi.decode_log(&topics, &data).unwrap();
+        assert_eq!(decoded["event"], "Transfer");
+        assert_eq!(decoded["args"][0]["value"], ALICE);
+        assert_eq!(decoded["args"][2]["value"], "500");
+
+        assert!(abi.decode_log(&[B256::ZERO], &data).is_err());
+    }
+}
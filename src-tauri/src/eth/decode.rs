@@ -0,0 +1,462 @@
+// Transaction Decoder
+// Turns a transaction request into a human-readable summary for the signing confirmation.
+// Recognises token transfers/approvals, smart account batches and Safe executions.
+
+use alloy_primitives::utils::format_ether;
+use alloy_primitives::{Address, Bytes, U256};
+use alloy_sol_types::{sol, SolCall};
+use serde::Serialize;
+
+use crate::eth::TxRequest;
+
+// Nested account/Safe calls deeper than this are not decoded
+const MAX_NESTING_DEPTH: usize = 3;
+
+sol! {
+    interface IERC20 {
+        function transfer(address to, uint256 amount) external returns (bool);
+        function approve(address spender, uint256 amount) external returns (bool);
+        function transferFrom(address from, address to, uint256 amount) external returns (bool);
+    }
+
+    interface IERC721 {
+        function safeTransferFrom(address from, address to, uint256 tokenId) external;
+        function safeTransferFrom(address from, address to, uint256 tokenId, bytes data) external;
+        function setApprovalForAll(address operator, bool approved) external;
+    }
+
+    interface IERC1155 {
+        function safeTransferFrom(address from, address to, uint256 id, uint256 amount, bytes data) external;
+        function safeBatchTransferFrom(address from, address to, uint256[] ids, uint256[] amounts, bytes data) external;
+    }
+
+    // ERC-4337 smart accounts (SimpleAccount v0.6 / v0.7)
+    interface IAccount {
+        function execute(address dest, uint256 value, bytes func) external;
+        function executeBatch(address[] dest, bytes[] func) external;
+        function executeBatch(address[] dest, uint256[] value, bytes[] func) external;
+    }
+
+    interface ISafe {
+        function execTransaction(
+            address to,
+            uint256 value,
+            bytes data,
+            uint8 operation,
+            uint256 safeTxGas,
+            uint256 baseGas,
+            uint256 gasPrice,
+            address gasToken,
+            address refundReceiver,
+            bytes signatures
+        ) external payable returns (bool success);
+    }
+}
+
+/// Kind of action a transaction (or nested call) performs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxAction {
+    NativeTransfer,
+    ContractCreation,
+    Erc20Transfer,
+    Erc20Approve,
+    TransferFrom,
+    Erc721Transfer,
+    SetApprovalForAll,
+    Erc1155Transfer,
+    Erc1155BatchTransfer,
+    AccountExecute,
+    AccountExecuteBatch,
+    SafeExec,
+    ContractCall,
+}
+
+/// Human-readable summary of a transaction shown before signing
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TxSummary {
+    pub action: TxAction,
+    pub description: String,
+    /// Address the call is sent to (token or account contract)
+    pub target: Option<Address>,
+    /// Token contract for token actions
+    pub token: Option<Address>,
+    /// Recipient, spender or operator depending on the action
+    pub recipient: Option<Address>,
+    /// Token amount in base units
+    pub amount: Option<String>,
+    pub token_id: Option<String>,
+    /// Native value in wei
+    pub value: String,
+    pub chain_id: Option<u64>,
+    pub selector: Option<String>,
+    pub warnings: Vec<String>,
+    /// Calls nested in account batches, Safe executions or ERC-1155 batch transfers
+    pub calls: Vec<TxSummary>,
+}
+
+impl TxSummary {
+    fn new(action: TxAction, target: Option<Address>, value: U256, description: String) -> Self {
+        Self {
+            action,
+            description,
+            target,
+            token: None,
+            recipient: None,
+            amount: None,
+            token_id: None,
+            value: value.to_string(),
+            chain_id: None,
+            selector: None,
+            warnings: Vec::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    fn warn(&mut self, warning: impl Into<String>) {
+        let warning = warning.into();
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    // Surface warnings of nested calls at this level so the UI sees them up front
+    fn add_call(&mut self, call: TxSummary) {
+        for warning in &call.warnings {
+            self.warn(warning.clone());
+        }
+        self.calls.push(call);
+    }
+}
+
+/// Decode a transaction request into a summary
+pub fn decode_transaction(tx: &TxRequest) -> TxSummary {
+    let mut summary = match tx.to {
+        Some(to) => decode_call(to, tx.value, &tx.data, 0),
+        None => {
+            let mut summary = TxSummary::new(
+                TxAction::ContractCreation, None, tx.value,
+                format!("Deploy a new contract ({} bytes of init code)", tx.data.len()),
+            );
+            summary.warn("Deploys a new contract; the init code cannot be reviewed here");
+            summary
+        }
+    };
+    summary.chain_id = tx.chain_id;
+    summary
+}
+
+fn decode_call(to: Address, value: U256, data: &Bytes, depth: usize) -> TxSummary {
+    let Some(selector) = data.get(..4) else {
+        let mut summary = TxSummary::new(TxAction::NativeTransfer, Some(to), value, format!("Send {} to {}", format_native(value), to));
+        summary.recipient = Some(to);
+        if !data.is_empty() {
+            summary.warn("Calldata is shorter than a method selector");
+        }
+        return summary;
+    };
+    let selector: [u8; 4] = selector.try_into().expect("slice of length 4");
+
+    let mut summary = decode_known_call(to, value, data, selector, depth).unwrap_or_else(|| {
+        let mut summary = TxSummary::new(
+            TxAction::ContractCall, Some(to), value,
+            format!("Call method 0x{} on contract {}", hex::encode(selector), to),
+        );
+        summary.warn("Unrecognised contract method; the call could not be decoded");
+        summary
+    });
+    summary.selector = Some(format!("0x{}", hex::encode(selector)));
+
+    let is_token_action = summary.token.is_some();
+    if is_token_action && !value.is_zero() {
+        summary.warn("Native value is sent along with a token call");
+    }
+    if is_token_action && summary.recipient == Some(Address::ZERO) {
+        summary.warn("Recipient is the zero address; tokens will be lost");
+    }
+    summary
+}
+
+// Returns None when the selector is unknown or the calldata does not match it
+fn decode_known_call(to: Address, value: U256, data: &[u8], selector: [u8; 4], depth: usize) -> Option<TxSummary> {
+    let mut summary = match selector {
+        IERC20::transferCall::SELECTOR => {
+            let call = IERC20::transferCall::abi_decode(data).ok()?;
+            let mut summary = TxSummary::new(
+                TxAction::Erc20Transfer, Some(to), value,
+                format!("Transfer {} of token {} to {}", call.amount, to, call.to),
+            );
+            summary.recipient = Some(call.to);
+            summary.amount = Some(call.amount.to_string());
+            summary
+        },
+        IERC20::approveCall::SELECTOR => {
+            let call = IERC20::approveCall::abi_decode(data).ok()?;
+            let description = if call.amount.is_zero() {
+                format!("Revoke approval of token {} for {}", to, call.spender)
+            } else if call.amount == U256::MAX {
+                format!("Approve {} to spend an unlimited amount of token {}", call.spender, to)
+            } else {
+                format!("Approve {} to spend {} of token {}", call.spender, call.amount, to)
+            };
+            let mut summary = TxSummary::new(TxAction::Erc20Approve, Some(to), value, description);
+            if call.amount == U256::MAX {
+                summary.warn("Unlimited approval: the spender can transfer your entire balance of this token");
+            }
+            summary.recipient = Some(call.spender);
+            summary.amount = Some(call.amount.to_string());
+            summary
+        },
+        IERC20::transferFromCall::SELECTOR => {
+            // ERC-20 and ERC-721 share this selector; the last argument is an amount or a token ID
+            let call = IERC20::transferFromCall::abi_decode(data).ok()?;
+            let mut summary = TxSummary::new(
+                TxAction::TransferFrom, Some(to), value,
+                format!("Transfer {} (amount or token ID) of token {} from {} to {}", call.amount, to, call.from, call.to),
+            );
+            summary.recipient = Some(call.to);
+            summary.amount = Some(call.amount.to_string());
+            summary
+        },
+        IERC721::safeTransferFrom_0Call::SELECTOR => {
+            let call = IERC721::safeTransferFrom_0Call::abi_decode(data).ok()?;
+            nft_transfer(to, value, call.from, call.to, call.tokenId)
+        },
+        IERC721::safeTransferFrom_1Call::SELECTOR => {
+            let call = IERC721::safeTransferFrom_1Call::abi_decode(data).ok()?;
+            nft_transfer(to, value, call.from, call.to, call.tokenId)
+        },
+        IERC721::setApprovalForAllCall::SELECTOR => {
+            let call = IERC721::setApprovalForAllCall::abi_decode(data).ok()?;
+            let description = if call.approved {
+                format!("Allow {} to manage all your tokens of collection {}", call.operator, to)
+            } else {
+                format!("Revoke {} as operator of collection {}", call.operator, to)
+            };
+            let mut summary = TxSummary::new(TxAction::SetApprovalForAll, Some(to), value, description);
+            if call.approved {
+                summary.warn("Grants the operator control over every token you own in this collection");
+            }
+            summary.recipient = Some(call.operator);
+            summary
+        },
+        IERC1155::safeTransferFromCall::SELECTOR => {
+            let call = IERC1155::safeTransferFromCall::abi_decode(data).ok()?;
+            erc1155_transfer(to, value, call.to, call.id, call.amount)
+        },
+        IERC1155::safeBatchTransferFromCall::SELECTOR => {
+            let call = IERC1155::safeBatchTransferFromCall::abi_decode(data).ok()?;
+            let mut summary = TxSummary::new(
+                TxAction::Erc1155BatchTransfer, Some(to), value,
+                format!("Transfer {} token types of collection {} to {}", call.ids.len(), to, call.to),
+            );
+            if call.ids.len() != call.amounts.len() {
+                summary.warn("Token ID and amount lists have different lengths");
+            }
+            for (id, amount) in call.ids.iter().zip(&call.amounts) {
+                summary.add_call(erc1155_transfer(to, U256::ZERO, call.to, *id, *amount));
+            }
+            summary.recipient = Some(call.to);
+            summary
+        },
+        IAccount::executeCall::SELECTOR => {
+            let call = IAccount::executeCall::abi_decode(data).ok()?;
+            let inner = decode_nested(call.dest, call.value, call.func, depth);
+            let mut summary = TxSummary::new(
+                TxAction::AccountExecute, Some(to), value,
+                format!("Smart account {}: {}", to, inner.description),
+            );
+            summary.add_call(inner);
+            summary
+        },
+        IAccount::executeBatch_0Call::SELECTOR => {
+            let call = IAccount::executeBatch_0Call::abi_decode(data).ok()?;
+            let values = vec![U256::ZERO; call.dest.len()];
+            account_batch(to, value, call.dest, values, call.func, depth)
+        },
+        IAccount::executeBatch_1Call::SELECTOR => {
+            let call = IAccount::executeBatch_1Call::abi_decode(data).ok()?;
+            account_batch(to, value, call.dest, call.value, call.func, depth)
+        },
+        ISafe::execTransactionCall::SELECTOR => {
+            let call = ISafe::execTransactionCall::abi_decode(data).ok()?;
+            let inner = decode_nested(call.to, call.value, call.data, depth);
+            let mut summary = TxSummary::new(
+                TxAction::SafeExec, Some(to), value,
+                format!("Safe {}: {}", to, inner.description),
+            );
+            if call.operation == 1 {
+                summary.warn("Safe transaction uses DELEGATECALL; the target can take full control of the Safe");
+            }
+            summary.add_call(inner);
+            summary
+        },
+        _ => return None,
+    };
+
+    if matches!(summary.action, TxAction::Erc20Transfer | TxAction::Erc20Approve | TxAction::TransferFrom
+        | TxAction::Erc721Transfer | TxAction::SetApprovalForAll | TxAction::Erc1155Transfer | TxAction::Erc1155BatchTransfer)
+    {
+        summary.token = Some(to);
+    }
+    Some(summary)
+}
+
+fn decode_nested(to: Address, value: U256, data: Bytes, depth: usize) -> TxSummary {
+    if depth + 1 >= MAX_NESTING_DEPTH && data.len() >= 4 {
+        let mut summary = TxSummary::new(
+            TxAction::ContractCall, Some(to), value,
+            format!("Call contract {} (nested too deeply to decode)", to),
+        );
+        summary.warn("Nested calls are too deep to decode");
+        return summary;
+    }
+    decode_call(to, value, &data, depth + 1)
+}
+
+fn account_batch(to: Address, value: U256, dests: Vec<Address>, values: Vec<U256>, funcs: Vec<Bytes>, depth: usize) -> TxSummary {
+    let mut summary = TxSummary::new(
+        TxAction::AccountExecuteBatch, Some(to), value,
+        format!("Smart account {}: batch of {} calls", to, dests.len()),
+    );
+    if dests.len() != funcs.len() || dests.len() != values.len() {
+        summary.warn("Batch call lists have different lengths");
+    }
+    for ((dest, call_value), func) in dests.into_iter().zip(values).zip(funcs) {
+        summary.add_call(decode_nested(dest, call_value, func, depth));
+    }
+    summary
+}
+
+fn nft_transfer(token: Address, value: U256, from: Address, to: Address, token_id: U256) -> TxSummary {
+    let mut summary = TxSummary::new(
+        TxAction::Erc721Transfer, Some(token), value,
+        format!("Transfer NFT #{} of collection {} from {} to {}", token_id, token, from, to),
+    );
+    summary.recipient = Some(to);
+    summary.token_id = Some(token_id.to_string());
+    summary
+}
+
+fn erc1155_transfer(token: Address, value: U256, to: Address, id: U256, amount: U256) -> TxSummary {
+    let mut summary = TxSummary::new(
+        TxAction::Erc1155Transfer, Some(token), value,
+        format!("Transfer {} of token #{} of collection {} to {}", amount, id, token, to),
+    );
+    summary.token = Some(token);
+    summary.recipient = Some(to);
+    summary.amount = Some(amount.to_string());
+    summary.token_id = Some(id.to_string());
+    summary
+}
+
+// Format wei as ETH without trailing zeros
+fn format_native(value: U256) -> String {
+    let formatted = format_ether(value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    format!("{} ETH", trimmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const TOKEN: &str = "0x1c7D4B196Cb0C7B01d743Fbc6116a902379C7238";
+    const ALICE: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+    fn address(s: &str) -> Address {
+        Address::from_str(s).unwrap()
+    }
+
+    fn call_tx(to: Address, data: Vec<u8>) -> TxRequest {
+        TxRequest { to: Some(to), value: U256::ZERO, data: data.into(), chain_id: Some(11155111), ..Default::default() }
+    }
+
+    #[test]
+    fn test_native_transfer() {
+        let tx = TxRequest::from_json(&format!(r#"{{"to": "{}", "value": "1500000000000000000"}}"#, ALICE)).unwrap();
+        let summary = decode_transaction(&tx);
+        assert_eq!(summary.action, TxAction::NativeTransfer);
+        assert_eq!(summary.description, format!("Send 1.5 ETH to {}", ALICE));
+        assert!(summary.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_erc20_transfer_and_unlimited_approve() {
+        let transfer = IERC20::transferCall { to: address(ALICE), amount: U256::from(1_000_000u64) }.abi_encode();
+        let summary = decode_transaction(&call_tx(address(TOKEN), transfer));
+        assert_eq!(summary.action, TxAction::Erc20Transfer);
+        assert_eq!(summary.token, Some(address(TOKEN)));
+        assert_eq!(summary.recipient, Some(address(ALICE)));
+        assert_eq!(summary.amount.as_deref(), Some("1000000"));
+        assert_eq!(summary.selector.as_deref(), Some("0xa9059cbb"));
+
+        let approve = IERC20::approveCall { spender: address(ALICE), amount: U256::MAX }.abi_encode();
+        let summary = decode_transaction(&call_tx(address(TOKEN), approve));
+        assert_eq!(summary.action, TxAction::Erc20Approve);
+        assert!(summary.warnings.iter().any(|w| w.contains("Unlimited approval")));
+    }
+
+    #[test]
+    fn test_nft_transfers() {
+        let nft = IERC721::safeTransferFrom_0Call { from: address(ALICE), to: address(TOKEN), tokenId: U256::from(7) }.abi_encode();
+        let summary = decode_transaction(&call_tx(address(TOKEN), nft));
+        assert_eq!(summary.action, TxAction::Erc721Transfer);
+        assert_eq!(summary.token_id.as_deref(), Some("7"));
+
+        let batch = IERC1155::safeBatchTransferFromCall {
+            from: address(ALICE),
+            to: address(TOKEN),
+            ids: vec![U256::from(1), U256::from(2)],
+            amounts: vec![U256::from(10), U256::from(20)],
+            data: Bytes::new(),
+        }.abi_encode();
+        let summary = decode_transaction(&call_tx(address(TOKEN), batch));
+        assert_eq!(summary.action, TxAction::Erc1155BatchTransfer);
+        assert_eq!(summary.calls.len(), 2);
+        assert_eq!(summary.calls[1].amount.as_deref(), Some("20"));
+    }
+
+    #[test]
+    fn test_nested_account_and_safe_calls() {
+        let approve = IERC20::approveCall { spender: address(ALICE), amount: U256::MAX }.abi_encode();
+        let batch = IAccount::executeBatch_0Call {
+            dest: vec![address(TOKEN), address(ALICE)],
+            func: vec![approve.clone().into(), Bytes::new()],
+        }.abi_encode();
+        let summary = decode_transaction(&call_tx(address(ALICE), batch));
+        assert_eq!(summary.action, TxAction::AccountExecuteBatch);
+        assert_eq!(summary.calls[0].action, TxAction::Erc20Approve);
+        assert_eq!(summary.calls[1].action, TxAction::NativeTransfer);
+        // Warnings of nested calls are surfaced at the top level
+        assert!(summary.warnings.iter().any(|w| w.contains("Unlimited approval")));
+
+        let safe = ISafe::execTransactionCall {
+            to: address(TOKEN),
+            value: U256::ZERO,
+            data: approve.into(),
+            operation: 1,
+            safeTxGas: U256::ZERO,
+            baseGas: U256::ZERO,
+            gasPrice: U256::ZERO,
+            gasToken: Address::ZERO,
+            refundReceiver: Address::ZERO,
+            signatures: Bytes::new(),
+        }.abi_encode();
+        let summary = decode_transaction(&call_tx(address(ALICE), safe));
+        assert_eq!(summary.action, TxAction::SafeExec);
+        assert!(summary.warnings.iter().any(|w| w.contains("DELEGATECALL")));
+    }
+
+    #[test]
+    fn test_unknown_and_malformed_calls() {
+        let summary = decode_transaction(&call_tx(address(TOKEN), vec![0xde, 0xad, 0xbe, 0xef, 0x00]));
+        assert_eq!(summary.action, TxAction::ContractCall);
+        assert!(!summary.warnings.is_empty());
+
+        // Known selector with truncated arguments is not trusted as a transfer
+        let summary = decode_transaction(&call_tx(address(TOKEN), vec![0xa9, 0x05, 0x9c, 0xbb, 0x00]));
+        assert_eq!(summary.action, TxAction::ContractCall);
+    }
+}
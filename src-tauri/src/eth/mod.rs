@@ -0,0 +1,16 @@
+// Ethereum Helpers
+// Parsing of the transaction requests passed to the TEE for signing and
+// decoding them into human-readable summaries for confirmation, plus contract ABI support
+// and ERC-4337 UserOperations and Safe multisig transactions
+
+mod abi;
+mod decode;
+mod safe_tx;
+mod tx;
+mod user_op;
+
+pub use abi::{decode_revert_with, ContractAbi};
+pub use decode::{decode_transaction, TxAction, TxSummary};
+pub use safe_tx::{SafeTransaction, SafeTransactionRequest};
+pub use tx::{TxFees, TxRequest};
+pub use user_op::{EntryPointVersion, UserOperation, UserOperationRequest, ENTRY_POINT_V06, ENTRY_POINT_V07};
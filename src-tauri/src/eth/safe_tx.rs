@@ -0,0 +1,145 @@
+// Safe Transactions
+// Safe{Wallet} multisig transactions (SafeTx) and their EIP-712 hash as computed by
+// the Safe contract's `getTransactionHash` (Safe v1.3.0 and later, chain ID in the domain)
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use alloy_sol_types::{sol, Eip712Domain, SolStruct};
+use serde::{Deserialize, Serialize};
+
+use crate::eth::TxRequest;
+
+sol! {
+    struct SafeTx {
+        address to;
+        uint256 value;
+        bytes data;
+        uint8 operation;
+        uint256 safeTxGas;
+        uint256 baseGas;
+        uint256 gasPrice;
+        address gasToken;
+        address refundReceiver;
+        uint256 nonce;
+    }
+}
+
+// Safe operations: 0 is a call, 1 a delegatecall from the Safe
+const SAFE_OPERATION_DELEGATE_CALL: u8 = 1;
+
+/// Safe transaction in the format of the Safe Transaction Service
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeTransaction {
+    pub to: Address,
+    #[serde(default)]
+    pub value: U256,
+    #[serde(default)]
+    pub data: Bytes,
+    #[serde(default)]
+    pub operation: u8,
+    #[serde(default)]
+    pub safe_tx_gas: U256,
+    #[serde(default)]
+    pub base_gas: U256,
+    #[serde(default)]
+    pub gas_price: U256,
+    #[serde(default)]
+    pub gas_token: Address,
+    #[serde(default)]
+    pub refund_receiver: Address,
+    pub nonce: U256,
+}
+
+impl SafeTransaction {
+    /// SafeTx hash owners sign for `safe` on `chain_id`
+    pub fn hash(&self, safe: Address, chain_id: u64) -> B256 {
+        let domain = Eip712Domain {
+            chain_id: Some(U256::from(chain_id)),
+            verifying_contract: Some(safe),
+            ..Default::default()
+        };
+        SafeTx {
+            to: self.to,
+            value: self.value,
+            data: self.data.clone(),
+            operation: self.operation,
+            safeTxGas: self.safe_tx_gas,
+            baseGas: self.base_gas,
+            gasPrice: self.gas_price,
+            gasToken: self.gas_token,
+            refundReceiver: self.refund_receiver,
+            nonce: self.nonce,
+        }.eip712_signing_hash(&domain)
+    }
+}
+
+/// Data passed to the TEE to sign a Safe transaction as one of its owners
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeTransactionRequest {
+    pub safe: Address,
+    pub chain_id: u64,
+    pub tx: SafeTransaction,
+}
+
+impl SafeTransactionRequest {
+    pub fn from_json(data: &str) -> Result<Self, String> {
+        let request: Self = serde_json::from_str(data).map_err(|e| format!("Invalid Safe transaction data: {}", e))?;
+        if request.tx.operation > SAFE_OPERATION_DELEGATE_CALL {
+            return Err(format!("Invalid Safe operation {}", request.tx.operation));
+        }
+        Ok(request)
+    }
+
+    pub fn safe_tx_hash(&self) -> B256 {
+        self.tx.hash(self.safe, self.chain_id)
+    }
+
+    /// Whether the Safe runs the target's code as itself rather than calling it
+    pub fn is_delegate_call(&self) -> bool {
+        self.tx.operation == SAFE_OPERATION_DELEGATE_CALL
+    }
+
+    /// Transaction checked by the signing policy: the call the Safe makes, or the target a
+    /// delegatecall runs
+    pub fn policy_request(&self) -> TxRequest {
+        TxRequest {
+            from: Some(self.safe),
+            to: Some(self.tx.to),
+            value: self.tx.value,
+            data: self.tx.data.clone(),
+            chain_id: Some(self.chain_id),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, b256};
+    use alloy_sol_types::SolValue;
+
+    #[test]
+    fn test_safe_tx_hash() {
+        // Type hash and domain separator as defined in Safe.sol
+        assert_eq!(
+            alloy_primitives::keccak256(SafeTx::eip712_encode_type().as_bytes()),
+            b256!("bb8310d486368db6bd6f849402fdd73ad53d316b5a4b2644ad6efe0f941286d8")
+        );
+        let safe = address!("1111111111111111111111111111111111111111");
+        let domain = Eip712Domain { chain_id: Some(U256::from(1)), verifying_contract: Some(safe), ..Default::default() };
+        let expected_separator = alloy_primitives::keccak256(
+            (b256!("47e79534a245952e8b16893a336b85a3d9ea9fa8c573f3d803afb92a79469218"), U256::from(1), safe).abi_encode()
+        );
+        assert_eq!(domain.separator(), expected_separator);
+
+        let tx = SafeTransaction { to: Address::repeat_byte(2), value: U256::from(1), nonce: U256::from(3), ..Default::default() };
+        let request = SafeTransactionRequest { safe, chain_id: 1, tx: tx.clone() };
+        assert_eq!(request.safe_tx_hash(), tx.hash(safe, 1));
+        assert_ne!(request.safe_tx_hash(), tx.hash(safe, 10));
+
+        let json = serde_json::to_string(&SafeTransactionRequest { tx: SafeTransaction { operation: 2, ..tx }, ..request }).unwrap();
+        assert!(SafeTransactionRequest::from_json(&json).is_err());
+    }
+}
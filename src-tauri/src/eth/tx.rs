@@ -0,0 +1,329 @@
+// Transaction Request
+// Parses the JSON transaction data sent by the frontend ({"to", "value", "data", "chainId", ...})
+// and RLP-encodes complete transactions for signing (legacy EIP-155 and EIP-1559)
+
+use std::str::FromStr;
+
+use alloy_primitives::{keccak256, Address, Bytes, Signature, B256, U256};
+use alloy_rlp::{Encodable, Header};
+use serde_json::{json, Map, Value};
+
+// EIP-2718 type byte of EIP-1559 transactions
+const EIP1559_TX_TYPE: u8 = 0x02;
+
+/// Transaction fields as sent by the frontend; signing fields may be missing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxRequest {
+    pub from: Option<Address>,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Bytes,
+    pub chain_id: Option<u64>,
+    pub nonce: Option<u64>,
+    pub gas_limit: Option<u64>,
+    pub gas_price: Option<u128>,
+    pub max_fee_per_gas: Option<u128>,
+    pub max_priority_fee_per_gas: Option<u128>,
+}
+
+/// Fee fields of a complete transaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TxFees {
+    Legacy { gas_price: u128 },
+    Eip1559 { max_fee_per_gas: u128, max_priority_fee_per_gas: u128 },
+}
+
+/// Transaction with every field needed for signing
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsignedTx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_limit: u64,
+    pub fees: TxFees,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Bytes,
+}
+
+impl TxRequest {
+    /// Parse transaction JSON; quantities may be hex strings, decimal strings or numbers
+    pub fn from_json(tx_data: &str) -> Result<Self, String> {
+        let tx_value: Value = serde_json::from_str(tx_data)
+            .map_err(|e| format!("Invalid transaction data: {}", e))?;
+        let fields = tx_value.as_object()
+            .ok_or_else(|| "Invalid transaction data: expected a JSON object".to_string())?;
+        let field = |names: &[&str]| names.iter().find_map(|name| fields.get(*name)).filter(|v| !v.is_null());
+
+        let address = |name: &str| -> Result<Option<Address>, String> {
+            match field(&[name]) {
+                Some(Value::String(s)) if !s.is_empty() => Address::from_str(s)
+                    .map(Some)
+                    .map_err(|e| format!("Invalid {} address {}: {}", name, s, e)),
+                Some(Value::String(_)) | None => Ok(None),
+                Some(other) => Err(format!("Invalid {} address: {}", name, other)),
+            }
+        };
+        let quantity = |names: &[&str]| -> Result<Option<U256>, String> {
+            field(names).map(|v| parse_quantity(v, names[0])).transpose()
+        };
+        let small_quantity = |names: &[&str]| -> Result<Option<u64>, String> {
+            quantity(names)?
+                .map(|v| u64::try_from(v).map_err(|_| format!("{} out of range", names[0])))
+                .transpose()
+        };
+        let fee = |names: &[&str]| -> Result<Option<u128>, String> {
+            quantity(names)?
+                .map(|v| u128::try_from(v).map_err(|_| format!("{} out of range", names[0])))
+                .transpose()
+        };
+
+        let data = match field(&["data", "input"]) {
+            Some(Value::String(s)) => Bytes::from_str(s).map_err(|e| format!("Invalid data field: {}", e))?,
+            Some(other) => return Err(format!("Invalid data field: {}", other)),
+            None => Bytes::new(),
+        };
+
+        Ok(Self {
+            from: address("from")?,
+            to: address("to")?,
+            value: quantity(&["value"])?.unwrap_or_default(),
+            data,
+            chain_id: small_quantity(&["chainId", "chain_id"])?,
+            nonce: small_quantity(&["nonce"])?,
+            gas_limit: small_quantity(&["gas", "gasLimit", "gas_limit"])?,
+            gas_price: fee(&["gasPrice", "gas_price"])?,
+            max_fee_per_gas: fee(&["maxFeePerGas", "max_fee_per_gas"])?,
+            max_priority_fee_per_gas: fee(&["maxPriorityFeePerGas", "max_priority_fee_per_gas"])?,
+        })
+    }
+
+    /// Transaction JSON in the format accepted by `from_json`; quantities are hex strings
+    pub fn to_json(&self) -> Value {
+        let mut fields = Map::new();
+        let mut put = |name: &str, value: Value| {
+            fields.insert(name.to_string(), value);
+        };
+        let quantity = |value: u128| json!(format!("{:#x}", value));
+
+        if let Some(from) = self.from { put("from", json!(from)); }
+        if let Some(to) = self.to { put("to", json!(to)); }
+        put("value", json!(self.value));
+        put("data", json!(self.data));
+        if let Some(chain_id) = self.chain_id { put("chainId", json!(chain_id)); }
+        if let Some(nonce) = self.nonce { put("nonce", json!(nonce)); }
+        if let Some(gas_limit) = self.gas_limit { put("gas", json!(gas_limit)); }
+        if let Some(gas_price) = self.gas_price { put("gasPrice", quantity(gas_price)); }
+        if let Some(max_fee) = self.max_fee_per_gas { put("maxFeePerGas", quantity(max_fee)); }
+        if let Some(max_priority_fee) = self.max_priority_fee_per_gas { put("maxPriorityFeePerGas", quantity(max_priority_fee)); }
+
+        Value::Object(fields)
+    }
+
+    /// 4-byte method selector when the transaction carries calldata
+    pub fn selector(&self) -> Option<[u8; 4]> {
+        self.data.get(..4).map(|s| [s[0], s[1], s[2], s[3]])
+    }
+
+    /// A contract call (or deployment) rather than a plain value transfer
+    pub fn is_contract_call(&self) -> bool {
+        !self.data.is_empty()
+    }
+
+    /// Complete transaction for signing; EIP-1559 when max fees are set, legacy otherwise
+    pub fn to_unsigned(&self) -> Result<UnsignedTx, String> {
+        let missing = |name: &str| format!("Transaction is missing {}", name);
+
+        let fees = match (self.max_fee_per_gas, self.max_priority_fee_per_gas, self.gas_price) {
+            (Some(max_fee_per_gas), Some(max_priority_fee_per_gas), _) => {
+                if max_priority_fee_per_gas > max_fee_per_gas {
+                    return Err("maxPriorityFeePerGas exceeds maxFeePerGas".to_string());
+                }
+                TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas }
+            },
+            (None, None, Some(gas_price)) => TxFees::Legacy { gas_price },
+            _ => return Err(missing("gasPrice or maxFeePerGas/maxPriorityFeePerGas")),
+        };
+
+        Ok(UnsignedTx {
+            chain_id: self.chain_id.ok_or_else(|| missing("chainId"))?,
+            nonce: self.nonce.ok_or_else(|| missing("nonce"))?,
+            gas_limit: self.gas_limit.ok_or_else(|| missing("gas"))?,
+            fees,
+            to: self.to,
+            value: self.value,
+            data: self.data.clone(),
+        })
+    }
+}
+
+impl UnsignedTx {
+    /// Hash signed by the sender
+    pub fn signature_hash(&self) -> B256 {
+        let mut out = Vec::new();
+        match self.fees {
+            TxFees::Legacy { .. } => {
+                // EIP-155: chainId, 0, 0 take the place of v, r, s
+                self.encode_list(&mut out, |fields| {
+                    self.chain_id.encode(fields);
+                    0u8.encode(fields);
+                    0u8.encode(fields);
+                });
+            },
+            TxFees::Eip1559 { .. } => {
+                out.push(EIP1559_TX_TYPE);
+                self.encode_list(&mut out, |_| {});
+            },
+        }
+        keccak256(&out)
+    }
+
+    /// Signed transaction ready for eth_sendRawTransaction
+    pub fn encode_signed(&self, signature: &Signature) -> Bytes {
+        let mut out = Vec::new();
+        match self.fees {
+            TxFees::Legacy { .. } => {
+                let v = self.chain_id * 2 + 35 + u64::from(signature.v());
+                self.encode_list(&mut out, |fields| {
+                    v.encode(fields);
+                    signature.r().encode(fields);
+                    signature.s().encode(fields);
+                });
+            },
+            TxFees::Eip1559 { .. } => {
+                out.push(EIP1559_TX_TYPE);
+                self.encode_list(&mut out, |fields| {
+                    signature.v().encode(fields);
+                    signature.r().encode(fields);
+                    signature.s().encode(fields);
+                });
+            },
+        }
+        out.into()
+    }
+
+    // RLP list of the transaction fields followed by the given trailing fields
+    fn encode_list(&self, out: &mut Vec<u8>, trailer: impl FnOnce(&mut Vec<u8>)) {
+        let mut fields = Vec::new();
+        match self.fees {
+            TxFees::Legacy { gas_price } => {
+                self.nonce.encode(&mut fields);
+                gas_price.encode(&mut fields);
+                self.gas_limit.encode(&mut fields);
+                self.encode_to(&mut fields);
+                self.value.encode(&mut fields);
+                self.data.encode(&mut fields);
+            },
+            TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => {
+                self.chain_id.encode(&mut fields);
+                self.nonce.encode(&mut fields);
+                max_priority_fee_per_gas.encode(&mut fields);
+                max_fee_per_gas.encode(&mut fields);
+                self.gas_limit.encode(&mut fields);
+                self.encode_to(&mut fields);
+                self.value.encode(&mut fields);
+                self.data.encode(&mut fields);
+                // Empty access list
+                Header { list: true, payload_length: 0 }.encode(&mut fields);
+            },
+        }
+        trailer(&mut fields);
+
+        Header { list: true, payload_length: fields.len() }.encode(out);
+        out.extend_from_slice(&fields);
+    }
+
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match &self.to {
+            Some(to) => to.encode(out),
+            // Contract creation
+            None => Bytes::new().encode(out),
+        }
+    }
+}
+
+fn parse_quantity(value: &Value, name: &str) -> Result<U256, String> {
+    match value {
+        Value::Number(n) => n.as_u64()
+            .map(U256::from)
+            .ok_or_else(|| format!("Invalid {}: {}", name, n)),
+        Value::String(s) => U256::from_str(s.trim())
+            .map_err(|e| format!("Invalid {} {}: {}", name, s, e)),
+        other => Err(format!("Invalid {}: {}", name, other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+
+    #[test]
+    fn test_parse_transaction_json() {
+        let tx = TxRequest::from_json(r#"{
+            "to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266",
+            "value": "0xde0b6b3a7640000",
+            "data": "0xa9059cbb00",
+            "chainId": 11155111
+        }"#).unwrap();
+
+        assert_eq!(tx.to, Some(Address::from_str("0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266").unwrap()));
+        assert_eq!(tx.value, U256::from(10u64).pow(U256::from(18)));
+        assert_eq!(tx.selector(), Some([0xa9, 0x05, 0x9c, 0xbb]));
+        assert_eq!(tx.chain_id, Some(11155111));
+
+        // Decimal strings and missing fields
+        let tx = TxRequest::from_json(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266", "value": "1000"}"#).unwrap();
+        assert_eq!(tx.value, U256::from(1000));
+        assert!(!tx.is_contract_call());
+        assert_eq!(tx.chain_id, None);
+        assert!(tx.to_unsigned().is_err());
+
+        assert!(TxRequest::from_json(r#"{"to": "0x1234"}"#).is_err());
+        assert!(TxRequest::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn test_legacy_eip155_signing_vector() {
+        // Example from EIP-155
+        let tx = TxRequest::from_json(r#"{
+            "nonce": 9, "gasPrice": "20000000000", "gas": 21000, "chainId": 1,
+            "to": "0x3535353535353535353535353535353535353535", "value": "1000000000000000000"
+        }"#).unwrap().to_unsigned().unwrap();
+
+        assert_eq!(
+            tx.signature_hash().to_string(),
+            "0xdaf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+
+        let key = SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let signature = Signature::from(key.sign_prehash_recoverable(tx.signature_hash().as_slice()).unwrap());
+        assert_eq!(
+            hex::encode(tx.encode_signed(&signature)),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f\
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+    }
+
+    #[test]
+    fn test_eip1559_signing_recovers_sender() {
+        let request = TxRequest::from_json(r#"{
+            "nonce": "0x1", "gas": "0x5208", "chainId": 11155111,
+            "maxFeePerGas": "2000000000", "maxPriorityFeePerGas": "100000000",
+            "to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266", "value": "1", "data": "0x"
+        }"#).unwrap();
+        assert_eq!(TxRequest::from_json(&request.to_json().to_string()).unwrap(), request);
+
+        let tx = request.to_unsigned().unwrap();
+        assert!(matches!(tx.fees, TxFees::Eip1559 { .. }));
+
+        let key = SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let signature = Signature::from(key.sign_prehash_recoverable(tx.signature_hash().as_slice()).unwrap());
+        let raw = tx.encode_signed(&signature);
+        assert_eq!(raw[0], EIP1559_TX_TYPE);
+        assert_eq!(
+            signature.recover_address_from_prehash(&tx.signature_hash()).unwrap(),
+            Address::from_private_key(&key)
+        );
+    }
+}
@@ -0,0 +1,407 @@
+// ERC-4337 UserOperation
+// UserOperations in the RPC format used by bundlers (EntryPoint v0.7, or v0.6 with
+// initCode/paymasterAndData), with the userOpHash the EntryPoint computes for signing
+
+use alloy_primitives::{eip191_hash_message, keccak256, Address, Bytes, B256, U256};
+use alloy_sol_types::{SolCall, SolValue};
+use serde::{Deserialize, Serialize};
+
+use super::decode::IAccount;
+use crate::eth::TxRequest;
+
+/// Canonical EntryPoint v0.6 deployment
+pub const ENTRY_POINT_V06: Address = alloy_primitives::address!("5FF137D4b0FDCD49DcA30c7CF57E578a026d2789");
+/// Canonical EntryPoint v0.7 deployment
+pub const ENTRY_POINT_V07: Address = alloy_primitives::address!("0000000071727De22E5E9d8BAf0edAc6f37da032");
+
+/// EntryPoint version, which decides the UserOperation format and hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryPointVersion {
+    V06,
+    V07,
+}
+
+impl EntryPointVersion {
+    /// Version of a canonical EntryPoint deployment
+    pub fn of(entry_point: Address) -> Option<Self> {
+        match entry_point {
+            ENTRY_POINT_V06 => Some(Self::V06),
+            ENTRY_POINT_V07 => Some(Self::V07),
+            _ => None,
+        }
+    }
+}
+
+/// UserOperation as sent to and returned by bundlers
+///
+/// v0.7 uses the factory and paymaster fields, v0.6 the `init_code` and `paymaster_and_data` fields.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperation {
+    pub sender: Address,
+    /// 192-bit key in the high bits, sequence number in the low 64 bits
+    #[serde(default)]
+    pub nonce: U256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factory: Option<Address>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factory_data: Option<Bytes>,
+    #[serde(default)]
+    pub call_data: Bytes,
+    #[serde(default)]
+    pub call_gas_limit: U256,
+    #[serde(default)]
+    pub verification_gas_limit: U256,
+    #[serde(default)]
+    pub pre_verification_gas: U256,
+    #[serde(default)]
+    pub max_fee_per_gas: U256,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: U256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster: Option<Address>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_verification_gas_limit: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_post_op_gas_limit: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_data: Option<Bytes>,
+    /// EntryPoint v0.6: factory ++ factoryData
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_code: Option<Bytes>,
+    /// EntryPoint v0.6: paymaster ++ paymasterData
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_and_data: Option<Bytes>,
+    #[serde(default)]
+    pub signature: Bytes,
+}
+
+/// Data passed to the TEE to sign a UserOperation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationRequest {
+    pub user_op: UserOperation,
+    pub entry_point: Address,
+    pub chain_id: u64,
+}
+
+impl UserOperationRequest {
+    pub fn from_json(data: &str) -> Result<Self, String> {
+        serde_json::from_str(data).map_err(|e| format!("Invalid UserOperation data: {}", e))
+    }
+
+    pub fn user_op_hash(&self) -> B256 {
+        self.user_op.hash(self.entry_point, self.chain_id)
+    }
+
+    pub fn version(&self) -> EntryPointVersion {
+        EntryPointVersion::of(self.entry_point).unwrap_or(EntryPointVersion::V07)
+    }
+
+    /// Digest the account signs: the userOpHash as an EIP-191 personal message
+    pub fn signing_hash(&self) -> B256 {
+        eip191_hash_message(self.user_op_hash())
+    }
+}
+
+impl UserOperation {
+    /// Check the fields match the EntryPoint version, filling the fields v0.6 bundlers require
+    pub fn normalize_for(&mut self, version: EntryPointVersion) -> Result<(), String> {
+        match version {
+            EntryPointVersion::V06 => {
+                if self.factory.is_some() || self.paymaster.is_some() {
+                    return Err("EntryPoint v0.6 UserOperations use initCode and paymasterAndData".to_string());
+                }
+                self.init_code.get_or_insert_with(Bytes::new);
+                self.paymaster_and_data.get_or_insert_with(Bytes::new);
+            },
+            EntryPointVersion::V07 => {
+                if self.init_code.is_some() || self.paymaster_and_data.is_some() {
+                    return Err("EntryPoint v0.7 UserOperations use the factory and paymaster fields".to_string());
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// factory ++ factoryData, empty when the account is already deployed
+    pub fn packed_init_code(&self) -> Bytes {
+        if let Some(init_code) = &self.init_code {
+            return init_code.clone();
+        }
+        match self.factory {
+            Some(factory) => [factory.as_slice(), optional_bytes(&self.factory_data)].concat().into(),
+            None => Bytes::new(),
+        }
+    }
+
+    /// v0.7: paymaster ++ verificationGasLimit (16 bytes) ++ postOpGasLimit (16 bytes) ++ paymasterData;
+    /// v0.6: the `paymaster_and_data` field as is
+    pub fn packed_paymaster_and_data(&self) -> Bytes {
+        if let Some(paymaster_and_data) = &self.paymaster_and_data {
+            return paymaster_and_data.clone();
+        }
+        match self.paymaster {
+            Some(paymaster) => [
+                paymaster.as_slice(),
+                &gas_bytes(self.paymaster_verification_gas_limit.unwrap_or_default()),
+                &gas_bytes(self.paymaster_post_op_gas_limit.unwrap_or_default()),
+                optional_bytes(&self.paymaster_data),
+            ].concat().into(),
+            None => Bytes::new(),
+        }
+    }
+
+    /// v0.7 accountGasLimits: verificationGasLimit and callGasLimit as two uint128
+    pub fn account_gas_limits(&self) -> B256 {
+        pack_u128_pair(self.verification_gas_limit, self.call_gas_limit)
+    }
+
+    /// v0.7 paymaster verification and post-op gas limits as two uint128
+    pub fn paymaster_gas_limits(&self) -> B256 {
+        pack_u128_pair(
+            self.paymaster_verification_gas_limit.unwrap_or_default(),
+            self.paymaster_post_op_gas_limit.unwrap_or_default(),
+        )
+    }
+
+    /// v0.7 gasFees: maxPriorityFeePerGas and maxFeePerGas as two uint128
+    pub fn gas_fees(&self) -> B256 {
+        pack_u128_pair(self.max_priority_fee_per_gas, self.max_fee_per_gas)
+    }
+
+    /// userOpHash as computed by the EntryPoint's `getUserOpHash`; EntryPoints other
+    /// than the canonical deployments are treated as v0.7
+    pub fn hash(&self, entry_point: Address, chain_id: u64) -> B256 {
+        let packed = match EntryPointVersion::of(entry_point).unwrap_or(EntryPointVersion::V07) {
+            EntryPointVersion::V06 => (
+                self.sender,
+                self.nonce,
+                keccak256(self.packed_init_code()),
+                keccak256(&self.call_data),
+                self.call_gas_limit,
+                self.verification_gas_limit,
+                self.pre_verification_gas,
+                self.max_fee_per_gas,
+                self.max_priority_fee_per_gas,
+                keccak256(self.packed_paymaster_and_data()),
+            ).abi_encode(),
+            EntryPointVersion::V07 => (
+                self.sender,
+                self.nonce,
+                keccak256(self.packed_init_code()),
+                keccak256(&self.call_data),
+                self.account_gas_limits(),
+                self.pre_verification_gas,
+                self.gas_fees(),
+                keccak256(self.packed_paymaster_and_data()),
+            ).abi_encode(),
+        };
+
+        keccak256((keccak256(packed), entry_point, U256::from(chain_id)).abi_encode())
+    }
+
+    /// The account call itself, as sent by the EntryPoint
+    pub fn account_call(&self, chain_id: u64) -> TxRequest {
+        TxRequest {
+            from: Some(self.sender),
+            to: Some(self.sender),
+            data: self.call_data.clone(),
+            chain_id: Some(chain_id),
+            ..Default::default()
+        }
+    }
+
+    /// Transactions checked by the signing policy: the calls the account executes, one per
+    /// `execute` or batch entry
+    ///
+    /// Call data that is not an `execute` or `executeBatch` cannot be checked and is refused.
+    pub fn policy_requests(&self, chain_id: u64) -> Result<Vec<TxRequest>, String> {
+        let account_call = self.account_call(chain_id);
+        if self.call_data.is_empty() {
+            return Ok(vec![account_call]);
+        }
+        let call = |dest: Address, value: U256, func: Bytes| TxRequest {
+            to: Some(dest),
+            value,
+            data: func,
+            ..account_call.clone()
+        };
+        let invalid = |e: alloy_sol_types::Error| format!("Invalid account call data: {}", e);
+
+        let selector = self.call_data.get(..4).unwrap_or_default();
+        if selector == IAccount::executeCall::SELECTOR {
+            let execute = IAccount::executeCall::abi_decode(&self.call_data).map_err(invalid)?;
+            Ok(vec![call(execute.dest, execute.value, execute.func)])
+        } else if selector == IAccount::executeBatch_0Call::SELECTOR {
+            let batch = IAccount::executeBatch_0Call::abi_decode(&self.call_data).map_err(invalid)?;
+            if batch.dest.len() != batch.func.len() {
+                return Err("Account batch has mismatched dest and func lengths".to_string());
+            }
+            Ok(batch.dest.into_iter().zip(batch.func).map(|(dest, func)| call(dest, U256::ZERO, func)).collect())
+        } else if selector == IAccount::executeBatch_1Call::SELECTOR {
+            let batch = IAccount::executeBatch_1Call::abi_decode(&self.call_data).map_err(invalid)?;
+            // Accounts accept an empty value array for a batch without value
+            let values = if batch.value.is_empty() { vec![U256::ZERO; batch.dest.len()] } else { batch.value };
+            if batch.dest.len() != batch.func.len() || values.len() != batch.func.len() {
+                return Err("Account batch has mismatched dest, value and func lengths".to_string());
+            }
+            Ok(batch.dest.into_iter().zip(values).zip(batch.func).map(|((dest, value), func)| call(dest, value, func)).collect())
+        } else {
+            Err(format!("Cannot check account call 0x{} against the signing policy", hex::encode(selector)))
+        }
+    }
+}
+
+// Gas limits and fees are packed as two uint128 values into one bytes32
+fn pack_u128_pair(high: U256, low: U256) -> B256 {
+    B256::from((high << 128) | (low & U256::from(u128::MAX)))
+}
+
+fn optional_bytes(data: &Option<Bytes>) -> &[u8] {
+    data.as_ref().map(|data| data.as_ref()).unwrap_or_default()
+}
+
+fn gas_bytes(value: U256) -> [u8; 16] {
+    u128::try_from(value).unwrap_or(u128::MAX).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::Signature;
+    use k256::ecdsa::SigningKey;
+
+    fn sample_user_op() -> UserOperation {
+        serde_json::from_value(serde_json::json!({
+            "sender": "0x1111111111111111111111111111111111111111",
+            "nonce": "0x5",
+            "factory": "0x2222222222222222222222222222222222222222",
+            "factoryData": "0xabcd",
+            "callData": "0x",
+            "callGasLimit": "0x10000",
+            "verificationGasLimit": "0x20000",
+            "preVerificationGas": "0x5000",
+            "maxFeePerGas": "0x77359400",
+            "maxPriorityFeePerGas": "0x5f5e100",
+            "paymaster": "0x3333333333333333333333333333333333333333",
+            "paymasterVerificationGasLimit": "0x100",
+            "paymasterPostOpGasLimit": "0x200",
+            "paymasterData": "0x01",
+            "signature": "0x"
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_packing_and_hash() {
+        let user_op = sample_user_op();
+        assert_eq!(
+            hex::encode(user_op.packed_init_code()),
+            "2222222222222222222222222222222222222222abcd"
+        );
+        assert_eq!(
+            hex::encode(user_op.packed_paymaster_and_data()),
+            "3333333333333333333333333333333333333333\
+             00000000000000000000000000000100\
+             00000000000000000000000000000200\
+             01"
+        );
+        assert_eq!(
+            user_op.account_gas_limits().to_string(),
+            "0x0000000000000000000000000002000000000000000000000000000000010000"
+        );
+
+        // The hash commits to the chain, EntryPoint and every field except the signature
+        let hash = user_op.hash(ENTRY_POINT_V07, 11155111);
+        assert_ne!(hash, user_op.hash(ENTRY_POINT_V07, 1));
+        assert_ne!(hash, user_op.hash(Address::ZERO, 11155111));
+        let mut signed = user_op.clone();
+        signed.signature = Bytes::from_static(&[1; 65]);
+        assert_eq!(signed.hash(ENTRY_POINT_V07, 11155111), hash);
+        signed.call_gas_limit += U256::from(1);
+        assert_ne!(signed.hash(ENTRY_POINT_V07, 11155111), hash);
+
+        // v0.6 hashes the unpacked gas fields
+        assert_ne!(user_op.hash(ENTRY_POINT_V06, 11155111), hash);
+
+        // Round trip through the bundler JSON format
+        let json = serde_json::to_value(&user_op).unwrap();
+        assert_eq!(json["callGasLimit"], "0x10000");
+        assert_eq!(serde_json::from_value::<UserOperation>(json).unwrap(), user_op);
+    }
+
+    #[test]
+    fn test_normalize_for_entry_point_version() {
+        let mut user_op = sample_user_op();
+        assert!(user_op.normalize_for(EntryPointVersion::V07).is_ok());
+        assert!(user_op.normalize_for(EntryPointVersion::V06).is_err());
+
+        // v0.6 bundlers expect initCode and paymasterAndData to be present
+        let mut user_op = UserOperation { sender: Address::repeat_byte(1), ..Default::default() };
+        user_op.normalize_for(EntryPointVersion::V06).unwrap();
+        let json = serde_json::to_value(&user_op).unwrap();
+        assert_eq!(json["initCode"], "0x");
+        assert_eq!(json["paymasterAndData"], "0x");
+        assert!(json.get("factory").is_none());
+        assert!(user_op.normalize_for(EntryPointVersion::V07).is_err());
+    }
+
+    #[test]
+    fn test_signing_hash_recovers_owner() {
+        let request = UserOperationRequest {
+            user_op: sample_user_op(),
+            entry_point: ENTRY_POINT_V07,
+            chain_id: 11155111,
+        };
+        let key = SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let signature = Signature::from(key.sign_prehash_recoverable(request.signing_hash().as_slice()).unwrap());
+        assert_eq!(
+            signature.recover_address_from_msg(request.user_op_hash()).unwrap(),
+            Address::from_private_key(&key)
+        );
+    }
+
+    #[test]
+    fn test_policy_request_unwraps_execute() {
+        let mut user_op = sample_user_op();
+        let dest = Address::repeat_byte(0x44);
+        user_op.call_data = IAccount::executeCall { dest, value: U256::from(7), func: Bytes::from_static(&[0xaa]) }
+            .abi_encode()
+            .into();
+
+        let calls = user_op.policy_requests(10).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].to, Some(dest));
+        assert_eq!(calls[0].value, U256::from(7));
+        assert_eq!(calls[0].data, Bytes::from_static(&[0xaa]));
+
+        // Account calls that cannot be decoded are refused
+        user_op.call_data = Bytes::from_static(&[1, 2, 3, 4]);
+        assert!(user_op.policy_requests(10).is_err());
+    }
+
+    #[test]
+    fn test_policy_requests_unwrap_batches() {
+        let mut user_op = sample_user_op();
+        let dests = vec![Address::repeat_byte(0x44), Address::repeat_byte(0x55)];
+        user_op.call_data = IAccount::executeBatch_1Call {
+            dest: dests.clone(),
+            value: vec![U256::from(7), U256::from(8)],
+            func: vec![Bytes::new(), Bytes::from_static(&[0xaa])],
+        }.abi_encode().into();
+        let calls = user_op.policy_requests(10).unwrap();
+        assert_eq!(calls.iter().map(|call| call.to.unwrap()).collect::<Vec<_>>(), dests);
+        assert_eq!(calls[1].value, U256::from(8));
+        assert_eq!(calls[1].chain_id, Some(10));
+
+        user_op.call_data = IAccount::executeBatch_0Call { dest: dests.clone(), func: vec![Bytes::new(), Bytes::new()] }
+            .abi_encode()
+            .into();
+        assert_eq!(user_op.policy_requests(10).unwrap().len(), 2);
+
+        // Batches whose arrays do not line up are refused
+        user_op.call_data = IAccount::executeBatch_0Call { dest: dests, func: vec![Bytes::new()] }.abi_encode().into();
+        assert!(user_op.policy_requests(10).is_err());
+    }
+}
@@ -0,0 +1,431 @@
+// Authenticator Attestation Policy
+// Optional per-profile requirement that passkeys are created by certified hardware. Trust
+// anchors and certification status come from an offline FIDO Metadata Service (MDS3) blob:
+// a JWT whose signing chain has to lead to the FIDO MDS root certificate before any of its
+// entries are used. Entries are then filtered by AAGUID and certification level.
+
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine as _};
+use openssl::bn::BigNum;
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::sign::Verifier;
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{X509StoreContext, X509};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use webauthn_rs::prelude::{AttestationCaList, AttestationCaListBuilder};
+
+use crate::storage;
+
+// Status reports after which an authenticator model is never trusted
+const COMPROMISED_STATUSES: &[&str] = &[
+    "REVOKED",
+    "ATTESTATION_KEY_COMPROMISE",
+    "USER_VERIFICATION_BYPASS",
+    "USER_KEY_REMOTE_COMPROMISE",
+    "USER_KEY_PHYSICAL_COMPROMISE",
+];
+
+/// FIDO authenticator certification levels, lowest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CertificationLevel {
+    #[serde(rename = "FIDO_CERTIFIED_L1")]
+    L1,
+    #[serde(rename = "FIDO_CERTIFIED_L1plus")]
+    L1Plus,
+    #[serde(rename = "FIDO_CERTIFIED_L2")]
+    L2,
+    #[serde(rename = "FIDO_CERTIFIED_L2plus")]
+    L2Plus,
+    #[serde(rename = "FIDO_CERTIFIED_L3")]
+    L3,
+    #[serde(rename = "FIDO_CERTIFIED_L3plus")]
+    L3Plus,
+}
+
+impl CertificationLevel {
+    /// Level certified by an MDS status report; plain FIDO_CERTIFIED predates levels and is L1
+    pub fn from_status(status: &str) -> Option<Self> {
+        match status {
+            "FIDO_CERTIFIED" | "FIDO_CERTIFIED_L1" => Some(Self::L1),
+            "FIDO_CERTIFIED_L1plus" => Some(Self::L1Plus),
+            "FIDO_CERTIFIED_L2" => Some(Self::L2),
+            "FIDO_CERTIFIED_L2plus" => Some(Self::L2Plus),
+            "FIDO_CERTIFIED_L3" => Some(Self::L3),
+            "FIDO_CERTIFIED_L3plus" => Some(Self::L3Plus),
+            _ => None,
+        }
+    }
+}
+
+/// Which authenticators may register passkeys for a relying party profile
+///
+/// Relative paths are resolved against the app data directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttestationPolicy {
+    /// MDS3 blob as downloaded from the FIDO Metadata Service
+    pub mds_blob_path: PathBuf,
+    /// PEM root certificate the blob's signing chain has to lead to
+    pub mds_root_certificate_path: PathBuf,
+    /// Only these authenticator models if any are listed
+    #[serde(default)]
+    pub allowed_aaguids: Vec<Uuid>,
+    #[serde(default)]
+    pub denied_aaguids: Vec<Uuid>,
+    /// Lowest certification level accepted; uncertified models are accepted if not set
+    #[serde(default)]
+    pub min_certification_level: Option<CertificationLevel>,
+}
+
+/// Authenticator model a credential was attested by
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttestedAuthenticator {
+    pub aaguid: Uuid,
+    pub description: String,
+    pub certification_level: Option<CertificationLevel>,
+}
+
+/// Authenticator models of a verified metadata blob that the policy accepts
+#[derive(Debug)]
+pub struct TrustedMetadata {
+    /// Serial number of the blob
+    pub blob_number: u64,
+    /// Date by which FIDO publishes the next blob
+    pub next_update: String,
+    authenticators: HashMap<Uuid, AttestedAuthenticator>,
+    ca_list: AttestationCaList,
+}
+
+impl TrustedMetadata {
+    /// Attestation root certificates of the accepted models
+    pub fn ca_list(&self) -> &AttestationCaList {
+        &self.ca_list
+    }
+
+    pub fn authenticator(&self, aaguid: Uuid) -> Option<&AttestedAuthenticator> {
+        self.authenticators.get(&aaguid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.authenticators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.authenticators.is_empty()
+    }
+}
+
+// MDS3 blob payload, only the fields the policy uses
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetadataBlob {
+    no: u64,
+    next_update: String,
+    entries: Vec<MetadataBlobEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetadataBlobEntry {
+    // U2F authenticators are listed by key identifier instead and cannot be matched here
+    aaguid: Option<Uuid>,
+    metadata_statement: Option<MetadataStatement>,
+    #[serde(default)]
+    status_reports: Vec<StatusReport>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetadataStatement {
+    description: String,
+    #[serde(default)]
+    attestation_root_certificates: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct StatusReport {
+    status: String,
+}
+
+impl AttestationPolicy {
+    /// Read and verify the metadata blob and collect the authenticator models the policy accepts
+    pub fn load(&self) -> Result<TrustedMetadata, String> {
+        let blob_path = resolve(&self.mds_blob_path)?;
+        let root_path = resolve(&self.mds_root_certificate_path)?;
+        let blob = std::fs::read_to_string(&blob_path)
+            .map_err(|e| format!("Failed to read metadata blob {}: {}", blob_path.display(), e))?;
+        let root = std::fs::read(&root_path)
+            .map_err(|e| format!("Failed to read {}: {}", root_path.display(), e))
+            .and_then(|pem| X509::from_pem(&pem).map_err(|e| format!("Invalid MDS root certificate {}: {}", root_path.display(), e)))?;
+
+        let payload = verify_blob(blob.trim(), &root)?;
+        let blob: MetadataBlob = serde_json::from_value(payload)
+            .map_err(|e| format!("Invalid metadata blob payload: {}", e))?;
+        let trusted = self.select(blob)?;
+        println!("COS72-Tauri: Loaded FIDO metadata blob {} ({} authenticator models accepted, next update {})",
+                 trusted.blob_number, trusted.len(), trusted.next_update);
+        Ok(trusted)
+    }
+
+    /// Whether the policy accepts a model with this AAGUID and certification level
+    pub fn permits(&self, aaguid: Uuid, level: Option<CertificationLevel>) -> bool {
+        if self.denied_aaguids.contains(&aaguid) {
+            return false;
+        }
+        if !self.allowed_aaguids.is_empty() && !self.allowed_aaguids.contains(&aaguid) {
+            return false;
+        }
+        self.min_certification_level.is_none_or(|min| level.is_some_and(|level| level >= min))
+    }
+
+    fn select(&self, blob: MetadataBlob) -> Result<TrustedMetadata, String> {
+        let mut authenticators = HashMap::new();
+        let mut builder = AttestationCaListBuilder::new();
+        for entry in blob.entries {
+            let (Some(aaguid), Some(statement)) = (entry.aaguid, entry.metadata_statement) else {
+                continue;
+            };
+            if entry.status_reports.iter().any(|report| COMPROMISED_STATUSES.contains(&report.status.as_str())) {
+                continue;
+            }
+            let level = entry.status_reports.iter()
+                .filter_map(|report| CertificationLevel::from_status(&report.status))
+                .max();
+            if !self.permits(aaguid, level) {
+                continue;
+            }
+
+            let mut anchors = 0;
+            for certificate in &statement.attestation_root_certificates {
+                let inserted = STANDARD.decode(certificate).map_err(|e| e.to_string()).and_then(|der| {
+                    builder.insert_device_der(&der, aaguid, statement.description.clone(), BTreeMap::new())
+                        .map_err(|e| e.to_string())
+                });
+                match inserted {
+                    Ok(()) => anchors += 1,
+                    Err(e) => println!("COS72-Tauri: Skipping invalid attestation root of {}: {}", aaguid, e),
+                }
+            }
+            if anchors > 0 {
+                authenticators.insert(aaguid, AttestedAuthenticator {
+                    aaguid,
+                    description: statement.description,
+                    certification_level: level,
+                });
+            }
+        }
+
+        if authenticators.is_empty() {
+            return Err("No authenticator in the metadata blob is accepted by the attestation policy".to_string());
+        }
+        Ok(TrustedMetadata {
+            blob_number: blob.no,
+            next_update: blob.next_update,
+            authenticators,
+            ca_list: builder.build(),
+        })
+    }
+}
+
+// Paths in the configuration are relative to the app data directory
+fn resolve(path: &Path) -> Result<PathBuf, String> {
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+    storage::data_dir()
+        .map(|dir| dir.join(path))
+        .map_err(|e| format!("Failed to open data directory: {}", e))
+}
+
+// Check the blob's certificate chain and JWS signature and return its payload
+fn verify_blob(blob: &str, root: &X509) -> Result<Value, String> {
+    let parts: Vec<&str> = blob.split('.').collect();
+    let [header_b64, payload_b64, signature_b64] = parts[..] else {
+        return Err("Metadata blob is not a JWT".to_string());
+    };
+    let header: Value = decode_json(header_b64).map_err(|e| format!("Invalid metadata blob header: {}", e))?;
+
+    let chain = header["x5c"].as_array()
+        .ok_or_else(|| "Metadata blob header has no x5c certificate chain".to_string())?
+        .iter()
+        .map(|certificate| {
+            let der = STANDARD.decode(certificate.as_str().unwrap_or_default()).map_err(|e| e.to_string())?;
+            X509::from_der(&der).map_err(|e| e.to_string())
+        })
+        .collect::<Result<Vec<X509>, String>>()
+        .map_err(|e| format!("Invalid certificate in metadata blob header: {}", e))?;
+    let (signer, intermediates) = chain.split_first()
+        .ok_or_else(|| "Metadata blob header has an empty certificate chain".to_string())?;
+    verify_chain(signer, intermediates, root)?;
+
+    let signature = URL_SAFE_NO_PAD.decode(signature_b64)
+        .map_err(|e| format!("Invalid metadata blob signature: {}", e))?;
+    let signature = match header["alg"].as_str() {
+        Some("RS256") => signature,
+        // JWS carries ECDSA signatures as r || s, OpenSSL expects DER
+        Some("ES256") if signature.len() == 64 => {
+            let r = BigNum::from_slice(&signature[..32]).map_err(|e| e.to_string())?;
+            let s = BigNum::from_slice(&signature[32..]).map_err(|e| e.to_string())?;
+            EcdsaSig::from_private_components(r, s).and_then(|sig| sig.to_der()).map_err(|e| e.to_string())?
+        }
+        alg => return Err(format!("Unsupported metadata blob signature algorithm {:?}", alg)),
+    };
+
+    let key = signer.public_key().map_err(|e| format!("Invalid metadata signing key: {}", e))?;
+    let valid = Verifier::new(MessageDigest::sha256(), &key)
+        .and_then(|mut verifier| {
+            verifier.update(header_b64.as_bytes())?;
+            verifier.update(b".")?;
+            verifier.update(payload_b64.as_bytes())?;
+            verifier.verify(&signature)
+        })
+        .unwrap_or(false);
+    if !valid {
+        return Err("Metadata blob signature is invalid".to_string());
+    }
+
+    decode_json(payload_b64).map_err(|e| format!("Invalid metadata blob payload: {}", e))
+}
+
+// The signing certificate has to chain up to the configured root
+fn verify_chain(signer: &X509, intermediates: &[X509], root: &X509) -> Result<(), String> {
+    let openssl_error = |e: openssl::error::ErrorStack| format!("Failed to verify metadata signing certificate: {}", e);
+    let mut store = X509StoreBuilder::new().map_err(openssl_error)?;
+    store.add_cert(root.clone()).map_err(openssl_error)?;
+    let store = store.build();
+    let mut chain = Stack::new().map_err(openssl_error)?;
+    for certificate in intermediates {
+        chain.push(certificate.clone()).map_err(openssl_error)?;
+    }
+
+    let mut context = X509StoreContext::new().map_err(openssl_error)?;
+    let verified = context.init(&store, signer, &chain, |context| {
+        Ok(context.verify_cert()?.then_some(()).ok_or_else(|| context.error().error_string().to_string()))
+    }).map_err(openssl_error)?;
+    verified.map_err(|e| format!("Metadata signing certificate is not trusted: {}", e))
+}
+
+fn decode_json(part: &str) -> Result<Value, String> {
+    let bytes = URL_SAFE_NO_PAD.decode(part).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fido::test_authenticator::TestCertificateAuthority;
+    use openssl::pkey::{PKey, Private};
+    use openssl::sign::Signer;
+    use serde_json::json;
+
+    // MDS3 blob with `entries`, signed with ES256 by `signer`
+    pub(crate) fn test_blob(signer: &(PKey<Private>, X509), entries: Value) -> String {
+        let header = json!({
+            "alg": "ES256",
+            "typ": "JWT",
+            "x5c": [STANDARD.encode(signer.1.to_der().unwrap())],
+        });
+        let payload = json!({
+            "legalHeader": "Test metadata",
+            "no": 7,
+            "nextUpdate": "2030-01-01",
+            "entries": entries,
+        });
+        let signed = format!("{}.{}", URL_SAFE_NO_PAD.encode(header.to_string()), URL_SAFE_NO_PAD.encode(payload.to_string()));
+        let mut jws_signer = Signer::new(MessageDigest::sha256(), &signer.0).unwrap();
+        jws_signer.update(signed.as_bytes()).unwrap();
+        let der = EcdsaSig::from_der(&jws_signer.sign_to_vec().unwrap()).unwrap();
+        let mut signature = der.r().to_vec_padded(32).unwrap();
+        signature.extend(der.s().to_vec_padded(32).unwrap());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    // Metadata entry of a model attested under `root`
+    pub(crate) fn test_entry(aaguid: Uuid, description: &str, root: &X509, statuses: &[&str]) -> Value {
+        json!({
+            "aaguid": aaguid,
+            "metadataStatement": {
+                "description": description,
+                "attestationRootCertificates": [STANDARD.encode(root.to_der().unwrap())],
+            },
+            "statusReports": statuses.iter().map(|status| json!({ "status": status })).collect::<Vec<_>>(),
+        })
+    }
+
+    // Policy reading `blob`, signed under `mds_root`, from a temporary directory
+    pub(crate) fn test_policy(dir: &Path, blob: &str, mds_root: &X509) -> AttestationPolicy {
+        std::fs::write(dir.join("mds.jwt"), blob).unwrap();
+        std::fs::write(dir.join("mds-root.pem"), mds_root.to_pem().unwrap()).unwrap();
+        AttestationPolicy {
+            mds_blob_path: dir.join("mds.jwt"),
+            mds_root_certificate_path: dir.join("mds-root.pem"),
+            allowed_aaguids: Vec::new(),
+            denied_aaguids: Vec::new(),
+            min_certification_level: None,
+        }
+    }
+
+    #[test]
+    fn test_metadata_blob_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let mds_root = TestCertificateAuthority::new("Test MDS Root");
+        let signer = mds_root.issue("Test MDS Signer");
+        let vendor_root = TestCertificateAuthority::new("Test Vendor Root");
+        let aaguid = Uuid::new_v4();
+        let entries = json!([test_entry(aaguid, "Test Key", &vendor_root.certificate, &["FIDO_CERTIFIED_L2"])]);
+
+        let blob = test_blob(&signer, entries.clone());
+        let policy = test_policy(dir.path(), &blob, &mds_root.certificate);
+        let trusted = policy.load().unwrap();
+        assert_eq!(trusted.blob_number, 7);
+        assert_eq!(trusted.authenticator(aaguid).unwrap().certification_level, Some(CertificationLevel::L2));
+        assert_eq!(trusted.ca_list().len(), 1);
+
+        // Payload changed after signing
+        let parts: Vec<&str> = blob.split('.').collect();
+        let forged = json!({ "no": 8, "nextUpdate": "2030-01-01", "entries": entries });
+        let tampered = format!("{}.{}.{}", parts[0], URL_SAFE_NO_PAD.encode(forged.to_string()), parts[2]);
+        std::fs::write(&policy.mds_blob_path, tampered).unwrap();
+        assert!(policy.load().unwrap_err().contains("signature is invalid"));
+
+        // Signed by a certificate that does not lead to the MDS root
+        let other_root = TestCertificateAuthority::new("Other Root");
+        std::fs::write(&policy.mds_blob_path, test_blob(&other_root.issue("Other Signer"), entries)).unwrap();
+        assert!(policy.load().unwrap_err().contains("not trusted"));
+    }
+
+    #[test]
+    fn test_policy_filters_authenticators() {
+        let dir = tempfile::tempdir().unwrap();
+        let mds_root = TestCertificateAuthority::new("Test MDS Root");
+        let vendor_root = TestCertificateAuthority::new("Test Vendor Root");
+        let (l1, l3, uncertified, revoked) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let blob = test_blob(&mds_root.issue("Test MDS Signer"), json!([
+            test_entry(l1, "Level 1", &vendor_root.certificate, &["FIDO_CERTIFIED"]),
+            test_entry(l3, "Level 3", &vendor_root.certificate, &["FIDO_CERTIFIED_L1", "FIDO_CERTIFIED_L3"]),
+            test_entry(uncertified, "Uncertified", &vendor_root.certificate, &["NOT_FIDO_CERTIFIED"]),
+            test_entry(revoked, "Revoked", &vendor_root.certificate, &["FIDO_CERTIFIED_L2", "ATTESTATION_KEY_COMPROMISE"]),
+        ]));
+        let policy = test_policy(dir.path(), &blob, &mds_root.certificate);
+
+        let trusted = policy.load().unwrap();
+        assert_eq!(trusted.len(), 3);
+        assert!(trusted.authenticator(revoked).is_none());
+
+        let level_2 = AttestationPolicy { min_certification_level: Some(CertificationLevel::L2), ..policy.clone() };
+        let trusted = level_2.load().unwrap();
+        assert_eq!(trusted.len(), 1);
+        assert_eq!(trusted.authenticator(l3).unwrap().certification_level, Some(CertificationLevel::L3));
+
+        let allowed = AttestationPolicy { allowed_aaguids: vec![l1, uncertified], denied_aaguids: vec![uncertified], ..policy.clone() };
+        let trusted = allowed.load().unwrap();
+        assert!(trusted.authenticator(l1).is_some() && trusted.len() == 1);
+
+        let nothing = AttestationPolicy { allowed_aaguids: vec![revoked], ..policy };
+        assert!(nothing.load().is_err());
+    }
+}
@@ -0,0 +1,163 @@
+// WebAuthn Relying Party Configuration
+// Relying party ID, name, allowed origins and timeout per community profile, stored in
+// webauthn.json in the app data directory. The default profile covers the Tauri webview
+// (tauri://localhost, http://tauri.localhost on Windows) and the dev server. A profile can
+// require attestation from certified authenticators. The wallet owner, whose passkeys
+// confirm signing and exports, can be pinned to a user.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use url::Url;
+use uuid::Uuid;
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+use super::attestation::AttestationPolicy;
+
+// Constants
+pub const WEBAUTHN_CONFIG_FILE: &str = "webauthn.json";
+pub const DEFAULT_PROFILE: &str = "default";
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// Relying party settings of one community
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelyingPartyProfile {
+    /// Domain passkeys are scoped to; every origin has to be on it
+    pub rp_id: String,
+    pub rp_name: String,
+    /// Origins the app's pages are served from
+    pub origins: Vec<String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Only authenticators accepted by the policy can register; any authenticator if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationPolicy>,
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+impl Default for RelyingPartyProfile {
+    fn default() -> Self {
+        Self {
+            rp_id: "localhost".to_string(),
+            rp_name: "COS72-Tauri".to_string(),
+            origins: vec![
+                "tauri://localhost".to_string(),
+                "http://tauri.localhost".to_string(),
+                "http://localhost:3000".to_string(),
+            ],
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            attestation: None,
+        }
+    }
+}
+
+impl RelyingPartyProfile {
+    /// Parsed origins, checked against the RP ID
+    pub fn validate(&self) -> Result<Vec<Url>, String> {
+        if self.rp_id.is_empty() {
+            return Err("rp_id must not be empty".to_string());
+        }
+        if self.origins.is_empty() {
+            return Err(format!("Relying party {} has no origins", self.rp_id));
+        }
+        if self.timeout_secs == 0 {
+            return Err("timeout_secs must not be 0".to_string());
+        }
+
+        self.origins.iter().map(|origin| {
+            let url = Url::parse(origin).map_err(|e| format!("Invalid origin {}: {}", origin, e))?;
+            // The RP ID has to be the origin's domain or a parent domain of it
+            let on_rp_domain = url.domain().is_some_and(|domain| {
+                domain == self.rp_id || domain.ends_with(&format!(".{}", self.rp_id))
+            });
+            if !on_rp_domain {
+                return Err(format!("Origin {} is not on the relying party domain {}", origin, self.rp_id));
+            }
+            Ok(url)
+        }).collect()
+    }
+
+    /// WebAuthn instance for this relying party
+    pub fn build(&self) -> Result<Webauthn, String> {
+        let origins = self.validate()?;
+        let mut builder = WebauthnBuilder::new(&self.rp_id, &origins[0])
+            .map_err(|e| format!("Invalid relying party {}: {}", self.rp_id, e))?
+            .rp_name(&self.rp_name)
+            .timeout(Duration::from_secs(self.timeout_secs));
+        for origin in &origins[1..] {
+            builder = builder.append_allowed_origin(origin);
+        }
+        builder.build().map_err(|e| format!("Failed to build WebAuthn: {}", e))
+    }
+}
+
+/// Relying party profiles and the one in use
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebauthnConfig {
+    pub active_profile: String,
+    pub profiles: BTreeMap<String, RelyingPartyProfile>,
+    /// User whose passkeys confirm wallet operations; the first registered user if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallet_owner: Option<Uuid>,
+}
+
+impl Default for WebauthnConfig {
+    fn default() -> Self {
+        Self {
+            active_profile: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), RelyingPartyProfile::default())]),
+            wallet_owner: None,
+        }
+    }
+}
+
+impl WebauthnConfig {
+    /// Check that every profile can be used and the active one exists
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, profile) in &self.profiles {
+            profile.validate().map_err(|e| format!("Profile {}: {}", name, e))?;
+        }
+        self.active().map(|_| ())
+    }
+
+    pub fn active(&self) -> Result<&RelyingPartyProfile, String> {
+        self.profiles.get(&self.active_profile)
+            .ok_or_else(|| format!("Unknown WebAuthn profile {}", self.active_profile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_validation() {
+        let config = WebauthnConfig::default();
+        config.validate().unwrap();
+        let webauthn = config.active().unwrap().build().unwrap();
+        assert_eq!(webauthn.get_allowed_origins().len(), 3);
+
+        let community = RelyingPartyProfile {
+            rp_id: "community.example".to_string(),
+            rp_name: "Community".to_string(),
+            origins: vec!["https://wallet.community.example".to_string(), "https://community.example".to_string()],
+            timeout_secs: 120,
+            attestation: None,
+        };
+        community.build().unwrap();
+
+        let other_domain = RelyingPartyProfile { origins: vec!["https://evil.example".to_string()], ..community.clone() };
+        assert!(other_domain.validate().is_err());
+        // A suffix that is not a parent domain
+        let suffix = RelyingPartyProfile { origins: vec!["https://notcommunity.example".to_string()], ..community.clone() };
+        assert!(suffix.validate().is_err());
+        let empty = RelyingPartyProfile { rp_id: String::new(), ..community };
+        assert!(empty.validate().is_err());
+
+        let missing = WebauthnConfig { active_profile: "community".to_string(), ..WebauthnConfig::default() };
+        assert!(missing.validate().is_err());
+    }
+}
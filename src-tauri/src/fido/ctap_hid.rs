@@ -0,0 +1,661 @@
+// CTAPHID Security Key Transport
+// CTAP2 over USB HID for roaming security keys. A HidDevice exchanges raw 64-byte reports
+// (hidraw on Linux, a virtual security key in tests); CtapHidToken does the CTAPHID framing
+// on top of it and implements the webauthn-authenticator-rs Token trait, so the library's
+// CTAP2 client runs getInfo, the client PIN protocol and getAssertion.
+
+use async_trait::async_trait;
+use futures::executor::block_on;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use url::Url;
+use webauthn_authenticator_rs::ctap2::CtapAuthenticator;
+use webauthn_authenticator_rs::error::{CtapError, WebauthnCError};
+use webauthn_authenticator_rs::transport::Token;
+use webauthn_authenticator_rs::types::{CableRequestType, CableState, EnrollSampleStatus};
+use webauthn_authenticator_rs::ui::UiCallback;
+use webauthn_authenticator_rs::AuthenticatorBackend;
+use webauthn_rs_core::proto::{AuthenticatorTransport, PublicKeyCredential, PublicKeyCredentialRequestOptions};
+
+// Constants
+/// Size of CTAPHID input and output reports
+pub const HID_REPORT_SIZE: usize = 64;
+const CID_BROADCAST: u32 = 0xffff_ffff;
+const CTAPHID_INIT: u8 = 0x86;
+const CTAPHID_CBOR: u8 = 0x90;
+const CTAPHID_CANCEL: u8 = 0x91;
+const CTAPHID_KEEPALIVE: u8 = 0xbb;
+const CTAPHID_ERROR: u8 = 0xbf;
+const CAPABILITY_CBOR: u8 = 0x04;
+const KEEPALIVE_PROCESSING: u8 = 1;
+const KEEPALIVE_USER_PRESENCE_NEEDED: u8 = 2;
+// Payload bytes of initialization and continuation packets, and of a whole message
+const INIT_PAYLOAD_SIZE: usize = HID_REPORT_SIZE - 7;
+const CONT_PAYLOAD_SIZE: usize = HID_REPORT_SIZE - 5;
+const MAX_PAYLOAD_SIZE: usize = INIT_PAYLOAD_SIZE + 128 * CONT_PAYLOAD_SIZE;
+// Used when the request options carry no timeout
+const DEFAULT_TIMEOUT_MS: u32 = 60_000;
+
+/// Raw HID connection to a FIDO authenticator
+pub trait HidDevice: fmt::Debug + Send + Sync {
+    /// Send one output report
+    fn write_report(&mut self, report: &[u8; HID_REPORT_SIZE]) -> std::io::Result<()>;
+    /// Next input report, or `None` if nothing arrived within `timeout`
+    fn read_report(&mut self, timeout: Duration) -> std::io::Result<Option<[u8; HID_REPORT_SIZE]>>;
+}
+
+/// CTAP2 token on a CTAPHID channel of `D`
+#[derive(Debug)]
+pub struct CtapHidToken<D: HidDevice> {
+    device: D,
+    // Channel allocated by CTAPHID_INIT
+    cid: u32,
+    // Longest wait for the response to a request, including the user's touch
+    timeout: Duration,
+}
+
+impl<D: HidDevice> CtapHidToken<D> {
+    pub fn new(device: D, timeout: Duration) -> Self {
+        Self { device, cid: CID_BROADCAST, timeout }
+    }
+
+    fn send(&mut self, cid: u32, cmd: u8, payload: &[u8]) -> Result<(), WebauthnCError> {
+        if payload.len() > MAX_PAYLOAD_SIZE {
+            return Err(WebauthnCError::MessageTooLarge);
+        }
+        for report in frames(cid, cmd, payload) {
+            self.device.write_report(&report)?;
+        }
+        Ok(())
+    }
+
+    // Next complete message on channel `cid`, as command and payload
+    fn recv(&mut self, cid: u32, deadline: Instant) -> Result<(u8, Vec<u8>), WebauthnCError> {
+        let report = loop {
+            let report = self.next_report(cid, deadline)?;
+            // Continuation packets of a message that started before we listened are skipped
+            if report[4] & 0x80 != 0 {
+                break report;
+            }
+        };
+        let cmd = report[4];
+        let len = usize::from(u16::from_be_bytes([report[5], report[6]]));
+        if len > MAX_PAYLOAD_SIZE {
+            return Err(WebauthnCError::InvalidMessageLength);
+        }
+        let mut payload = report[7..7 + len.min(INIT_PAYLOAD_SIZE)].to_vec();
+        let mut seq = 0u8;
+        while payload.len() < len {
+            let report = self.next_report(cid, deadline)?;
+            if report[4] != seq {
+                return Err(WebauthnCError::Ctap(CtapError::Ctap1InvalidSeq));
+            }
+            seq += 1;
+            let size = (len - payload.len()).min(CONT_PAYLOAD_SIZE);
+            payload.extend_from_slice(&report[5..5 + size]);
+        }
+        Ok((cmd, payload))
+    }
+
+    // Next report on channel `cid`; reports for other clients' channels are skipped
+    fn next_report(&mut self, cid: u32, deadline: Instant) -> Result<[u8; HID_REPORT_SIZE], WebauthnCError> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(WebauthnCError::Ctap(CtapError::Ctap1Timeout));
+            }
+            if let Some(report) = self.device.read_report(remaining)? {
+                if report[..4] == cid.to_be_bytes() {
+                    return Ok(report);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<D: HidDevice> Token for CtapHidToken<D> {
+    type Id = ();
+
+    fn get_transport(&self) -> AuthenticatorTransport {
+        AuthenticatorTransport::Usb
+    }
+
+    async fn transmit_raw<U>(&mut self, cbor: &[u8], ui: &U) -> Result<Vec<u8>, WebauthnCError>
+    where
+        U: UiCallback,
+    {
+        let deadline = Instant::now() + self.timeout;
+        self.send(self.cid, CTAPHID_CBOR, cbor)?;
+        loop {
+            let (cmd, payload) = match self.recv(self.cid, deadline) {
+                Ok(message) => message,
+                Err(e) => {
+                    // Stop the key from waiting for a touch nobody is asked for anymore
+                    let _ = self.send(self.cid, CTAPHID_CANCEL, &[]);
+                    return Err(e);
+                }
+            };
+            match cmd {
+                CTAPHID_KEEPALIVE => match payload.first() {
+                    Some(&KEEPALIVE_USER_PRESENCE_NEEDED) => ui.request_touch(),
+                    Some(&KEEPALIVE_PROCESSING) => ui.processing(),
+                    _ => (),
+                },
+                CTAPHID_CBOR => {
+                    let (status, response) = payload.split_first().ok_or(WebauthnCError::MessageTooShort)?;
+                    return match CtapError::from(*status) {
+                        CtapError::Ok => Ok(response.to_vec()),
+                        e => Err(WebauthnCError::Ctap(e)),
+                    };
+                }
+                CTAPHID_ERROR => {
+                    let code = payload.first().copied().unwrap_or(0x7f);
+                    return Err(WebauthnCError::Ctap(CtapError::from(code)));
+                }
+                _ => return Err(WebauthnCError::Cbor),
+            }
+        }
+    }
+
+    async fn cancel(&mut self) -> Result<(), WebauthnCError> {
+        self.send(self.cid, CTAPHID_CANCEL, &[])
+    }
+
+    async fn init(&mut self) -> Result<(), WebauthnCError> {
+        if self.cid != CID_BROADCAST {
+            return Ok(());
+        }
+        let mut nonce = [0u8; 8];
+        getrandom::getrandom(&mut nonce).map_err(|_| WebauthnCError::Internal)?;
+        let deadline = Instant::now() + self.timeout;
+        self.send(CID_BROADCAST, CTAPHID_INIT, &nonce)?;
+        loop {
+            let (cmd, response) = self.recv(CID_BROADCAST, deadline)?;
+            // Nonce, channel ID, protocol and device versions, capabilities
+            if cmd != CTAPHID_INIT || response.len() < 17 || response[..8] != nonce {
+                continue;
+            }
+            if response[16] & CAPABILITY_CBOR == 0 {
+                return Err(WebauthnCError::NotSupported);
+            }
+            self.cid = u32::from_be_bytes([response[8], response[9], response[10], response[11]]);
+            return Ok(());
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), WebauthnCError> {
+        Ok(())
+    }
+}
+
+// Initialization packet and continuation packets carrying `payload`
+fn frames(cid: u32, cmd: u8, payload: &[u8]) -> Vec<[u8; HID_REPORT_SIZE]> {
+    let (first, rest) = payload.split_at(payload.len().min(INIT_PAYLOAD_SIZE));
+    let mut report = [0u8; HID_REPORT_SIZE];
+    report[..4].copy_from_slice(&cid.to_be_bytes());
+    report[4] = cmd;
+    report[5..7].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+    report[7..7 + first.len()].copy_from_slice(first);
+    let mut reports = vec![report];
+    for (seq, chunk) in rest.chunks(CONT_PAYLOAD_SIZE).enumerate() {
+        let mut report = [0u8; HID_REPORT_SIZE];
+        report[..4].copy_from_slice(&cid.to_be_bytes());
+        report[4] = seq as u8;
+        report[5..5 + chunk.len()].copy_from_slice(chunk);
+        reports.push(report);
+    }
+    reports
+}
+
+// Answers the PIN prompt with the caller's PIN, or cancels if there is none
+struct PinCallback {
+    pin: Option<String>,
+    touch_requested: AtomicBool,
+}
+
+impl fmt::Debug for PinCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinCallback").field("pin", &self.pin.as_ref().map(|_| "***")).finish()
+    }
+}
+
+impl UiCallback for PinCallback {
+    fn request_pin(&self) -> Option<String> {
+        self.pin.clone()
+    }
+
+    // Keep-alives repeat the request every 100ms
+    fn request_touch(&self) {
+        if !self.touch_requested.swap(true, Ordering::Relaxed) {
+            println!("COS72-Tauri: 请触摸FIDO2安全密钥");
+        }
+    }
+
+    fn processing(&self) {}
+
+    fn fingerprint_enrollment_feedback(&self, _remaining_samples: u32, _feedback: Option<EnrollSampleStatus>) {}
+
+    fn cable_qr_code(&self, _request_type: CableRequestType, _url: String) {}
+
+    fn dismiss_qr_code(&self) {}
+
+    fn cable_status_update(&self, _state: CableState) {}
+}
+
+/// Have the security key behind `device` sign `options`, acting as a client on `origin`
+///
+/// `pin` is sent if the key verifies users with its client PIN. The returned credential
+/// carries the clientDataJSON that was signed, ready for `webauthn::finish_authentication`.
+/// This blocks until the key answers or the request times out.
+pub fn get_assertion<D: HidDevice>(
+    device: D,
+    origin: Url,
+    options: PublicKeyCredentialRequestOptions,
+    pin: Option<&str>,
+) -> Result<PublicKeyCredential, WebauthnCError> {
+    let timeout_ms = options.timeout.unwrap_or(DEFAULT_TIMEOUT_MS);
+    let ui = PinCallback { pin: pin.map(str::to_string), touch_requested: AtomicBool::new(false) };
+    let mut token = CtapHidToken::new(device, Duration::from_millis(timeout_ms.into()));
+    // The token does blocking IO and never waits on another task
+    block_on(token.init())?;
+    let mut authenticator = block_on(CtapAuthenticator::new(token, &ui)).ok_or(WebauthnCError::NotSupported)?;
+    AuthenticatorBackend::perform_auth(&mut authenticator, origin, options, timeout_ms)
+}
+
+/// hidraw nodes of the connected FIDO authenticators
+#[cfg(target_os = "linux")]
+pub fn list_devices() -> Vec<std::path::PathBuf> {
+    let Ok(entries) = std::fs::read_dir("/sys/class/hidraw") else {
+        return Vec::new();
+    };
+    let mut devices: Vec<_> = entries.flatten()
+        .filter(|entry| {
+            std::fs::read(entry.path().join("device/report_descriptor"))
+                .is_ok_and(|descriptor| is_fido_descriptor(&descriptor))
+        })
+        .map(|entry| std::path::Path::new("/dev").join(entry.file_name()))
+        .collect();
+    devices.sort();
+    devices
+}
+
+// Whether a HID report descriptor declares the FIDO Alliance usage page
+#[cfg(target_os = "linux")]
+fn is_fido_descriptor(descriptor: &[u8]) -> bool {
+    const USAGE_PAGE: u8 = 0x04;
+    const FIDO_USAGE_PAGE: u32 = 0xf1d0;
+    let mut i = 0;
+    while i < descriptor.len() {
+        let prefix = descriptor[i];
+        // Long item, with its data size in the next byte
+        if prefix == 0xfe {
+            i += 3 + usize::from(descriptor.get(i + 1).copied().unwrap_or(0));
+            continue;
+        }
+        let size = match prefix & 0x03 {
+            3 => 4,
+            size => usize::from(size),
+        };
+        if prefix & 0xfc == USAGE_PAGE {
+            let data = descriptor.get(i + 1..i + 1 + size).unwrap_or_default();
+            let page = data.iter().rev().fold(0u32, |page, byte| page << 8 | u32::from(*byte));
+            if page == FIDO_USAGE_PAGE {
+                return true;
+            }
+        }
+        i += 1 + size;
+    }
+    false
+}
+
+/// FIDO authenticator opened through its Linux hidraw node
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct HidrawDevice {
+    file: std::fs::File,
+}
+
+#[cfg(target_os = "linux")]
+impl HidrawDevice {
+    pub fn open(path: &std::path::Path) -> std::io::Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)?;
+        Ok(Self { file })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl HidDevice for HidrawDevice {
+    fn write_report(&mut self, report: &[u8; HID_REPORT_SIZE]) -> std::io::Result<()> {
+        use std::io::Write;
+        // Report ID 0 first; FIDO devices do not number their reports
+        let mut buffer = [0u8; HID_REPORT_SIZE + 1];
+        buffer[1..].copy_from_slice(report);
+        self.file.write_all(&buffer)
+    }
+
+    fn read_report(&mut self, timeout: Duration) -> std::io::Result<Option<[u8; HID_REPORT_SIZE]>> {
+        use std::io::Read;
+        let deadline = Instant::now() + timeout;
+        let mut report = [0u8; HID_REPORT_SIZE];
+        loop {
+            match self.file.read(&mut report) {
+                Ok(_) => return Ok(Some(report)),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Ok(None);
+                    }
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fido::test_authenticator::TestAuthenticator;
+    use crate::fido::webauthn;
+    use crate::sender::EventSink;
+    use openssl::bn::{BigNum, BigNumContext};
+    use openssl::derive::Deriver;
+    use openssl::ec::{EcGroup, EcKey, EcPoint};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::sign::Signer;
+    use openssl::symm::{Cipher, Crypter, Mode};
+    use serde_cbor_2::Value as CborValue;
+    use sha2::{Digest, Sha256};
+    use std::collections::{BTreeMap, VecDeque};
+    use std::sync::Arc;
+
+    pub(crate) const TEST_PIN: &str = "4711";
+    const TEST_CID: u32 = 0x0102_0304;
+
+    // CTAP 2.0 security key with a client PIN (PIN protocol one) holding one credential
+    pub(crate) struct VirtualSecurityKey {
+        rp_id: String,
+        credential_id: Vec<u8>,
+        credential_key: EcKey<Private>,
+        counter: u32,
+        pin_hash: Vec<u8>,
+        key_agreement: EcKey<Private>,
+        pin_token: Vec<u8>,
+        // Request being received: channel, command, length and payload so far
+        request: Option<(u32, u8, usize, Vec<u8>)>,
+        responses: VecDeque<[u8; HID_REPORT_SIZE]>,
+    }
+
+    impl fmt::Debug for VirtualSecurityKey {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("VirtualSecurityKey").field("rp_id", &self.rp_id).field("counter", &self.counter).finish()
+        }
+    }
+
+    impl VirtualSecurityKey {
+        pub(crate) fn new(rp_id: &str, credential_id: Vec<u8>, credential_key: EcKey<Private>) -> Self {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            let mut pin_token = vec![0u8; 32];
+            getrandom::getrandom(&mut pin_token).unwrap();
+            Self {
+                rp_id: rp_id.to_string(),
+                credential_id,
+                credential_key,
+                counter: 0,
+                pin_hash: Sha256::digest(TEST_PIN.as_bytes())[..16].to_vec(),
+                key_agreement: EcKey::generate(&group).unwrap(),
+                pin_token,
+                request: None,
+                responses: VecDeque::new(),
+            }
+        }
+
+        fn handle(&mut self, cid: u32, cmd: u8, payload: Vec<u8>) {
+            match cmd {
+                CTAPHID_INIT => {
+                    let mut response = payload;
+                    response.extend_from_slice(&TEST_CID.to_be_bytes());
+                    response.extend_from_slice(&[2, 1, 0, 0, CAPABILITY_CBOR]);
+                    self.responses.extend(frames(CID_BROADCAST, CTAPHID_INIT, &response));
+                }
+                CTAPHID_CBOR => {
+                    let request: BTreeMap<i128, CborValue> = if payload.len() > 1 {
+                        serde_cbor_2::from_slice(&payload[1..]).unwrap()
+                    } else {
+                        BTreeMap::new()
+                    };
+                    let (status, response) = match payload[0] {
+                        0x04 => (0, Some(self.get_info())),
+                        0x06 => self.client_pin(&request),
+                        0x02 => {
+                            self.responses.extend(frames(cid, CTAPHID_KEEPALIVE, &[KEEPALIVE_USER_PRESENCE_NEEDED]));
+                            self.get_assertion(&request)
+                        }
+                        _ => (0x01, None),
+                    };
+                    let mut message = vec![status];
+                    if let Some(response) = response {
+                        message.extend(serde_cbor_2::to_vec(&response).unwrap());
+                    }
+                    self.responses.extend(frames(cid, CTAPHID_CBOR, &message));
+                }
+                _ => {}
+            }
+        }
+
+        fn get_info(&self) -> CborValue {
+            let options = BTreeMap::from([
+                (CborValue::Text("rk".to_string()), CborValue::Bool(false)),
+                (CborValue::Text("up".to_string()), CborValue::Bool(true)),
+                (CborValue::Text("clientPin".to_string()), CborValue::Bool(true)),
+            ]);
+            int_map(vec![
+                (1, CborValue::Array(vec![CborValue::Text("FIDO_2_0".to_string())])),
+                (3, CborValue::Bytes(vec![0; 16])),
+                (4, CborValue::Map(options)),
+                (6, CborValue::Array(vec![CborValue::Integer(1)])),
+            ])
+        }
+
+        fn client_pin(&mut self, request: &BTreeMap<i128, CborValue>) -> (u8, Option<CborValue>) {
+            match request.get(&2) {
+                // getPinRetries
+                Some(CborValue::Integer(1)) => (0, Some(int_map(vec![(3, CborValue::Integer(8))]))),
+                // getKeyAgreement
+                Some(CborValue::Integer(2)) => (0, Some(int_map(vec![(1, cose_key(&self.key_agreement, -25))]))),
+                // getPinToken
+                Some(CborValue::Integer(5)) => {
+                    let (Some(CborValue::Map(platform_key)), Some(CborValue::Bytes(pin_hash_enc))) = (request.get(&3), request.get(&6)) else {
+                        return (0x14, None);
+                    };
+                    let shared_secret = self.shared_secret(platform_key);
+                    if aes_cbc(Mode::Decrypt, &shared_secret, pin_hash_enc) != self.pin_hash {
+                        return (0x31, None);
+                    }
+                    let pin_token = aes_cbc(Mode::Encrypt, &shared_secret, &self.pin_token);
+                    (0, Some(int_map(vec![(2, CborValue::Bytes(pin_token))])))
+                }
+                _ => (0x3e, None),
+            }
+        }
+
+        fn get_assertion(&mut self, request: &BTreeMap<i128, CborValue>) -> (u8, Option<CborValue>) {
+            let (Some(CborValue::Text(rp_id)), Some(CborValue::Bytes(client_data_hash))) = (request.get(&1), request.get(&2)) else {
+                return (0x14, None);
+            };
+            // User verification through the PIN is required
+            let Some(CborValue::Bytes(pin_auth)) = request.get(&6) else {
+                return (0x36, None);
+            };
+            if pin_auth[..] != hmac(&self.pin_token, client_data_hash)[..16] {
+                return (0x33, None);
+            }
+            let allowed = match request.get(&3) {
+                Some(CborValue::Array(list)) => list.iter().any(|descriptor| match descriptor {
+                    CborValue::Map(map) => map.get(&CborValue::Text("id".to_string())) == Some(&CborValue::Bytes(self.credential_id.clone())),
+                    _ => false,
+                }),
+                _ => true,
+            };
+            if *rp_id != self.rp_id || !allowed {
+                return (0x2e, None);
+            }
+
+            self.counter += 1;
+            let mut auth_data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            // User present and verified
+            auth_data.push(0x05);
+            auth_data.extend_from_slice(&self.counter.to_be_bytes());
+            let key = PKey::from_ec_key(self.credential_key.clone()).unwrap();
+            let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+            signer.update(&auth_data).unwrap();
+            signer.update(client_data_hash).unwrap();
+            let credential = BTreeMap::from([
+                (CborValue::Text("id".to_string()), CborValue::Bytes(self.credential_id.clone())),
+                (CborValue::Text("type".to_string()), CborValue::Text("public-key".to_string())),
+            ]);
+            (0, Some(int_map(vec![
+                (1, CborValue::Map(credential)),
+                (2, CborValue::Bytes(auth_data)),
+                (3, CborValue::Bytes(signer.sign_to_vec().unwrap())),
+            ])))
+        }
+
+        // SHA-256 of the ECDH x-coordinate
+        fn shared_secret(&self, platform_key: &BTreeMap<CborValue, CborValue>) -> Vec<u8> {
+            let coordinate = |label: i128| match platform_key.get(&CborValue::Integer(label)) {
+                Some(CborValue::Bytes(bytes)) => BigNum::from_slice(bytes).unwrap(),
+                _ => panic!("platform key without coordinate {}", label),
+            };
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            let mut ctx = BigNumContext::new().unwrap();
+            let mut point = EcPoint::new(&group).unwrap();
+            point.set_affine_coordinates_gfp(&group, &coordinate(-2), &coordinate(-3), &mut ctx).unwrap();
+            let peer = PKey::from_ec_key(EcKey::from_public_key(&group, &point).unwrap()).unwrap();
+            let own = PKey::from_ec_key(self.key_agreement.clone()).unwrap();
+            let mut deriver = Deriver::new(&own).unwrap();
+            deriver.set_peer(&peer).unwrap();
+            Sha256::digest(deriver.derive_to_vec().unwrap()).to_vec()
+        }
+    }
+
+    impl HidDevice for VirtualSecurityKey {
+        fn write_report(&mut self, report: &[u8; HID_REPORT_SIZE]) -> std::io::Result<()> {
+            let cid = u32::from_be_bytes([report[0], report[1], report[2], report[3]]);
+            if report[4] & 0x80 != 0 {
+                let len = usize::from(u16::from_be_bytes([report[5], report[6]]));
+                self.request = Some((cid, report[4], len, report[7..7 + len.min(INIT_PAYLOAD_SIZE)].to_vec()));
+            } else if let Some((_, _, len, payload)) = self.request.as_mut() {
+                let size = (*len - payload.len()).min(CONT_PAYLOAD_SIZE);
+                payload.extend_from_slice(&report[5..5 + size]);
+            }
+            if self.request.as_ref().is_some_and(|(_, _, len, payload)| payload.len() == *len) {
+                let (cid, cmd, _, payload) = self.request.take().unwrap();
+                self.handle(cid, cmd, payload);
+            }
+            Ok(())
+        }
+
+        fn read_report(&mut self, _timeout: Duration) -> std::io::Result<Option<[u8; HID_REPORT_SIZE]>> {
+            Ok(self.responses.pop_front())
+        }
+    }
+
+    fn int_map(entries: Vec<(i128, CborValue)>) -> CborValue {
+        CborValue::Map(entries.into_iter().map(|(key, value)| (CborValue::Integer(key), value)).collect())
+    }
+
+    fn cose_key(key: &EcKey<Private>, alg: i128) -> CborValue {
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut x = BigNum::new().unwrap();
+        let mut y = BigNum::new().unwrap();
+        key.public_key().affine_coordinates(key.group(), &mut x, &mut y, &mut ctx).unwrap();
+        int_map(vec![
+            (1, CborValue::Integer(2)),
+            (3, CborValue::Integer(alg)),
+            (-1, CborValue::Integer(1)),
+            (-2, CborValue::Bytes(x.to_vec_padded(32).unwrap())),
+            (-3, CborValue::Bytes(y.to_vec_padded(32).unwrap())),
+        ])
+    }
+
+    // AES-256-CBC with a zero IV and no padding
+    fn aes_cbc(mode: Mode, key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut crypter = Crypter::new(Cipher::aes_256_cbc(), mode, key, Some(&[0; 16])).unwrap();
+        crypter.pad(false);
+        let mut out = vec![0; data.len() + 16];
+        let mut len = crypter.update(data, &mut out).unwrap();
+        len += crypter.finalize(&mut out[len..]).unwrap();
+        out.truncate(len);
+        out
+    }
+
+    fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let key = PKey::hmac(key).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.update(data).unwrap();
+        signer.sign_to_vec().unwrap()
+    }
+
+    #[test]
+    fn test_security_key_assertion_with_pin() {
+        let _guard = webauthn::tests::setup();
+        let events: EventSink = Arc::new(|_, _| {});
+        let (origin, _) = webauthn::client_context().unwrap();
+        let rp_id = webauthn::get_config().active().unwrap().rp_id.clone();
+
+        // Register a credential, then move it onto the virtual security key
+        let mut authenticator = TestAuthenticator::new(origin.as_str());
+        let started = webauthn::start_registration("alice").unwrap();
+        let user_id = started["user_id"].as_str().unwrap().to_string();
+        let response = authenticator.register(&started["challenge"]);
+        webauthn::finish_registration(&user_id, &response, None).unwrap();
+        let (credential_id, credential_key) = authenticator.credential_key();
+        let security_key = || VirtualSecurityKey::new(&rp_id, credential_id.clone(), credential_key.clone());
+
+        // Sign the challenge of a ceremony the way the app signs an external challenge
+        let ceremony = webauthn::start_authentication(Some(&user_id)).unwrap();
+        let challenge = ceremony["challenge"]["publicKey"]["challenge"].as_str().unwrap();
+        let challenge = base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, challenge).unwrap();
+        let options = webauthn::challenge_request_options(&challenge).unwrap();
+        let assertion = get_assertion(security_key(), origin.clone(), options.clone(), Some(TEST_PIN)).unwrap();
+        assert!(!assertion.response.client_data_json.is_empty());
+        let result = webauthn::finish_authentication(
+            ceremony["ceremony_id"].as_str().unwrap(),
+            &serde_json::to_string(&assertion).unwrap(),
+            &events,
+        ).unwrap();
+        assert_eq!(result["user_id"], user_id.as_str());
+
+        // The key refuses to sign with a wrong PIN, and without one the PIN prompt is cancelled
+        let wrong_pin = get_assertion(security_key(), origin.clone(), options.clone(), Some("0000")).unwrap_err();
+        assert!(matches!(wrong_pin, WebauthnCError::Ctap(CtapError::Ctap2PinInvalid)), "{:?}", wrong_pin);
+        let no_pin = get_assertion(security_key(), origin, options, None).unwrap_err();
+        assert!(matches!(no_pin, WebauthnCError::Cancelled), "{:?}", no_pin);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fido_report_descriptor() {
+        // Usage page 0xF1D0, usage CTAPHID, application collection, 64-byte input and output
+        let fido = [
+            0x06, 0xd0, 0xf1, 0x09, 0x01, 0xa1, 0x01, 0x09, 0x20, 0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08,
+            0x95, 0x40, 0x81, 0x02, 0x09, 0x21, 0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08, 0x95, 0x40, 0x91,
+            0x02, 0xc0,
+        ];
+        assert!(is_fido_descriptor(&fido));
+        // Generic desktop keyboard
+        let keyboard = [0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0x05, 0x07, 0x19, 0xe0, 0x29, 0xe7, 0xc0];
+        assert!(!is_fido_descriptor(&keyboard));
+        assert!(!is_fido_descriptor(&[0x06, 0xd0]));
+    }
+}
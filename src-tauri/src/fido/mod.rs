@@ -1,6 +1,15 @@
 pub mod passkey;
 pub mod webauthn;  // 新的WebAuthn实现模块
 pub mod biometric;  // 新增biometric子模块
+pub mod store;  // Passkey持久化存储
+pub mod config;  // WebAuthn依赖方配置
+pub mod attestation;  // 认证器证明策略与FIDO元数据
+#[cfg(feature = "device-support")]
+pub mod soft_authenticator;  // 软件Passkey认证器（无硬件环境与CI）
+#[cfg(feature = "device-support")]
+pub mod ctap_hid;  // USB安全密钥CTAP2传输(CTAPHID)
+#[cfg(test)]
+pub(crate) mod test_authenticator;
 
 // 此模块提供FIDO2/WebAuthn相关功能，用于生物识别签名
 
@@ -1,12 +1,22 @@
 use std::io::Error as IoError;
 use std::fmt;
 use base64::{Engine as _, engine::general_purpose};
+#[cfg(feature = "device-support")]
+use webauthn_authenticator_rs::error::{CtapError, WebauthnCError};
+#[cfg(all(target_os = "linux", feature = "device-support"))]
+use webauthn_rs_core::proto::PublicKeyCredential;
+
+#[cfg(all(target_os = "linux", feature = "device-support"))]
+use super::{ctap_hid, webauthn};
 
 // 错误类型定义
+// 部分变体只在特定平台或启用device-support功能时构造
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum PasskeyError {
     NotSupported,
     DeviceError(String),
+    PinRequired,
     UserCancelled,
     IoError(IoError),
     Other(String),
@@ -17,6 +27,7 @@ impl fmt::Display for PasskeyError {
         match self {
             PasskeyError::NotSupported => write!(f, "Platform does not support FIDO2/WebAuthn"),
             PasskeyError::DeviceError(msg) => write!(f, "Device error: {}", msg),
+            PasskeyError::PinRequired => write!(f, "The security key requires its PIN"),
             PasskeyError::UserCancelled => write!(f, "User cancelled the operation"),
             PasskeyError::IoError(e) => write!(f, "I/O error: {}", e),
             PasskeyError::Other(msg) => write!(f, "Error: {}", msg),
@@ -37,7 +48,9 @@ impl From<String> for PasskeyError {
 }
 
 // 对Challenge进行签名
-pub async fn sign_challenge(challenge: &str) -> Result<String, PasskeyError> {
+// pin: FIDO2安全密钥的PIN（目前仅Linux使用）
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+pub async fn sign_challenge(challenge: &str, pin: Option<&str>) -> Result<String, PasskeyError> {
     // Base64解码challenge
     let challenge_bytes = match general_purpose::STANDARD.decode(challenge) {
         Ok(bytes) => bytes,
@@ -55,7 +68,7 @@ pub async fn sign_challenge(challenge: &str) -> Result<String, PasskeyError> {
     }
     #[cfg(target_os = "linux")]
     {
-        sign_challenge_linux(&challenge_bytes).await
+        sign_challenge_linux(&challenge_bytes, pin).await
     }
     #[cfg(target_os = "android")]
     {
@@ -77,6 +90,17 @@ pub async fn sign_challenge(challenge: &str) -> Result<String, PasskeyError> {
     }
 }
 
+// 用安全密钥签名WebAuthn仪式的挑战（publicKey.challenge, Base64URL编码）
+// 返回断言JSON, 可直接交给finish_authentication或Passkey二次验证的finish_step_up
+pub async fn sign_ceremony_challenge(challenge: &str, pin: Option<&str>) -> Result<String, PasskeyError> {
+    let challenge = general_purpose::URL_SAFE_NO_PAD.decode(challenge.trim_end_matches('='))
+        .map_err(|_| PasskeyError::Other("Invalid challenge format".to_string()))?;
+    let assertion = sign_challenge(&general_purpose::STANDARD.encode(challenge), pin).await?;
+    let assertion = general_purpose::STANDARD.decode(assertion)
+        .map_err(|_| PasskeyError::Other("Authenticator returned no WebAuthn assertion".to_string()))?;
+    String::from_utf8(assertion).map_err(|_| PasskeyError::Other("Authenticator returned no WebAuthn assertion".to_string()))
+}
+
 // Windows平台实现 (使用Windows Hello)
 #[cfg(target_os = "windows")]
 async fn sign_challenge_windows(challenge: &[u8]) -> Result<String, PasskeyError> {
@@ -140,9 +164,10 @@ async fn sign_challenge_macos(challenge: &[u8]) -> Result<String, PasskeyError>
     Ok(result)
 }
 
-// Linux平台实现
+// Linux平台实现 (USB FIDO2安全密钥, CTAP2 over hidraw)
+// 返回Base64编码的WebAuthn断言JSON (authenticatorData, clientDataJSON, signature)
 #[cfg(target_os = "linux")]
-async fn sign_challenge_linux(challenge: &[u8]) -> Result<String, PasskeyError> {
+async fn sign_challenge_linux(challenge: &[u8], pin: Option<&str>) -> Result<String, PasskeyError> {
     // 检查挑战是否为空
     if challenge.is_empty() {
         println!("COS72-Tauri: Passkey错误 - 挑战为空");
@@ -153,78 +178,95 @@ async fn sign_challenge_linux(challenge: &[u8]) -> Result<String, PasskeyError>
     println!("COS72-Tauri: 开始Linux FIDO2签名流程");
     println!("COS72-Tauri: 挑战字节长度: {}", challenge.len());
     println!("COS72-Tauri: 挑战前16字节: {:?}", &challenge[..std::cmp::min(16, challenge.len())]);
-    
-    // 检查是否有FIDO2设备连接
-    let has_device = check_fido2_device_connected();
-    println!("COS72-Tauri: FIDO2设备连接状态: {}", has_device);
-    
-    if !has_device {
-        println!("COS72-Tauri: 没有FIDO2设备连接");
-        return Err(PasskeyError::DeviceError("No FIDO2 device connected".to_string()));
+
+    #[cfg(feature = "device-support")]
+    {
+        // 设备IO是阻塞的，放到阻塞线程池中执行
+        let challenge = challenge.to_vec();
+        let pin = pin.map(str::to_string);
+        let assertion = tokio::task::spawn_blocking(move || sign_with_security_key(&challenge, pin.as_deref()))
+            .await
+            .map_err(|e| PasskeyError::Other(format!("Security key task failed: {}", e)))??;
+        let assertion = serde_json::to_vec(&assertion)
+            .map_err(|e| PasskeyError::Other(format!("Failed to serialize assertion: {}", e)))?;
+
+        // 返回Base64编码的断言
+        let result = general_purpose::STANDARD.encode(&assertion);
+        println!("COS72-Tauri: FIDO2签名成功，返回签名结果");
+        println!("COS72-Tauri: 签名长度: {}", result.len());
+        Ok(result)
+    }
+    #[cfg(not(feature = "device-support"))]
+    {
+        let _ = pin;
+        println!("COS72-Tauri: 未启用device-support功能，无法访问FIDO2设备");
+        Err(PasskeyError::NotSupported)
     }
-    
-    println!("COS72-Tauri: 调用Linux FIDO2设备进行签名...");
-    println!("COS72-Tauri: (这里应该会弹出FIDO2设备操作提示)");
-    
-    // 实际生产环境中，应使用libfido2通过FFI调用：
-    // 1. 构建签名选项
-    // 2. 识别FIDO2设备
-    // 3. 发送挑战并接收签名
-    //
-    // 伪代码示例：
-    // ```
-    // let device = fido2_device_open_first();
-    // let credential_id = get_credential_for_device(device);
-    // let signature = fido2_authenticate(device, challenge, credential_id);
-    // ```
-    
-    // 模拟FIDO2设备验证延迟
-    println!("COS72-Tauri: 等待用户验证...");
-    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-    
-    // 生成模拟签名（WebAuthn格式的模拟响应）
-    let mut signature = Vec::from("FIDO2_SIGNATURE:".as_bytes());
-    signature.extend_from_slice(challenge);
-    
-    // 添加模拟authenticatorData和clientDataJSON字段
-    let auth_data = b"authenticator_data_placeholder";
-    let client_data = b"client_data_json_placeholder";
-    
-    signature.extend_from_slice(b"::");
-    signature.extend_from_slice(auth_data);
-    signature.extend_from_slice(b"::");
-    signature.extend_from_slice(client_data);
-    
-    // 返回Base64编码的签名
-    let result = general_purpose::STANDARD.encode(&signature);
-    println!("COS72-Tauri: FIDO2签名成功，返回签名结果");
-    println!("COS72-Tauri: 签名长度: {}", result.len());
-    
-    Ok(result)
 }
 
-// 辅助函数：检查FIDO2设备是否连接（模拟实现）
-#[cfg(target_os = "linux")]
-fn check_fido2_device_connected() -> bool {
-    // 在实际生产环境中，应检查/dev/hidraw*设备或使用libfido2 API
-    println!("COS72-Tauri: 检查FIDO2设备连接状态");
-    
-    // 检查常见的FIDO2设备路径
-    let fido_paths = [
-        "/dev/hidraw0", "/dev/hidraw1", "/dev/hidraw2", 
-        "/dev/hidraw3", "/dev/hidraw4"
-    ];
-    
-    for path in fido_paths.iter() {
-        if std::path::Path::new(path).exists() {
-            println!("COS72-Tauri: 发现可能的FIDO2设备: {}", path);
-            return true;
+// 使用已连接的FIDO2设备签名
+#[cfg(all(target_os = "linux", feature = "device-support"))]
+fn sign_with_security_key(challenge: &[u8], pin: Option<&str>) -> Result<PublicKeyCredential, PasskeyError> {
+    let devices = ctap_hid::list_devices();
+    println!("COS72-Tauri: 发现{}个FIDO2设备", devices.len());
+    let devices = devices.into_iter().map(|path| {
+        let device = ctap_hid::HidrawDevice::open(&path);
+        (path.display().to_string(), device)
+    });
+    sign_with_devices(devices, challenge, pin)
+}
+
+// 依次尝试FIDO2设备，直到其中一个持有已注册的凭证
+#[cfg(all(target_os = "linux", feature = "device-support"))]
+fn sign_with_devices<D: ctap_hid::HidDevice>(
+    devices: impl IntoIterator<Item = (String, std::io::Result<D>)>,
+    challenge: &[u8],
+    pin: Option<&str>,
+) -> Result<PublicKeyCredential, PasskeyError> {
+    let (origin, _) = webauthn::client_context()?;
+    let options = webauthn::challenge_request_options(challenge)?;
+
+    let mut result = Err(PasskeyError::DeviceError("No FIDO2 device connected".to_string()));
+    for (name, device) in devices {
+        println!("COS72-Tauri: 调用FIDO2设备进行签名: {}", name);
+        let device = match device {
+            Ok(device) => device,
+            Err(e) => {
+                println!("COS72-Tauri: 无法打开{}: {}", name, e);
+                result = Err(PasskeyError::DeviceError(format!("Cannot open {}: {}", name, e)));
+                continue;
+            }
+        };
+        match ctap_hid::get_assertion(device, origin.clone(), options.clone(), pin) {
+            Ok(assertion) => return Ok(assertion),
+            // 凭证可能在另一个设备上
+            Err(WebauthnCError::Ctap(CtapError::Ctap2NoCredentials)) => {
+                println!("COS72-Tauri: {}上没有已注册的凭证", name);
+                result = Err(PasskeyError::DeviceError("No registered passkey on the connected security keys".to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    result
+}
+
+#[cfg(feature = "device-support")]
+impl From<WebauthnCError> for PasskeyError {
+    fn from(error: WebauthnCError) -> Self {
+        match error {
+            // 没有提供PIN时PIN输入被取消
+            WebauthnCError::Cancelled => PasskeyError::PinRequired,
+            WebauthnCError::Ctap(CtapError::Ctap2PinInvalid) => PasskeyError::DeviceError("Incorrect PIN".to_string()),
+            WebauthnCError::Ctap(CtapError::Ctap2PinBlocked | CtapError::Ctap2PinAuthBlocked) => {
+                PasskeyError::DeviceError("PIN is blocked".to_string())
+            }
+            WebauthnCError::Ctap(CtapError::Ctap2OperationDenied | CtapError::Ctap2KeepAliveCancel) => PasskeyError::UserCancelled,
+            WebauthnCError::Ctap(CtapError::Ctap1Timeout | CtapError::Ctap2UserActionTimeout) => {
+                PasskeyError::DeviceError("Timed out waiting for the security key".to_string())
+            }
+            e => PasskeyError::DeviceError(format!("{:?}", e)),
         }
     }
-    
-    // 这里简单模拟一个设备总是连接的情况，便于测试
-    println!("COS72-Tauri: 没有找到物理FIDO2设备，但为测试目的返回true");
-    true
 }
 
 // Android平台实现
@@ -245,4 +287,48 @@ async fn sign_challenge_ios(challenge: &[u8]) -> Result<String, PasskeyError> {
     
     // 模拟成功返回
     Ok(general_purpose::STANDARD.encode("ios_signature_placeholder"))
-} 
\ No newline at end of file
+}
+
+#[cfg(all(test, target_os = "linux", feature = "device-support"))]
+mod tests {
+    use super::*;
+    use crate::fido::ctap_hid::tests::{VirtualSecurityKey, TEST_PIN};
+    use crate::fido::test_authenticator::TestAuthenticator;
+    use crate::policy::step_up;
+    use crate::sender::EventSink;
+    use crate::tee::TeeOperation;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_security_key_confirms_step_up() {
+        let _guard = webauthn::tests::setup();
+        let events: EventSink = Arc::new(|_, _| {});
+        let (origin, _) = webauthn::client_context().unwrap();
+        let rp_id = webauthn::get_config().active().unwrap().rp_id.clone();
+
+        // Register a credential, then move it onto the virtual security key
+        let mut authenticator = TestAuthenticator::new(origin.as_str());
+        let started = webauthn::start_registration("alice").unwrap();
+        let user_id = started["user_id"].as_str().unwrap().to_string();
+        let response = authenticator.register(&started["challenge"]);
+        webauthn::finish_registration(&user_id, &response, None).unwrap();
+        let (credential_id, credential_key) = authenticator.credential_key();
+
+        // The step-up challenge is signed by the first device that opens and holds the credential
+        let op = TeeOperation::ExportWallet(true);
+        let ceremony = step_up::start(&op).unwrap();
+        let challenge = ceremony["challenge"]["publicKey"]["challenge"].as_str().unwrap();
+        let challenge = general_purpose::URL_SAFE_NO_PAD.decode(challenge).unwrap();
+        let devices = vec![
+            ("/dev/hidraw9".to_string(), Err(IoError::from(std::io::ErrorKind::PermissionDenied))),
+            ("virtual".to_string(), Ok(VirtualSecurityKey::new(&rp_id, credential_id, credential_key))),
+        ];
+        let assertion = sign_with_devices(devices, &challenge, Some(TEST_PIN)).unwrap();
+        step_up::finish(ceremony["ceremony_id"].as_str().unwrap(), &serde_json::to_string(&assertion).unwrap(), &events).unwrap();
+        assert!(step_up::take_grant(&op));
+
+        // Without a device there is nothing to sign with
+        let none: Vec<(String, std::io::Result<VirtualSecurityKey>)> = Vec::new();
+        assert!(matches!(sign_with_devices(none, &challenge, Some(TEST_PIN)), Err(PasskeyError::DeviceError(_))));
+    }
+}
@@ -0,0 +1,179 @@
+// Software Passkey Authenticator
+// Passkey backend for headless Linux boxes and CI without authenticator hardware: a
+// webauthn-authenticator-rs soft token answers the registration and authentication
+// ceremonies of the in-process WebAuthn instance. Its state (attestation CA, credential keys
+// and counter) is kept in a CBOR file in the app data directory so the same passkeys keep
+// working across restarts. The keys are not protected in any way; this is for test and
+// headless setups only.
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use webauthn_authenticator_rs::softtoken::SoftToken;
+use webauthn_authenticator_rs::AuthenticatorBackend;
+use webauthn_rs::prelude::{CreationChallengeResponse, RequestChallengeResponse};
+use webauthn_rs_core::proto::ResidentKeyRequirement;
+
+use super::webauthn::{self, CredentialAuthorization};
+use crate::sender::EventSink;
+use crate::storage;
+
+// Constants
+pub const SOFT_TOKEN_FILE: &str = "soft-token.cbor";
+const SOFT_PASSKEY_LABEL: &str = "Software passkey";
+
+// Soft authenticator of the app data directory, opened on first use
+static SOFT_AUTHENTICATOR: Lazy<Mutex<Option<SoftAuthenticator>>> = Lazy::new(|| Mutex::new(None));
+
+/// Soft token and the file its state is stored in
+pub struct SoftAuthenticator {
+    path: PathBuf,
+    token: SoftToken,
+}
+
+impl SoftAuthenticator {
+    /// Soft token stored at `path`, created and saved if the file does not exist yet
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        if path.exists() {
+            let content = std::fs::read(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let token = SoftToken::from_cbor(&content)
+                .map_err(|e| format!("Invalid soft token file {}: {:?}", path.display(), e))?;
+            return Ok(Self { path, token });
+        }
+        // Report user verification; there is nobody at the machine to verify
+        let (token, _) = SoftToken::new(true).map_err(|e| format!("Failed to create soft token: {:?}", e))?;
+        let authenticator = Self { path, token };
+        authenticator.save()?;
+        Ok(authenticator)
+    }
+
+    /// soft-token.cbor in the app data directory
+    pub fn in_data_dir() -> Result<Self, String> {
+        let dir = storage::data_dir().map_err(|e| format!("Failed to open data directory: {}", e))?;
+        Self::open(dir.join(SOFT_TOKEN_FILE))
+    }
+
+    /// Register a soft passkey for `username`, added as another device if the user exists;
+    /// adding a device needs one of the user's soft passkeys on this token
+    pub fn register(&mut self, username: &str, events: &EventSink) -> Result<Value, String> {
+        let started = match webauthn::find_user(username) {
+            Some(user_id) => {
+                let ceremony = webauthn::start_add_device_authorization(&user_id.to_string())?;
+                let (ceremony_id, response) = self.assert(&ceremony)?;
+                let authorization = CredentialAuthorization { ceremony_id, response, events: events.clone() };
+                webauthn::start_add_device(&user_id.to_string(), &authorization)?
+            }
+            None => webauthn::start_registration(username)?,
+        };
+        let user_id = started["user_id"].as_str().unwrap_or_default().to_string();
+        let mut ccr: CreationChallengeResponse = serde_json::from_value(started["challenge"].clone())
+            .map_err(|e| format!("Invalid registration challenge: {}", e))?;
+        // Soft tokens cannot store discoverable credentials, so these passkeys sign in by user ID
+        if let Some(selection) = ccr.public_key.authenticator_selection.as_mut() {
+            selection.resident_key = Some(ResidentKeyRequirement::Discouraged);
+            selection.require_resident_key = false;
+        }
+
+        let (origin, timeout_ms) = webauthn::client_context()?;
+        let credential = self.token.perform_register(origin, ccr.public_key, timeout_ms)
+            .map_err(|e| format!("Soft passkey registration failed: {:?}", e))?;
+        // The key has to be on disk before the relying party relies on it
+        self.save()?;
+
+        let response = serde_json::to_string(&credential)
+            .map_err(|e| format!("Failed to serialize registration response: {}", e))?;
+        webauthn::finish_registration(&user_id, &response, Some(SOFT_PASSKEY_LABEL))
+    }
+
+    /// Sign in as `user_id` with one of the soft passkeys registered for the user
+    pub fn authenticate(&mut self, user_id: &str, events: &EventSink) -> Result<Value, String> {
+        let ceremony = webauthn::start_authentication(Some(user_id))?;
+        let (ceremony_id, response) = self.assert(&ceremony)?;
+        webauthn::finish_authentication(&ceremony_id, &response, events)
+    }
+
+    // Sign the challenge of an authentication ceremony, returning its ID and the assertion
+    fn assert(&mut self, ceremony: &Value) -> Result<(String, String), String> {
+        let ceremony_id = ceremony["ceremony_id"].as_str().unwrap_or_default().to_string();
+        let rcr: RequestChallengeResponse = serde_json::from_value(ceremony["challenge"].clone())
+            .map_err(|e| format!("Invalid authentication challenge: {}", e))?;
+
+        let (origin, timeout_ms) = webauthn::client_context()?;
+        let assertion = self.token.perform_auth(origin, rcr.public_key, timeout_ms)
+            .map_err(|e| format!("Soft passkey authentication failed: {:?}", e))?;
+        self.save()?;
+
+        let response = serde_json::to_string(&assertion)
+            .map_err(|e| format!("Failed to serialize authentication response: {}", e))?;
+        Ok((ceremony_id, response))
+    }
+
+    // Written to a temporary file and renamed into place, like the passkey store
+    fn save(&self) -> Result<(), String> {
+        let content = self.token.to_cbor()
+            .map_err(|e| format!("Failed to serialize soft token: {:?}", e))?;
+        let tmp_path = self.path.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&tmp_path)?;
+            file.write_all(&content)?;
+            file.sync_all()?;
+            std::fs::rename(&tmp_path, &self.path)
+        };
+        write().map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+fn with_authenticator<T>(run: impl FnOnce(&mut SoftAuthenticator) -> Result<T, String>) -> Result<T, String> {
+    let mut authenticator = SOFT_AUTHENTICATOR.lock().unwrap_or_else(|p| p.into_inner());
+    if authenticator.is_none() {
+        *authenticator = Some(SoftAuthenticator::in_data_dir()?);
+    }
+    run(authenticator.as_mut().expect("soft authenticator opened"))
+}
+
+/// Register a soft passkey for `username` with the app's soft authenticator
+pub fn register(username: &str, events: &EventSink) -> Result<Value, String> {
+    println!("COS72-Tauri: Registering soft passkey for {}", username);
+    with_authenticator(|authenticator| authenticator.register(username, events))
+}
+
+/// Authenticate `user_id` with the app's soft authenticator
+pub fn authenticate(user_id: &str, events: &EventSink) -> Result<Value, String> {
+    println!("COS72-Tauri: Authenticating {} with soft passkey", user_id);
+    with_authenticator(|authenticator| authenticator.authenticate(user_id, events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_soft_passkey_ceremonies_survive_restart() {
+        let _guard = webauthn::tests::setup();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SOFT_TOKEN_FILE);
+        let events: EventSink = Arc::new(|_, _| {});
+
+        let mut authenticator = SoftAuthenticator::open(path.clone()).unwrap();
+        let registered = authenticator.register("ci-node", &events).unwrap();
+        let user_id = registered["user_id"].as_str().unwrap().to_string();
+        assert_eq!(registered["label"], SOFT_PASSKEY_LABEL);
+        assert!(path.exists());
+        let result = authenticator.authenticate(&user_id, &events).unwrap();
+        assert_eq!(result["user_id"], user_id.as_str());
+
+        // A new process reads the same keys from the file
+        let mut reopened = SoftAuthenticator::open(path).unwrap();
+        let result = reopened.authenticate(&user_id, &events).unwrap();
+        assert_eq!(result["credential_id"], registered["credential_id"]);
+
+        // Registering again adds a device to the existing user, authorized by the first passkey
+        let added = reopened.register("ci-node", &events).unwrap();
+        assert_eq!(added["user_id"], user_id.as_str());
+        assert_eq!(webauthn::get_credentials(&user_id).unwrap().as_array().unwrap().len(), 2);
+    }
+}
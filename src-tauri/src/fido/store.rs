@@ -0,0 +1,300 @@
+// Passkey Credential Store
+// Registered WebAuthn users and their passkeys behind a storage trait: a versioned JSON
+// file in the app data directory, replaced atomically on every change, and an in-memory
+// store for tests
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use uuid::Uuid;
+use webauthn_rs::prelude::{AuthenticationResult, Passkey};
+
+use super::attestation::AttestedAuthenticator;
+use crate::storage;
+
+// Constants
+/// Version of the passkey file layout, stored in the file
+pub const PASSKEY_SCHEMA_VERSION: u32 = 1;
+const PASSKEYS_FILE: &str = "passkeys.json";
+
+/// WebAuthn user entity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyUser {
+    /// User handle sent to authenticators
+    pub id: Uuid,
+    pub username: String,
+    pub display_name: String,
+    pub created_at: String,
+}
+
+/// Registered passkey with the metadata shown to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredCredential {
+    /// Base64url credential ID
+    pub credential_id: String,
+    pub user_id: Uuid,
+    pub label: String,
+    pub passkey: Passkey,
+    /// Signature counter last reported by the authenticator, 0 if it keeps none
+    pub counter: u32,
+    pub backup_eligible: bool,
+    pub backup_state: bool,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    /// Set when the credential may be cloned; locked credentials cannot authenticate
+    #[serde(default)]
+    pub locked: Option<CredentialLock>,
+    /// Authenticator model, if the credential was registered with verified attestation
+    #[serde(default)]
+    pub attestation: Option<AttestedAuthenticator>,
+}
+
+/// Why and when a credential was locked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CredentialLock {
+    pub reason: String,
+    pub locked_at: String,
+}
+
+impl StoredCredential {
+    /// Counter and backup flags are read from the passkey as registered
+    pub fn new(user_id: Uuid, label: &str, passkey: Passkey) -> Self {
+        let credential = serde_json::to_value(&passkey).map(|value| value["cred"].clone()).unwrap_or(Value::Null);
+        Self {
+            credential_id: super::webauthn::credential_id_to_string(passkey.cred_id()),
+            user_id,
+            label: label.to_string(),
+            counter: credential["counter"].as_u64().and_then(|counter| u32::try_from(counter).ok()).unwrap_or(0),
+            backup_eligible: credential["backup_eligible"].as_bool().unwrap_or(false),
+            backup_state: credential["backup_state"].as_bool().unwrap_or(false),
+            passkey,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            last_used_at: None,
+            locked: None,
+            attestation: None,
+        }
+    }
+
+    /// Take over the counter and backup flags reported by an authentication
+    pub fn record_authentication(&mut self, result: &AuthenticationResult) {
+        self.passkey.update_credential(result);
+        self.counter = self.counter.max(result.counter());
+        self.backup_eligible |= result.backup_eligible();
+        self.backup_state = result.backup_state();
+        self.last_used_at = Some(chrono::Utc::now().to_rfc3339());
+    }
+}
+
+/// Credential metadata shown to the user, without the key material
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CredentialInfo {
+    pub credential_id: String,
+    pub user_id: Uuid,
+    pub label: String,
+    pub counter: u32,
+    pub backup_eligible: bool,
+    pub backup_state: bool,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub locked: Option<CredentialLock>,
+    pub attestation: Option<AttestedAuthenticator>,
+}
+
+impl From<&StoredCredential> for CredentialInfo {
+    fn from(credential: &StoredCredential) -> Self {
+        Self {
+            credential_id: credential.credential_id.clone(),
+            user_id: credential.user_id,
+            label: credential.label.clone(),
+            counter: credential.counter,
+            backup_eligible: credential.backup_eligible,
+            backup_state: credential.backup_state,
+            created_at: credential.created_at.clone(),
+            last_used_at: credential.last_used_at.clone(),
+            locked: credential.locked.clone(),
+            attestation: credential.attestation.clone(),
+        }
+    }
+}
+
+/// Everything the store holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyData {
+    pub version: u32,
+    pub users: Vec<PasskeyUser>,
+    pub credentials: Vec<StoredCredential>,
+}
+
+impl Default for PasskeyData {
+    fn default() -> Self {
+        Self { version: PASSKEY_SCHEMA_VERSION, users: Vec::new(), credentials: Vec::new() }
+    }
+}
+
+impl PasskeyData {
+    pub fn user(&self, user_id: Uuid) -> Option<&PasskeyUser> {
+        self.users.iter().find(|user| user.id == user_id)
+    }
+
+    pub fn user_by_name(&self, username: &str) -> Option<&PasskeyUser> {
+        self.users.iter().find(|user| user.username == username)
+    }
+
+    pub fn credentials_of(&self, user_id: Uuid) -> impl Iterator<Item = &StoredCredential> {
+        self.credentials.iter().filter(move |credential| credential.user_id == user_id)
+    }
+
+    pub fn credential_mut(&mut self, credential_id: &str) -> Option<&mut StoredCredential> {
+        self.credentials.iter_mut().find(|credential| credential.credential_id == credential_id)
+    }
+
+    // Bring data written by an older version to the current layout
+    fn migrate(value: Value) -> Result<Self, String> {
+        let version = value.get("version").and_then(Value::as_u64)
+            .ok_or_else(|| "Passkey store has no schema version".to_string())?;
+        if version > u64::from(PASSKEY_SCHEMA_VERSION) {
+            return Err(format!(
+                "Passkey store has schema version {}, this version of the app supports up to {}",
+                version, PASSKEY_SCHEMA_VERSION
+            ));
+        }
+        serde_json::from_value(value).map_err(|e| format!("Invalid passkey store: {}", e))
+    }
+}
+
+/// Persistence of users and passkeys
+pub trait PasskeyStore: Send + Sync {
+    /// Stored data, empty if nothing was stored yet
+    fn load(&self) -> Result<PasskeyData, String>;
+    /// Replace the stored data
+    fn save(&self, data: &PasskeyData) -> Result<(), String>;
+}
+
+/// Store in a JSON file, written to a temporary file and renamed into place
+pub struct FilePasskeyStore {
+    path: PathBuf,
+}
+
+impl FilePasskeyStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// passkeys.json in the app data directory
+    pub fn in_data_dir() -> Result<Self, String> {
+        let dir = storage::data_dir().map_err(|e| format!("Failed to open data directory: {}", e))?;
+        Ok(Self::new(dir.join(PASSKEYS_FILE)))
+    }
+}
+
+impl PasskeyStore for FilePasskeyStore {
+    fn load(&self) -> Result<PasskeyData, String> {
+        if !self.path.exists() {
+            return Ok(PasskeyData::default());
+        }
+        let content = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+        let value = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid JSON in {}: {}", self.path.display(), e))?;
+        PasskeyData::migrate(value)
+    }
+
+    fn save(&self, data: &PasskeyData) -> Result<(), String> {
+        storage::save_json_at(&self.path, data).map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+/// Store that keeps everything in memory
+#[derive(Default)]
+pub struct MemoryPasskeyStore {
+    data: Mutex<PasskeyData>,
+}
+
+impl PasskeyStore for MemoryPasskeyStore {
+    fn load(&self) -> Result<PasskeyData, String> {
+        Ok(self.data.lock().unwrap_or_else(|p| p.into_inner()).clone())
+    }
+
+    fn save(&self, data: &PasskeyData) -> Result<(), String> {
+        *self.data.lock().unwrap_or_else(|p| p.into_inner()) = data.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Passkey with an ES256 key as stored after a registration without attestation
+    pub(crate) fn test_passkey(credential_id: &[u8], counter: u32) -> Passkey {
+        serde_json::from_value(json!({
+            "cred": {
+                "cred_id": base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, credential_id),
+                "cred": {
+                    "type_": "ES256",
+                    "key": { "EC_EC2": { "curve": "SECP256R1", "x": vec![1u8; 32], "y": vec![2u8; 32] } },
+                },
+                "counter": counter,
+                "transports": null,
+                "user_verified": true,
+                "backup_eligible": true,
+                "backup_state": false,
+                "registration_policy": "required",
+                "extensions": {},
+                "attestation": { "data": "None", "metadata": "None" },
+                "attestation_format": "none",
+            }
+        })).expect("valid passkey")
+    }
+
+    fn sample_data() -> PasskeyData {
+        let user = PasskeyUser {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            display_name: "Alice".to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let credential = StoredCredential::new(user.id, "Laptop", test_passkey(&[7; 16], 3));
+        PasskeyData { users: vec![user], credentials: vec![credential], ..Default::default() }
+    }
+
+    #[test]
+    fn test_credential_metadata_from_passkey() {
+        let credential = StoredCredential::new(Uuid::new_v4(), "Phone", test_passkey(&[1, 2, 3], 9));
+        assert_eq!(credential.credential_id, "AQID");
+        assert_eq!(credential.counter, 9);
+        assert!(credential.backup_eligible && !credential.backup_state);
+    }
+
+    #[test]
+    fn test_file_store_round_trip_and_versioning() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PASSKEYS_FILE);
+        let store = FilePasskeyStore::new(path.clone());
+        assert!(store.load().unwrap().users.is_empty());
+
+        let data = sample_data();
+        store.save(&data).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.users, data.users);
+        assert_eq!(loaded.credentials[0].passkey, data.credentials[0].passkey);
+        assert_eq!(loaded.credentials_of(data.users[0].id).count(), 1);
+
+        // Files from a newer version are not overwritten with older data
+        let mut newer = serde_json::to_value(&data).unwrap();
+        newer["version"] = json!(PASSKEY_SCHEMA_VERSION + 1);
+        std::fs::write(&path, newer.to_string()).unwrap();
+        assert!(store.load().unwrap_err().contains("schema version"));
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryPasskeyStore::default();
+        let data = sample_data();
+        store.save(&data).unwrap();
+        assert_eq!(store.load().unwrap().users, data.users);
+    }
+}
@@ -0,0 +1,270 @@
+// Test Authenticator
+// Virtual platform authenticator for tests. Creates ES256 credentials with "none" or
+// "packed" attestation and answers assertion requests, producing the JSON a browser hands
+// to the backend after navigator.credentials.create/get.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, BigNumContext, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use openssl::x509::extension::{BasicConstraints, KeyUsage};
+use openssl::x509::{X509Builder, X509NameBuilder, X509};
+use serde_cbor_2::Value as CborValue;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+// Authenticator data flags
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_BACKUP_ELIGIBLE: u8 = 0x08;
+const FLAG_BACKUP_STATE: u8 = 0x10;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+struct VirtualCredential {
+    id: Vec<u8>,
+    rp_id: String,
+    user_handle: Vec<u8>,
+    key: EcKey<Private>,
+    counter: u32,
+}
+
+pub(crate) struct TestAuthenticator {
+    origin: String,
+    credentials: Vec<VirtualCredential>,
+    pub backup_eligible: bool,
+    pub backup_state: bool,
+    /// Added to a credential's counter on every assertion; 0 for authenticators without one
+    pub counter_step: u32,
+    // Model and attestation key and certificate for packed attestation
+    attestation: Option<(Uuid, PKey<Private>, X509)>,
+}
+
+impl TestAuthenticator {
+    /// Authenticator used by a client on `origin`
+    pub fn new(origin: &str) -> Self {
+        Self { origin: origin.to_string(), credentials: Vec::new(), backup_eligible: false, backup_state: false, counter_step: 1, attestation: None }
+    }
+
+    /// Attest new credentials as model `aaguid` with a certificate issued by `ca`
+    pub fn with_attestation(mut self, ca: &TestCertificateAuthority, aaguid: Uuid) -> Self {
+        let (key, certificate) = ca.issue("Test Authenticator");
+        self.attestation = Some((aaguid, key, certificate));
+        self
+    }
+
+    /// Answer creation options (the `publicKey` wrapper as returned by webauthn-rs)
+    pub fn register(&mut self, ccr: &Value) -> String {
+        let options = &ccr["publicKey"];
+        let rp_id = options["rp"]["id"].as_str().expect("rp id").to_string();
+        let user_handle = decode(&options["user"]["id"]);
+        let mut id = vec![0u8; 16];
+        getrandom::getrandom(&mut id).unwrap();
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = EcKey::generate(&group).unwrap();
+
+        let client_data = self.client_data("webauthn.create", &options["challenge"]);
+        let mut auth_data = self.auth_data(&rp_id, FLAG_ATTESTED_CREDENTIAL_DATA, 0);
+        let aaguid = self.attestation.as_ref().map_or(Uuid::nil(), |(aaguid, _, _)| *aaguid);
+        auth_data.extend_from_slice(aaguid.as_bytes());
+        auth_data.extend_from_slice(&(id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&id);
+        auth_data.extend_from_slice(&cose_key(&key));
+
+        let (fmt, statement) = match &self.attestation {
+            // The attestation key signs the authenticator data and the client data hash
+            Some((_, attestation_key, certificate)) => {
+                let signature = sign(attestation_key, &[&auth_data, &Sha256::digest(&client_data)]);
+                ("packed", cbor_map(vec![
+                    (CborValue::Text("alg".to_string()), CborValue::Integer(-7)),
+                    (CborValue::Text("sig".to_string()), CborValue::Bytes(signature)),
+                    (CborValue::Text("x5c".to_string()), CborValue::Array(vec![CborValue::Bytes(certificate.to_der().unwrap())])),
+                ]))
+            }
+            None => ("none", CborValue::Map(BTreeMap::new())),
+        };
+        let attestation_object = cbor_map(vec![
+            (CborValue::Text("fmt".to_string()), CborValue::Text(fmt.to_string())),
+            (CborValue::Text("attStmt".to_string()), statement),
+            (CborValue::Text("authData".to_string()), CborValue::Bytes(auth_data)),
+        ]);
+
+        let response = json!({
+            "id": URL_SAFE_NO_PAD.encode(&id),
+            "rawId": URL_SAFE_NO_PAD.encode(&id),
+            "response": {
+                "attestationObject": URL_SAFE_NO_PAD.encode(serde_cbor_2::to_vec(&attestation_object).unwrap()),
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(&client_data),
+                "transports": ["internal"],
+            },
+            "type": "public-key",
+            "extensions": {},
+        });
+        self.credentials.push(VirtualCredential { id, rp_id, user_handle, key, counter: 0 });
+        response.to_string()
+    }
+
+    /// Answer request options with the first credential they allow, or any credential for
+    /// the RP if none are listed (discoverable credentials)
+    pub fn authenticate(&mut self, rcr: &Value) -> String {
+        let options = &rcr["publicKey"];
+        let rp_id = options["rpId"].as_str().expect("rp id");
+        let allowed: Vec<Vec<u8>> = options["allowCredentials"].as_array().map(|list| {
+            list.iter().map(|credential| decode(&credential["id"])).collect()
+        }).unwrap_or_default();
+        let index = self.credentials.iter()
+            .position(|credential| credential.rp_id == rp_id && (allowed.is_empty() || allowed.contains(&credential.id)))
+            .expect("no matching credential");
+
+        let client_data = self.client_data("webauthn.get", &options["challenge"]);
+        let counter = self.credentials[index].counter.wrapping_add(self.counter_step);
+        self.credentials[index].counter = counter;
+        let auth_data = self.auth_data(rp_id, 0, counter);
+
+        let credential = &self.credentials[index];
+        let pkey = PKey::from_ec_key(credential.key.clone()).unwrap();
+        let signature = sign(&pkey, &[&auth_data, &Sha256::digest(&client_data)]);
+
+        json!({
+            "id": URL_SAFE_NO_PAD.encode(&credential.id),
+            "rawId": URL_SAFE_NO_PAD.encode(&credential.id),
+            "response": {
+                "authenticatorData": URL_SAFE_NO_PAD.encode(&auth_data),
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(&client_data),
+                "signature": URL_SAFE_NO_PAD.encode(&signature),
+                "userHandle": URL_SAFE_NO_PAD.encode(&credential.user_handle),
+            },
+            "type": "public-key",
+            "extensions": {},
+        }).to_string()
+    }
+
+    /// ID and key of the most recently registered credential, to put it on another authenticator
+    pub fn credential_key(&self) -> (Vec<u8>, EcKey<Private>) {
+        let credential = self.credentials.last().expect("no registered credential");
+        (credential.id.clone(), credential.key.clone())
+    }
+
+    /// Set the signature counter of every credential, e.g. to simulate a cloned authenticator
+    pub fn set_counter(&mut self, counter: u32) {
+        for credential in &mut self.credentials {
+            credential.counter = counter;
+        }
+    }
+
+    fn client_data(&self, type_: &str, challenge: &Value) -> Vec<u8> {
+        json!({
+            "type": type_,
+            "challenge": challenge.as_str().expect("challenge"),
+            "origin": self.origin,
+            "crossOrigin": false,
+        }).to_string().into_bytes()
+    }
+
+    fn auth_data(&self, rp_id: &str, flags: u8, counter: u32) -> Vec<u8> {
+        let mut flags = flags | FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+        if self.backup_eligible {
+            flags |= FLAG_BACKUP_ELIGIBLE;
+        }
+        if self.backup_state {
+            flags |= FLAG_BACKUP_STATE;
+        }
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&counter.to_be_bytes());
+        data
+    }
+}
+
+/// Certificate authority for attestation and metadata signing certificates
+pub(crate) struct TestCertificateAuthority {
+    key: PKey<Private>,
+    pub certificate: X509,
+}
+
+impl TestCertificateAuthority {
+    /// Self-signed root named `name`
+    pub fn new(name: &str) -> Self {
+        let key = new_key();
+        let mut builder = certificate_builder(name, &key);
+        builder.append_extension(BasicConstraints::new().critical().ca().build().unwrap()).unwrap();
+        builder.append_extension(KeyUsage::new().critical().key_cert_sign().build().unwrap()).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        Self { key, certificate: builder.build() }
+    }
+
+    /// End-entity key and certificate named `name`, with the subject an attestation
+    /// certificate needs
+    pub fn issue(&self, name: &str) -> (PKey<Private>, X509) {
+        let key = new_key();
+        let mut builder = certificate_builder(name, &key);
+        builder.append_extension(BasicConstraints::new().build().unwrap()).unwrap();
+        builder.set_issuer_name(self.certificate.subject_name()).unwrap();
+        builder.sign(&self.key, MessageDigest::sha256()).unwrap();
+        (key, builder.build())
+    }
+}
+
+fn new_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+// Self-signed X.509 v3 certificate valid for a year
+fn certificate_builder(name: &str, key: &PKey<Private>) -> X509Builder {
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_text("C", "SG").unwrap();
+    subject.append_entry_by_text("O", "COS72 Test").unwrap();
+    subject.append_entry_by_text("OU", "Authenticator Attestation").unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let subject = subject.build();
+
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&serial.to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_issuer_name(&subject).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(365).unwrap()).unwrap();
+    builder
+}
+
+fn sign(key: &PKey<Private>, parts: &[&[u8]]) -> Vec<u8> {
+    let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+    for part in parts {
+        signer.update(part).unwrap();
+    }
+    signer.sign_to_vec().unwrap()
+}
+
+fn decode(value: &Value) -> Vec<u8> {
+    URL_SAFE_NO_PAD.decode(value.as_str().expect("base64url value")).expect("valid base64url")
+}
+
+fn cbor_map(entries: Vec<(CborValue, CborValue)>) -> CborValue {
+    CborValue::Map(entries.into_iter().collect())
+}
+
+// EC2 public key in COSE format, ES256
+fn cose_key(key: &EcKey<Private>) -> Vec<u8> {
+    let mut ctx = BigNumContext::new().unwrap();
+    let mut x = openssl::bn::BigNum::new().unwrap();
+    let mut y = openssl::bn::BigNum::new().unwrap();
+    key.public_key().affine_coordinates(key.group(), &mut x, &mut y, &mut ctx).unwrap();
+    let cose = cbor_map(vec![
+        (CborValue::Integer(1), CborValue::Integer(2)),
+        (CborValue::Integer(3), CborValue::Integer(-7)),
+        (CborValue::Integer(-1), CborValue::Integer(1)),
+        (CborValue::Integer(-2), CborValue::Bytes(x.to_vec_padded(32).unwrap())),
+        (CborValue::Integer(-3), CborValue::Bytes(y.to_vec_padded(32).unwrap())),
+    ]);
+    serde_cbor_2::to_vec(&cose).unwrap()
+}
@@ -2,46 +2,290 @@
 // Based on WebAuthn-rs library 0.5.1 and webauthn-authenticator-rs 0.5.1
 // Implementation of FIDO2/Passkey registration and verification
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
 use base64::{Engine as _, engine::general_purpose};
 use serde_json::Value;
-use url::Url;
 use webauthn_rs::prelude::*;
 use tracing::info;
 use uuid::Uuid;
+use serde::Serialize;
 use serde_json::json;
 use webauthn_rs::Webauthn;
-use webauthn_rs_core::proto::UserVerificationPolicy;
+use webauthn_rs_core::proto::{AllowCredentials, PublicKeyCredentialRequestOptions, ResidentKeyRequirement, UserVerificationPolicy};
 
-// Global registration state store (for testing only)
-// In a real application, this would be persisted in a database
+use crate::policy;
+use crate::sender::EventSink;
+use super::attestation::{AttestedAuthenticator, TrustedMetadata};
+use super::config::{WebauthnConfig, WEBAUTHN_CONFIG_FILE};
+use super::store::{CredentialInfo, CredentialLock, FilePasskeyStore, MemoryPasskeyStore, PasskeyData, PasskeyStore, PasskeyUser, StoredCredential};
+
+// Longest credential label
+const MAX_LABEL_LENGTH: usize = 64;
+
+// How long an authentication ceremony can be finished after it was started
+const AUTHENTICATION_TTL: Duration = Duration::from_secs(120);
+
+// Authentication state waiting for the client's assertion
+struct PendingAuthentication {
+    state: CeremonyState,
+    expires_at: Instant,
+    // Change the ceremony authorizes besides signing in
+    authorizes: Option<Authorizes>,
+}
+
+// Changes that need an assertion by one of the user's passkeys
+#[derive(Debug, Clone, PartialEq)]
+enum Authorizes {
+    // Renaming, unlocking or revoking a credential; the assertion must come from another one
+    CredentialChange(String),
+    // Registering another device for a user
+    AddDevice(Uuid),
+}
+
+// Registration state; attested registrations only accept authenticators the policy trusts
+#[derive(Debug, Clone)]
+enum RegistrationState {
+    Passkey(PasskeyRegistration),
+    Attested(AttestedPasskeyRegistration),
+}
+
+enum CeremonyState {
+    // Assertion by one of the listed credentials
+    Passkey(PasskeyAuthentication),
+    // Usernameless: the authenticator picks a discoverable credential and returns its user handle
+    Discoverable(DiscoverableAuthentication),
+}
+
+/// Event emitted when an authenticator reports a signature counter that went backwards
+pub const EVENT_PASSKEY_COUNTER_REGRESSION: &str = "passkey-counter-regression";
+
+/// Counter regression of a credential, a sign that the authenticator may have been cloned
+#[derive(Debug, Clone, Serialize)]
+pub struct CounterRegression {
+    pub credential_id: String,
+    pub user_id: Uuid,
+    /// Highest counter seen so far
+    pub stored_counter: u32,
+    /// Counter in the rejected assertion
+    pub reported_counter: u32,
+}
+
+/// Response to a counter regression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegressionAction {
+    /// Lock the credential until it is unlocked or revoked
+    Lock,
+    /// Only reject the assertion and report it
+    Report,
+}
+
+/// Assertion of a `start_credential_change` or `start_add_device_authorization` ceremony,
+/// required to rename, unlock or revoke a credential or to add a device
+pub struct CredentialAuthorization {
+    pub ceremony_id: String,
+    pub response: String,
+    pub events: EventSink,
+}
+
+/// Decides how to respond to a counter regression
+pub type CounterRegressionPolicy = Box<dyn Fn(&CounterRegression) -> RegressionAction + Send + Sync>;
+
+// Global registration state store, keyed by user ID with the username being registered
+// Authentication states are keyed by ceremony ID and used at most once
+// Registered users and passkeys live in the passkey store
 lazy_static::lazy_static! {
-    static ref REGISTRATION_STATES: Mutex<HashMap<String, PasskeyRegistration>> = Mutex::new(HashMap::new());
-    static ref REGISTERED_PASSKEYS: Mutex<HashMap<String, Passkey>> = Mutex::new(HashMap::new());
-
-    // WebAuthn context
-    static ref WEBAUTHN_INSTANCE: Webauthn = {
-        // Create valid configuration for RP (Relying Party/Server)
-        // Empty rp_id will use effective domain 
-        let rp_id = "";  // Empty to let browser use current domain
-        let rp_origin = Url::parse("https://cos72.app").expect("Invalid URL");
-        
-        // Create WebAuthn builder
-        let builder = WebauthnBuilder::new(rp_id, &rp_origin)
-            .expect("Failed to create WebAuthn builder")
-            // Set RP name
-            .rp_name("COS72-Tauri")
-            // Timeout setting
-            .timeout(Duration::from_secs(60));
-
-        builder.build().expect("Failed to build WebAuthn")
-    };
+    static ref REGISTRATION_STATES: Mutex<HashMap<String, (String, RegistrationState)>> = Mutex::new(HashMap::new());
+    static ref AUTHENTICATION_STATES: Mutex<HashMap<String, PendingAuthentication>> = Mutex::new(HashMap::new());
+    static ref PASSKEY_REGISTRY: Mutex<PasskeyRegistry> = Mutex::new(PasskeyRegistry::open());
+
+    static ref COUNTER_REGRESSION_POLICY: RwLock<CounterRegressionPolicy> = RwLock::new(default_counter_regression_policy());
+
+    // WebAuthn context, rebuilt when the relying party configuration changes
+    static ref RELYING_PARTY: RwLock<RelyingParty> = RwLock::new(RelyingParty::load());
+}
+
+// Relying party configuration and the WebAuthn instance built from its active profile
+struct RelyingParty {
+    config: WebauthnConfig,
+    instance: Arc<Webauthn>,
+    // Authenticators trusted when the active profile requires attestation, or why the
+    // metadata could not be loaded; registrations are refused until it can
+    attestation: Option<Result<Arc<TrustedMetadata>, String>>,
+}
+
+impl RelyingParty {
+    fn load() -> Self {
+        let config = match crate::storage::load_json::<WebauthnConfig>(WEBAUTHN_CONFIG_FILE) {
+            Ok(config) => config.unwrap_or_default(),
+            Err(e) => {
+                println!("COS72-Tauri: Failed to load WebAuthn configuration, using defaults: {}", e);
+                WebauthnConfig::default()
+            }
+        };
+        match Self::build(config) {
+            Ok(relying_party) => {
+                if let Some(Err(e)) = &relying_party.attestation {
+                    println!("COS72-Tauri: Attestation metadata unavailable, registration is disabled: {}", e);
+                }
+                relying_party
+            }
+            Err(e) => {
+                println!("COS72-Tauri: Invalid WebAuthn configuration, using defaults: {}", e);
+                Self::build(WebauthnConfig::default()).expect("default WebAuthn configuration is valid")
+            }
+        }
+    }
+
+    fn build(config: WebauthnConfig) -> Result<Self, String> {
+        config.validate()?;
+        let profile = config.active()?;
+        let instance = Arc::new(profile.build()?);
+        let attestation = profile.attestation.as_ref().map(|policy| policy.load().map(Arc::new));
+        Ok(Self { config, instance, attestation })
+    }
+}
+
+// WebAuthn instance of the active relying party profile
+fn webauthn() -> Arc<Webauthn> {
+    RELYING_PARTY.read().unwrap_or_else(|p| p.into_inner()).instance.clone()
+}
+
+// Trusted authenticators if the active profile requires attestation
+fn attestation_trust() -> Result<Option<Arc<TrustedMetadata>>, String> {
+    match &RELYING_PARTY.read().unwrap_or_else(|p| p.into_inner()).attestation {
+        Some(Ok(trusted)) => Ok(Some(trusted.clone())),
+        Some(Err(e)) => Err(format!("Attestation metadata unavailable: {}", e)),
+        None => Ok(None),
+    }
+}
+
+/// Current relying party configuration
+pub fn get_config() -> WebauthnConfig {
+    RELYING_PARTY.read().unwrap_or_else(|p| p.into_inner()).config.clone()
+}
+
+/// Replace the relying party configuration and rebuild the WebAuthn instance
+///
+/// Registrations and authentications in progress were started for the previous relying
+/// party and are dropped. Refused in server mode, where the operator manages the
+/// configuration file.
+pub fn set_config(config: WebauthnConfig) -> Result<(), String> {
+    if policy::is_server_mode() {
+        return Err("WebAuthn configuration cannot be changed by clients in server mode".to_string());
+    }
+    let relying_party = RelyingParty::build(config)?;
+    if let Some(Err(e)) = &relying_party.attestation {
+        return Err(format!("Failed to load attestation metadata: {}", e));
+    }
+    crate::storage::save_json(WEBAUTHN_CONFIG_FILE, &relying_party.config)
+        .map_err(|e| format!("Failed to save WebAuthn configuration: {}", e))?;
+
+    let mut current = RELYING_PARTY.write().unwrap_or_else(|p| p.into_inner());
+    *current = relying_party;
+    REGISTRATION_STATES.lock().unwrap_or_else(|p| p.into_inner()).clear();
+    AUTHENTICATION_STATES.lock().unwrap_or_else(|p| p.into_inner()).clear();
+    println!("COS72-Tauri: WebAuthn relying party set to profile {}", current.config.active_profile);
+    Ok(())
+}
+
+/// Read the active profile's metadata blob again, e.g. after a newer one was downloaded;
+/// refused in server mode like `set_config`
+pub fn reload_attestation_metadata() -> Result<Value, String> {
+    if policy::is_server_mode() {
+        return Err("Attestation metadata cannot be reloaded by clients in server mode".to_string());
+    }
+    let mut relying_party = RELYING_PARTY.write().unwrap_or_else(|p| p.into_inner());
+    let policy = relying_party.config.active()?.attestation.clone()
+        .ok_or_else(|| format!("Profile {} has no attestation policy", relying_party.config.active_profile))?;
+    let trusted = policy.load()?;
+    let result = json!({
+        "blob_number": trusted.blob_number,
+        "next_update": trusted.next_update,
+        "authenticators": trusted.len(),
+    });
+    relying_party.attestation = Some(Ok(Arc::new(trusted)));
+    Ok(result)
+}
+
+// Passkey store and the data loaded from it
+struct PasskeyRegistry {
+    store: Box<dyn PasskeyStore>,
+    data: PasskeyData,
+    // Set when the store could not be read; changes are refused so it is not overwritten
+    load_error: Option<String>,
+}
+
+impl PasskeyRegistry {
+    // File store in the app data directory
+    fn open() -> Self {
+        match FilePasskeyStore::in_data_dir() {
+            Ok(store) => Self::with_store(Box::new(store)),
+            Err(e) => {
+                println!("COS72-Tauri: Passkey store unavailable, keeping passkeys in memory: {}", e);
+                Self::with_store(Box::new(MemoryPasskeyStore::default()))
+            }
+        }
+    }
+
+    fn with_store(store: Box<dyn PasskeyStore>) -> Self {
+        match store.load() {
+            Ok(data) => Self { store, data, load_error: None },
+            Err(e) => {
+                println!("COS72-Tauri: Failed to load passkeys: {}", e);
+                Self { store, data: PasskeyData::default(), load_error: Some(e) }
+            }
+        }
+    }
+
+    // Apply a change and persist it; the loaded data only changes once it is saved
+    fn update<T>(&mut self, change: impl FnOnce(&mut PasskeyData) -> Result<T, String>) -> Result<T, String> {
+        if let Some(e) = &self.load_error {
+            return Err(format!("Passkey store could not be loaded: {}", e));
+        }
+        let mut data = self.data.clone();
+        let result = change(&mut data)?;
+        self.store.save(&data)?;
+        self.data = data;
+        Ok(result)
+    }
+}
+
+fn lock_registry() -> std::sync::MutexGuard<'static, PasskeyRegistry> {
+    PASSKEY_REGISTRY.lock().unwrap_or_else(|p| p.into_inner())
+}
+
+/// Load registered passkeys from the store, returning the number of credentials
+pub fn load_passkeys() -> Result<usize, String> {
+    let registry = lock_registry();
+    match &registry.load_error {
+        Some(e) => Err(e.clone()),
+        None => Ok(registry.data.credentials.len()),
+    }
+}
+
+/// Replace the passkey store, e.g. with an in-memory store for tests
+pub fn set_passkey_store(store: Box<dyn PasskeyStore>) -> Result<(), String> {
+    let registry = PasskeyRegistry::with_store(store);
+    let result = registry.load_error.clone().map_or(Ok(()), Err);
+    *lock_registry() = registry;
+    result
+}
+
+// Possibly cloned credentials are locked
+fn default_counter_regression_policy() -> CounterRegressionPolicy {
+    Box::new(|_| RegressionAction::Lock)
+}
+
+/// Replace the policy applied to counter regressions
+pub fn set_counter_regression_policy(policy: CounterRegressionPolicy) {
+    *COUNTER_REGRESSION_POLICY.write().unwrap_or_else(|p| p.into_inner()) = policy;
 }
 
 // Helper function to convert credential ID to string
-fn credential_id_to_string(cred_id: &webauthn_rs::prelude::CredentialID) -> String {
+pub(crate) fn credential_id_to_string(cred_id: &webauthn_rs::prelude::CredentialID) -> String {
     // Use Base64 encoding for credential ID
     general_purpose::URL_SAFE_NO_PAD.encode(cred_id.as_ref())
 }
@@ -50,29 +294,105 @@ fn credential_id_to_string(cred_id: &webauthn_rs::prelude::CredentialID) -> Stri
 pub fn start_registration(username: &str) -> Result<Value, String> {
     println!("COS72-Tauri: 开始WebAuthn注册流程，用户名: {}", username);
     
-    // 创建更安全的用户ID
-    let user_id = Uuid::new_v4();
-    println!("COS72-Tauri: 生成用户ID: {}", user_id);
+    // 同一用户名始终对应同一用户ID，新用户生成随机ID
+    let existing_user = {
+        let registry = lock_registry();
+        registry.data.user_by_name(username)
+            .map(|user| (user.id, registry.data.credentials_of(user.id).count()))
+    };
+    if let Some((user_id, count)) = existing_user.filter(|(_, count)| *count > 0) {
+        // 已有凭证的用户只能通过添加设备流程注册新凭证
+        return Err(format!("用户 {} ({}) 已注册 {} 个Passkey，请使用添加设备流程", username, user_id, count));
+    }
+    let user_id = existing_user.map(|(user_id, _)| user_id).unwrap_or_else(Uuid::new_v4);
+    println!("COS72-Tauri: 用户ID: {} (已存在: {})", user_id, existing_user.is_some());
     
+    begin_registration(user_id, username, None)
+}
+
+/// User whose passkeys confirm wallet operations: the owner pinned in the configuration, or
+/// else the first user registered on this device. Adding a device to a user needs one of the
+/// user's passkeys, so registering another user does not grant access to the wallet.
+pub fn wallet_owner() -> Result<Uuid, String> {
+    if let Some(owner) = get_config().wallet_owner {
+        return Ok(owner);
+    }
+    lock_registry().data.users.first()
+        .map(|user| user.id)
+        .ok_or_else(|| "No passkey registered for the wallet owner".to_string())
+}
+
+/// ID of the user registered under `username`
+pub fn find_user(username: &str) -> Option<Uuid> {
+    lock_registry().data.user_by_name(username).map(|user| user.id)
+}
+
+/// Start the authentication that authorizes adding a device to `user_id`: an assertion by
+/// one of the user's unlocked passkeys, so knowing the user's ID is not enough to add one
+pub fn start_add_device_authorization(user_id: &str) -> Result<Value, String> {
+    info!("COS72-Tauri: Starting authentication to add a device, user ID: {}", user_id);
+    let uuid = Uuid::parse_str(user_id)
+        .map_err(|e| format!("Failed to parse user_id as UUID: {}", e))?;
+    begin_authentication(Some(user_id), Some(&Authorizes::AddDevice(uuid)))
+}
+
+// Start registering another device for an existing user
+// The user's registered credentials are excluded so the same authenticator is not added twice
+pub fn start_add_device(user_id: &str, authorization: &CredentialAuthorization) -> Result<Value, String> {
+    info!("COS72-Tauri: Starting add-device registration, user ID: {}", user_id);
+    let uuid = Uuid::parse_str(user_id)
+        .map_err(|e| format!("Failed to parse user_id as UUID: {}", e))?;
+    let result = finish_ceremony(&authorization.ceremony_id, &authorization.response, &authorization.events,
+                                 Some(&Authorizes::AddDevice(uuid)))?;
+    // The ceremony only allows the user's credentials; checked again before registering
+    if result["user_id"] != user_id {
+        return Err(format!("Adding a device to user {} needs one of the user's passkeys", user_id));
+    }
+    let (username, exclude) = {
+        let registry = lock_registry();
+        let user = registry.data.user(uuid)
+            .ok_or_else(|| format!("Unknown user ID: {}", user_id))?;
+        let exclude: Vec<CredentialID> = registry.data.credentials_of(uuid)
+            .map(|credential| credential.passkey.cred_id().clone())
+            .collect();
+        (user.username.clone(), exclude)
+    };
+    begin_registration(uuid, &username, Some(exclude))
+}
+
+// Create registration options for a user and keep the registration state
+fn begin_registration(user_id: Uuid, username: &str, exclude_credentials: Option<Vec<CredentialID>>) -> Result<Value, String> {
     // 创建WebAuthn实例
     println!("COS72-Tauri: 创建WebAuthn配置，使用自动RP ID");
     
     // 使用已存在的WebAuthn实例
     println!("COS72-Tauri: 使用已存在的WebAuthn实例");
     
-    let webauthn = &*WEBAUTHN_INSTANCE;
+    let webauthn = webauthn();
     
     println!("COS72-Tauri: WebAuthn实例获取成功");
     
     // 改进注册选项，明确需要平台验证器
     println!("COS72-Tauri: 创建注册选项...");
     let policy = UserVerificationPolicy::Required;
-    let (ccr, reg_state) = match webauthn.start_passkey_registration(
-        user_id,
-        username,
-        username,
-        None,
-    ) {
+    // 依赖方要求证明时，只接受元数据中受信任的认证器
+    let started = match attestation_trust()? {
+        Some(trusted) => webauthn.start_attested_passkey_registration(
+            user_id,
+            username,
+            username,
+            exclude_credentials,
+            trusted.ca_list().clone(),
+            None,
+        ).map(|(ccr, state)| (ccr, RegistrationState::Attested(state))),
+        None => webauthn.start_passkey_registration(
+            user_id,
+            username,
+            username,
+            exclude_credentials,
+        ).map(|(ccr, state)| (ccr, RegistrationState::Passkey(state))),
+    };
+    let (mut ccr, reg_state) = match started {
         Ok(result) => result,
         Err(e) => {
             println!("COS72-Tauri: 注册开始失败: {}", e);
@@ -80,12 +400,18 @@ pub fn start_registration(username: &str) -> Result<Value, String> {
         }
     };
     
+    // 要求可发现凭证（resident key），以便无需输入用户名即可登录
+    if let Some(selection) = ccr.public_key.authenticator_selection.as_mut() {
+        selection.resident_key = Some(ResidentKeyRequirement::Required);
+        selection.require_resident_key = true;
+    }
+    
     println!("COS72-Tauri: 成功创建注册请求");
     println!("COS72-Tauri: 注册状态类型: {:?}", reg_state);
     
     // 保存注册状态到全局存储
     let mut states = REGISTRATION_STATES.lock().unwrap();
-    states.insert(user_id.to_string(), reg_state);
+    states.insert(user_id.to_string(), (username.to_string(), reg_state));
     
     // 将注册挑战数据转换为前端格式
     println!("COS72-Tauri: 构建前端注册数据...");
@@ -110,19 +436,22 @@ pub fn start_registration(username: &str) -> Result<Value, String> {
 }
 
 // Complete registration process
-pub fn finish_registration(user_id: &str, response: &str) -> Result<serde_json::Value, String> {
+// The credential is added to the user's passkeys under `label`, "Passkey N" if none is given
+pub fn finish_registration(user_id: &str, response: &str, label: Option<&str>) -> Result<serde_json::Value, String> {
     info!("COS72-Tauri: Completing registration process, user ID: {}", user_id);
     
-    // Parse user ID to UUID (underscore prefix to indicate it's not directly used)
-    let _uuid = Uuid::parse_str(user_id)
+    // Parse user ID to UUID
+    let uuid = Uuid::parse_str(user_id)
         .map_err(|e| format!("Failed to parse user_id as UUID: {}", e))?;
     
     // Parse response JSON
     let reg_response: RegisterPublicKeyCredential = serde_json::from_str(response)
         .map_err(|e| format!("Failed to parse response JSON: {}", e))?;
     
+    let label = label.map(validate_label).transpose()?;
+    
     // Retrieve registration state
-    let reg_state = {
+    let (username, reg_state) = {
         let states = REGISTRATION_STATES.lock().unwrap();
         match states.get(user_id) {
             Some(state) => state.clone(),
@@ -130,16 +459,46 @@ pub fn finish_registration(user_id: &str, response: &str) -> Result<serde_json::
         }
     };
     
-    // Complete registration
-    match WEBAUTHN_INSTANCE.finish_passkey_registration(&reg_response, &reg_state) {
-        Ok(passkey) => {
+    // Complete registration; attested credentials keep the authenticator model they came from
+    let registered = match reg_state {
+        RegistrationState::Passkey(state) => webauthn().finish_passkey_registration(&reg_response, &state)
+            .map(|passkey| (passkey, None))
+            .map_err(|e| e.to_string()),
+        RegistrationState::Attested(state) => webauthn().finish_attested_passkey_registration(&reg_response, &state)
+            .map_err(|e| format!("authenticator attestation not accepted: {}", e))
+            .and_then(|attested| {
+                let authenticator = attested_authenticator(attested.attestation())?;
+                Ok((Passkey::from(attested), Some(authenticator)))
+            }),
+    };
+    match registered {
+        Ok((passkey, attestation)) => {
             info!("COS72-Tauri: Registration completed successfully for user ID: {}", user_id);
             
-            // Store the passkey
-            {
-                let mut passkeys = REGISTERED_PASSKEYS.lock().unwrap();
-                passkeys.insert(user_id.to_string(), passkey.clone());
-            }
+            // Store the user and add the passkey to the user's credentials
+            let credential_id = credential_id_to_string(passkey.cred_id());
+            let stored = lock_registry().update(|data| {
+                if data.credentials.iter().any(|credential| credential.credential_id == credential_id) {
+                    return Err(format!("Credential {} is already registered", credential_id));
+                }
+                if data.user(uuid).is_none() {
+                    // Another registration for the same new username may have finished first
+                    if let Some(user) = data.user_by_name(&username) {
+                        return Err(format!("Username {} was registered as user {} meanwhile; use the add-device flow", username, user.id));
+                    }
+                    data.users.push(PasskeyUser {
+                        id: uuid,
+                        username: username.clone(),
+                        display_name: username.clone(),
+                        created_at: chrono::Utc::now().to_rfc3339(),
+                    });
+                }
+                let label = label.unwrap_or_else(|| format!("Passkey {}", data.credentials_of(uuid).count() + 1));
+                let mut stored = StoredCredential::new(uuid, &label, passkey.clone());
+                stored.attestation = attestation.clone();
+                data.credentials.push(stored.clone());
+                Ok(stored)
+            })?;
             
             // Remove registration state
             {
@@ -151,8 +510,10 @@ pub fn finish_registration(user_id: &str, response: &str) -> Result<serde_json::
             let result = serde_json::json!({
                 "status": "success",
                 "user_id": user_id,
-                "credential_id": credential_id_to_string(passkey.cred_id()),
-                "registered_at": chrono::Utc::now().to_rfc3339(),
+                "credential_id": stored.credential_id,
+                "label": stored.label,
+                "authenticator": stored.attestation,
+                "registered_at": stored.created_at,
             });
             
             Ok(result)
@@ -164,6 +525,17 @@ pub fn finish_registration(user_id: &str, response: &str) -> Result<serde_json::
     }
 }
 
+// Authenticator model of a verified attestation, as listed in the trusted metadata
+fn attested_authenticator(attestation: &ParsedAttestation) -> Result<AttestedAuthenticator, String> {
+    let aaguid = match attestation.metadata {
+        AttestationMetadata::Packed { aaguid } | AttestationMetadata::Tpm { aaguid, .. } => aaguid,
+        _ => return Err("attestation does not identify the authenticator model".to_string()),
+    };
+    attestation_trust()?
+        .and_then(|trusted| trusted.authenticator(aaguid).cloned())
+        .ok_or_else(|| format!("authenticator model {} is not trusted", aaguid))
+}
+
 // Create authentication challenge - simplified for current project's verify_passkey command
 pub fn create_auth_challenge() -> Result<String, String> {
     // Generate a random challenge
@@ -203,72 +575,179 @@ pub async fn verify_challenge(challenge: &str) -> Result<String, String> {
         }
     }
     
-    // Get registered passkeys
-    let passkeys = {
-        let registered = REGISTERED_PASSKEYS.lock().unwrap();
-        registered.values().cloned().collect::<Vec<Passkey>>()
-    };
+    // Start a usernameless WebAuthn verification
+    let ceremony = start_authentication(None)?;
+    let challenge_json = serde_json::to_string(&ceremony)
+        .map_err(|e| format!("Failed to serialize challenge: {}", e))?;
     
-    println!("COS72-Tauri: Ready to start WebAuthn verification, passkeys count: {}", passkeys.len());
+    info!("COS72-Tauri: Created verification challenge, waiting for user response");
+    println!("COS72-Tauri: Verification challenge created successfully, length: {}", challenge_json.len());
     
-    // Start WebAuthn verification
-    match WEBAUTHN_INSTANCE.start_passkey_authentication(&passkeys) {
-        Ok((rcr, _auth_state)) => {
-            // Return serializable challenge
-            let challenge_json = serde_json::to_string(&rcr)
-                .map_err(|e| format!("Failed to serialize challenge: {}", e))?;
-            
-            info!("COS72-Tauri: Created verification challenge, waiting for user response");
-            println!("COS72-Tauri: Verification challenge created successfully, length: {}", challenge_json.len());
-            println!("COS72-Tauri: First 50 characters of verification challenge: {}...", challenge_json.chars().take(50).collect::<String>());
-            
-            // Return challenge info, frontend should use it to call navigator.credentials.get
-            Ok(challenge_json)
-        },
-        Err(e) => {
-            info!("COS72-Tauri: Failed to create verification challenge: {}", e);
-            println!("COS72-Tauri: Error details: {:?}", e);
-            Err(format!("Failed to create verification challenge: {}", e))
+    // Return ceremony ID and challenge, frontend should use it to call navigator.credentials.get
+    // and pass the assertion to finish_authentication
+    Ok(challenge_json)
+}
+
+// Start authentication process
+// Without a user ID the sign-in is usernameless: the authenticator offers its discoverable
+// credentials and the user is resolved from the returned user handle
+pub fn start_authentication(user_id: Option<&str>) -> Result<Value, String> {
+    info!("COS72-Tauri: Starting authentication process, user ID: {:?}", user_id);
+    begin_authentication(user_id, None)
+}
+
+/// Start the authentication that authorizes renaming, unlocking or revoking `credential_id`:
+/// an assertion by another unlocked passkey of the same user, so a cloned or stolen
+/// credential cannot clear its own lock or take over the user's other passkeys
+pub fn start_credential_change(credential_id: &str) -> Result<Value, String> {
+    info!("COS72-Tauri: Starting authentication to change credential {}", credential_id);
+    let user_id = lock_registry().data.credentials.iter()
+        .find(|credential| credential.credential_id == credential_id)
+        .map(|credential| credential.user_id)
+        .ok_or_else(|| format!("Unknown credential: {}", credential_id))?;
+    begin_authentication(Some(&user_id.to_string()), Some(&Authorizes::CredentialChange(credential_id.to_string())))
+}
+
+fn begin_authentication(user_id: Option<&str>, authorizes: Option<&Authorizes>) -> Result<Value, String> {
+    let (rcr, auth_state) = match user_id {
+        Some(user_id) => {
+            let uuid = Uuid::parse_str(user_id)
+                .map_err(|e| format!("Failed to parse user_id as UUID: {}", e))?;
+            let passkeys: Vec<Passkey> = lock_registry().data.credentials_of(uuid)
+                .filter(|credential| credential.locked.is_none())
+                .filter(|credential| !matches!(authorizes, Some(Authorizes::CredentialChange(id)) if *id == credential.credential_id))
+                .map(|credential| credential.passkey.clone())
+                .collect();
+            if passkeys.is_empty() {
+                return Err(match authorizes {
+                    Some(Authorizes::CredentialChange(_)) => format!("User {} has no other unlocked passkey to authorize the change", user_id),
+                    Some(Authorizes::AddDevice(_)) => format!("User {} has no unlocked passkey to authorize a new device", user_id),
+                    None => format!("No passkeys registered for user ID: {}", user_id),
+                });
+            }
+            let (rcr, state) = webauthn().start_passkey_authentication(&passkeys)
+                .map_err(|e| format!("Failed to create authentication challenge: {}", e))?;
+            (rcr, CeremonyState::Passkey(state))
+        }
+        None => {
+            if lock_registry().data.credentials.is_empty() {
+                return Err("No passkeys registered".to_string());
+            }
+            let (mut rcr, state) = webauthn().start_discoverable_authentication()
+                .map_err(|e| format!("Failed to create authentication challenge: {}", e))?;
+            // Show the authenticator prompt right away rather than waiting for form autofill
+            rcr.mediation = None;
+            (rcr, CeremonyState::Discoverable(state))
         }
+    };
+    
+    // Keep the state until the assertion comes back; expired ceremonies are dropped here
+    let ceremony_id = Uuid::new_v4().to_string();
+    {
+        let mut states = AUTHENTICATION_STATES.lock().unwrap_or_else(|p| p.into_inner());
+        let now = Instant::now();
+        states.retain(|_, pending| pending.expires_at > now);
+        states.insert(ceremony_id.clone(), PendingAuthentication {
+            state: auth_state,
+            expires_at: now + AUTHENTICATION_TTL,
+            authorizes: authorizes.cloned(),
+        });
     }
+    
+    let expires_at = chrono::Utc::now() + chrono::Duration::from_std(AUTHENTICATION_TTL).unwrap_or_default();
+    Ok(json!({
+        "ceremony_id": ceremony_id,
+        "challenge": rcr,
+        "expires_at": expires_at.to_rfc3339(),
+    }))
 }
 
 // Complete authentication process
-pub fn finish_authentication(response: &str) -> Result<serde_json::Value, String> {
-    info!("COS72-Tauri: Completing authentication process");
+// The ceremony's state is consumed whether or not the assertion verifies. Counter and backup
+// flags are stored; a counter regression is reported to `events` and handled by the
+// counter regression policy.
+pub fn finish_authentication(ceremony_id: &str, response: &str, events: &EventSink) -> Result<serde_json::Value, String> {
+    info!("COS72-Tauri: Completing authentication process, ceremony ID: {}", ceremony_id);
+    finish_ceremony(ceremony_id, response, events, None)
+}
+
+// Verify the assertion of a ceremony started for `authorizes` (a credential change or a new
+// device) or for a plain sign-in; ceremonies cannot be finished for another purpose
+fn finish_ceremony(ceremony_id: &str, response: &str, events: &EventSink, authorizes: Option<&Authorizes>) -> Result<Value, String> {
+    // Retrieve authentication state
+    let pending = AUTHENTICATION_STATES.lock().unwrap_or_else(|p| p.into_inner()).remove(ceremony_id)
+        .ok_or_else(|| format!("No authentication in progress for ceremony ID: {}", ceremony_id))?;
+    if pending.expires_at <= Instant::now() {
+        return Err(format!("Authentication ceremony {} has expired", ceremony_id));
+    }
+    if pending.authorizes.as_ref() != authorizes {
+        return Err(format!("Authentication ceremony {} was not started for this operation", ceremony_id));
+    }
     
     // Parse authentication response
     let auth_response: PublicKeyCredential = serde_json::from_str(response)
         .map_err(|e| format!("Failed to parse authentication response: {}", e))?;
     
-    // Get registered passkeys
-    let passkeys = {
-        let registered = REGISTERED_PASSKEYS.lock().unwrap();
-        registered.values().cloned().collect::<Vec<Passkey>>()
-    };
-    
-    // Create an authentication state - for actual implementation this would be retrieved from storage
-    let auth_state = WEBAUTHN_INSTANCE.start_passkey_authentication(&passkeys)
-        .map_err(|e| format!("Failed to create authentication state: {}", e))?
-        .1;
-    
     // Finish authentication
-    match WEBAUTHN_INSTANCE.finish_passkey_authentication(&auth_response, &auth_state) {
+    let webauthn = webauthn();
+    let verified = match pending.state {
+        CeremonyState::Passkey(state) => webauthn.finish_passkey_authentication(&auth_response, &state),
+        CeremonyState::Discoverable(state) => {
+            // The user handle names the user; only that user's credentials can verify the assertion
+            let (user_id, _) = webauthn.identify_discoverable_authentication(&auth_response)
+                .map_err(|e| format!("Authentication response has no valid user handle: {}", e))?;
+            let keys: Vec<DiscoverableKey> = lock_registry().data.credentials_of(user_id)
+                .filter(|credential| credential.locked.is_none())
+                .map(|credential| DiscoverableKey::from(&credential.passkey))
+                .collect();
+            if keys.is_empty() {
+                return Err(format!("No passkeys registered for user ID: {}", user_id));
+            }
+            webauthn.finish_discoverable_authentication(&auth_response, state, &keys)
+        }
+    };
+    match verified {
         Ok(auth_result) => {
             // Use the method to get credential ID and convert it to string
             let cred_id_str = credential_id_to_string(auth_result.cred_id());
             info!("COS72-Tauri: Authentication successful for credential ID: {}", cred_id_str);
             
-            // Return success result
+            // Store the counter, backup flags and when the credential was last used
+            let mut registry = lock_registry();
+            let (user_id, authenticator) = registry.data.credentials.iter()
+                .find(|credential| credential.credential_id == cred_id_str)
+                .map(|credential| (credential.user_id, credential.attestation.clone()))
+                .ok_or_else(|| format!("Credential {} is no longer registered", cred_id_str))?;
+            let username = registry.data.user(user_id).map(|user| user.username.clone());
+            let used = registry.update(|data| {
+                if let Some(credential) = data.credential_mut(&cred_id_str) {
+                    credential.record_authentication(&auth_result);
+                }
+                Ok(())
+            });
+            if let Err(e) = used {
+                println!("COS72-Tauri: Failed to record passkey use: {}", e);
+            }
+            
+            // Return success result with the user that authenticated
             let result = serde_json::json!({
                 "status": "success",
+                "ceremony_id": ceremony_id,
+                "user_id": user_id.to_string(),
+                "username": username,
                 "credential_id": cred_id_str,
                 "user_verified": auth_result.user_verified(),
+                "authenticator": authenticator,
                 "authenticated_at": chrono::Utc::now().to_rfc3339(),
             });
             
             Ok(result)
         },
+        Err(WebauthnError::CredentialPossibleCompromise) => {
+            let credential_id = general_purpose::URL_SAFE_NO_PAD.encode(auth_response.get_credential_id());
+            handle_counter_regression(&credential_id, &auth_response, events);
+            Err(format!("Authentication failed: the signature counter of credential {} went backwards, the authenticator may have been cloned", credential_id))
+        }
         Err(e) => {
             info!("COS72-Tauri: Authentication failed: {}", e);
             Err(format!("Authentication failed: {}", e))
@@ -276,6 +755,80 @@ pub fn finish_authentication(response: &str) -> Result<serde_json::Value, String
     }
 }
 
+// Report a counter regression and apply the policy's response
+fn handle_counter_regression(credential_id: &str, auth_response: &PublicKeyCredential, events: &EventSink) {
+    // The counter follows the RP ID hash and flags in the authenticator data
+    let reported_counter = auth_response.response.authenticator_data.get(33..37)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .unwrap_or(0);
+    let mut registry = lock_registry();
+    let Some(credential) = registry.data.credentials.iter().find(|credential| credential.credential_id == credential_id) else {
+        return;
+    };
+    let regression = CounterRegression {
+        credential_id: credential_id.to_string(),
+        user_id: credential.user_id,
+        stored_counter: credential.counter,
+        reported_counter,
+    };
+    let action = (COUNTER_REGRESSION_POLICY.read().unwrap_or_else(|p| p.into_inner()))(&regression);
+    println!("COS72-Tauri: Counter regression on credential {} ({} after {}), action: {:?}",
+             credential_id, reported_counter, regression.stored_counter, action);
+
+    if action == RegressionAction::Lock {
+        let locked = registry.update(|data| {
+            if let Some(credential) = data.credential_mut(credential_id) {
+                credential.locked = Some(CredentialLock {
+                    reason: format!("Signature counter went back from {} to {}", regression.stored_counter, reported_counter),
+                    locked_at: chrono::Utc::now().to_rfc3339(),
+                });
+            }
+            Ok(())
+        });
+        if let Err(e) = locked {
+            println!("COS72-Tauri: Failed to lock credential {}: {}", credential_id, e);
+        }
+    }
+    drop(registry);
+    events(EVENT_PASSKEY_COUNTER_REGRESSION, json!({ "regression": regression, "action": action }));
+}
+
+/// Origin and timeout (in milliseconds) for clients running in this process, such as the
+/// soft token or a USB security key: the first origin of the active relying party
+pub fn client_context() -> Result<(url::Url, u32), String> {
+    let config = get_config();
+    let profile = config.active()?;
+    let origin = profile.origins.first()
+        .ok_or_else(|| format!("Relying party {} has no origins", profile.rp_id))?;
+    let origin = url::Url::parse(origin).map_err(|e| format!("Invalid origin {}: {}", origin, e))?;
+    let timeout_ms = u32::try_from(profile.timeout_secs.saturating_mul(1000)).unwrap_or(u32::MAX);
+    Ok((origin, timeout_ms))
+}
+
+/// Assertion request for an externally supplied challenge, allowing every unlocked passkey
+/// of the active relying party. No ceremony is recorded; the caller verifies the assertion.
+pub fn challenge_request_options(challenge: &[u8]) -> Result<PublicKeyCredentialRequestOptions, String> {
+    let config = get_config();
+    let profile = config.active()?;
+    let allow_credentials = lock_registry().data.credentials.iter()
+        .filter(|credential| credential.locked.is_none())
+        .map(|credential| AllowCredentials {
+            type_: "public-key".to_string(),
+            id: credential.passkey.cred_id().to_vec().into(),
+            transports: None,
+        })
+        .collect();
+    Ok(PublicKeyCredentialRequestOptions {
+        challenge: challenge.to_vec().into(),
+        timeout: Some(u32::try_from(profile.timeout_secs.saturating_mul(1000)).unwrap_or(u32::MAX)),
+        rp_id: profile.rp_id.clone(),
+        allow_credentials,
+        user_verification: UserVerificationPolicy::Preferred,
+        hints: None,
+        extensions: None,
+    })
+}
+
 // Check if WebAuthn is supported
 pub fn is_webauthn_supported() -> bool {
     // Check if current platform supports WebAuthn
@@ -303,30 +856,488 @@ pub fn is_biometric_supported() -> bool {
     }
 }
 
-// Get Passkey credentials from context
+// Get the metadata of a user's Passkey credentials
 pub fn get_credentials(user_id: &str) -> Result<serde_json::Value, String> {
-    // In a real application, this would retrieve credentials from database
     // Get credentials for the specified user ID
-    let passkeys = {
-        let registered = REGISTERED_PASSKEYS.lock().unwrap();
-        match registered.get(user_id) {
-            Some(passkey) => vec![passkey.clone()],
-            None => Vec::new()
-        }
-    };
-    
-    // Return all registered passkeys for debug purposes
-    let all_passkeys = {
-        let registered = REGISTERED_PASSKEYS.lock().unwrap();
-        registered.len()
+    let uuid = Uuid::parse_str(user_id)
+        .map_err(|e| format!("Failed to parse user_id as UUID: {}", e))?;
+    let (credentials, all_passkeys) = {
+        let registry = lock_registry();
+        let credentials = registry.data.credentials_of(uuid)
+            .map(CredentialInfo::from)
+            .collect::<Vec<CredentialInfo>>();
+        (credentials, registry.data.credentials.len())
     };
     
     println!("COS72-Tauri: Retrieving credentials for user ID: {}", user_id);
     println!("COS72-Tauri: Found {} passkeys for this user (total registered: {})", 
-             passkeys.len(), all_passkeys);
+             credentials.len(), all_passkeys);
     
-    match serde_json::to_value(passkeys) {
+    match serde_json::to_value(credentials) {
         Ok(value) => Ok(value),
         Err(e) => Err(format!("Failed to serialize credentials: {}", e)),
     }
-} 
\ No newline at end of file
+}
+
+// Verify the assertion of a `start_credential_change` ceremony for `credential_id`
+fn authorize_credential_change(credential_id: &str, authorization: &CredentialAuthorization) -> Result<(), String> {
+    let result = finish_ceremony(&authorization.ceremony_id, &authorization.response, &authorization.events,
+                                 Some(&Authorizes::CredentialChange(credential_id.to_string())))?;
+    let registry = lock_registry();
+    let owner = registry.data.credentials.iter()
+        .find(|credential| credential.credential_id == credential_id)
+        .map(|credential| credential.user_id.to_string())
+        .ok_or_else(|| format!("Unknown credential: {}", credential_id))?;
+    // The ceremony only allows the owner's other credentials; checked again before changing anything
+    if result["credential_id"] == credential_id || result["user_id"] != owner.as_str() {
+        return Err(format!("Changing credential {} needs another passkey of its user", credential_id));
+    }
+    Ok(())
+}
+
+// Rename a credential, e.g. after the device it lives on
+pub fn rename_credential(credential_id: &str, label: &str, authorization: &CredentialAuthorization) -> Result<serde_json::Value, String> {
+    let label = validate_label(label)?;
+    authorize_credential_change(credential_id, authorization)?;
+    let renamed = lock_registry().update(|data| {
+        let credential = data.credential_mut(credential_id)
+            .ok_or_else(|| format!("Unknown credential: {}", credential_id))?;
+        credential.label = label;
+        Ok(CredentialInfo::from(&*credential))
+    })?;
+    println!("COS72-Tauri: Renamed credential {} to {}", credential_id, renamed.label);
+    serde_json::to_value(renamed).map_err(|e| format!("Failed to serialize credential: {}", e))
+}
+
+// Unlock a credential locked after a counter regression
+pub fn unlock_credential(credential_id: &str, authorization: &CredentialAuthorization) -> Result<serde_json::Value, String> {
+    authorize_credential_change(credential_id, authorization)?;
+    let unlocked = lock_registry().update(|data| {
+        let credential = data.credential_mut(credential_id)
+            .ok_or_else(|| format!("Unknown credential: {}", credential_id))?;
+        credential.locked = None;
+        Ok(CredentialInfo::from(&*credential))
+    })?;
+    println!("COS72-Tauri: Unlocked credential {}", credential_id);
+    serde_json::to_value(unlocked).map_err(|e| format!("Failed to serialize credential: {}", e))
+}
+
+// Revoke a credential; it can no longer be used to authenticate
+pub fn revoke_credential(credential_id: &str, authorization: &CredentialAuthorization) -> Result<serde_json::Value, String> {
+    authorize_credential_change(credential_id, authorization)?;
+    let revoked = lock_registry().update(|data| {
+        let index = data.credentials.iter().position(|credential| credential.credential_id == credential_id)
+            .ok_or_else(|| format!("Unknown credential: {}", credential_id))?;
+        Ok(data.credentials.remove(index))
+    })?;
+    println!("COS72-Tauri: Revoked credential {} of user {}", credential_id, revoked.user_id);
+    serde_json::to_value(CredentialInfo::from(&revoked)).map_err(|e| format!("Failed to serialize credential: {}", e))
+}
+
+fn validate_label(label: &str) -> Result<String, String> {
+    let label = label.trim();
+    if label.is_empty() || label.chars().count() > MAX_LABEL_LENGTH {
+        return Err(format!("Credential label must be 1 to {} characters", MAX_LABEL_LENGTH));
+    }
+    Ok(label.to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fido::config::RelyingPartyProfile;
+    use crate::fido::test_authenticator::TestAuthenticator;
+
+    // Tests share the global registry, configuration and ceremony states
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    const TEST_ORIGIN: &str = "tauri://localhost";
+
+    pub(crate) fn setup() -> std::sync::MutexGuard<'static, ()> {
+        let guard = TEST_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+        crate::storage::use_test_data_dir();
+        set_config(WebauthnConfig::default()).unwrap();
+        set_passkey_store(Box::new(MemoryPasskeyStore::default())).unwrap();
+        set_counter_regression_policy(default_counter_regression_policy());
+        guard
+    }
+
+    fn no_events() -> EventSink {
+        Arc::new(|_, _| {})
+    }
+
+    pub(crate) fn register(authenticator: &mut TestAuthenticator, username: &str) -> String {
+        let started = start_registration(username).unwrap();
+        let user_id = started["user_id"].as_str().unwrap().to_string();
+        let response = authenticator.register(&started["challenge"]);
+        finish_registration(&user_id, &response, None).unwrap();
+        user_id
+    }
+
+    #[test]
+    fn test_authentication_ceremony() {
+        let _guard = setup();
+        let mut authenticator = TestAuthenticator::new(TEST_ORIGIN);
+        let user_id = register(&mut authenticator, "alice");
+
+        let ceremony = start_authentication(Some(&user_id)).unwrap();
+        let ceremony_id = ceremony["ceremony_id"].as_str().unwrap();
+        let response = authenticator.authenticate(&ceremony["challenge"]);
+        let result = finish_authentication(ceremony_id, &response, &no_events()).unwrap();
+        assert_eq!(result["user_id"], json!(user_id));
+        assert_eq!(result["username"], json!("alice"));
+
+        // Each ceremony can be finished once
+        let replayed = finish_authentication(ceremony_id, &response, &no_events()).unwrap_err();
+        assert!(replayed.contains("No authentication in progress"), "{}", replayed);
+
+        // An assertion is only valid for the challenge of its own ceremony
+        let first = start_authentication(None).unwrap();
+        let second = start_authentication(None).unwrap();
+        let response = authenticator.authenticate(&first["challenge"]);
+        assert!(finish_authentication(second["ceremony_id"].as_str().unwrap(), &response, &no_events()).is_err());
+        // and the failed attempt used up the second ceremony
+        let response = authenticator.authenticate(&second["challenge"]);
+        assert!(finish_authentication(second["ceremony_id"].as_str().unwrap(), &response, &no_events()).is_err());
+    }
+
+    #[test]
+    fn test_authentication_ceremony_expires() {
+        let _guard = setup();
+        assert!(start_authentication(None).is_err());
+
+        let mut authenticator = TestAuthenticator::new(TEST_ORIGIN);
+        register(&mut authenticator, "bob");
+        let ceremony = start_authentication(None).unwrap();
+        let ceremony_id = ceremony["ceremony_id"].as_str().unwrap();
+        AUTHENTICATION_STATES.lock().unwrap().get_mut(ceremony_id).unwrap().expires_at = Instant::now();
+
+        let response = authenticator.authenticate(&ceremony["challenge"]);
+        let expired = finish_authentication(ceremony_id, &response, &no_events()).unwrap_err();
+        assert!(expired.contains("expired"), "{}", expired);
+    }
+
+    #[test]
+    fn test_relying_party_reconfiguration() {
+        let _guard = setup();
+        let mut authenticator = TestAuthenticator::new(TEST_ORIGIN);
+        register(&mut authenticator, "carol");
+        let pending = start_authentication(None).unwrap();
+
+        let mut config = WebauthnConfig::default();
+        config.profiles.insert("community".to_string(), RelyingPartyProfile {
+            rp_id: "community.example".to_string(),
+            rp_name: "Community".to_string(),
+            origins: vec!["https://wallet.community.example".to_string()],
+            timeout_secs: 30,
+            attestation: None,
+        });
+        config.active_profile = "community".to_string();
+        set_config(config.clone()).unwrap();
+        assert_eq!(get_config(), config);
+        assert_eq!(crate::storage::load_json::<WebauthnConfig>(WEBAUTHN_CONFIG_FILE).unwrap(), Some(config));
+
+        // Ceremonies of the previous relying party are gone
+        let response = authenticator.authenticate(&pending["challenge"]);
+        assert!(finish_authentication(pending["ceremony_id"].as_str().unwrap(), &response, &no_events()).is_err());
+
+        // New ceremonies are for the community's RP ID and origin
+        let started = start_registration("dave").unwrap();
+        assert_eq!(started["challenge"]["publicKey"]["rp"]["id"], json!("community.example"));
+        let mut community_authenticator = TestAuthenticator::new("https://wallet.community.example");
+        let response = community_authenticator.register(&started["challenge"]);
+        finish_registration(started["user_id"].as_str().unwrap(), &response, None).unwrap();
+
+        // Invalid configurations are refused and keep the current one
+        let invalid = WebauthnConfig { active_profile: "missing".to_string(), ..WebauthnConfig::default() };
+        assert!(set_config(invalid).is_err());
+        assert_eq!(get_config().active_profile, "community");
+    }
+
+    #[test]
+    fn test_config_is_managed_by_the_operator_in_server_mode() {
+        let _guard = setup();
+        policy::set_test_server_mode(true);
+        let error = set_config(WebauthnConfig { active_profile: "community".to_string(), ..WebauthnConfig::default() }).unwrap_err();
+        assert!(error.contains("server mode"), "{}", error);
+        let error = reload_attestation_metadata().unwrap_err();
+        assert!(error.contains("server mode"), "{}", error);
+        policy::set_test_server_mode(false);
+        assert_eq!(get_config(), WebauthnConfig::default());
+    }
+
+    #[test]
+    fn test_usernameless_authentication() {
+        let _guard = setup();
+        let mut alice_device = TestAuthenticator::new(TEST_ORIGIN);
+        let mut bob_device = TestAuthenticator::new(TEST_ORIGIN);
+        let alice = register(&mut alice_device, "alice");
+        let bob = register(&mut bob_device, "bob");
+
+        // Registration asks for a discoverable credential
+        let started = start_add_device(&alice, &authorize_add_device(&mut alice_device, &alice)).unwrap();
+        assert_eq!(started["user_id"], json!(alice));
+        let selection = &started["challenge"]["publicKey"]["authenticatorSelection"];
+        assert_eq!(selection["residentKey"], json!("required"));
+        assert_eq!(selection["requireResidentKey"], json!(true));
+
+        // No credentials are listed; the user comes from the credential's user handle
+        let ceremony = start_authentication(None).unwrap();
+        assert_eq!(ceremony["challenge"]["publicKey"]["allowCredentials"], json!([]));
+        assert!(ceremony["challenge"].get("mediation").is_none());
+        let response = bob_device.authenticate(&ceremony["challenge"]);
+        let result = finish_authentication(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).unwrap();
+        assert_eq!(result["user_id"], json!(bob));
+        assert_eq!(result["username"], json!("bob"));
+
+        // A user handle naming another user does not match the signing credential
+        let ceremony = start_authentication(None).unwrap();
+        let mut response: Value = serde_json::from_str(&alice_device.authenticate(&ceremony["challenge"])).unwrap();
+        let bob_handle = Uuid::parse_str(&bob).unwrap();
+        response["response"]["userHandle"] = json!(general_purpose::URL_SAFE_NO_PAD.encode(bob_handle.as_bytes()));
+        assert!(finish_authentication(ceremony["ceremony_id"].as_str().unwrap(), &response.to_string(), &no_events()).is_err());
+    }
+
+    // Authorize adding a device to `user_id` with one of the user's passkeys
+    fn authorize_add_device(device: &mut TestAuthenticator, user_id: &str) -> CredentialAuthorization {
+        let ceremony = start_add_device_authorization(user_id).unwrap();
+        CredentialAuthorization {
+            ceremony_id: ceremony["ceremony_id"].as_str().unwrap().to_string(),
+            response: device.authenticate(&ceremony["challenge"]),
+            events: no_events(),
+        }
+    }
+
+    #[test]
+    fn test_adding_a_device_needs_a_passkey_of_the_user() {
+        let _guard = setup();
+        let mut alice_device = TestAuthenticator::new(TEST_ORIGIN);
+        let mut bob_device = TestAuthenticator::new(TEST_ORIGIN);
+        let alice = register(&mut alice_device, "alice");
+        let bob = register(&mut bob_device, "bob");
+
+        // A sign-in assertion does not authorize adding a device
+        let ceremony = start_authentication(Some(&alice)).unwrap();
+        let sign_in_assertion = CredentialAuthorization {
+            ceremony_id: ceremony["ceremony_id"].as_str().unwrap().to_string(),
+            response: alice_device.authenticate(&ceremony["challenge"]),
+            events: no_events(),
+        };
+        assert!(start_add_device(&alice, &sign_in_assertion).is_err());
+
+        // Nor does another user's passkey
+        let error = start_add_device(&alice, &authorize_add_device(&mut bob_device, &bob)).unwrap_err();
+        assert!(error.contains("not started for this operation"), "{}", error);
+
+        // An assertion is only good for one device
+        let authorization = authorize_add_device(&mut alice_device, &alice);
+        start_add_device(&alice, &authorization).unwrap();
+        assert!(start_add_device(&alice, &authorization).is_err());
+
+        // A locked passkey cannot authorize a new device
+        lock_registry().update(|data| {
+            data.credentials.iter_mut().filter(|credential| credential.user_id.to_string() == alice).for_each(|credential| {
+                credential.locked = Some(CredentialLock { reason: "test".to_string(), locked_at: chrono::Utc::now().to_rfc3339() });
+            });
+            Ok(())
+        }).unwrap();
+        let error = start_add_device_authorization(&alice).unwrap_err();
+        assert!(error.contains("no unlocked passkey"), "{}", error);
+    }
+
+    // Authorize a change of `credential_id` with another passkey of its user
+    fn authorize_change(device: &mut TestAuthenticator, credential_id: &str) -> CredentialAuthorization {
+        let ceremony = start_credential_change(credential_id).unwrap();
+        CredentialAuthorization {
+            ceremony_id: ceremony["ceremony_id"].as_str().unwrap().to_string(),
+            response: device.authenticate(&ceremony["challenge"]),
+            events: no_events(),
+        }
+    }
+
+    #[test]
+    fn test_multiple_credentials_per_user() {
+        let _guard = setup();
+        let mut laptop = TestAuthenticator::new(TEST_ORIGIN);
+        let mut phone = TestAuthenticator::new(TEST_ORIGIN);
+        let user_id = register(&mut laptop, "erin");
+
+        // A registered username needs the add-device flow, which excludes known credentials
+        assert!(start_registration("erin").is_err());
+        let started = start_add_device(&user_id, &authorize_add_device(&mut laptop, &user_id)).unwrap();
+        assert_eq!(started["user_id"], json!(user_id));
+        let laptop_id = get_credentials(&user_id).unwrap()[0]["credential_id"].clone();
+        assert_eq!(started["challenge"]["publicKey"]["excludeCredentials"][0]["id"], laptop_id);
+        let response = phone.register(&started["challenge"]);
+        let added = finish_registration(&user_id, &response, Some(" Phone ")).unwrap();
+        assert_eq!(added["label"], json!("Phone"));
+
+        // Both devices can sign in
+        let credentials = get_credentials(&user_id).unwrap();
+        assert_eq!(credentials.as_array().unwrap().len(), 2);
+        assert_eq!(credentials[0]["label"], json!("Passkey 1"));
+        assert!(credentials[0].get("passkey").is_none());
+        for device in [&mut laptop, &mut phone] {
+            let ceremony = start_authentication(Some(&user_id)).unwrap();
+            let response = device.authenticate(&ceremony["challenge"]);
+            finish_authentication(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).unwrap();
+        }
+
+        // Changes need an assertion by another passkey of the user
+        let phone_id = added["credential_id"].as_str().unwrap();
+        let authorization = authorize_change(&mut laptop, phone_id);
+        assert_eq!(rename_credential(phone_id, "Work phone", &authorization).unwrap()["label"], json!("Work phone"));
+        assert!(rename_credential(phone_id, "Work phone", &authorization).is_err());
+        assert!(rename_credential(phone_id, "  ", &authorize_change(&mut laptop, phone_id)).is_err());
+        assert!(start_credential_change("unknown").is_err());
+        let ceremony = start_credential_change(phone_id).unwrap();
+        assert_eq!(ceremony["challenge"]["publicKey"]["allowCredentials"].as_array().unwrap().len(), 1);
+        assert_eq!(ceremony["challenge"]["publicKey"]["allowCredentials"][0]["id"], laptop_id);
+        let sign_in = start_authentication(Some(&user_id)).unwrap();
+        let sign_in_assertion = CredentialAuthorization {
+            ceremony_id: sign_in["ceremony_id"].as_str().unwrap().to_string(),
+            response: laptop.authenticate(&sign_in["challenge"]),
+            events: no_events(),
+        };
+        assert!(revoke_credential(phone_id, &sign_in_assertion).is_err());
+
+        // Revoked credentials no longer authenticate
+        revoke_credential(phone_id, &authorize_change(&mut laptop, phone_id)).unwrap();
+        let ceremony = start_authentication(None).unwrap();
+        let response = phone.authenticate(&ceremony["challenge"]);
+        assert!(finish_authentication(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).is_err());
+
+        // The last passkey cannot be changed, as no other passkey can authorize it
+        let laptop_id = laptop_id.as_str().unwrap();
+        assert!(start_credential_change(laptop_id).is_err());
+
+        // Once all credentials are removed the username can register again as the same user
+        lock_registry().update(|data| {
+            data.credentials.retain(|credential| credential.credential_id != laptop_id);
+            Ok(())
+        }).unwrap();
+        assert_eq!(start_registration("erin").unwrap()["user_id"], json!(user_id));
+    }
+
+    #[test]
+    fn test_concurrent_registrations_of_a_username() {
+        let _guard = setup();
+        let first = start_registration("henry").unwrap();
+        let second = start_registration("henry").unwrap();
+        assert_ne!(first["user_id"], second["user_id"]);
+
+        // Only the registration finished first creates the user
+        let response = TestAuthenticator::new(TEST_ORIGIN).register(&second["challenge"]);
+        finish_registration(second["user_id"].as_str().unwrap(), &response, None).unwrap();
+        let response = TestAuthenticator::new(TEST_ORIGIN).register(&first["challenge"]);
+        assert!(finish_registration(first["user_id"].as_str().unwrap(), &response, None).is_err());
+        assert_eq!(find_user("henry").map(|id| id.to_string()), second["user_id"].as_str().map(str::to_string));
+        assert!(get_credentials(first["user_id"].as_str().unwrap()).unwrap().as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_counter_and_backup_state_updates() {
+        let _guard = setup();
+        let mut authenticator = TestAuthenticator::new(TEST_ORIGIN);
+        authenticator.backup_eligible = true;
+        let user_id = register(&mut authenticator, "frank");
+        // A second device, to authorize unlocking the first one later
+        let started = start_add_device(&user_id, &authorize_add_device(&mut authenticator, &user_id)).unwrap();
+        let mut other_device = TestAuthenticator::new(TEST_ORIGIN);
+        let response = other_device.register(&started["challenge"]);
+        finish_registration(&user_id, &response, None).unwrap();
+        let authenticate = |authenticator: &mut TestAuthenticator, events: &EventSink| {
+            let ceremony = start_authentication(Some(&user_id))?;
+            let response = authenticator.authenticate(&ceremony["challenge"]);
+            finish_authentication(ceremony["ceremony_id"].as_str().unwrap(), &response, events)
+        };
+
+        // The passkey is synced to another device after registration
+        authenticator.backup_state = true;
+        authenticate(&mut authenticator, &no_events()).unwrap();
+        authenticate(&mut authenticator, &no_events()).unwrap();
+        let credential = &get_credentials(&user_id).unwrap()[0];
+        assert_eq!(credential["counter"], json!(3));
+        assert_eq!(credential["backup_state"], json!(true));
+        assert!(credential["last_used_at"].is_string());
+
+        // A counter that goes backwards is reported and locks the credential
+        let events: Arc<Mutex<Vec<(String, Value)>>> = Arc::default();
+        let recorded = events.clone();
+        let sink: EventSink = Arc::new(move |event, payload| recorded.lock().unwrap().push((event.to_string(), payload)));
+        authenticator.set_counter(0);
+        let error = authenticate(&mut authenticator, &sink).unwrap_err();
+        assert!(error.contains("cloned"), "{}", error);
+        let (event, payload) = events.lock().unwrap()[0].clone();
+        assert_eq!(event, EVENT_PASSKEY_COUNTER_REGRESSION);
+        assert_eq!(payload["regression"]["stored_counter"], json!(3));
+        assert_eq!(payload["regression"]["reported_counter"], json!(1));
+        assert_eq!(payload["action"], json!("lock"));
+
+        let credential_id = get_credentials(&user_id).unwrap()[0]["credential_id"].as_str().unwrap().to_string();
+        assert!(get_credentials(&user_id).unwrap()[0]["locked"].is_object());
+        // The locked credential is no longer offered for sign-in
+        let ceremony = start_authentication(Some(&user_id)).unwrap();
+        let allowed = ceremony["challenge"]["publicKey"]["allowCredentials"].as_array().unwrap();
+        assert!(allowed.iter().all(|allowed| allowed["id"] != credential_id.as_str()));
+        authenticator.set_counter(10);
+
+        // Another passkey of the user authorizes the unlock
+        unlock_credential(&credential_id, &authorize_change(&mut other_device, &credential_id)).unwrap();
+
+        // The policy can report regressions without locking
+        authenticate(&mut authenticator, &no_events()).unwrap();
+        set_counter_regression_policy(Box::new(|_| RegressionAction::Report));
+        authenticator.set_counter(3);
+        assert!(authenticate(&mut authenticator, &sink).is_err());
+        assert_eq!(events.lock().unwrap()[1].1["action"], json!("report"));
+        assert!(get_credentials(&user_id).unwrap()[0]["locked"].is_null());
+    }
+
+    #[test]
+    fn test_attestation_policy() {
+        use crate::fido::attestation::tests::{test_blob, test_entry, test_policy};
+        use crate::fido::test_authenticator::TestCertificateAuthority;
+
+        let _guard = setup();
+        let dir = tempfile::tempdir().unwrap();
+        let mds_root = TestCertificateAuthority::new("Test MDS Root");
+        let vendor_root = TestCertificateAuthority::new("Test Vendor Root");
+        let (certified, unlisted) = (Uuid::new_v4(), Uuid::new_v4());
+        let blob = test_blob(&mds_root.issue("Test MDS Signer"), json!([
+            test_entry(certified, "Certified Key", &vendor_root.certificate, &["FIDO_CERTIFIED_L2"]),
+        ]));
+        let mut config = WebauthnConfig::default();
+        let policy = test_policy(dir.path(), &blob, &mds_root.certificate);
+        config.profiles.get_mut(crate::fido::config::DEFAULT_PROFILE).unwrap().attestation = Some(policy.clone());
+        set_config(config.clone()).unwrap();
+
+        // Authenticators without attestation or of models not in the metadata are refused
+        let started = start_registration("grace").unwrap();
+        assert_eq!(started["challenge"]["publicKey"]["attestation"], json!("direct"));
+        let response = TestAuthenticator::new(TEST_ORIGIN).register(&started["challenge"]);
+        assert!(finish_registration(started["user_id"].as_str().unwrap(), &response, None).is_err());
+        let started = start_registration("grace").unwrap();
+        let mut unlisted_device = TestAuthenticator::new(TEST_ORIGIN).with_attestation(&vendor_root, unlisted);
+        let response = unlisted_device.register(&started["challenge"]);
+        assert!(finish_registration(started["user_id"].as_str().unwrap(), &response, None).is_err());
+
+        // A certified model registers and is recorded with the credential
+        let mut device = TestAuthenticator::new(TEST_ORIGIN).with_attestation(&vendor_root, certified);
+        let user_id = register(&mut device, "grace");
+        let credential = &get_credentials(&user_id).unwrap()[0];
+        assert_eq!(credential["attestation"]["aaguid"], json!(certified));
+        assert_eq!(credential["attestation"]["certification_level"], json!("FIDO_CERTIFIED_L2"));
+        let ceremony = start_authentication(Some(&user_id)).unwrap();
+        let response = device.authenticate(&ceremony["challenge"]);
+        let result = finish_authentication(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).unwrap();
+        assert_eq!(result["authenticator"]["description"], json!("Certified Key"));
+
+        // The metadata can be replaced; a blob that cannot be verified is refused
+        assert_eq!(reload_attestation_metadata().unwrap()["authenticators"], json!(1));
+        let other_root = TestCertificateAuthority::new("Other Root");
+        std::fs::write(&policy.mds_blob_path, test_blob(&other_root.issue("Other Signer"), json!([]))).unwrap();
+        assert!(reload_attestation_metadata().is_err());
+        assert!(set_config(config).is_err());
+    }
+}
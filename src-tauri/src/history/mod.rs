@@ -0,0 +1,504 @@
+// Transaction History
+// Append-only journal of every signing request and its outcome: which adapter signed,
+// the decoded summary, broadcast hash and confirmation status. Each change appends a
+// full snapshot of the entry; the latest snapshot per entry wins when reading.
+
+use alloy_primitives::{eip191_hash_message, Address, B256, U256};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::eth::{self, SafeTransactionRequest, TxRequest, UserOperationRequest};
+use crate::siwe::SiweMessage;
+use crate::storage;
+use crate::tee::{TeeError, TeeOperation, TeeResult};
+
+// Constants
+const HISTORY_FILE: &str = "history.jsonl";
+// Characters of a signed message shown in its summary
+const MESSAGE_SUMMARY_CHARS: usize = 80;
+
+// Serializes read-modify-append updates of the journal
+static JOURNAL_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Transaction,
+    UserOperation,
+    /// Owner signature over a Safe transaction
+    SafeTransaction,
+    /// EIP-191 personal message, including Sign-In With Ethereum
+    Message,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryStatus {
+    /// Refused by the transaction policy
+    Rejected,
+    /// Signing, broadcast or submission failed
+    Failed,
+    Signed,
+    Submitted,
+    Confirmed,
+    /// Included on chain but the call reverted
+    Reverted,
+    /// Superseded by a speed-up or cancellation with the same nonce
+    Replaced,
+}
+
+/// One signing request and everything that happened to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub kind: EntryKind,
+    pub status: EntryStatus,
+    pub chain_id: Option<u64>,
+    /// Signing account, or the smart account for UserOperations
+    pub from: Option<Address>,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub nonce: Option<U256>,
+    pub summary: Option<String>,
+    /// TEE adapter that handled the request
+    pub adapter: String,
+    /// Transaction hash, or userOpHash for UserOperations
+    pub hash: Option<B256>,
+    /// Transaction that included the entry on chain
+    pub transaction_hash: Option<B256>,
+    pub block_number: Option<u64>,
+    /// Hash of the transaction this one replaces
+    #[serde(default)]
+    pub replaces: Option<B256>,
+    /// Hash of the transaction that replaced this one
+    #[serde(default)]
+    pub replaced_by: Option<B256>,
+    pub error: Option<String>,
+    /// Signing request as passed to the TEE
+    pub request: Value,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl HistoryEntry {
+    /// Entry for a signing operation; other TEE operations are not journaled
+    pub fn for_operation(op: &TeeOperation, adapter: &str) -> Option<Self> {
+        let (kind, data) = match op {
+            TeeOperation::SignTransaction(data) => (EntryKind::Transaction, data),
+            TeeOperation::SignUserOperation(data) => (EntryKind::UserOperation, data),
+            TeeOperation::SignSafeTransaction(data) => (EntryKind::SafeTransaction, data),
+            TeeOperation::SignMessage(data) => (EntryKind::Message, data),
+            _ => return None,
+        };
+
+        let now = chrono::Utc::now();
+        let mut entry = Self {
+            id: format!("{}-{}", now.timestamp_millis(), NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            kind,
+            status: EntryStatus::Signed,
+            chain_id: None,
+            from: None,
+            to: None,
+            value: U256::ZERO,
+            nonce: None,
+            summary: None,
+            adapter: adapter.to_string(),
+            hash: None,
+            transaction_hash: None,
+            block_number: None,
+            replaces: None,
+            replaced_by: None,
+            error: None,
+            request: serde_json::from_str(data).unwrap_or_else(|_| Value::String(data.clone())),
+            created_at: now.to_rfc3339(),
+            updated_at: now.to_rfc3339(),
+        };
+
+        match kind {
+            EntryKind::Transaction => {
+                if let Ok(tx) = TxRequest::from_json(data) {
+                    entry.fill_call(&tx);
+                    entry.from = tx.from;
+                    entry.nonce = tx.nonce.map(U256::from);
+                }
+            },
+            EntryKind::UserOperation => {
+                if let Ok(request) = UserOperationRequest::from_json(data) {
+                    // A single execute is journaled as its inner call, batches as the account call
+                    // with the total value
+                    match request.user_op.policy_requests(request.chain_id).as_deref() {
+                        Ok([call]) => entry.fill_call(call),
+                        calls => {
+                            entry.fill_call(&request.user_op.account_call(request.chain_id));
+                            entry.value = calls.map(|calls| calls.iter().fold(U256::ZERO, |total, call| total.saturating_add(call.value)))
+                                .unwrap_or_default();
+                        },
+                    }
+                    entry.from = Some(request.user_op.sender);
+                    entry.nonce = Some(request.user_op.nonce);
+                    // The userOpHash does not depend on the signature
+                    entry.hash = Some(request.user_op_hash());
+                }
+            },
+            EntryKind::SafeTransaction => {
+                if let Ok(request) = SafeTransactionRequest::from_json(data) {
+                    // Journaled as the call the Safe makes; the signing owner is not the sender
+                    entry.fill_call(&request.policy_request());
+                    entry.from = Some(request.safe);
+                    entry.nonce = Some(request.tx.nonce);
+                    entry.hash = Some(request.safe_tx_hash());
+                }
+            },
+            EntryKind::Message => {
+                // Messages are text; one that happens to parse as JSON is still kept verbatim
+                entry.request = Value::String(data.clone());
+                entry.hash = Some(eip191_hash_message(data));
+                entry.summary = Some(match SiweMessage::parse(data) {
+                    Ok(message) => {
+                        entry.chain_id = Some(message.chain_id);
+                        format!("Sign in to {} as {}", message.domain, message.address)
+                    },
+                    Err(_) => {
+                        let text: String = data.lines().next().unwrap_or_default().chars().take(MESSAGE_SUMMARY_CHARS).collect();
+                        format!("Sign message \"{}\"", text)
+                    },
+                });
+            },
+        }
+        Some(entry)
+    }
+
+    fn fill_call(&mut self, tx: &TxRequest) {
+        self.chain_id = tx.chain_id;
+        self.to = tx.to;
+        self.value = tx.value;
+        self.summary = Some(eth::decode_transaction(tx).description);
+    }
+}
+
+/// Record the outcome of a journaled signing operation
+pub fn record_signing(mut entry: HistoryEntry, result: &Result<TeeResult, TeeError>) {
+    match result {
+        Ok(result) if result.success => {
+            let data: Value = result.data.as_deref()
+                .and_then(|data| serde_json::from_str(data).ok())
+                .unwrap_or_default();
+            if let Some(tx_hash) = data.get("tx_hash").and_then(|hash| serde_json::from_value(hash.clone()).ok()) {
+                entry.hash = Some(tx_hash);
+            }
+            if let Some(from) = data.get("from").and_then(|from| serde_json::from_value(from.clone()).ok()) {
+                entry.from = Some(from);
+            }
+            // Message signatures only name the signer
+            if entry.from.is_none() {
+                entry.from = data.get("signer").and_then(|signer| serde_json::from_value(signer.clone()).ok());
+            }
+        },
+        Ok(result) => {
+            entry.status = EntryStatus::Failed;
+            entry.error = Some(result.message.clone());
+        },
+        Err(e) => {
+            entry.status = match e {
+                TeeError::PolicyDenied(_) | TeeError::StepUpRequired(_) => EntryStatus::Rejected,
+                _ => EntryStatus::Failed,
+            };
+            entry.error = Some(e.to_string());
+        },
+    }
+
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+    append(&entry);
+}
+
+/// Mark the entry signed as `hash` as broadcast or handed to the bundler
+pub fn mark_submitted(hash: B256) {
+    update(hash, |entry| entry.status = EntryStatus::Submitted);
+}
+
+/// Mark the entry signed as `hash` as failed after signing
+pub fn mark_failed(hash: B256, error: &str) {
+    update(hash, |entry| {
+        entry.status = EntryStatus::Failed;
+        entry.error = Some(error.to_string());
+    });
+}
+
+/// Record the on-chain inclusion of the entry signed as `hash`
+pub fn mark_included(hash: B256, transaction_hash: B256, block_number: Option<u64>, error: Option<String>) {
+    update(hash, |entry| {
+        entry.status = if error.is_none() { EntryStatus::Confirmed } else { EntryStatus::Reverted };
+        entry.transaction_hash = Some(transaction_hash);
+        entry.block_number = block_number;
+        entry.error = error;
+    });
+}
+
+/// Link a replacement transaction to the pending transaction it supersedes
+pub fn mark_replaced(hash: B256, replacement: B256) {
+    update(hash, |entry| {
+        entry.status = EntryStatus::Replaced;
+        entry.replaced_by = Some(replacement);
+    });
+    update(replacement, |entry| entry.replaces = Some(hash));
+}
+
+// Apply a change to the latest entry with the hash; entries signed outside the TEE are not journaled
+fn update(hash: B256, change: impl FnOnce(&mut HistoryEntry)) {
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+    let entries = match load() {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("COS72-Tauri: Failed to read history: {}", e);
+            return;
+        }
+    };
+
+    if let Some(mut entry) = entries.into_iter().rev().find(|entry| entry.hash == Some(hash)) {
+        change(&mut entry);
+        entry.updated_at = chrono::Utc::now().to_rfc3339();
+        append(&entry);
+    }
+}
+
+fn append(entry: &HistoryEntry) {
+    let result = serde_json::to_string(entry)
+        .map_err(|e| e.to_string())
+        .and_then(|line| storage::append_line(HISTORY_FILE, &line).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        println!("COS72-Tauri: Failed to record history entry {}: {}", entry.id, e);
+    }
+}
+
+// Latest snapshot of each entry, oldest entry first
+fn load() -> Result<Vec<HistoryEntry>, String> {
+    let lines = storage::read_lines(HISTORY_FILE)
+        .map_err(|e| format!("Failed to read history: {}", e))?;
+
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut positions = HashMap::new();
+    for line in lines {
+        // A torn last line from a crash is skipped
+        let Ok(entry) = serde_json::from_str::<HistoryEntry>(&line) else { continue };
+        match positions.get(&entry.id) {
+            Some(&position) => entries[position] = entry,
+            None => {
+                positions.insert(entry.id.clone(), entries.len());
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// History query; unset fields match everything
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryFilter {
+    pub kind: Option<EntryKind>,
+    pub status: Option<EntryStatus>,
+    pub chain_id: Option<u64>,
+    /// Matches the sender or the recipient
+    pub address: Option<Address>,
+    /// RFC 3339 bounds on the creation time
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        let created_at = chrono::DateTime::parse_from_rfc3339(&entry.created_at).ok();
+        let bound = |bound: &Option<String>| bound.as_deref().and_then(|bound| chrono::DateTime::parse_from_rfc3339(bound).ok());
+
+        self.kind.is_none_or(|kind| kind == entry.kind)
+            && self.status.is_none_or(|status| status == entry.status)
+            && self.chain_id.is_none_or(|chain_id| entry.chain_id == Some(chain_id))
+            && self.address.is_none_or(|address| entry.from == Some(address) || entry.to == Some(address))
+            && bound(&self.since).is_none_or(|since| created_at.is_some_and(|created_at| created_at >= since))
+            && bound(&self.until).is_none_or(|until| created_at.is_some_and(|created_at| created_at <= until))
+    }
+}
+
+/// Highest value signed for the sender and nonce of `tx` by a transaction that can still be
+/// mined: what a replacement of it has already been charged for
+pub fn replaced_value(tx: &TxRequest) -> U256 {
+    let (Some(chain_id), Some(from), Some(nonce)) = (tx.chain_id, tx.from, tx.nonce) else {
+        return U256::ZERO;
+    };
+    let entries = match load() {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("COS72-Tauri: Failed to read history: {}", e);
+            return U256::ZERO;
+        }
+    };
+    entries.iter()
+        .filter(|entry| entry.kind == EntryKind::Transaction
+            && matches!(entry.status, EntryStatus::Signed | EntryStatus::Submitted | EntryStatus::Replaced)
+            && entry.chain_id == Some(chain_id)
+            && entry.from == Some(from)
+            && entry.nonce == Some(U256::from(nonce)))
+        .map(|entry| entry.value)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Matching entries, newest first
+pub fn query(filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, String> {
+    let entries = load()?;
+    let matching = entries.into_iter().rev().filter(|entry| filter.matches(entry));
+    Ok(match filter.limit {
+        Some(limit) => matching.take(limit).collect(),
+        None => matching.collect(),
+    })
+}
+
+/// Latest state of one entry
+pub fn get_entry(id: &str) -> Result<Option<HistoryEntry>, String> {
+    Ok(load()?.into_iter().find(|entry| entry.id == id))
+}
+
+/// Export format for `export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// Export matching entries as a JSON array or CSV text
+pub fn export(filter: &HistoryFilter, format: ExportFormat) -> Result<String, String> {
+    let entries = query(filter)?;
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&entries).map_err(|e| e.to_string()),
+        ExportFormat::Csv => Ok(to_csv(&entries)),
+    }
+}
+
+fn to_csv(entries: &[HistoryEntry]) -> String {
+    let mut csv = String::from(
+        "id,created_at,updated_at,kind,status,chain_id,from,to,value,nonce,hash,transaction_hash,block_number,adapter,summary,error\n"
+    );
+    let text = |value: Option<String>| value.unwrap_or_default();
+    for entry in entries {
+        let fields = [
+            entry.id.clone(),
+            entry.created_at.clone(),
+            entry.updated_at.clone(),
+            enum_name(&entry.kind),
+            enum_name(&entry.status),
+            text(entry.chain_id.map(|chain_id| chain_id.to_string())),
+            text(entry.from.map(|from| from.to_string())),
+            text(entry.to.map(|to| to.to_string())),
+            entry.value.to_string(),
+            text(entry.nonce.map(|nonce| nonce.to_string())),
+            text(entry.hash.map(|hash| hash.to_string())),
+            text(entry.transaction_hash.map(|hash| hash.to_string())),
+            text(entry.block_number.map(|block| block.to_string())),
+            entry.adapter.clone(),
+            text(entry.summary.clone()),
+            text(entry.error.clone()),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn enum_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value).ok().and_then(|value| value.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_journal_lifecycle_query_and_export() {
+        storage::use_test_data_dir();
+        let from = Address::repeat_byte(0x31);
+        let to = Address::repeat_byte(0x32);
+        let tx_data = json!({ "from": from, "to": to, "value": "1000", "chainId": 84532, "nonce": 4 }).to_string();
+
+        let entry = HistoryEntry::for_operation(&TeeOperation::SignTransaction(tx_data.clone()), "Teaclave").unwrap();
+        assert_eq!(entry.summary.as_deref(), Some(eth::decode_transaction(&TxRequest::from_json(&tx_data).unwrap()).description.as_str()));
+        let hash = B256::repeat_byte(0x33);
+        let signed = TeeResult {
+            success: true,
+            message: String::new(),
+            data: Some(json!({ "from": from, "tx_hash": hash }).to_string()),
+        };
+        record_signing(entry.clone(), &Ok(signed));
+        mark_submitted(hash);
+        // A replacement with the same nonce has already been charged for the pending value
+        let replacement = TxRequest::from_json(&tx_data).unwrap();
+        assert_eq!(replaced_value(&replacement), U256::from(1000));
+        assert_eq!(replaced_value(&TxRequest { nonce: Some(5), ..replacement.clone() }), U256::ZERO);
+        mark_included(hash, hash, Some(12), None);
+        assert_eq!(replaced_value(&replacement), U256::ZERO);
+
+        let rejected = HistoryEntry::for_operation(&TeeOperation::SignTransaction(tx_data), "Teaclave").unwrap();
+        record_signing(rejected.clone(), &Err(TeeError::PolicyDenied("limit, \"daily\"".to_string())));
+        assert!(HistoryEntry::for_operation(&TeeOperation::GetPublicKey, "Teaclave").is_none());
+
+        // Safe transactions are journaled as the Safe's call, messages with their signer
+        let safe_request = SafeTransactionRequest {
+            safe: Address::repeat_byte(0x34),
+            chain_id: 84532,
+            tx: eth::SafeTransaction { to, value: U256::from(7), nonce: U256::from(2), ..Default::default() },
+        };
+        let safe_entry = HistoryEntry::for_operation(&TeeOperation::SignSafeTransaction(serde_json::to_string(&safe_request).unwrap()), "Teaclave").unwrap();
+        assert_eq!(safe_entry.kind, EntryKind::SafeTransaction);
+        assert_eq!((safe_entry.from, safe_entry.to, safe_entry.value), (Some(safe_request.safe), Some(to), U256::from(7)));
+        assert_eq!(safe_entry.hash, Some(safe_request.safe_tx_hash()));
+        let message = HistoryEntry::for_operation(&TeeOperation::SignMessage("{\"hello\": 1}\nsecond line".to_string()), "Teaclave").unwrap();
+        assert_eq!(message.kind, EntryKind::Message);
+        assert_eq!(message.request, json!("{\"hello\": 1}\nsecond line"));
+        assert_eq!(message.summary.as_deref(), Some("Sign message \"{\"hello\": 1}\""));
+        let signer = Address::repeat_byte(0x35);
+        record_signing(message.clone(), &Ok(TeeResult {
+            success: true,
+            message: String::new(),
+            data: Some(json!({ "signer": signer, "message_hash": message.hash }).to_string()),
+        }));
+        assert_eq!(get_entry(&message.id).unwrap().unwrap().from, Some(signer));
+
+        let confirmed = get_entry(&entry.id).unwrap().unwrap();
+        assert_eq!(confirmed.status, EntryStatus::Confirmed);
+        assert_eq!(confirmed.hash, Some(hash));
+        assert_eq!(confirmed.block_number, Some(12));
+        assert_eq!(confirmed.nonce, Some(U256::from(4)));
+        assert_eq!(get_entry(&rejected.id).unwrap().unwrap().status, EntryStatus::Rejected);
+
+        // Other tests share the data directory, so filter on this test's account
+        let filter = HistoryFilter { address: Some(to), ..Default::default() };
+        let entries = query(&filter).unwrap();
+        assert_eq!(entries.iter().map(|entry| entry.id.clone()).collect::<Vec<_>>(), vec![rejected.id.clone(), entry.id.clone()]);
+        let confirmed_only = HistoryFilter { status: Some(EntryStatus::Confirmed), ..filter.clone() };
+        assert_eq!(query(&confirmed_only).unwrap().len(), 1);
+        let future = HistoryFilter { since: Some("2999-01-01T00:00:00Z".to_string()), ..filter.clone() };
+        assert!(query(&future).unwrap().is_empty());
+
+        let csv = export(&filter, ExportFormat::Csv).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.contains("\"Transaction rejected by policy: limit, \"\"daily\"\"\""));
+        let json: Vec<HistoryEntry> = serde_json::from_str(&export(&filter, ExportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json, entries);
+    }
+}
@@ -4,6 +4,17 @@ pub mod hardware;
 pub mod fido;
 pub mod tee;
 pub mod plugin;
+pub mod eth;
+pub mod chain;
+pub mod policy;
+pub mod sender;
+pub mod bundler;
+pub mod paymaster;
+pub mod history;
+pub mod siwe;
+pub mod signature;
+pub mod safe;
+pub mod storage;
 
 // 重新导出常用类型
 pub use fido::passkey::PasskeyError;
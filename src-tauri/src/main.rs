@@ -8,6 +8,17 @@ mod fido;
 mod tee;
 mod plugin;
 mod demo;
+mod eth;
+mod chain;
+mod policy;
+mod sender;
+mod bundler;
+mod paymaster;
+mod history;
+mod siwe;
+mod signature;
+mod safe;
+mod storage;
 
 // 将biometric.rs添加到fido模块
 use fido::biometric;
@@ -25,6 +36,12 @@ fn main() {
     println!("COS72-Tauri: 操作系统: {}", std::env::consts::OS);
     println!("COS72-Tauri: 架构: {}", std::env::consts::ARCH);
     
+    // 启动时加载已注册的Passkey
+    match webauthn::load_passkeys() {
+        Ok(count) => println!("COS72-Tauri: 已加载 {} 个Passkey凭证", count),
+        Err(e) => println!("COS72-Tauri: Passkey存储加载失败: {}", e),
+    }
+    
     // 创建Tauri应用 - 简化版本，遵循Tauri 2.0标准模式
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
@@ -34,12 +51,65 @@ fn main() {
             get_tee_status,
             perform_tee_operation,
             initialize_tee,
+            get_chains,
+            save_chain,
+            remove_chain,
+            chain_get_balance,
+            chain_get_receipt,
+            chain_send_raw_transaction,
+            send_transaction,
+            speed_up_transaction,
+            cancel_transaction,
+            send_user_operation,
+            estimate_user_operation_gas,
+            get_user_operation_receipt,
+            get_user_operation,
+            bundler_supported_entry_points,
+            get_sponsorships,
+            get_history,
+            get_history_entry,
+            export_history,
+            siwe_create_message,
+            siwe_prepare,
+            siwe_sign,
+            siwe_verify,
+            verify_signature,
+            safe_get_info,
+            safe_propose_transaction,
+            safe_get_transactions,
+            safe_sign_transaction,
+            safe_add_signature,
+            safe_build_execution,
+            safe_remove_transaction,
+            get_tx_policy,
+            decode_transaction,
+            abi_encode_call,
+            abi_decode_call,
+            abi_decode_output,
+            abi_decode_revert,
+            abi_decode_log,
+            set_tx_policy,
             webauthn_supported,
             webauthn_biometric_supported,
             webauthn_start_registration,
             webauthn_finish_registration,
+            webauthn_start_add_device_authorization,
+            webauthn_start_add_device,
             webauthn_get_credentials,
+            security_key_sign_challenge,
+            webauthn_start_credential_change,
+            webauthn_rename_credential,
+            webauthn_unlock_credential,
+            webauthn_revoke_credential,
+            webauthn_get_config,
+            webauthn_set_config,
+            webauthn_reload_attestation_metadata,
+            soft_passkey_register,
+            soft_passkey_authenticate,
+            webauthn_start_authentication,
             webauthn_finish_authentication,
+            request_step_up,
+            finish_step_up,
             check_biometric_permission,
             request_biometric_permission,
             test_api_connection,
@@ -176,8 +246,32 @@ async fn get_tee_status() -> Result<tee::TeeStatus, String> {
 async fn perform_tee_operation(operation: String) -> Result<TeeResult, String> {
     println!("COS72-Tauri: Executing TEE operation: {}", operation);
     
-    // Parse operation type
-    let op = match operation.as_str() {
+    let op = parse_tee_operation(&operation)?;
+    
+    // Check if TEE environment is available
+    let tee_status = tee::get_tee_status().await.map_err(|e| e.to_string())?;
+    
+    if tee_status.available {
+        println!("COS72-Tauri: TEE environment available, executing operation");
+        match tee::perform_tee_operation(op).await {
+            Ok(result) => {
+                println!("COS72-Tauri: TEE operation successful");
+                Ok(result)
+            },
+            Err(e) => {
+                println!("COS72-Tauri: TEE operation failed: {:?}", e);
+                Err(e.to_string())
+            }
+        }
+    } else {
+        println!("COS72-Tauri: TEE environment not available");
+        Err("TEE environment not available, cannot execute operation".to_string())
+    }
+}
+
+// 解析TEE操作, 操作名或带 type 字段的 JSON
+fn parse_tee_operation(operation: &str) -> Result<TeeOperation, String> {
+    let op = match operation {
         "CreateWallet" => TeeOperation::CreateWallet,
         "GetPublicKey" => TeeOperation::GetPublicKey,
         "ExportWallet" => TeeOperation::ExportWallet(false),
@@ -185,7 +279,7 @@ async fn perform_tee_operation(operation: String) -> Result<TeeResult, String> {
         "ImportWallet" => TeeOperation::ImportWallet(String::new()),
         _ => {
             // Check if it's a JSON operation with parameters
-            if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&operation) {
+            if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(operation) {
                 if let Some(op_type) = json_value.get("type").and_then(|v| v.as_str()) {
                     match op_type {
                         "SignTransaction" => {
@@ -195,6 +289,21 @@ async fn perform_tee_operation(operation: String) -> Result<TeeResult, String> {
                                 return Err("Missing txData for SignTransaction operation".to_string());
                             }
                         },
+                        "SignUserOperation" => {
+                            let user_op_data = json_value.get("userOpData").and_then(|v| v.as_str())
+                                .ok_or_else(|| "Missing userOpData for SignUserOperation".to_string())?;
+                            TeeOperation::SignUserOperation(user_op_data.to_string())
+                        },
+                        "SignMessage" => {
+                            let message = json_value.get("message").and_then(|v| v.as_str())
+                                .ok_or_else(|| "Missing message for SignMessage".to_string())?;
+                            TeeOperation::SignMessage(message.to_string())
+                        },
+                        "SignSafeTransaction" => {
+                            let safe_tx_data = json_value.get("safeTxData").and_then(|v| v.as_str())
+                                .ok_or_else(|| "Missing safeTxData for SignSafeTransaction".to_string())?;
+                            TeeOperation::SignSafeTransaction(safe_tx_data.to_string())
+                        },
                         "VerifySignature" => {
                             let message = json_value.get("message").and_then(|v| v.as_str())
                                 .ok_or_else(|| "Missing message for VerifySignature".to_string())?;
@@ -207,6 +316,27 @@ async fn perform_tee_operation(operation: String) -> Result<TeeResult, String> {
                                 .ok_or_else(|| "Missing walletData for ImportWallet".to_string())?;
                             TeeOperation::ImportWallet(wallet_data.to_string())
                         },
+                        "CreateBackupShares" => {
+                            let threshold = json_value.get("threshold").and_then(|v| v.as_u64())
+                                .ok_or_else(|| "Missing threshold for CreateBackupShares".to_string())?;
+                            let share_count = json_value.get("shareCount").and_then(|v| v.as_u64())
+                                .ok_or_else(|| "Missing shareCount for CreateBackupShares".to_string())?;
+                            let passphrase = json_value.get("passphrase").and_then(|v| v.as_str()).unwrap_or("");
+                            let threshold = u8::try_from(threshold).map_err(|_| "threshold out of range".to_string())?;
+                            let share_count = u8::try_from(share_count).map_err(|_| "shareCount out of range".to_string())?;
+                            TeeOperation::CreateBackupShares(threshold, share_count, passphrase.to_string())
+                        },
+                        "RecoverWallet" => {
+                            // Recover from SLIP-39 shares through the regular import path
+                            let shares = json_value.get("shares").and_then(|v| v.as_array())
+                                .ok_or_else(|| "Missing shares for RecoverWallet".to_string())?;
+                            let passphrase = json_value.get("passphrase").and_then(|v| v.as_str()).unwrap_or("");
+                            let wallet_data = serde_json::json!({
+                                "slip39_shares": shares,
+                                "passphrase": passphrase
+                            });
+                            TeeOperation::ImportWallet(wallet_data.to_string())
+                        },
                         _ => return Err(format!("Unknown TEE operation type: {}", op_type))
                     }
                 } else {
@@ -217,26 +347,438 @@ async fn perform_tee_operation(operation: String) -> Result<TeeResult, String> {
             }
         }
     };
-    
-    // Check if TEE environment is available
-    let tee_status = tee::get_tee_status().await.map_err(|e| e.to_string())?;
-    
-    if tee_status.available {
-        println!("COS72-Tauri: TEE environment available, executing operation");
-        match tee::perform_tee_operation(op).await {
-            Ok(result) => {
-                println!("COS72-Tauri: TEE operation successful");
-                Ok(result)
-            },
-            Err(e) => {
-                println!("COS72-Tauri: TEE operation failed: {:?}", e);
-                Err(e.to_string())
+    Ok(op)
+}
+
+// 解析交易内容, 供签名确认界面展示
+// 链已配置时在最新区块上模拟执行, 返回是否成功、revert 原因、gas 用量和余额变化
+#[tauri::command]
+async fn decode_transaction(tx_data: String, chain_id: Option<u64>) -> Result<Value, String> {
+    use sender::TransactionSigner;
+
+    println!("COS72-Tauri: Decoding transaction for confirmation");
+    let mut tx = eth::TxRequest::from_json(&tx_data)?;
+    let summary = eth::decode_transaction(&tx);
+    println!("COS72-Tauri: Transaction summary: {}", summary.description);
+
+    let simulation = match chain_id.or(tx.chain_id).map(chain::get_chain) {
+        Some(Ok(chain)) => {
+            if tx.from.is_none() {
+                tx.from = sender::TeeSigner.address().await.ok();
+            }
+            let client = chain::RpcClient::new(chain.rpc_urls.clone());
+            match sender::simulate::simulate(&client, &tx).await {
+                Ok(simulation) => Some(simulation),
+                Err(e) => {
+                    println!("COS72-Tauri: Transaction simulation failed: {}", e);
+                    None
+                }
             }
+        },
+        _ => None,
+    };
+
+    let mut payload = serde_json::to_value(summary).map_err(|e| e.to_string())?;
+    payload["simulation"] = serde_json::json!(simulation);
+    Ok(payload)
+}
+
+// ABI编码合约调用
+#[tauri::command]
+fn abi_encode_call(abi: String, function: String, args: Vec<Value>) -> Result<Value, String> {
+    println!("COS72-Tauri: Encoding contract call: {}", function);
+    let contract = eth::ContractAbi::parse(&abi)?;
+    let data = contract.encode_call(&function, &args)?;
+    let signature = contract.function(&function, Some(args.len()))?.signature();
+    Ok(serde_json::json!({
+        "data": data,
+        "selector": format!("0x{}", hex::encode(&data[..4])),
+        "signature": signature
+    }))
+}
+
+// ABI解码调用数据
+#[tauri::command]
+fn abi_decode_call(abi: String, data: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Decoding contract calldata");
+    eth::ContractAbi::parse(&abi)?.decode_call(&parse_hex_data(&data)?)
+}
+
+// ABI解码函数返回值
+#[tauri::command]
+fn abi_decode_output(abi: String, function: String, data: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Decoding return data of {}", function);
+    eth::ContractAbi::parse(&abi)?.decode_output(&function, &parse_hex_data(&data)?)
+}
+
+// 解码revert原因 (Error(string)、Panic(uint256)或ABI中的自定义错误)
+#[tauri::command]
+fn abi_decode_revert(data: String, abi: Option<String>) -> Result<Value, String> {
+    println!("COS72-Tauri: Decoding revert data");
+    let contract = abi.as_deref().map(eth::ContractAbi::parse).transpose()?;
+    Ok(eth::decode_revert_with(contract.as_ref(), &parse_hex_data(&data)?))
+}
+
+// ABI解码事件日志
+#[tauri::command]
+fn abi_decode_log(abi: String, topics: Vec<String>, data: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Decoding event log");
+    let topics = topics.iter()
+        .map(|topic| topic.parse::<alloy_primitives::B256>().map_err(|e| format!("Invalid topic {}: {}", topic, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    eth::ContractAbi::parse(&abi)?.decode_log(&topics, &parse_hex_data(&data)?)
+}
+
+fn parse_hex_data(data: &str) -> Result<alloy_primitives::Bytes, String> {
+    data.trim().parse().map_err(|e| format!("Invalid hex data: {}", e))
+}
+
+fn parse_address(address: &str) -> Result<alloy_primitives::Address, String> {
+    address.trim().parse().map_err(|e| format!("Invalid address: {}", e))
+}
+
+fn parse_hash(hash: &str) -> Result<alloy_primitives::B256, String> {
+    hash.trim().parse().map_err(|e| format!("Invalid hash: {}", e))
+}
+
+// 获取链配置列表
+#[tauri::command]
+fn get_chains() -> Vec<chain::ChainConfig> {
+    println!("COS72-Tauri: Getting chain registry");
+    chain::list_chains()
+}
+
+// 添加或更新链配置
+#[tauri::command]
+fn save_chain(config: chain::ChainConfig) -> Result<Vec<chain::ChainConfig>, String> {
+    println!("COS72-Tauri: Saving chain configuration: {} ({})", config.name, config.chain_id);
+    chain::save_chain(config)?;
+    Ok(chain::list_chains())
+}
+
+// 删除自定义链配置
+#[tauri::command]
+fn remove_chain(chain_id: u64) -> Result<bool, String> {
+    println!("COS72-Tauri: Removing chain configuration: {}", chain_id);
+    chain::remove_chain(chain_id)
+}
+
+// 查询账户余额
+#[tauri::command]
+async fn chain_get_balance(chain_id: u64, address: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Getting balance of {} on chain {}", address, chain_id);
+    let address: alloy_primitives::Address = address.parse().map_err(|e| format!("Invalid address: {}", e))?;
+    let (config, client) = chain::client(chain_id)?;
+    let balance = client.get_balance(address, "latest").await.map_err(|e| e.to_string())?;
+    Ok(serde_json::json!({
+        "balance": balance.to_string(),
+        "formatted": alloy_primitives::utils::format_units(balance, config.native_currency.decimals).map_err(|e| e.to_string())?,
+        "symbol": config.native_currency.symbol
+    }))
+}
+
+// 查询交易回执
+#[tauri::command]
+async fn chain_get_receipt(chain_id: u64, tx_hash: String) -> Result<Option<chain::TransactionReceipt>, String> {
+    println!("COS72-Tauri: Getting receipt of {} on chain {}", tx_hash, chain_id);
+    let tx_hash = tx_hash.parse().map_err(|e| format!("Invalid transaction hash: {}", e))?;
+    let (_, client) = chain::client(chain_id)?;
+    client.get_transaction_receipt(tx_hash).await.map_err(|e| e.to_string())
+}
+
+// 广播已签名交易
+#[tauri::command]
+async fn chain_send_raw_transaction(chain_id: u64, raw_tx: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Broadcasting raw transaction on chain {}", chain_id);
+    let (config, client) = chain::client(chain_id)?;
+    let tx_hash = client.send_raw_transaction(&parse_hex_data(&raw_tx)?).await.map_err(|e| e.to_string())?;
+    let tx_hash = tx_hash.to_string();
+    Ok(serde_json::json!({
+        "tx_hash": tx_hash,
+        "explorer_url": config.explorer_tx_url(&tx_hash)
+    }))
+}
+
+// 发送交易: 获取 nonce、估算 gas 和 EIP-1559 费用、策略检查、TEE 签名并广播
+// 状态通过窗口事件推送: tx-submitted, tx-confirmed, tx-failed
+#[tauri::command]
+async fn send_transaction(
+    window: tauri::Window,
+    chain_id: u64,
+    from: Option<String>,
+    to: Option<String>,
+    value: Option<String>,
+    data: Option<String>,
+) -> Result<Value, String> {
+    println!("COS72-Tauri: Sending transaction on chain {}", chain_id);
+    let chain = chain::get_chain(chain_id)?;
+    let tx = eth::TxRequest::from_json(&serde_json::json!({
+        "from": from,
+        "to": to,
+        "value": value,
+        "data": data,
+        "chainId": chain_id
+    }).to_string())?;
+
+    let submitted = sender::send_transaction(&chain, &sender::TeeSigner, tx, window_events(window), sender::SendOptions::default()).await?;
+    serde_json::to_value(submitted).map_err(|e| e.to_string())
+}
+
+// 加速待确认交易: 以相同 nonce 和提高至少 10% 的费用重新签名并广播
+#[tauri::command]
+async fn speed_up_transaction(window: tauri::Window, id: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Speeding up transaction {}", id);
+    replace_transaction(window, &id, sender::ReplacementKind::SpeedUp).await
+}
+
+// 取消待确认交易: 以相同 nonce 发送 0 金额的自转账替换
+#[tauri::command]
+async fn cancel_transaction(window: tauri::Window, id: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Cancelling transaction {}", id);
+    replace_transaction(window, &id, sender::ReplacementKind::Cancel).await
+}
+
+async fn replace_transaction(window: tauri::Window, id: &str, kind: sender::ReplacementKind) -> Result<Value, String> {
+    let entry = history::get_entry(id)?.ok_or_else(|| format!("No history entry {}", id))?;
+    let chain_id = entry.chain_id.ok_or_else(|| format!("History entry {} has no chain", id))?;
+    let chain = chain::get_chain(chain_id)?;
+    let submitted = sender::replace_transaction(
+        &chain, &sender::TeeSigner, &entry, kind, window_events(window), sender::SendOptions::default()
+    ).await?;
+    serde_json::to_value(submitted).map_err(|e| e.to_string())
+}
+
+// 将发送状态事件转发到窗口
+fn window_events(window: tauri::Window) -> sender::EventSink {
+    use tauri::Emitter;
+
+    std::sync::Arc::new(move |event, payload| {
+        if let Err(e) = window.emit(event, payload) {
+            println!("COS72-Tauri: Failed to emit {} event: {}", event, e);
         }
-    } else {
-        println!("COS72-Tauri: TEE environment not available");
-        Err("TEE environment not available, cannot execute operation".to_string())
-    }
+    })
+}
+
+// 发送 ERC-4337 UserOperation: 从 EntryPoint 获取 nonce、估算费用和 gas、TEE 签名并提交给 bundler
+// 指定 sponsor 时由该链配置的社区 paymaster 代付 gas
+// 状态通过窗口事件推送: userop-submitted, userop-confirmed, userop-failed
+#[tauri::command]
+async fn send_user_operation(
+    window: tauri::Window,
+    chain_id: u64,
+    user_op: eth::UserOperation,
+    entry_point: Option<String>,
+    sponsor: Option<String>,
+) -> Result<Value, String> {
+    println!("COS72-Tauri: Sending UserOperation from {} on chain {}", user_op.sender, chain_id);
+    let chain = chain::get_chain(chain_id)?;
+    let entry_point = parse_entry_point(entry_point)?;
+    let submitted = bundler::send_user_operation(
+        &chain, &sender::TeeSigner, user_op, entry_point, sponsor.as_deref(),
+        window_events(window), sender::SendOptions::default()
+    ).await?;
+    serde_json::to_value(submitted).map_err(|e| e.to_string())
+}
+
+// 估算 UserOperation 的 gas
+#[tauri::command]
+async fn estimate_user_operation_gas(chain_id: u64, user_op: eth::UserOperation, entry_point: Option<String>) -> Result<Value, String> {
+    println!("COS72-Tauri: Estimating UserOperation gas on chain {}", chain_id);
+    let bundler = bundler::client_for(&chain::get_chain(chain_id)?)?;
+    let entry_point = parse_entry_point(entry_point)?.unwrap_or(eth::ENTRY_POINT_V07);
+    let estimate = bundler.estimate_user_operation_gas(&user_op, entry_point).await.map_err(|e| e.to_string())?;
+    serde_json::to_value(estimate).map_err(|e| e.to_string())
+}
+
+// 获取 UserOperation 回执
+#[tauri::command]
+async fn get_user_operation_receipt(chain_id: u64, user_op_hash: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Getting UserOperation receipt of {} on chain {}", user_op_hash, chain_id);
+    let user_op_hash = user_op_hash.parse().map_err(|e| format!("Invalid UserOperation hash: {}", e))?;
+    let bundler = bundler::client_for(&chain::get_chain(chain_id)?)?;
+    let receipt = bundler.get_user_operation_receipt(user_op_hash).await.map_err(|e| e.to_string())?;
+    serde_json::to_value(receipt).map_err(|e| e.to_string())
+}
+
+// 按哈希查询 UserOperation
+#[tauri::command]
+async fn get_user_operation(chain_id: u64, user_op_hash: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Getting UserOperation {} on chain {}", user_op_hash, chain_id);
+    let user_op_hash = user_op_hash.parse().map_err(|e| format!("Invalid UserOperation hash: {}", e))?;
+    let bundler = bundler::client_for(&chain::get_chain(chain_id)?)?;
+    let user_op = bundler.get_user_operation_by_hash(user_op_hash).await.map_err(|e| e.to_string())?;
+    serde_json::to_value(user_op).map_err(|e| e.to_string())
+}
+
+// 获取 bundler 支持的 EntryPoint 列表
+#[tauri::command]
+async fn bundler_supported_entry_points(chain_id: u64) -> Result<Vec<String>, String> {
+    println!("COS72-Tauri: Getting supported EntryPoints on chain {}", chain_id);
+    let bundler = bundler::client_for(&chain::get_chain(chain_id)?)?;
+    let entry_points = bundler.supported_entry_points().await.map_err(|e| e.to_string())?;
+    Ok(entry_points.iter().map(|entry_point| entry_point.to_checksum(None)).collect())
+}
+
+// 获取 paymaster 代付记录
+#[tauri::command]
+fn get_sponsorships() -> Result<Vec<Value>, String> {
+    println!("COS72-Tauri: Getting sponsorship records");
+    paymaster::list_sponsorships()
+}
+
+// 查询本地交易和 UserOperation 历史记录 (最新的在前)
+#[tauri::command]
+fn get_history(filter: Option<history::HistoryFilter>) -> Result<Vec<history::HistoryEntry>, String> {
+    println!("COS72-Tauri: Querying transaction history");
+    history::query(&filter.unwrap_or_default())
+}
+
+// 获取单条历史记录的最新状态
+#[tauri::command]
+fn get_history_entry(id: String) -> Result<Option<history::HistoryEntry>, String> {
+    println!("COS72-Tauri: Getting history entry {}", id);
+    history::get_entry(&id)
+}
+
+// 导出历史记录, format 为 "json" 或 "csv"
+#[tauri::command]
+fn export_history(filter: Option<history::HistoryFilter>, format: history::ExportFormat) -> Result<String, String> {
+    println!("COS72-Tauri: Exporting transaction history as {:?}", format);
+    history::export(&filter.unwrap_or_default(), format)
+}
+
+fn parse_entry_point(entry_point: Option<String>) -> Result<Option<alloy_primitives::Address>, String> {
+    entry_point
+        .map(|entry_point| entry_point.parse().map_err(|e| format!("Invalid EntryPoint address: {}", e)))
+        .transpose()
+}
+
+// 生成 SIWE (EIP-4361) 登录消息, 未指定地址时使用钱包地址, 未指定 nonce 时随机生成
+#[tauri::command]
+async fn siwe_create_message(request: siwe::SiweRequest) -> Result<String, String> {
+    use sender::TransactionSigner;
+
+    println!("COS72-Tauri: Creating SIWE message for {}", request.domain);
+    let wallet = match request.address {
+        Some(address) => address,
+        None => sender::TeeSigner.address().await.map_err(|e| e.to_string())?,
+    };
+    request.build(wallet)
+}
+
+// 解析并校验 SIWE 登录请求, 返回确认界面展示的摘要; origin 为发起请求的域名
+#[tauri::command]
+async fn siwe_prepare(message: String, origin: Option<String>) -> Result<siwe::SiweSummary, String> {
+    use sender::TransactionSigner;
+
+    println!("COS72-Tauri: Preparing SIWE confirmation");
+    let wallet = sender::TeeSigner.address().await.map_err(|e| e.to_string())?;
+    siwe::prepare(&message, origin.as_deref(), wallet)
+}
+
+// 使用 TEE 钱包签名 SIWE 登录消息
+#[tauri::command]
+async fn siwe_sign(message: String, origin: Option<String>) -> Result<siwe::SignedSiwe, String> {
+    println!("COS72-Tauri: Signing SIWE message");
+    siwe::sign(&message, origin.as_deref(), &sender::TeeSigner).await
+}
+
+// 验证 SIWE 签名, 智能合约账户通过消息所在链的 ERC-1271 校验
+#[tauri::command]
+async fn siwe_verify(message: String, signature: String, expected: Option<siwe::SiweExpectations>) -> Result<siwe::VerifiedSiwe, String> {
+    println!("COS72-Tauri: Verifying SIWE signature");
+    let signature = parse_hex_data(&signature)?;
+    let chain_id = siwe::SiweMessage::parse(&message)?.chain_id;
+    let client = chain::get_chain(chain_id).ok().map(|chain| chain::RpcClient::new(chain.rpc_urls));
+    siwe::verify(&message, &signature, &expected.unwrap_or_default(), client.as_ref()).await
+}
+
+// 验证个人消息签名, 支持 EOA、已部署智能账户 (ERC-1271) 和未部署智能账户 (ERC-6492)
+#[tauri::command]
+async fn verify_signature(address: String, message: String, signature: String, chain_id: Option<u64>) -> Result<Value, String> {
+    println!("COS72-Tauri: Verifying signature of {}", address);
+    let address = parse_address(&address)?;
+    let signature = parse_hex_data(&signature)?;
+    let client = match chain_id {
+        Some(chain_id) => Some(chain::client(chain_id)?.1),
+        None => None,
+    };
+    let kind = signature::verify(client.as_ref(), address, signature::personal_message_hash(&message), &signature).await?;
+    Ok(serde_json::json!({ "is_valid": kind.is_some(), "kind": kind }))
+}
+
+// 读取 Safe 多签钱包的所有者、阈值和 nonce
+#[tauri::command]
+async fn safe_get_info(chain_id: u64, safe: String) -> Result<safe::SafeInfo, String> {
+    println!("COS72-Tauri: Reading Safe {} on chain {}", safe, chain_id);
+    let (_, client) = chain::client(chain_id)?;
+    safe::get_info(&client, parse_address(&safe)?).await
+}
+
+// 发起 Safe 多签交易, 未指定 nonce 时使用 Safe 当前 nonce
+#[tauri::command]
+async fn safe_propose_transaction(chain_id: u64, safe: String, tx: Value) -> Result<safe::PendingSafeTransaction, String> {
+    println!("COS72-Tauri: Proposing Safe transaction for {}", safe);
+    let (_, client) = chain::client(chain_id)?;
+    safe::propose(&client, parse_address(&safe)?, chain_id, tx).await
+}
+
+// 获取待签名的 Safe 多签交易
+#[tauri::command]
+fn safe_get_transactions(safe: Option<String>) -> Result<Vec<safe::PendingSafeTransaction>, String> {
+    println!("COS72-Tauri: Getting pending Safe transactions");
+    safe::list_pending(safe.as_deref().map(parse_address).transpose()?)
+}
+
+// 使用 TEE 钱包作为所有者签名 Safe 多签交易
+#[tauri::command]
+async fn safe_sign_transaction(safe_tx_hash: String) -> Result<safe::PendingSafeTransaction, String> {
+    println!("COS72-Tauri: Signing Safe transaction {}", safe_tx_hash);
+    let safe_tx_hash = parse_hash(&safe_tx_hash)?;
+    let (_, client) = chain::client(safe::get_pending(safe_tx_hash)?.chain_id)?;
+    safe::sign(&client, safe_tx_hash, &sender::TeeSigner).await
+}
+
+// 导入其他所有者的签名
+#[tauri::command]
+async fn safe_add_signature(safe_tx_hash: String, signature: String) -> Result<safe::PendingSafeTransaction, String> {
+    println!("COS72-Tauri: Adding owner signature to Safe transaction {}", safe_tx_hash);
+    let safe_tx_hash = parse_hash(&safe_tx_hash)?;
+    let (_, client) = chain::client(safe::get_pending(safe_tx_hash)?.chain_id)?;
+    safe::add_signature(&client, safe_tx_hash, &parse_hex_data(&signature)?).await
+}
+
+// 签名达到阈值后构建 execTransaction 调用
+#[tauri::command]
+async fn safe_build_execution(safe_tx_hash: String) -> Result<safe::SafeExecution, String> {
+    println!("COS72-Tauri: Building execution of Safe transaction {}", safe_tx_hash);
+    let safe_tx_hash = parse_hash(&safe_tx_hash)?;
+    let (_, client) = chain::client(safe::get_pending(safe_tx_hash)?.chain_id)?;
+    safe::build_execution(&client, safe_tx_hash).await
+}
+
+// 删除待签名的 Safe 多签交易 (已执行或放弃)
+#[tauri::command]
+fn safe_remove_transaction(safe_tx_hash: String) -> Result<bool, String> {
+    println!("COS72-Tauri: Removing Safe transaction {}", safe_tx_hash);
+    safe::remove_pending(parse_hash(&safe_tx_hash)?)
+}
+
+// 获取交易签名策略
+#[tauri::command]
+fn get_tx_policy() -> Value {
+    println!("COS72-Tauri: Getting transaction policy");
+    policy::get_policy()
+}
+
+// 更新交易签名策略 (服务器模式下禁止客户端修改)
+#[tauri::command]
+fn set_tx_policy(rules: Value) -> Result<Value, String> {
+    println!("COS72-Tauri: Updating transaction policy");
+    let rules: policy::PolicyRules = serde_json::from_value(rules)
+        .map_err(|e| format!("Invalid transaction policy: {}", e))?;
+    policy::set_policy(rules)?;
+    Ok(policy::get_policy())
 }
 
 // 检查是否支持WebAuthn
@@ -264,18 +806,146 @@ async fn webauthn_start_registration(username: String) -> Result<Value, String>
     webauthn::start_registration(&username)
 }
 
-// 完成注册流程
+// 开始添加设备前的认证，需由该用户已有的Passkey确认
 #[tauri::command]
-async fn webauthn_finish_registration(user_id: String, response: String) -> Result<Value, String> {
+async fn webauthn_start_add_device_authorization(user_id: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Starting authentication to add a Passkey device, user ID: {}", user_id);
+    webauthn::start_add_device_authorization(&user_id)
+}
+
+// 为已有用户添加另一台设备的Passkey，需附上该用户已有Passkey的断言
+#[tauri::command]
+async fn webauthn_start_add_device(window: tauri::Window, user_id: String, ceremony_id: String, response: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Starting Passkey add-device registration, user ID: {}", user_id);
+    webauthn::start_add_device(&user_id, &credential_authorization(window, ceremony_id, response))
+}
+
+// 完成注册流程，可为新凭证指定名称
+#[tauri::command]
+async fn webauthn_finish_registration(user_id: String, response: String, label: Option<String>) -> Result<Value, String> {
     println!("COS72-Tauri: Completing Passkey registration process, user ID: {}", user_id);
-    webauthn::finish_registration(&user_id, &response)
+    webauthn::finish_registration(&user_id, &response, label.as_deref())
+}
+
+// 开始认证流程，不指定用户时为无用户名登录（可发现凭证）
+#[tauri::command]
+async fn webauthn_start_authentication(user_id: Option<String>) -> Result<Value, String> {
+    println!("COS72-Tauri: Starting Passkey authentication process, user ID: {:?}", user_id);
+    webauthn::start_authentication(user_id.as_deref())
 }
 
 // 完成认证流程
 #[tauri::command]
-async fn webauthn_finish_authentication(response: String) -> Result<Value, String> {
-    println!("COS72-Tauri: Completing Passkey authentication process");
-    webauthn::finish_authentication(&response)
+async fn webauthn_finish_authentication(window: tauri::Window, ceremony_id: String, response: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Completing Passkey authentication process, ceremony ID: {}", ceremony_id);
+    webauthn::finish_authentication(&ceremony_id, &response, &window_events(window))
+}
+
+// 为签名交易或导出钱包发起Passkey二次验证, 须由钱包所有者的Passkey完成, 挑战与操作摘要绑定
+#[tauri::command]
+async fn request_step_up(operation: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Requesting passkey step-up for TEE operation");
+    let op = parse_tee_operation(&operation)?;
+    policy::step_up::start(&op)
+}
+
+// 完成Passkey二次验证, 通过后对应操作可在短时间内执行一次
+#[tauri::command]
+async fn finish_step_up(window: tauri::Window, ceremony_id: String, response: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Completing passkey step-up, ceremony ID: {}", ceremony_id);
+    policy::step_up::finish(&ceremony_id, &response, &window_events(window))
+}
+
+// 开始认证流程以授权修改凭证, 须由同一用户的另一个未锁定Passkey完成
+#[tauri::command]
+async fn webauthn_start_credential_change(credential_id: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Starting authentication to change Passkey credential {}", credential_id);
+    webauthn::start_credential_change(&credential_id)
+}
+
+fn credential_authorization(window: tauri::Window, ceremony_id: String, response: String) -> webauthn::CredentialAuthorization {
+    webauthn::CredentialAuthorization { ceremony_id, response, events: window_events(window) }
+}
+
+// 使用USB安全密钥签名WebAuthn仪式的挑战（如Passkey二次验证），返回的断言交给对应的finish命令
+#[tauri::command]
+async fn security_key_sign_challenge(challenge: String, pin: Option<String>) -> Result<Value, String> {
+    println!("COS72-Tauri: Signing ceremony challenge with a security key");
+    let response = fido::passkey::sign_ceremony_challenge(&challenge, pin.as_deref()).await
+        .map_err(|e| e.to_string())?;
+    Ok(serde_json::json!({ "response": response }))
+}
+
+// 重命名凭证
+#[tauri::command]
+async fn webauthn_rename_credential(window: tauri::Window, credential_id: String, label: String, ceremony_id: String, response: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Renaming Passkey credential {}", credential_id);
+    webauthn::rename_credential(&credential_id, &label, &credential_authorization(window, ceremony_id, response))
+}
+
+// 解锁因签名计数器回退而被锁定的凭证
+#[tauri::command]
+async fn webauthn_unlock_credential(window: tauri::Window, credential_id: String, ceremony_id: String, response: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Unlocking Passkey credential {}", credential_id);
+    webauthn::unlock_credential(&credential_id, &credential_authorization(window, ceremony_id, response))
+}
+
+// 吊销凭证
+#[tauri::command]
+async fn webauthn_revoke_credential(window: tauri::Window, credential_id: String, ceremony_id: String, response: String) -> Result<Value, String> {
+    println!("COS72-Tauri: Revoking Passkey credential {}", credential_id);
+    webauthn::revoke_credential(&credential_id, &credential_authorization(window, ceremony_id, response))
+}
+
+// 使用软件Passkey认证器注册（无指纹识别器的Linux主机和CI，需要device-support功能）
+#[tauri::command]
+async fn soft_passkey_register(window: tauri::Window, username: String) -> Result<Value, String> {
+    #[cfg(feature = "device-support")]
+    {
+        fido::soft_authenticator::register(&username, &window_events(window))
+    }
+    #[cfg(not(feature = "device-support"))]
+    {
+        let _ = window;
+        Err(format!("Soft passkey for {} unavailable: built without the device-support feature", username))
+    }
+}
+
+// 使用软件Passkey认证器登录
+#[tauri::command]
+async fn soft_passkey_authenticate(window: tauri::Window, user_id: String) -> Result<Value, String> {
+    #[cfg(feature = "device-support")]
+    {
+        fido::soft_authenticator::authenticate(&user_id, &window_events(window))
+    }
+    #[cfg(not(feature = "device-support"))]
+    {
+        let _ = window;
+        Err(format!("Soft passkey for {} unavailable: built without the device-support feature", user_id))
+    }
+}
+
+// 获取WebAuthn依赖方配置
+#[tauri::command]
+fn webauthn_get_config() -> Result<Value, String> {
+    serde_json::to_value(webauthn::get_config()).map_err(|e| format!("Failed to serialize WebAuthn configuration: {}", e))
+}
+
+// 更新WebAuthn依赖方配置（RP ID、名称、允许的来源和超时），并重建WebAuthn实例
+#[tauri::command]
+fn webauthn_set_config(config: Value) -> Result<Value, String> {
+    println!("COS72-Tauri: Updating WebAuthn relying party configuration");
+    let config: fido::config::WebauthnConfig = serde_json::from_value(config)
+        .map_err(|e| format!("Invalid WebAuthn configuration: {}", e))?;
+    webauthn::set_config(config)?;
+    webauthn_get_config()
+}
+
+// 重新加载当前配置的FIDO元数据文件（离线MDS3 blob），用于更新受信任的认证器列表
+#[tauri::command]
+fn webauthn_reload_attestation_metadata() -> Result<Value, String> {
+    println!("COS72-Tauri: Reloading FIDO attestation metadata");
+    webauthn::reload_attestation_metadata()
 }
 
 // 获取用户凭证
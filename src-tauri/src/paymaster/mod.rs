@@ -0,0 +1,216 @@
+// Paymaster Module
+// Gas sponsorship for UserOperations through community paymaster services (ERC-7677
+// pm_getPaymasterStubData / pm_getPaymasterData), verification of verifying-paymaster
+// signatures before the user signs, and a record of which sponsor paid.
+
+pub mod verifying;
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::chain::rpc::{RpcClient, RpcError};
+use crate::chain::PaymasterConfig;
+use crate::eth::{EntryPointVersion, UserOperation};
+use crate::storage;
+use verifying::VerifyingPaymasterData;
+
+// Constants
+const SPONSORSHIP_LOG_FILE: &str = "sponsorships.jsonl";
+
+/// Sponsor information a paymaster service may return for display
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SponsorInfo {
+    pub name: String,
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+/// Result of pm_getPaymasterStubData and pm_getPaymasterData
+///
+/// v0.7 services return the paymaster fields, v0.6 services `paymasterAndData`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymasterResponse {
+    #[serde(default)]
+    pub paymaster: Option<Address>,
+    #[serde(default)]
+    pub paymaster_data: Option<Bytes>,
+    #[serde(default)]
+    pub paymaster_verification_gas_limit: Option<U256>,
+    #[serde(default)]
+    pub paymaster_post_op_gas_limit: Option<U256>,
+    #[serde(default)]
+    pub paymaster_and_data: Option<Bytes>,
+    #[serde(default)]
+    pub sponsor: Option<SponsorInfo>,
+    /// Stub data is already final and pm_getPaymasterData can be skipped
+    #[serde(default)]
+    pub is_final: bool,
+}
+
+impl PaymasterResponse {
+    /// Fill the UserOperation's paymaster fields for the EntryPoint version
+    pub fn apply(&self, user_op: &mut UserOperation, version: EntryPointVersion) -> Result<(), String> {
+        match version {
+            EntryPointVersion::V06 => {
+                let paymaster_and_data = self.paymaster_and_data.clone()
+                    .ok_or_else(|| "Paymaster response has no paymasterAndData".to_string())?;
+                user_op.paymaster_and_data = Some(paymaster_and_data);
+            },
+            EntryPointVersion::V07 => {
+                user_op.paymaster = Some(self.paymaster
+                    .ok_or_else(|| "Paymaster response has no paymaster".to_string())?);
+                user_op.paymaster_data = Some(self.paymaster_data.clone().unwrap_or_default());
+                if self.paymaster_verification_gas_limit.is_some() {
+                    user_op.paymaster_verification_gas_limit = self.paymaster_verification_gas_limit;
+                }
+                if self.paymaster_post_op_gas_limit.is_some() {
+                    user_op.paymaster_post_op_gas_limit = self.paymaster_post_op_gas_limit;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// ERC-7677 client for one paymaster service
+pub struct PaymasterClient {
+    rpc: RpcClient,
+    context: Value,
+}
+
+impl PaymasterClient {
+    pub fn new(config: &PaymasterConfig) -> Self {
+        Self {
+            rpc: RpcClient::new(vec![config.url.clone()]),
+            context: config.context.clone().unwrap_or_else(|| json!({})),
+        }
+    }
+
+    /// Placeholder paymaster data for gas estimation
+    pub async fn get_paymaster_stub_data(&self, user_op: &UserOperation, entry_point: Address, chain_id: u64) -> Result<PaymasterResponse, RpcError> {
+        self.rpc.request("pm_getPaymasterStubData", self.params(user_op, entry_point, chain_id)).await
+    }
+
+    /// Final paymaster data for the estimated UserOperation
+    pub async fn get_paymaster_data(&self, user_op: &UserOperation, entry_point: Address, chain_id: u64) -> Result<PaymasterResponse, RpcError> {
+        self.rpc.request("pm_getPaymasterData", self.params(user_op, entry_point, chain_id)).await
+    }
+
+    fn params(&self, user_op: &UserOperation, entry_point: Address, chain_id: u64) -> Value {
+        json!([user_op, entry_point, format!("{:#x}", chain_id), self.context])
+    }
+}
+
+/// Paymaster address and the data after it, for either EntryPoint version
+fn paymaster_fields(user_op: &UserOperation, version: EntryPointVersion) -> Option<(Address, Bytes)> {
+    match version {
+        EntryPointVersion::V06 => {
+            let paymaster_and_data = user_op.paymaster_and_data.as_ref().filter(|data| data.len() >= 20)?;
+            Some((Address::from_slice(&paymaster_and_data[..20]), paymaster_and_data.slice(20..)))
+        },
+        EntryPointVersion::V07 => Some((user_op.paymaster?, user_op.paymaster_data.clone().unwrap_or_default())),
+    }
+}
+
+/// Check a verifying paymaster's signature and validity window before the user signs
+pub fn verify_sponsorship(
+    user_op: &UserOperation,
+    version: EntryPointVersion,
+    chain_id: u64,
+    expected_signer: Address,
+    now: u64,
+) -> Result<Address, String> {
+    let (paymaster, paymaster_data) = paymaster_fields(user_op, version)
+        .ok_or_else(|| "UserOperation has no paymaster".to_string())?;
+    let data = VerifyingPaymasterData::decode(&paymaster_data)?;
+    data.check_validity(now)?;
+
+    let signer = data.recover_signer(user_op, version, paymaster, chain_id)?;
+    if signer != expected_signer {
+        return Err(format!("Paymaster data signed by {}, expected {}", signer, expected_signer));
+    }
+    Ok(paymaster)
+}
+
+/// Sponsorship of a UserOperation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sponsorship {
+    /// Community sponsor that paid, as reported by the service or configured
+    pub sponsor: String,
+    /// Configured paymaster service
+    pub service: String,
+    pub paymaster: Address,
+}
+
+impl Sponsorship {
+    pub fn new(config: &PaymasterConfig, user_op: &UserOperation, version: EntryPointVersion, sponsor: Option<&SponsorInfo>) -> Option<Self> {
+        let (paymaster, _) = paymaster_fields(user_op, version)?;
+        Some(Self {
+            sponsor: sponsor.map(|sponsor| sponsor.name.clone()).unwrap_or_else(|| config.name.clone()),
+            service: config.name.clone(),
+            paymaster,
+        })
+    }
+}
+
+/// Record a sponsored UserOperation once included
+pub fn record_sponsorship(sponsorship: &Sponsorship, chain_id: u64, user_op_hash: B256, transaction_hash: B256, actual_gas_cost: U256) {
+    let record = json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "chain_id": chain_id,
+        "user_op_hash": user_op_hash,
+        "transaction_hash": transaction_hash,
+        "sponsor": sponsorship.sponsor,
+        "service": sponsorship.service,
+        "paymaster": sponsorship.paymaster,
+        "actual_gas_cost": actual_gas_cost.to_string(),
+    });
+    if let Err(e) = storage::append_line(SPONSORSHIP_LOG_FILE, &record.to_string()) {
+        println!("COS72-Tauri: Failed to record sponsorship: {}", e);
+    }
+}
+
+/// Recorded sponsorships, oldest first
+pub fn list_sponsorships() -> Result<Vec<Value>, String> {
+    let lines = storage::read_lines(SPONSORSHIP_LOG_FILE)
+        .map_err(|e| format!("Failed to read sponsorships: {}", e))?;
+    Ok(lines.iter().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_paymaster_response() {
+        let paymaster = Address::repeat_byte(0x99);
+        let response: PaymasterResponse = serde_json::from_value(json!({
+            "paymaster": paymaster,
+            "paymasterData": "0x1234",
+            "paymasterVerificationGasLimit": "0x1000",
+            "sponsor": { "name": "AAStar Community" },
+            "isFinal": true
+        })).unwrap();
+
+        let mut user_op = UserOperation { paymaster_post_op_gas_limit: Some(U256::from(5)), ..Default::default() };
+        response.apply(&mut user_op, EntryPointVersion::V07).unwrap();
+        assert_eq!(user_op.paymaster, Some(paymaster));
+        assert_eq!(user_op.paymaster_verification_gas_limit, Some(U256::from(0x1000)));
+        assert_eq!(user_op.paymaster_post_op_gas_limit, Some(U256::from(5)));
+        assert!(response.apply(&mut user_op, EntryPointVersion::V06).is_err());
+
+        let v06: PaymasterResponse = serde_json::from_value(json!({
+            "paymasterAndData": format!("{}{}", paymaster, "abcd")
+        })).unwrap();
+        let mut user_op = UserOperation::default();
+        v06.apply(&mut user_op, EntryPointVersion::V06).unwrap();
+        assert_eq!(paymaster_fields(&user_op, EntryPointVersion::V06), Some((paymaster, Bytes::from_static(&[0xab, 0xcd]))));
+
+        let config = PaymasterConfig { name: "Service".to_string(), url: String::new(), context: None, verifying_signer: None };
+        let sponsorship = Sponsorship::new(&config, &user_op, EntryPointVersion::V06, response.sponsor.as_ref()).unwrap();
+        assert_eq!(sponsorship.sponsor, "AAStar Community");
+        assert_eq!(sponsorship.service, "Service");
+    }
+}
@@ -0,0 +1,160 @@
+// Verifying Paymaster
+// paymasterData format of the reference VerifyingPaymaster:
+// abi.encode(uint48 validUntil, uint48 validAfter) ++ signature, where the signature is
+// the paymaster signer's EIP-191 signature over `getHash(userOp, validUntil, validAfter)`
+
+use alloy_primitives::{eip191_hash_message, keccak256, Address, Bytes, Signature, B256, U256};
+use alloy_sol_types::SolValue;
+
+use crate::eth::{EntryPointVersion, UserOperation};
+
+// Constants
+const VALIDITY_LENGTH: usize = 64;
+const SIGNATURE_LENGTH: usize = 65;
+const MAX_UINT48: u64 = (1 << 48) - 1;
+
+/// Decoded verifying paymaster data
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyingPaymasterData {
+    /// Last valid timestamp, 0 for no expiry
+    pub valid_until: u64,
+    pub valid_after: u64,
+    pub signature: Bytes,
+}
+
+impl VerifyingPaymasterData {
+    /// Decode paymasterData (the part after the paymaster address and, in v0.7, its gas limits)
+    pub fn decode(paymaster_data: &[u8]) -> Result<Self, String> {
+        if paymaster_data.len() != VALIDITY_LENGTH + SIGNATURE_LENGTH {
+            return Err(format!(
+                "Verifying paymaster data must be {} bytes, got {}",
+                VALIDITY_LENGTH + SIGNATURE_LENGTH, paymaster_data.len()
+            ));
+        }
+
+        let (valid_until, valid_after) = <(U256, U256)>::abi_decode(&paymaster_data[..VALIDITY_LENGTH])
+            .map_err(|e| format!("Invalid paymaster validity: {}", e))?;
+        let to_uint48 = |value: U256| u64::try_from(value).ok()
+            .filter(|value| *value <= MAX_UINT48)
+            .ok_or_else(|| "Paymaster validity does not fit in uint48".to_string());
+
+        Ok(Self {
+            valid_until: to_uint48(valid_until)?,
+            valid_after: to_uint48(valid_after)?,
+            signature: Bytes::copy_from_slice(&paymaster_data[VALIDITY_LENGTH..]),
+        })
+    }
+
+    pub fn encode(&self) -> Bytes {
+        let mut data = (U256::from(self.valid_until), U256::from(self.valid_after)).abi_encode();
+        data.extend_from_slice(&self.signature);
+        data.into()
+    }
+
+    /// Check the sponsorship is valid at the given Unix time
+    pub fn check_validity(&self, now: u64) -> Result<(), String> {
+        if self.valid_after > now {
+            return Err(format!("Paymaster sponsorship is not valid before {}", self.valid_after));
+        }
+        if self.valid_until != 0 && self.valid_until < now {
+            return Err(format!("Paymaster sponsorship expired at {}", self.valid_until));
+        }
+        Ok(())
+    }
+
+    /// Address that signed the sponsorship
+    pub fn recover_signer(&self, user_op: &UserOperation, version: EntryPointVersion, paymaster: Address, chain_id: u64) -> Result<Address, String> {
+        let signature = Signature::try_from(self.signature.as_ref())
+            .map_err(|e| format!("Invalid paymaster signature: {}", e))?;
+        let hash = sponsorship_hash(user_op, version, paymaster, chain_id, self.valid_until, self.valid_after);
+        signature.recover_address_from_prehash(&eip191_hash_message(hash))
+            .map_err(|e| format!("Invalid paymaster signature: {}", e))
+    }
+}
+
+/// Hash signed by the paymaster signer (`VerifyingPaymaster.getHash`)
+pub fn sponsorship_hash(
+    user_op: &UserOperation,
+    version: EntryPointVersion,
+    paymaster: Address,
+    chain_id: u64,
+    valid_until: u64,
+    valid_after: u64,
+) -> B256 {
+    let encoded = match version {
+        EntryPointVersion::V06 => (
+            user_op.sender,
+            user_op.nonce,
+            keccak256(user_op.packed_init_code()),
+            keccak256(&user_op.call_data),
+            user_op.call_gas_limit,
+            user_op.verification_gas_limit,
+            user_op.pre_verification_gas,
+            user_op.max_fee_per_gas,
+            user_op.max_priority_fee_per_gas,
+            U256::from(chain_id),
+            paymaster,
+            U256::from(valid_until),
+            U256::from(valid_after),
+        ).abi_encode(),
+        EntryPointVersion::V07 => (
+            user_op.sender,
+            user_op.nonce,
+            keccak256(user_op.packed_init_code()),
+            keccak256(&user_op.call_data),
+            user_op.account_gas_limits(),
+            user_op.paymaster_gas_limits(),
+            user_op.pre_verification_gas,
+            user_op.gas_fees(),
+            U256::from(chain_id),
+            paymaster,
+            U256::from(valid_until),
+            U256::from(valid_after),
+        ).abi_encode(),
+    };
+    keccak256(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+
+    #[test]
+    fn test_round_trip_and_signer_recovery() {
+        let key = SigningKey::from_slice(&[0x77; 32]).unwrap();
+        let paymaster = Address::repeat_byte(0x99);
+        let user_op = UserOperation {
+            sender: Address::repeat_byte(1),
+            call_gas_limit: U256::from(100_000),
+            paymaster: Some(paymaster),
+            paymaster_verification_gas_limit: Some(U256::from(50_000)),
+            ..Default::default()
+        };
+
+        for version in [EntryPointVersion::V06, EntryPointVersion::V07] {
+            let hash = sponsorship_hash(&user_op, version, paymaster, 10, 2_000, 1_000);
+            let signature = Signature::from(key.sign_prehash_recoverable(eip191_hash_message(hash).as_slice()).unwrap());
+            let data = VerifyingPaymasterData { valid_until: 2_000, valid_after: 1_000, signature: signature.as_bytes().into() };
+
+            let decoded = VerifyingPaymasterData::decode(&data.encode()).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(decoded.recover_signer(&user_op, version, paymaster, 10).unwrap(), Address::from_private_key(&key));
+            // Any other chain gives a different signer
+            assert_ne!(decoded.recover_signer(&user_op, version, paymaster, 1).unwrap(), Address::from_private_key(&key));
+        }
+    }
+
+    #[test]
+    fn test_validity_window() {
+        let data = VerifyingPaymasterData { valid_until: 2_000, valid_after: 1_000, signature: Bytes::new() };
+        assert!(data.check_validity(1_500).is_ok());
+        assert!(data.check_validity(500).is_err());
+        assert!(data.check_validity(2_500).is_err());
+
+        let no_expiry = VerifyingPaymasterData { valid_until: 0, ..data };
+        assert!(no_expiry.check_validity(u64::from(u32::MAX)).is_ok());
+
+        assert!(VerifyingPaymasterData::decode(&[0; 64]).is_err());
+    }
+}
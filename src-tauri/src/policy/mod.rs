@@ -0,0 +1,281 @@
+// Transaction Policy Engine
+// Declarative rules checked before a transaction reaches the TEE for signing.
+// Rules are stored in the app data directory; in server mode they can only be
+// changed on the node itself and signing is refused until a policy exists.
+// Signing, wallet export and backup shares also need a passkey step-up over the exact operation.
+
+mod rules;
+pub mod step_up;
+
+pub use rules::{PolicyDecision, PolicyRules};
+
+use alloy_primitives::U256;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+
+use crate::eth::{SafeTransactionRequest, TxRequest, UserOperationRequest};
+use crate::storage;
+use crate::tee::{TeeError, TeeOperation};
+
+// Constants
+const POLICY_FILE: &str = "tx_policy.json";
+const SPEND_STATE_FILE: &str = "tx_policy_spend.json";
+const DECISION_LOG_FILE: &str = "tx_policy_decisions.jsonl";
+const SERVER_MODE_ENV: &str = "COS72_SERVER_MODE";
+
+// Value signed during one UTC day
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DailySpend {
+    day: String,
+    spent: U256,
+}
+
+struct PolicyEngine {
+    rules: Option<PolicyRules>,
+    // Set when the policy file exists but cannot be read; all signing is denied
+    load_error: Option<String>,
+    daily: DailySpend,
+}
+
+static POLICY_ENGINE: Lazy<Mutex<PolicyEngine>> = Lazy::new(|| {
+    let (rules, load_error) = match storage::load_json::<PolicyRules>(POLICY_FILE) {
+        Ok(rules) => (rules, None),
+        Err(e) => {
+            println!("COS72-Tauri: Failed to load transaction policy: {}", e);
+            (None, Some(e.to_string()))
+        }
+    };
+    let daily = storage::load_json::<DailySpend>(SPEND_STATE_FILE).ok().flatten().unwrap_or_default();
+    Mutex::new(PolicyEngine { rules, load_error, daily })
+});
+
+impl PolicyEngine {
+    fn spent_today(&self) -> U256 {
+        if self.daily.day == today() { self.daily.spent } else { U256::ZERO }
+    }
+
+    // `delegate_call` marks the single call of a Safe delegatecall
+    fn evaluate(&self, calls: &[TxRequest], delegate_call: bool) -> PolicyDecision {
+        // A broken policy file must not silently turn into "allow everything"
+        if let Some(error) = &self.load_error {
+            return PolicyDecision::Deny(format!("Transaction policy could not be loaded: {}", error));
+        }
+
+        let evaluate = |rules: &PolicyRules| match calls {
+            [call] if delegate_call => rules.evaluate_delegate_call(call, self.spent_today()),
+            _ => rules.evaluate_calls(calls, self.spent_today()),
+        };
+        match &self.rules {
+            Some(rules) => evaluate(rules),
+            None if is_server_mode() => {
+                PolicyDecision::Deny("No transaction policy configured; signing is disabled in server mode".to_string())
+            },
+            None => evaluate(&PolicyRules::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    // Server mode for the current test thread, leaving the process environment to other tests
+    static TEST_SERVER_MODE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Whether the app runs as a community-operated signing node
+pub fn is_server_mode() -> bool {
+    #[cfg(test)]
+    if TEST_SERVER_MODE.with(|mode| mode.get()) {
+        return true;
+    }
+    std::env::var(SERVER_MODE_ENV)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Run the current test thread as in server mode
+#[cfg(test)]
+pub(crate) fn set_test_server_mode(enabled: bool) {
+    TEST_SERVER_MODE.with(|mode| mode.set(enabled));
+}
+
+/// Check a TEE operation against the policy and log the decision
+///
+/// Returns the transaction value to record once signing succeeds, or `None` for
+/// operations that do not sign a transaction.
+pub fn authorize(op: &TeeOperation) -> Result<Option<U256>, TeeError> {
+    // The step-up is used up only once the operation it confirms is let through
+    let required = step_up::is_required(op);
+    let stepped_up = step_up::has_grant(op);
+    if required && !stepped_up {
+        return Err(TeeError::StepUpRequired("Confirm the operation with a passkey first".to_string()));
+    }
+
+    let mut delegate_call = false;
+    let (operation, calls) = match op {
+        TeeOperation::SignTransaction(tx_data) => ("sign_transaction", TxRequest::from_json(tx_data).map(|tx| vec![tx])),
+        // UserOperations are checked as the calls their account executes
+        TeeOperation::SignUserOperation(user_op_data) => ("sign_user_operation", UserOperationRequest::from_json(user_op_data)
+            .and_then(|request| request.user_op.policy_requests(request.chain_id))),
+        // Safe transactions are checked as the call the Safe makes; delegatecalls only to allowlisted targets
+        TeeOperation::SignSafeTransaction(safe_tx_data) => ("sign_safe_transaction", SafeTransactionRequest::from_json(safe_tx_data)
+            .map(|request| {
+                delegate_call = request.is_delegate_call();
+                vec![request.policy_request()]
+            })),
+        _ if required => return use_grant(op).map(|_| None),
+        _ => return Ok(None),
+    };
+
+    let (decision, calls) = match calls {
+        Ok(calls) => (lock_engine().evaluate(&calls, delegate_call), calls),
+        Err(e) => (PolicyDecision::Deny(format!("Cannot evaluate transaction: {}", e)), Vec::new()),
+    };
+    log_decision(operation, &decision, &calls);
+    let value = calls.iter().fold(U256::ZERO, |total, call| total.saturating_add(call.value));
+
+    match decision {
+        PolicyDecision::Allow if required => use_grant(op).map(|_| Some(value)),
+        PolicyDecision::Allow => Ok(Some(value)),
+        PolicyDecision::Deny(reason) => Err(TeeError::PolicyDenied(reason)),
+        // The passkey step-up above already covers this transaction
+        PolicyDecision::RequireStepUp(_) if stepped_up => use_grant(op).map(|_| Some(value)),
+        PolicyDecision::RequireStepUp(reason) => Err(TeeError::StepUpRequired(reason)),
+    }
+}
+
+// Use up the step-up of an operation being let through; it may have expired since it was checked
+fn use_grant(op: &TeeOperation) -> Result<(), TeeError> {
+    if step_up::take_grant(op) {
+        Ok(())
+    } else {
+        Err(TeeError::StepUpRequired("Confirm the operation with a passkey first".to_string()))
+    }
+}
+
+/// Add a signed transaction's value to today's total
+pub fn record_spend(value: U256) {
+    let mut engine = lock_engine();
+    let day = today();
+    if engine.daily.day != day {
+        engine.daily = DailySpend { day, spent: U256::ZERO };
+    }
+    engine.daily.spent = engine.daily.spent.saturating_add(value);
+
+    if let Err(e) = storage::save_json(SPEND_STATE_FILE, &engine.daily) {
+        println!("COS72-Tauri: Failed to persist daily spend: {}", e);
+    }
+}
+
+/// Current policy, server mode flag and today's signed value
+pub fn get_policy() -> Value {
+    let engine = lock_engine();
+    json!({
+        "server_mode": is_server_mode(),
+        "configured": engine.rules.is_some(),
+        "load_error": engine.load_error,
+        "rules": engine.rules.clone().unwrap_or_default(),
+        "spent_today": engine.spent_today(),
+    })
+}
+
+/// Replace the policy; refused in server mode where the policy file is managed by the operator
+pub fn set_policy(rules: PolicyRules) -> Result<(), String> {
+    if is_server_mode() {
+        return Err("Transaction policy cannot be changed by clients in server mode".to_string());
+    }
+    rules.validate()?;
+
+    storage::save_json(POLICY_FILE, &rules)
+        .map_err(|e| format!("Failed to save transaction policy: {}", e))?;
+    let mut engine = lock_engine();
+    engine.rules = Some(rules);
+    engine.load_error = None;
+    println!("COS72-Tauri: Transaction policy updated");
+    Ok(())
+}
+
+fn lock_engine() -> std::sync::MutexGuard<'static, PolicyEngine> {
+    POLICY_ENGINE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn today() -> String {
+    chrono::Utc::now().format("%Y-%m-%d").to_string()
+}
+
+// `operation` names the kind of TEE operation, as in the OP-TEE API
+fn log_decision(operation: &str, decision: &PolicyDecision, calls: &[TxRequest]) {
+    println!("COS72-Tauri: Policy decision for {}: {:?}", operation, decision);
+
+    let mut entry = json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "operation": operation,
+        "server_mode": is_server_mode(),
+    });
+    if let (Value::Object(entry), Value::Object(decision)) = (&mut entry, json!(decision)) {
+        entry.extend(decision);
+    }
+    let call_entry = |tx: &TxRequest| json!({
+        "to": tx.to,
+        "value": tx.value,
+        "selector": tx.selector().map(|s| format!("0x{}", hex::encode(s))),
+    });
+    match calls {
+        [] => {},
+        [tx] => {
+            if let (Value::Object(entry), Value::Object(call)) = (&mut entry, call_entry(tx)) {
+                entry.extend(call);
+            }
+            entry["chain_id"] = json!(tx.chain_id);
+        },
+        // Batches log every call and the total value
+        [first, ..] => {
+            entry["calls"] = calls.iter().map(call_entry).collect();
+            entry["value"] = json!(calls.iter().fold(U256::ZERO, |total, call| total.saturating_add(call.value)));
+            entry["chain_id"] = json!(first.chain_id);
+        },
+    }
+
+    if let Err(e) = storage::append_line(DECISION_LOG_FILE, &entry.to_string()) {
+        println!("COS72-Tauri: Failed to write policy decision log: {}", e);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::eth::SafeTransaction;
+    use alloy_primitives::Address;
+    use std::sync::MutexGuard;
+
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Serialize tests that change the global policy or sign through it
+    pub(crate) fn lock_policy() -> MutexGuard<'static, ()> {
+        TEST_LOCK.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    #[test]
+    fn test_safe_delegate_call_needs_an_allowlisted_target() {
+        let _guard = lock_policy();
+        storage::use_test_data_dir();
+        let multi_send = Address::repeat_byte(0x40);
+        let request = |operation: u8| {
+            let tx = SafeTransaction { to: multi_send, operation, ..Default::default() };
+            let request = SafeTransactionRequest { safe: Address::repeat_byte(1), chain_id: 1, tx };
+            TeeOperation::SignSafeTransaction(serde_json::to_string(&request).unwrap())
+        };
+
+        set_policy(PolicyRules::default()).unwrap();
+        let call = authorize(&request(0));
+        let delegate_call = authorize(&request(1));
+        set_policy(PolicyRules { allowed_delegate_calls: vec![multi_send], ..Default::default() }).unwrap();
+        let allowlisted = authorize(&request(1));
+        set_policy(PolicyRules::default()).unwrap();
+
+        assert!(call.is_ok());
+        assert!(matches!(delegate_call, Err(TeeError::PolicyDenied(_))), "{:?}", delegate_call);
+        assert!(allowlisted.is_ok());
+    }
+}
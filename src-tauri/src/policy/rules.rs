@@ -0,0 +1,347 @@
+// Policy Rules
+// Declarative transaction rules and their evaluation against a single transaction
+//
+// Value rules cover native value. ERC-20 transfer, approve and transferFrom amounts are
+// checked per transaction against `token_limits`; there is no daily limit on token amounts.
+// Unlimited ERC-20 approvals always need a passkey step-up. Other token standards (ERC-721,
+// ERC-1155) and custom token methods are only covered by the allowlists and blocked selectors.
+// Safe delegatecalls run the target's code as the Safe and are denied unless the target is
+// in `allowed_delegate_calls`, e.g. MultiSendCallOnly for batches.
+
+use alloy_primitives::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::eth::{decode_transaction, TxAction, TxRequest};
+
+/// Declarative signing rules; unset rules do not restrict anything except Safe delegatecalls
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyRules {
+    /// Maximum value (wei) of a single transaction
+    pub max_value_per_tx: Option<U256>,
+    /// Maximum total value (wei) signed per UTC day
+    pub max_value_per_day: Option<U256>,
+    /// Allowed recipients of plain value transfers
+    pub allowed_recipients: Option<Vec<Address>>,
+    /// Allowed targets of contract calls; contract creation is denied when set
+    pub allowed_contracts: Option<Vec<Address>>,
+    /// Allowed chain IDs; transactions without a chainId are denied when set
+    pub allowed_chain_ids: Option<Vec<u64>>,
+    /// Blocked 4-byte method selectors, e.g. "0x095ea7b3" (approve)
+    pub blocked_selectors: Vec<String>,
+    /// Transactions above this value (wei) require a passkey step-up
+    pub step_up_above: Option<U256>,
+    /// Amount limits for ERC-20 tokens, applied to transfer, approve and transferFrom
+    pub token_limits: Vec<TokenLimit>,
+    /// Contracts a Safe may delegatecall, checked like calls; other delegatecalls are denied
+    pub allowed_delegate_calls: Vec<Address>,
+}
+
+/// Amount limits for one ERC-20 token, in the token's base units
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLimit {
+    pub token: Address,
+    /// Maximum amount transferred or approved by a single call
+    #[serde(default)]
+    pub max_amount_per_tx: Option<U256>,
+    /// Amounts above this require a passkey step-up
+    #[serde(default)]
+    pub step_up_above: Option<U256>,
+}
+
+/// Outcome of evaluating a transaction against the rules
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "decision", content = "reason", rename_all = "snake_case")]
+pub enum PolicyDecision {
+    Allow,
+    Deny(String),
+    RequireStepUp(String),
+}
+
+impl PolicyRules {
+    /// Check that the rules are well formed
+    pub fn validate(&self) -> Result<(), String> {
+        for selector in &self.blocked_selectors {
+            parse_selector(selector)?;
+        }
+        for (index, limit) in self.token_limits.iter().enumerate() {
+            if self.token_limits[..index].iter().any(|other| other.token == limit.token) {
+                return Err(format!("Token {} has more than one limit", limit.token));
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate a transaction given the value already signed today
+    pub fn evaluate(&self, tx: &TxRequest, spent_today: U256) -> PolicyDecision {
+        if let Some(chain_ids) = &self.allowed_chain_ids {
+            match tx.chain_id {
+                Some(chain_id) if chain_ids.contains(&chain_id) => {},
+                Some(chain_id) => return PolicyDecision::Deny(format!("Chain {} is not allowed", chain_id)),
+                None => return PolicyDecision::Deny("Transaction has no chainId".to_string()),
+            }
+        }
+
+        if let Some(selector) = tx.selector() {
+            let blocked = self.blocked_selectors.iter()
+                .any(|blocked| parse_selector(blocked).map(|b| b == selector).unwrap_or(false));
+            if blocked {
+                return PolicyDecision::Deny(format!("Method selector 0x{} is blocked", hex::encode(selector)));
+            }
+        }
+
+        if tx.is_contract_call() {
+            if let Some(contracts) = &self.allowed_contracts {
+                match tx.to {
+                    Some(to) if contracts.contains(&to) => {},
+                    Some(to) => return PolicyDecision::Deny(format!("Contract {} is not in the allowlist", to)),
+                    None => return PolicyDecision::Deny("Contract creation is not allowed".to_string()),
+                }
+            }
+        } else if let Some(recipients) = &self.allowed_recipients {
+            match tx.to {
+                Some(to) if recipients.contains(&to) => {},
+                Some(to) => return PolicyDecision::Deny(format!("Recipient {} is not in the allowlist", to)),
+                None => return PolicyDecision::Deny("Transaction has no recipient".to_string()),
+            }
+        }
+
+        if let Some(max) = self.max_value_per_tx {
+            if tx.value > max {
+                return PolicyDecision::Deny(format!("Value {} exceeds the per-transaction limit {}", tx.value, max));
+            }
+        }
+
+        if let Some(max) = self.max_value_per_day {
+            if spent_today.saturating_add(tx.value) > max {
+                return PolicyDecision::Deny(format!(
+                    "Value {} would exceed the daily limit {} ({} already signed today)", tx.value, max, spent_today
+                ));
+            }
+        }
+
+        if let Some(threshold) = self.step_up_above {
+            if tx.value > threshold {
+                return PolicyDecision::RequireStepUp(format!("Value {} is above the step-up threshold {}", tx.value, threshold));
+            }
+        }
+
+        self.evaluate_token_amount(tx)
+    }
+
+    // Check the amount of an ERC-20 transfer, approve or transferFrom
+    fn evaluate_token_amount(&self, tx: &TxRequest) -> PolicyDecision {
+        let summary = decode_transaction(tx);
+        if !matches!(summary.action, TxAction::Erc20Transfer | TxAction::Erc20Approve | TxAction::TransferFrom) {
+            return PolicyDecision::Allow;
+        }
+        let (Some(token), Some(amount)) = (summary.target, summary.amount.and_then(|amount| U256::from_str(&amount).ok())) else {
+            return PolicyDecision::Allow;
+        };
+
+        if summary.action == TxAction::Erc20Approve && amount == U256::MAX {
+            return PolicyDecision::RequireStepUp(format!("Unlimited approval of token {}", token));
+        }
+        let Some(limit) = self.token_limits.iter().find(|limit| limit.token == token) else {
+            return PolicyDecision::Allow;
+        };
+        if let Some(max) = limit.max_amount_per_tx {
+            if amount > max {
+                return PolicyDecision::Deny(format!("Amount {} of token {} exceeds the per-transaction limit {}", amount, token, max));
+            }
+        }
+        if let Some(threshold) = limit.step_up_above {
+            if amount > threshold {
+                return PolicyDecision::RequireStepUp(format!("Amount {} of token {} is above the step-up threshold {}", amount, token, threshold));
+            }
+        }
+        PolicyDecision::Allow
+    }
+
+    /// Evaluate a Safe delegatecall given as the call the Safe makes
+    pub fn evaluate_delegate_call(&self, call: &TxRequest, spent_today: U256) -> PolicyDecision {
+        match call.to {
+            Some(to) if self.allowed_delegate_calls.contains(&to) => self.evaluate(call, spent_today),
+            Some(to) => PolicyDecision::Deny(format!("Delegatecall to {} is not in the allowlist", to)),
+            None => PolicyDecision::Deny("Delegatecall has no target".to_string()),
+        }
+    }
+
+    /// Evaluate the calls of one signing request, such as a smart account batch
+    ///
+    /// Every call is checked on its own, and the summed value is charged against the value
+    /// limits and the step-up threshold as one transaction.
+    pub fn evaluate_calls(&self, calls: &[TxRequest], spent_today: U256) -> PolicyDecision {
+        let mut step_up = None;
+        let mut total = U256::ZERO;
+        for call in calls {
+            match self.evaluate(call, spent_today.saturating_add(total)) {
+                PolicyDecision::Allow => {},
+                PolicyDecision::Deny(reason) => return PolicyDecision::Deny(reason),
+                PolicyDecision::RequireStepUp(reason) => {
+                    step_up.get_or_insert(reason);
+                },
+            }
+            total = total.saturating_add(call.value);
+        }
+
+        if let Some(max) = self.max_value_per_tx {
+            if total > max {
+                return PolicyDecision::Deny(format!("Total value {} exceeds the per-transaction limit {}", total, max));
+            }
+        }
+        if let Some(threshold) = self.step_up_above {
+            if total > threshold {
+                step_up.get_or_insert(format!("Total value {} is above the step-up threshold {}", total, threshold));
+            }
+        }
+        step_up.map_or(PolicyDecision::Allow, PolicyDecision::RequireStepUp)
+    }
+}
+
+fn parse_selector(selector: &str) -> Result<[u8; 4], String> {
+    let stripped = selector.trim().strip_prefix("0x").unwrap_or(selector.trim());
+    let bytes = hex::decode(stripped).map_err(|e| format!("Invalid selector {}: {}", selector, e))?;
+    <[u8; 4]>::try_from(bytes.as_slice()).map_err(|_| format!("Selector {} must be 4 bytes", selector))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPIENT: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+    const TOKEN: &str = "0x1c7D4B196Cb0C7B01d743Fbc6116a902379C7238";
+
+    fn tx(json: &str) -> TxRequest {
+        TxRequest::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn test_default_rules_allow_everything() {
+        let rules = PolicyRules::default();
+        let transfer = tx(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266", "value": "0xffffffffffffffff"}"#);
+        assert_eq!(rules.evaluate(&transfer, U256::ZERO), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn test_value_limits_and_step_up() {
+        let rules: PolicyRules = serde_json::from_str(r#"{
+            "max_value_per_tx": "1000",
+            "max_value_per_day": "1500",
+            "step_up_above": "500"
+        }"#).unwrap();
+
+        let small = tx(&format!(r#"{{"to": "{}", "value": "100"}}"#, RECIPIENT));
+        let large = tx(&format!(r#"{{"to": "{}", "value": "800"}}"#, RECIPIENT));
+        let too_large = tx(&format!(r#"{{"to": "{}", "value": "1001"}}"#, RECIPIENT));
+
+        assert_eq!(rules.evaluate(&small, U256::ZERO), PolicyDecision::Allow);
+        assert!(matches!(rules.evaluate(&large, U256::ZERO), PolicyDecision::RequireStepUp(_)));
+        assert!(matches!(rules.evaluate(&too_large, U256::ZERO), PolicyDecision::Deny(_)));
+        // Daily limit counts what was already signed today
+        assert!(matches!(rules.evaluate(&small, U256::from(1450)), PolicyDecision::Deny(_)));
+    }
+
+    #[test]
+    fn test_batch_is_charged_as_a_whole() {
+        let rules: PolicyRules = serde_json::from_str(&format!(r#"{{
+            "max_value_per_tx": "1000",
+            "max_value_per_day": "1500",
+            "step_up_above": "500",
+            "allowed_recipients": ["{}"]
+        }}"#, RECIPIENT)).unwrap();
+        let call = |value: u64| tx(&format!(r#"{{"to": "{}", "value": "{}"}}"#, RECIPIENT, value));
+
+        assert_eq!(rules.evaluate_calls(&[call(100), call(200)], U256::ZERO), PolicyDecision::Allow);
+        // Calls that pass on their own add up to a step-up, the per-transaction and the daily limit
+        assert!(matches!(rules.evaluate_calls(&[call(300), call(300)], U256::ZERO), PolicyDecision::RequireStepUp(_)));
+        assert!(matches!(rules.evaluate_calls(&[call(400), call(400), call(400)], U256::ZERO), PolicyDecision::Deny(_)));
+        assert!(matches!(rules.evaluate_calls(&[call(300), call(300)], U256::from(1000)), PolicyDecision::Deny(_)));
+        // Every call has to pass the allowlist
+        let other = tx(&format!(r#"{{"to": "{}", "value": "1"}}"#, TOKEN));
+        assert!(matches!(rules.evaluate_calls(&[call(1), other], U256::ZERO), PolicyDecision::Deny(_)));
+    }
+
+    #[test]
+    fn test_token_amount_limits() {
+        let rules: PolicyRules = serde_json::from_str(&format!(r#"{{
+            "token_limits": [{{"token": "{}", "max_amount_per_tx": "1000", "step_up_above": "500"}}]
+        }}"#, TOKEN)).unwrap();
+        assert!(rules.validate().is_ok());
+        let call = |selector: &str, args: &[U256]| {
+            let data: String = args.iter().map(|arg| hex::encode(arg.to_be_bytes::<32>())).collect();
+            tx(&format!(r#"{{"to": "{}", "data": "0x{}{}"}}"#, TOKEN, selector, data))
+        };
+        let recipient = U256::from_be_slice(Address::from_str(RECIPIENT).unwrap().as_slice());
+        let transfer = |amount: u64| call("a9059cbb", &[recipient, U256::from(amount)]);
+        let approve = |amount: U256| call("095ea7b3", &[recipient, amount]);
+        let transfer_from = |amount: u64| call("23b872dd", &[recipient, recipient, U256::from(amount)]);
+
+        assert_eq!(rules.evaluate(&transfer(100), U256::ZERO), PolicyDecision::Allow);
+        assert!(matches!(rules.evaluate(&transfer(800), U256::ZERO), PolicyDecision::RequireStepUp(_)));
+        assert!(matches!(rules.evaluate(&transfer(1001), U256::ZERO), PolicyDecision::Deny(_)));
+        assert!(matches!(rules.evaluate(&transfer_from(1001), U256::ZERO), PolicyDecision::Deny(_)));
+        assert!(matches!(rules.evaluate(&approve(U256::from(1001)), U256::ZERO), PolicyDecision::Deny(_)));
+        assert!(matches!(rules.evaluate_calls(&[transfer(100), transfer(2000)], U256::ZERO), PolicyDecision::Deny(_)));
+
+        // Unlimited approvals need a step-up for every token, limited or not
+        assert!(matches!(rules.evaluate(&approve(U256::MAX), U256::ZERO), PolicyDecision::RequireStepUp(_)));
+        assert!(matches!(PolicyRules::default().evaluate(&approve(U256::MAX), U256::ZERO), PolicyDecision::RequireStepUp(_)));
+        assert_eq!(PolicyRules::default().evaluate(&transfer(2000), U256::ZERO), PolicyDecision::Allow);
+
+        let duplicate = PolicyRules { token_limits: vec![rules.token_limits[0].clone(); 2], ..Default::default() };
+        assert!(duplicate.validate().is_err());
+    }
+
+    #[test]
+    fn test_allowlists_and_selectors() {
+        let rules = PolicyRules {
+            allowed_recipients: Some(vec![Address::from_str(RECIPIENT).unwrap()]),
+            allowed_contracts: Some(vec![Address::from_str(TOKEN).unwrap()]),
+            allowed_chain_ids: Some(vec![11155111]),
+            blocked_selectors: vec!["0x095ea7b3".to_string()],
+            ..Default::default()
+        };
+        assert!(rules.validate().is_ok());
+
+        let transfer = tx(&format!(r#"{{"to": "{}", "value": "1", "chainId": 11155111}}"#, RECIPIENT));
+        assert_eq!(rules.evaluate(&transfer, U256::ZERO), PolicyDecision::Allow);
+
+        let token_call = tx(&format!(r#"{{"to": "{}", "data": "0xa9059cbb", "chainId": 11155111}}"#, TOKEN));
+        assert_eq!(rules.evaluate(&token_call, U256::ZERO), PolicyDecision::Allow);
+
+        let wrong_chain = tx(&format!(r#"{{"to": "{}", "chainId": 1}}"#, RECIPIENT));
+        let no_chain = tx(&format!(r#"{{"to": "{}"}}"#, RECIPIENT));
+        let unknown_recipient = tx(&format!(r#"{{"to": "{}", "chainId": 11155111}}"#, TOKEN));
+        let unknown_contract = tx(&format!(r#"{{"to": "{}", "data": "0xa9059cbb", "chainId": 11155111}}"#, RECIPIENT));
+        let approve = tx(&format!(r#"{{"to": "{}", "data": "0x095ea7b3", "chainId": 11155111}}"#, TOKEN));
+        let deploy = tx(r#"{"data": "0x6080", "chainId": 11155111}"#);
+
+        for denied in [wrong_chain, no_chain, unknown_recipient, unknown_contract, approve, deploy] {
+            assert!(matches!(rules.evaluate(&denied, U256::ZERO), PolicyDecision::Deny(_)), "{:?}", denied);
+        }
+
+        let invalid = PolicyRules { blocked_selectors: vec!["0x1234".to_string()], ..Default::default() };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_delegate_calls_need_an_allowlisted_target() {
+        let multi_send = Address::repeat_byte(0x40);
+        let call = |to: Address| TxRequest { to: Some(to), data: vec![0x8d, 0x80, 0xff, 0x0a].into(), ..Default::default() };
+
+        // Denied by default, even where the same call would be allowed
+        let rules = PolicyRules::default();
+        assert_eq!(rules.evaluate(&call(multi_send), U256::ZERO), PolicyDecision::Allow);
+        assert!(matches!(rules.evaluate_delegate_call(&call(multi_send), U256::ZERO), PolicyDecision::Deny(_)));
+
+        // Allowlisted targets are checked like calls
+        let rules = PolicyRules { allowed_delegate_calls: vec![multi_send], blocked_selectors: vec!["0x8d80ff0a".to_string()], ..Default::default() };
+        assert!(matches!(rules.evaluate_delegate_call(&call(Address::repeat_byte(0x41)), U256::ZERO), PolicyDecision::Deny(_)));
+        let blocked = rules.evaluate_delegate_call(&call(multi_send), U256::ZERO);
+        assert_eq!(blocked, PolicyDecision::Deny("Method selector 0x8d80ff0a is blocked".to_string()));
+        let rules = PolicyRules { blocked_selectors: Vec::new(), ..rules };
+        assert_eq!(rules.evaluate_delegate_call(&call(multi_send), U256::ZERO), PolicyDecision::Allow);
+    }
+}
@@ -0,0 +1,399 @@
+// Passkey Step-Up
+// Signing a transaction, exporting the wallet and splitting its seed into backup shares need
+// the wallet owner at the device. The operation is hashed and the digest recorded against the
+// challenge of a WebAuthn ceremony for the owner's passkeys; the TEE only runs the operation
+// once an assertion over exactly that challenge has been verified, and only within a short
+// window afterwards. Each verified step-up authorizes a single operation.
+// The challenge is the random one webauthn-rs issues, not derived from the digest:
+// webauthn-rs 0.5 cannot verify an assertion over a caller-chosen challenge, so the digest is
+// bound to the ceremony on this side instead.
+
+use alloy_primitives::B256;
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::eth::{SafeTransactionRequest, TxRequest, UserOperationRequest};
+use crate::fido::webauthn;
+use crate::sender::EventSink;
+use crate::tee::{TeeError, TeeOperation};
+
+/// Event emitted when an operation waits for a passkey step-up
+pub const EVENT_STEP_UP_REQUIRED: &str = "step-up-required";
+
+// Constants
+// How long a step-up ceremony can be finished after it was started
+const CEREMONY_TIMEOUT: Duration = Duration::from_secs(120);
+// How long a verified step-up can be used for its operation
+const GRANT_WINDOW: Duration = Duration::from_secs(60);
+const GRANT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Operation digest of a started ceremony and the challenge issued for it
+struct PendingStepUp {
+    digest: B256,
+    owner: Uuid,
+    challenge: Vec<u8>,
+    expires_at: Instant,
+}
+
+// Verified step-up, used up by the operation it was made for
+struct Grant {
+    digest: B256,
+    expires_at: Instant,
+}
+
+static PENDING: Lazy<Mutex<HashMap<String, PendingStepUp>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static GRANTS: Lazy<Mutex<Vec<Grant>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Whether `op` can only run after a passkey step-up
+pub fn is_required(op: &TeeOperation) -> bool {
+    matches!(op, TeeOperation::SignTransaction(_) | TeeOperation::ExportWallet(_) | TeeOperation::CreateBackupShares(..))
+}
+
+/// Digest a step-up for `op` is bound to: the signature hash for transactions, the
+/// userOpHash and safeTxHash for UserOperations and Safe transactions (which the policy rules
+/// can send to a step-up), a fixed digest per export kind for wallet exports, and the share
+/// parameters for backup shares
+pub fn operation_digest(op: &TeeOperation) -> Result<B256, String> {
+    match op {
+        TeeOperation::SignTransaction(tx_data) => Ok(TxRequest::from_json(tx_data)?.to_unsigned()?.signature_hash()),
+        TeeOperation::SignUserOperation(user_op_data) => Ok(UserOperationRequest::from_json(user_op_data)?.user_op_hash()),
+        TeeOperation::SignSafeTransaction(safe_tx_data) => Ok(SafeTransactionRequest::from_json(safe_tx_data)?.safe_tx_hash()),
+        TeeOperation::ExportWallet(include_private) => {
+            let purpose = format!("export wallet, private key: {}", include_private);
+            Ok(B256::from_slice(&Sha256::digest(purpose.as_bytes())))
+        },
+        // The shares rebuild the seed, so they are confirmed like an export
+        TeeOperation::CreateBackupShares(threshold, share_count, passphrase) => {
+            let digest = Sha256::new()
+                .chain_update(format!("create backup shares: {} of {}, passphrase: ", threshold, share_count))
+                .chain_update(passphrase)
+                .finalize();
+            Ok(B256::from_slice(&digest))
+        },
+        _ => Err("Operation does not take a passkey step-up".to_string()),
+    }
+}
+
+/// Start a step-up for `op`: an authentication ceremony for the wallet owner's passkeys whose
+/// challenge is recorded against the operation digest
+pub fn start(op: &TeeOperation) -> Result<Value, String> {
+    let digest = operation_digest(op)?;
+    let owner = webauthn::wallet_owner()?;
+    let mut ceremony = webauthn::start_authentication(Some(&owner.to_string()))?;
+    let ceremony_id = ceremony["ceremony_id"].as_str().unwrap_or_default().to_string();
+    let challenge = ceremony["challenge"]["publicKey"]["challenge"].as_str()
+        .ok_or_else(|| "Authentication ceremony has no challenge".to_string())
+        .and_then(decode_challenge)?;
+    {
+        let mut pending = lock(&PENDING);
+        let now = Instant::now();
+        pending.retain(|_, step_up| step_up.expires_at > now);
+        pending.insert(ceremony_id.clone(), PendingStepUp { digest, owner, challenge, expires_at: now + CEREMONY_TIMEOUT });
+    }
+    println!("COS72-Tauri: Passkey step-up {} started for operation {}", ceremony_id, digest);
+
+    ceremony["operation_digest"] = json!(digest);
+    Ok(ceremony)
+}
+
+/// Verify the assertion of a step-up ceremony; the operation it was started for may then run
+/// once within the grant window
+pub fn finish(ceremony_id: &str, response: &str, events: &EventSink) -> Result<Value, String> {
+    let pending = lock(&PENDING).remove(ceremony_id)
+        .ok_or_else(|| format!("No step-up in progress for ceremony ID: {}", ceremony_id))?;
+    // The assertion must be over the challenge recorded for the digest, not just any challenge
+    // the ceremony state accepts
+    if signed_challenge(response)? != pending.challenge {
+        return Err(format!("Assertion for step-up {} is over another challenge", ceremony_id));
+    }
+    let mut result = webauthn::finish_authentication(ceremony_id, response, events)?;
+    // The ceremony only allows the owner's passkeys; checked again before granting
+    if result["user_id"] != pending.owner.to_string().as_str() {
+        return Err(format!("Step-up {} was not confirmed by the wallet owner", ceremony_id));
+    }
+
+    {
+        let mut grants = lock(&GRANTS);
+        let now = Instant::now();
+        grants.retain(|grant| grant.expires_at > now);
+        grants.push(Grant { digest: pending.digest, expires_at: now + GRANT_WINDOW });
+    }
+    println!("COS72-Tauri: Passkey step-up {} verified for operation {}", ceremony_id, pending.digest);
+
+    let authorized_until = chrono::Utc::now() + chrono::Duration::from_std(GRANT_WINDOW).unwrap_or_default();
+    result["operation_digest"] = json!(pending.digest);
+    result["authorized_until"] = json!(authorized_until.to_rfc3339());
+    Ok(result)
+}
+
+// Challenge in the client data an assertion signed
+fn signed_challenge(response: &str) -> Result<Vec<u8>, String> {
+    let credential: Value = serde_json::from_str(response)
+        .map_err(|e| format!("Failed to parse authentication response: {}", e))?;
+    let client_data = credential["response"]["clientDataJSON"].as_str()
+        .ok_or_else(|| "Authentication response has no client data".to_string())
+        .and_then(decode_challenge)?;
+    let client_data: Value = serde_json::from_slice(&client_data)
+        .map_err(|e| format!("Failed to parse client data: {}", e))?;
+    client_data["challenge"].as_str()
+        .ok_or_else(|| "Client data has no challenge".to_string())
+        .and_then(decode_challenge)
+}
+
+fn decode_challenge(encoded: &str) -> Result<Vec<u8>, String> {
+    general_purpose::URL_SAFE_NO_PAD.decode(encoded.trim_end_matches('='))
+        .map_err(|e| format!("Failed to decode challenge: {}", e))
+}
+
+/// Whether an unexpired step-up was made for exactly `op`, without using it up
+pub fn has_grant(op: &TeeOperation) -> bool {
+    let Ok(digest) = operation_digest(op) else {
+        return false;
+    };
+    let now = Instant::now();
+    lock(&GRANTS).iter().any(|grant| grant.digest == digest && grant.expires_at > now)
+}
+
+/// Use up an unexpired step-up made for exactly `op`
+pub fn take_grant(op: &TeeOperation) -> bool {
+    let Ok(digest) = operation_digest(op) else {
+        return false;
+    };
+    let mut grants = lock(&GRANTS);
+    let now = Instant::now();
+    grants.retain(|grant| grant.expires_at > now);
+    match grants.iter().position(|grant| grant.digest == digest) {
+        Some(index) => {
+            grants.remove(index);
+            true
+        },
+        None => false,
+    }
+}
+
+/// Announce with step-up-required that `op` waits for a passkey step-up and wait until the
+/// user has confirmed it; the window answers with `request_step_up` and `finish_step_up`
+pub async fn confirm(op: &TeeOperation, events: &EventSink) -> Result<(), TeeError> {
+    let digest = operation_digest(op).map_err(TeeError::StepUpRequired)?;
+    let deadline = Instant::now() + CEREMONY_TIMEOUT;
+    let expires_at = chrono::Utc::now() + chrono::Duration::from_std(CEREMONY_TIMEOUT).unwrap_or_default();
+    events(EVENT_STEP_UP_REQUIRED, json!({
+        "operation": operation_request(op),
+        "operation_digest": digest,
+        "expires_at": expires_at.to_rfc3339(),
+    }));
+
+    loop {
+        if lock(&GRANTS).iter().any(|grant| grant.digest == digest && grant.expires_at > Instant::now()) {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(TeeError::StepUpRequired("Passkey confirmation timed out".to_string()));
+        }
+        tokio::time::sleep(GRANT_POLL_INTERVAL).await;
+    }
+}
+
+// Operation as the perform_tee_operation and request_step_up commands take it
+fn operation_request(op: &TeeOperation) -> String {
+    match op {
+        TeeOperation::SignTransaction(tx_data) => json!({ "type": "SignTransaction", "txData": tx_data }).to_string(),
+        TeeOperation::SignUserOperation(user_op_data) => json!({ "type": "SignUserOperation", "userOpData": user_op_data }).to_string(),
+        TeeOperation::SignSafeTransaction(safe_tx_data) => json!({ "type": "SignSafeTransaction", "safeTxData": safe_tx_data }).to_string(),
+        TeeOperation::ExportWallet(false) => "ExportWallet".to_string(),
+        TeeOperation::ExportWallet(true) => "ExportWalletWithPrivate".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth::SafeTransaction;
+    use crate::fido::test_authenticator::TestAuthenticator;
+    use crate::policy::{self, PolicyRules};
+    use alloy_primitives::{Address, U256};
+    use std::sync::Arc;
+
+    const TX: &str = r#"{
+        "nonce": 9, "gasPrice": "20000000000", "gas": 21000, "chainId": 1,
+        "to": "0x3535353535353535353535353535353535353535", "value": "1000000000000000000"
+    }"#;
+
+    fn no_events() -> EventSink {
+        Arc::new(|_, _| {})
+    }
+
+    #[test]
+    fn test_step_up_is_bound_to_the_operation() {
+        let _guard = webauthn::tests::setup();
+        let mut authenticator = TestAuthenticator::new("tauri://localhost");
+        let user_id = webauthn::tests::register(&mut authenticator, "alice");
+        let op = TeeOperation::SignTransaction(TX.to_string());
+        let other = TeeOperation::SignTransaction(TX.replace("\"nonce\": 9", "\"nonce\": 10"));
+        assert!(!take_grant(&op));
+
+        let ceremony = start(&op).unwrap();
+        let ceremony_id = ceremony["ceremony_id"].as_str().unwrap();
+        let response = authenticator.authenticate(&ceremony["challenge"]);
+        let result = finish(ceremony_id, &response, &no_events()).unwrap();
+        assert_eq!(result["operation_digest"], json!(operation_digest(&op).unwrap()));
+        assert_eq!(result["user_id"], json!(user_id));
+
+        // Only the confirmed transaction is authorized, and only once
+        assert!(!take_grant(&other));
+        assert!(!take_grant(&TeeOperation::ExportWallet(true)));
+        assert!(take_grant(&op));
+        assert!(!take_grant(&op));
+    }
+
+    #[test]
+    fn test_step_up_rejects_assertion_over_other_challenge() {
+        let _guard = webauthn::tests::setup();
+        let mut authenticator = TestAuthenticator::new("tauri://localhost");
+        let user_id = webauthn::tests::register(&mut authenticator, "alice");
+        let op = TeeOperation::ExportWallet(true);
+
+        // An assertion over a plain sign-in challenge does not confirm the export
+        let ceremony = start(&op).unwrap();
+        let unrelated = webauthn::start_authentication(Some(&user_id)).unwrap();
+        let response = authenticator.authenticate(&unrelated["challenge"]);
+        assert!(finish(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).is_err());
+        assert!(!take_grant(&op));
+
+        // Ceremonies are single use
+        let response = authenticator.authenticate(&ceremony["challenge"]);
+        assert!(finish(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).is_err());
+        assert!(!take_grant(&op));
+    }
+
+    #[test]
+    fn test_step_up_is_confirmed_by_the_wallet_owner() {
+        let _guard = webauthn::tests::setup();
+        let mut owner_device = TestAuthenticator::new("tauri://localhost");
+        let mut other_device = TestAuthenticator::new("tauri://localhost");
+        let owner = webauthn::tests::register(&mut owner_device, "alice");
+        let other = webauthn::tests::register(&mut other_device, "mallory");
+        let op = TeeOperation::ExportWallet(true);
+
+        // The first registered user owns the wallet; other users' passkeys are not offered
+        assert_eq!(webauthn::wallet_owner().unwrap().to_string(), owner);
+        let ceremony = start(&op).unwrap();
+        let allowed = ceremony["challenge"]["publicKey"]["allowCredentials"].as_array().unwrap().clone();
+        assert_eq!(allowed.len(), 1);
+        assert_eq!(allowed[0]["id"], webauthn::get_credentials(&owner).unwrap()[0]["credential_id"]);
+
+        // The owner can be pinned in the configuration
+        let mut config = webauthn::get_config();
+        config.wallet_owner = Some(other.parse().unwrap());
+        webauthn::set_config(config).unwrap();
+        let ceremony = start(&op).unwrap();
+        let response = other_device.authenticate(&ceremony["challenge"]);
+        assert_eq!(finish(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).unwrap()["user_id"], json!(other));
+        assert!(take_grant(&op));
+    }
+
+    #[test]
+    fn test_expired_grant_is_rejected() {
+        let _guard = webauthn::tests::setup();
+        let op = TeeOperation::ExportWallet(false);
+        lock(&GRANTS).push(Grant { digest: operation_digest(&op).unwrap(), expires_at: Instant::now() });
+        assert!(!take_grant(&op));
+        assert!(is_required(&op));
+        assert!(!is_required(&TeeOperation::GetPublicKey));
+    }
+
+    #[test]
+    fn test_backup_shares_step_up_covers_share_parameters() {
+        let _guard = webauthn::tests::setup();
+        let mut authenticator = TestAuthenticator::new("tauri://localhost");
+        webauthn::tests::register(&mut authenticator, "alice");
+        let op = TeeOperation::CreateBackupShares(2, 3, "passphrase".to_string());
+        assert!(is_required(&op));
+
+        let ceremony = start(&op).unwrap();
+        let response = authenticator.authenticate(&ceremony["challenge"]);
+        finish(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).unwrap();
+
+        // Other share parameters are not covered by the step-up
+        assert!(!take_grant(&TeeOperation::CreateBackupShares(1, 3, "passphrase".to_string())));
+        assert!(!take_grant(&TeeOperation::CreateBackupShares(2, 3, String::new())));
+        assert!(!take_grant(&TeeOperation::ExportWallet(true)));
+        assert!(take_grant(&op));
+    }
+
+    #[test]
+    fn test_step_up_satisfies_policy_threshold_for_safe_transactions() {
+        let _guard = webauthn::tests::setup();
+        let _policy_guard = policy::tests::lock_policy();
+        let mut authenticator = TestAuthenticator::new("tauri://localhost");
+        webauthn::tests::register(&mut authenticator, "alice");
+        policy::set_policy(PolicyRules { step_up_above: Some(U256::from(100)), ..Default::default() }).unwrap();
+
+        let request = SafeTransactionRequest {
+            safe: Address::repeat_byte(1),
+            chain_id: 1,
+            tx: SafeTransaction { to: Address::repeat_byte(2), value: U256::from(1000), nonce: U256::from(3), ..Default::default() },
+        };
+        let op = TeeOperation::SignSafeTransaction(serde_json::to_string(&request).unwrap());
+        assert_eq!(operation_digest(&op).unwrap(), request.safe_tx_hash());
+        assert!(matches!(policy::authorize(&op), Err(TeeError::StepUpRequired(_))));
+
+        let ceremony = start(&op).unwrap();
+        let response = authenticator.authenticate(&ceremony["challenge"]);
+        finish(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).unwrap();
+        let approved = policy::authorize(&op);
+        policy::set_policy(PolicyRules::default()).unwrap();
+        assert_eq!(approved.unwrap(), Some(U256::from(1000)));
+    }
+
+    #[test]
+    fn test_grant_is_only_used_by_an_operation_it_lets_through() {
+        let _guard = webauthn::tests::setup();
+        let _policy_guard = policy::tests::lock_policy();
+        let mut authenticator = TestAuthenticator::new("tauri://localhost");
+        webauthn::tests::register(&mut authenticator, "alice");
+        let confirm = |authenticator: &mut TestAuthenticator, op: &TeeOperation| {
+            let ceremony = start(op).unwrap();
+            let response = authenticator.authenticate(&ceremony["challenge"]);
+            finish(ceremony["ceremony_id"].as_str().unwrap(), &response, &no_events()).unwrap();
+        };
+        let tx = TeeOperation::SignTransaction(TX.to_string());
+        let request = SafeTransactionRequest {
+            safe: Address::repeat_byte(1),
+            chain_id: 1,
+            tx: SafeTransaction { to: Address::repeat_byte(2), value: U256::from(1000), nonce: U256::from(4), ..Default::default() },
+        };
+        let safe_tx = TeeOperation::SignSafeTransaction(serde_json::to_string(&request).unwrap());
+        confirm(&mut authenticator, &tx);
+        confirm(&mut authenticator, &safe_tx);
+
+        // A denied transaction leaves the step-up for when the policy allows it
+        policy::set_policy(PolicyRules { max_value_per_tx: Some(U256::from(100)), ..Default::default() }).unwrap();
+        let denied = policy::authorize(&tx);
+        policy::set_policy(PolicyRules::default()).unwrap();
+        assert!(matches!(denied, Err(TeeError::PolicyDenied(_))));
+        assert!(has_grant(&tx));
+        assert!(policy::authorize(&tx).is_ok());
+        assert!(!has_grant(&tx));
+
+        // Safe transactions the policy allows without a step-up do not use one up
+        assert!(policy::authorize(&safe_tx).is_ok());
+        assert!(take_grant(&safe_tx));
+
+        // Decisions are logged with the kind of operation
+        let logged = crate::storage::read_lines(policy::DECISION_LOG_FILE).unwrap();
+        let entry: Value = serde_json::from_str(logged.last().unwrap()).unwrap();
+        assert_eq!(entry["operation"], json!("sign_safe_transaction"));
+    }
+}
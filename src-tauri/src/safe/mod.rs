@@ -0,0 +1,415 @@
+// Safe Multisig Module
+// Co-signing of Safe{Wallet} transactions for community treasuries: pending transactions
+// are kept locally by SafeTx hash, the TEE wallet adds its owner signature, signatures
+// of other owners are imported and checked against the Safe's owners, and once the
+// threshold is met they are sorted and packed into an `execTransaction` call.
+
+use alloy_primitives::{eip191_hash_message, Address, Bytes, Signature, B256, U256};
+use alloy_sol_types::{sol, SolCall};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Mutex;
+
+use crate::chain::rpc::CallRequest;
+use crate::chain::RpcClient;
+use crate::eth::{SafeTransaction, SafeTransactionRequest};
+use crate::sender::{self, TeeSigner};
+use crate::storage;
+use crate::tee::{self, TeeError, TeeOperation};
+
+// Constants
+const SAFE_TRANSACTIONS_FILE: &str = "safe_transactions.json";
+
+// Serializes read-modify-write updates of the pending transactions
+static STORE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+sol! {
+    interface ISafe {
+        function getOwners() external view returns (address[] memory);
+        function getThreshold() external view returns (uint256);
+        function nonce() external view returns (uint256);
+        function execTransaction(
+            address to,
+            uint256 value,
+            bytes data,
+            uint8 operation,
+            uint256 safeTxGas,
+            uint256 baseGas,
+            uint256 gasPrice,
+            address gasToken,
+            address refundReceiver,
+            bytes signatures
+        ) external payable returns (bool success);
+    }
+}
+
+/// Signs Safe transactions as one of the Safe's owners
+#[async_trait]
+pub trait SafeSigner: Send + Sync {
+    /// Owner signature over the SafeTx hash of `SafeTransactionRequest` JSON
+    async fn sign_safe_transaction(&self, request: String) -> Result<(Address, Bytes), TeeError>;
+}
+
+#[async_trait]
+impl SafeSigner for TeeSigner {
+    async fn sign_safe_transaction(&self, request: String) -> Result<(Address, Bytes), TeeError> {
+        let result = tee::perform_tee_operation(TeeOperation::SignSafeTransaction(request)).await?;
+        let data = sender::tee_result_data(result)?;
+        let invalid = |e: serde_json::Error| TeeError::OperationFailed(format!("Invalid signing result: {}", e));
+        let signer = serde_json::from_value(data["signer"].clone()).map_err(invalid)?;
+        let signature = serde_json::from_value(data["signature"].clone()).map_err(invalid)?;
+        Ok((signer, signature))
+    }
+}
+
+/// Owners, threshold and next nonce of a Safe
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SafeInfo {
+    pub owners: Vec<Address>,
+    pub threshold: u64,
+    pub nonce: U256,
+}
+
+pub async fn get_info(client: &RpcClient, safe: Address) -> Result<SafeInfo, String> {
+    let call = |data: Vec<u8>| CallRequest { to: Some(safe), data: Some(data.into()), ..Default::default() };
+    let failed = |e| format!("Failed to read Safe {}: {}", safe, e);
+
+    let owners = client.call(&call(ISafe::getOwnersCall {}.abi_encode()), "latest").await.map_err(failed)?;
+    let threshold = client.call(&call(ISafe::getThresholdCall {}.abi_encode()), "latest").await.map_err(failed)?;
+    let nonce = client.call(&call(ISafe::nonceCall {}.abi_encode()), "latest").await.map_err(failed)?;
+
+    let invalid = |e| format!("{} is not a Safe: {}", safe, e);
+    let threshold = ISafe::getThresholdCall::abi_decode_returns(&threshold).map_err(invalid)?;
+    Ok(SafeInfo {
+        owners: ISafe::getOwnersCall::abi_decode_returns(&owners).map_err(invalid)?,
+        threshold: threshold.try_into().map_err(|_| format!("Invalid threshold of Safe {}", safe))?,
+        nonce: ISafe::nonceCall::abi_decode_returns(&nonce).map_err(invalid)?,
+    })
+}
+
+/// One owner's signature over a SafeTx hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnerSignature {
+    pub signer: Address,
+    pub signature: Bytes,
+}
+
+/// Owner that produced an ECDSA signature over `safe_tx_hash`
+///
+/// Accepts plain EIP-712 signatures (v 27/28) and `eth_sign` signatures of the hash,
+/// which Safe marks with v + 4. Contract and pre-approved signatures are not supported.
+pub fn recover_signer(safe_tx_hash: B256, signature: &[u8]) -> Result<OwnerSignature, String> {
+    if signature.len() != 65 {
+        return Err(format!("Owner signature must be 65 bytes, got {}", signature.len()));
+    }
+    // Safe expects v as 27/28, some signers return the parity as 0/1
+    let mut signature = signature.to_vec();
+    if signature[64] < 2 {
+        signature[64] += 27;
+    }
+    let (hash, v) = match signature[64] {
+        27 | 28 => (safe_tx_hash, signature[64]),
+        31 | 32 => (eip191_hash_message(safe_tx_hash), signature[64] - 4),
+        v => return Err(format!("Unsupported Safe signature type v = {}", v)),
+    };
+
+    let mut ecdsa = signature.clone();
+    ecdsa[64] = v;
+    let signer = Signature::try_from(ecdsa.as_slice())
+        .and_then(|ecdsa| ecdsa.recover_address_from_prehash(&hash))
+        .map_err(|e| format!("Invalid owner signature: {}", e))?;
+    Ok(OwnerSignature { signer, signature: signature.into() })
+}
+
+/// Concatenated signatures in ascending owner order, as `execTransaction` requires
+pub fn pack_signatures(signatures: &[OwnerSignature]) -> Bytes {
+    let mut sorted = signatures.to_vec();
+    sorted.sort_by_key(|signature| signature.signer);
+    sorted.dedup_by_key(|signature| signature.signer);
+    sorted.iter().flat_map(|signature| signature.signature.iter().copied()).collect::<Vec<_>>().into()
+}
+
+/// Calldata of the Safe's `execTransaction` for `tx` with packed signatures
+pub fn exec_transaction_calldata(tx: &SafeTransaction, signatures: Bytes) -> Bytes {
+    ISafe::execTransactionCall {
+        to: tx.to,
+        value: tx.value,
+        data: tx.data.clone(),
+        operation: tx.operation,
+        safeTxGas: tx.safe_tx_gas,
+        baseGas: tx.base_gas,
+        gasPrice: tx.gas_price,
+        gasToken: tx.gas_token,
+        refundReceiver: tx.refund_receiver,
+        signatures,
+    }.abi_encode().into()
+}
+
+/// Safe transaction collecting owner signatures
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingSafeTransaction {
+    pub safe_tx_hash: B256,
+    pub safe: Address,
+    pub chain_id: u64,
+    pub tx: SafeTransaction,
+    pub signatures: Vec<OwnerSignature>,
+    pub created_at: String,
+}
+
+impl PendingSafeTransaction {
+    fn request(&self) -> SafeTransactionRequest {
+        SafeTransactionRequest { safe: self.safe, chain_id: self.chain_id, tx: self.tx.clone() }
+    }
+
+    // Add or replace the signature of one owner
+    fn add_signature(&mut self, signature: OwnerSignature) {
+        self.signatures.retain(|existing| existing.signer != signature.signer);
+        self.signatures.push(signature);
+    }
+}
+
+/// `execTransaction` call ready to be sent to the Safe by any account
+#[derive(Debug, Clone, Serialize)]
+pub struct SafeExecution {
+    pub safe_tx_hash: B256,
+    pub chain_id: u64,
+    pub to: Address,
+    pub data: Bytes,
+    pub signers: Vec<Address>,
+    pub threshold: u64,
+}
+
+fn load_pending() -> Result<Vec<PendingSafeTransaction>, String> {
+    storage::load_json(SAFE_TRANSACTIONS_FILE)
+        .map(Option::unwrap_or_default)
+        .map_err(|e| format!("Failed to read Safe transactions: {}", e))
+}
+
+fn save_pending(pending: &[PendingSafeTransaction]) -> Result<(), String> {
+    storage::save_json(SAFE_TRANSACTIONS_FILE, &pending)
+        .map_err(|e| format!("Failed to save Safe transactions: {}", e))
+}
+
+// Apply `update` to the stored transaction with the given hash
+fn update_pending(
+    safe_tx_hash: B256,
+    update: impl FnOnce(&mut PendingSafeTransaction),
+) -> Result<PendingSafeTransaction, String> {
+    let _guard = STORE_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+    let mut pending = load_pending()?;
+    let entry = pending.iter_mut().find(|entry| entry.safe_tx_hash == safe_tx_hash)
+        .ok_or_else(|| format!("Unknown Safe transaction {}", safe_tx_hash))?;
+    update(entry);
+    let updated = entry.clone();
+    save_pending(&pending)?;
+    Ok(updated)
+}
+
+/// Pending Safe transactions, optionally of one Safe
+pub fn list_pending(safe: Option<Address>) -> Result<Vec<PendingSafeTransaction>, String> {
+    Ok(load_pending()?.into_iter().filter(|entry| safe.is_none_or(|safe| entry.safe == safe)).collect())
+}
+
+pub fn get_pending(safe_tx_hash: B256) -> Result<PendingSafeTransaction, String> {
+    load_pending()?.into_iter().find(|entry| entry.safe_tx_hash == safe_tx_hash)
+        .ok_or_else(|| format!("Unknown Safe transaction {}", safe_tx_hash))
+}
+
+/// Start collecting signatures for a Safe transaction
+///
+/// `tx` is SafeTransaction JSON; without a nonce the Safe's next nonce is used. Proposing
+/// a transaction that is already pending returns it with its signatures.
+pub async fn propose(client: &RpcClient, safe: Address, chain_id: u64, mut tx: Value) -> Result<PendingSafeTransaction, String> {
+    if tx.get("nonce").is_none_or(Value::is_null) {
+        tx["nonce"] = serde_json::json!(get_info(client, safe).await?.nonce);
+    }
+    let request = SafeTransactionRequest::from_json(&serde_json::json!({ "safe": safe, "chainId": chain_id, "tx": tx }).to_string())?;
+    let safe_tx_hash = request.safe_tx_hash();
+
+    let _guard = STORE_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+    let mut pending = load_pending()?;
+    if let Some(existing) = pending.iter().find(|entry| entry.safe_tx_hash == safe_tx_hash) {
+        return Ok(existing.clone());
+    }
+    let entry = PendingSafeTransaction {
+        safe_tx_hash,
+        safe,
+        chain_id,
+        tx: request.tx,
+        signatures: Vec::new(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    pending.push(entry.clone());
+    save_pending(&pending)?;
+
+    println!("COS72-Tauri: Proposed Safe transaction {} for {}", safe_tx_hash, safe);
+    Ok(entry)
+}
+
+/// Add the wallet's owner signature to a pending Safe transaction
+pub async fn sign(client: &RpcClient, safe_tx_hash: B256, signer: &dyn SafeSigner) -> Result<PendingSafeTransaction, String> {
+    let entry = get_pending(safe_tx_hash)?;
+    let request = serde_json::to_string(&entry.request()).map_err(|e| e.to_string())?;
+    let (address, signature) = signer.sign_safe_transaction(request).await.map_err(|e| e.to_string())?;
+    let signature = recover_signer(safe_tx_hash, &signature)?;
+    if signature.signer != address {
+        return Err(format!("Signature recovers to {}, not the wallet address {}", signature.signer, address));
+    }
+    add_owner_signature(client, &entry, signature).await
+}
+
+/// Import another owner's signature, e.g. shared through the Safe Transaction Service
+pub async fn add_signature(client: &RpcClient, safe_tx_hash: B256, signature: &[u8]) -> Result<PendingSafeTransaction, String> {
+    let entry = get_pending(safe_tx_hash)?;
+    add_owner_signature(client, &entry, recover_signer(safe_tx_hash, signature)?).await
+}
+
+async fn add_owner_signature(client: &RpcClient, entry: &PendingSafeTransaction, signature: OwnerSignature) -> Result<PendingSafeTransaction, String> {
+    let info = get_info(client, entry.safe).await?;
+    if !info.owners.contains(&signature.signer) {
+        return Err(format!("{} is not an owner of Safe {}", signature.signer, entry.safe));
+    }
+    println!("COS72-Tauri: Added signature of {} to Safe transaction {}", signature.signer, entry.safe_tx_hash);
+    update_pending(entry.safe_tx_hash, |entry| entry.add_signature(signature))
+}
+
+/// Build the `execTransaction` call once enough current owners have signed
+pub async fn build_execution(client: &RpcClient, safe_tx_hash: B256) -> Result<SafeExecution, String> {
+    let entry = get_pending(safe_tx_hash)?;
+    let info = get_info(client, entry.safe).await?;
+    if entry.tx.nonce != info.nonce {
+        return Err(format!("Safe transaction has nonce {}, the Safe is at nonce {}", entry.tx.nonce, info.nonce));
+    }
+
+    // Owners may have changed since signing
+    let signatures: Vec<OwnerSignature> = entry.signatures.iter()
+        .filter(|signature| info.owners.contains(&signature.signer))
+        .cloned()
+        .collect();
+    if (signatures.len() as u64) < info.threshold {
+        return Err(format!("Safe transaction has {} of {} required owner signatures", signatures.len(), info.threshold));
+    }
+
+    let mut signers: Vec<Address> = signatures.iter().map(|signature| signature.signer).collect();
+    signers.sort();
+    Ok(SafeExecution {
+        safe_tx_hash,
+        chain_id: entry.chain_id,
+        to: entry.safe,
+        data: exec_transaction_calldata(&entry.tx, pack_signatures(&signatures)),
+        signers,
+        threshold: info.threshold,
+    })
+}
+
+/// Stop tracking a Safe transaction, e.g. after it was executed
+pub fn remove_pending(safe_tx_hash: B256) -> Result<bool, String> {
+    let _guard = STORE_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+    let mut pending = load_pending()?;
+    let count = pending.len();
+    pending.retain(|entry| entry.safe_tx_hash != safe_tx_hash);
+    if pending.len() == count {
+        return Ok(false);
+    }
+    save_pending(&pending)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_node::TestNode;
+    use crate::tee::wallet::WalletKey;
+    use alloy_sol_types::SolValue;
+    use serde_json::json;
+
+    #[async_trait]
+    impl SafeSigner for WalletKey {
+        async fn sign_safe_transaction(&self, request: String) -> Result<(Address, Bytes), TeeError> {
+            let signed = WalletKey::sign_safe_transaction(self, &request)?;
+            Ok((self.address(), serde_json::from_value(signed["signature"].clone()).unwrap()))
+        }
+    }
+
+    // Safe with the given owners and threshold at nonce 4
+    fn script_safe(node: &TestNode, safe: Address, owners: Vec<Address>, threshold: u64) {
+        node.on("eth_call", move |params| {
+            assert_eq!(params[0]["to"], json!(safe));
+            let data: Bytes = serde_json::from_value(params[0]["data"].clone()).unwrap();
+            let output = match data[..4].try_into().unwrap() {
+                ISafe::getOwnersCall::SELECTOR => owners.abi_encode(),
+                ISafe::getThresholdCall::SELECTOR => U256::from(threshold).abi_encode(),
+                ISafe::nonceCall::SELECTOR => U256::from(4).abi_encode(),
+                selector => panic!("unexpected call {:?}", selector),
+            };
+            Ok(json!(Bytes::from(output)))
+        });
+    }
+
+    #[test]
+    fn test_recover_and_pack_signatures() {
+        let hash = B256::repeat_byte(0x5a);
+        let owner = WalletKey::from_private_key_hex(&format!("0x{}", "81".repeat(32))).unwrap();
+        let signature = owner.sign_hash(&hash).unwrap().as_bytes();
+        assert_eq!(recover_signer(hash, &signature).unwrap().signer, owner.address());
+
+        // eth_sign signatures have v + 4 and sign the hash as a personal message
+        let mut eth_sign = owner.sign_hash(&eip191_hash_message(hash)).unwrap().as_bytes();
+        eth_sign[64] += 4;
+        let recovered = recover_signer(hash, &eth_sign).unwrap();
+        assert_eq!(recovered.signer, owner.address());
+        assert_eq!(recovered.signature[64], eth_sign[64]);
+        assert!(recover_signer(hash, &signature[..64]).is_err());
+
+        let low = OwnerSignature { signer: Address::repeat_byte(1), signature: Bytes::from(vec![1; 65]) };
+        let high = OwnerSignature { signer: Address::repeat_byte(2), signature: Bytes::from(vec![2; 65]) };
+        let packed = pack_signatures(&[high.clone(), low.clone(), high]);
+        assert_eq!(packed.len(), 130);
+        assert_eq!((packed[0], packed[65]), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_co_sign_and_execute() {
+        let node = TestNode::spawn().await;
+        let client = RpcClient::new(vec![node.url()]);
+        let wallet = WalletKey::from_private_key_hex(&format!("0x{}", "82".repeat(32))).unwrap();
+        let co_owner = WalletKey::from_private_key_hex(&format!("0x{}", "83".repeat(32))).unwrap();
+        let outsider = WalletKey::from_private_key_hex(&format!("0x{}", "84".repeat(32))).unwrap();
+        let safe = Address::repeat_byte(0x85);
+        script_safe(&node, safe, vec![wallet.address(), co_owner.address()], 2);
+
+        let tx = json!({ "to": Address::repeat_byte(0x86), "value": "0x64" });
+        let pending = propose(&client, safe, 31337, tx.clone()).await.unwrap();
+        assert_eq!(pending.tx.nonce, U256::from(4));
+        assert_eq!(pending.safe_tx_hash, pending.tx.hash(safe, 31337));
+        assert_eq!(propose(&client, safe, 31337, tx).await.unwrap().safe_tx_hash, pending.safe_tx_hash);
+        let hash = pending.safe_tx_hash;
+
+        let signed = sign(&client, hash, &wallet).await.unwrap();
+        assert_eq!(signed.signatures.len(), 1);
+        let error = build_execution(&client, hash).await.unwrap_err();
+        assert!(error.contains("1 of 2"), "{}", error);
+
+        let error = add_signature(&client, hash, &outsider.sign_hash(&hash).unwrap().as_bytes()).await.unwrap_err();
+        assert!(error.contains("not an owner"), "{}", error);
+        add_signature(&client, hash, &co_owner.sign_hash(&hash).unwrap().as_bytes()).await.unwrap();
+
+        let execution = build_execution(&client, hash).await.unwrap();
+        assert_eq!(execution.to, safe);
+        let call = ISafe::execTransactionCall::abi_decode(&execution.data).unwrap();
+        assert_eq!(call.value, U256::from(100));
+        assert_eq!(call.signatures.len(), 130);
+        let mut owners = vec![wallet.address(), co_owner.address()];
+        owners.sort();
+        assert_eq!(execution.signers, owners);
+        for (index, owner) in owners.iter().enumerate() {
+            let signature = &call.signatures[index * 65..(index + 1) * 65];
+            assert_eq!(recover_signer(hash, signature).unwrap().signer, *owner);
+        }
+
+        assert!(remove_pending(hash).unwrap());
+        assert!(get_pending(hash).is_err());
+    }
+}
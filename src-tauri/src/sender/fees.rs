@@ -0,0 +1,100 @@
+// Fee Estimation
+// EIP-1559 fees from recent fee history, with a legacy gas price fallback for
+// chains that do not report a base fee
+
+use alloy_primitives::U256;
+use serde_json::json;
+
+use crate::chain::rpc::{FeeHistory, RpcClient, RpcError};
+use crate::eth::TxFees;
+
+// Constants
+const FEE_HISTORY_BLOCKS: u64 = 10;
+const PRIORITY_FEE_PERCENTILE: f64 = 50.0;
+// Headroom for the base fee rising over the next blocks
+const BASE_FEE_MULTIPLIER: u128 = 2;
+
+/// Estimate fees for a transaction to be included in the next few blocks
+pub async fn estimate_fees(client: &RpcClient) -> Result<TxFees, RpcError> {
+    let history = client.fee_history(FEE_HISTORY_BLOCKS, "latest", &[PRIORITY_FEE_PERCENTILE]).await;
+    if let Ok(history) = &history {
+        if let Some(fees) = fees_from_history(history)? {
+            return Ok(fees);
+        }
+    }
+
+    let gas_price = client.request::<U256>("eth_gasPrice", json!([])).await?;
+    Ok(TxFees::Legacy { gas_price: to_u128(gas_price)? })
+}
+
+// EIP-1559 fees from fee history, None when the chain reports no base fee
+// Fees the node reports are not trusted to stay in range; overflowing values are an error
+fn fees_from_history(history: &FeeHistory) -> Result<Option<TxFees>, RpcError> {
+    // The last entry is the base fee of the next block
+    let Some(base_fee) = history.base_fee_per_gas.last() else {
+        return Ok(None);
+    };
+    let base_fee = to_u128(*base_fee)?;
+    if base_fee == 0 {
+        return Ok(None);
+    }
+
+    let rewards = history.reward.iter()
+        .flatten()
+        .filter_map(|block| block.first().copied().map(to_u128))
+        .collect::<Result<Vec<u128>, RpcError>>()?;
+    let max_priority_fee_per_gas = match rewards.len() {
+        0 => 0,
+        count => rewards.iter()
+            .try_fold(0u128, |sum, reward| sum.checked_add(*reward))
+            .ok_or_else(|| fee_overflow("priority fee rewards"))? / count as u128,
+    };
+    let max_fee_per_gas = base_fee.checked_mul(BASE_FEE_MULTIPLIER)
+        .and_then(|fee| fee.checked_add(max_priority_fee_per_gas))
+        .ok_or_else(|| fee_overflow("base fee"))?;
+
+    Ok(Some(TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas }))
+}
+
+fn to_u128(value: U256) -> Result<u128, RpcError> {
+    u128::try_from(value).map_err(|_| fee_overflow("fee"))
+}
+
+fn fee_overflow(what: &str) -> RpcError {
+    RpcError::InvalidResponse(format!("Node reported a {} too large to price a transaction", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::U64;
+
+    #[test]
+    fn test_fees_from_history() {
+        let gwei = |n: u64| U256::from(n * 1_000_000_000);
+        let mut history = FeeHistory {
+            oldest_block: U64::from(1),
+            base_fee_per_gas: vec![gwei(1), gwei(2), gwei(3)],
+            gas_used_ratio: vec![0.5, 0.5],
+            reward: Some(vec![vec![gwei(1)], vec![gwei(3)]]),
+        };
+
+        assert_eq!(fees_from_history(&history).unwrap(), Some(TxFees::Eip1559 {
+            max_fee_per_gas: 8_000_000_000,
+            max_priority_fee_per_gas: 2_000_000_000,
+        }));
+
+        // Values that overflow the fee arithmetic are refused
+        history.reward = Some(vec![vec![U256::from(u128::MAX)], vec![gwei(3)]]);
+        assert!(fees_from_history(&history).is_err());
+        history.reward = None;
+        history.base_fee_per_gas = vec![U256::from(u128::MAX)];
+        assert!(fees_from_history(&history).is_err());
+        history.base_fee_per_gas = vec![U256::MAX];
+        assert!(fees_from_history(&history).is_err());
+
+        // Pre-London chains fall back to legacy pricing
+        history.base_fee_per_gas = vec![U256::ZERO; 3];
+        assert_eq!(fees_from_history(&history).unwrap(), None);
+    }
+}
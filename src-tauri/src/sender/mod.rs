@@ -0,0 +1,549 @@
+// Transaction Sender
+// Full send flow for a transaction from the wallet: nonce with local pending tracking,
+// gas and EIP-1559 fee estimation, simulation, passkey step-up, policy check and signing through the TEE
+// adapter, broadcast, and status events (tx-submitted, tx-confirmed, tx-failed) until the
+// receipt arrives. Pending transactions can be sped up or cancelled by replacement.
+
+pub mod fees;
+pub mod nonce;
+pub mod replace;
+pub mod simulate;
+
+pub use replace::{replace_transaction, ReplacementKind};
+
+use alloy_primitives::{Address, Bytes, B256, U64};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::chain::rpc::{CallRequest, RpcError};
+use crate::chain::{ChainConfig, RpcClient};
+use crate::eth::{TxFees, TxRequest};
+use crate::history;
+use crate::policy::step_up;
+use crate::tee::{self, TeeError, TeeOperation};
+
+// Event names
+pub const EVENT_TX_SUBMITTED: &str = "tx-submitted";
+pub const EVENT_TX_CONFIRMED: &str = "tx-confirmed";
+pub const EVENT_TX_FAILED: &str = "tx-failed";
+
+// Constants
+const PLAIN_TRANSFER_GAS: u64 = 21_000;
+// Extra gas over the estimate for contract calls, in percent
+const GAS_LIMIT_BUFFER_PERCENT: u64 = 20;
+
+/// Receives status events; the app forwards them to the window
+pub type EventSink = Arc<dyn Fn(&str, Value) + Send + Sync>;
+
+/// Signed transaction ready for broadcast
+#[derive(Debug, Clone)]
+pub struct SignedTransaction {
+    pub tx_hash: B256,
+    pub raw_transaction: Bytes,
+}
+
+/// Signs complete transactions for one account
+#[async_trait]
+pub trait TransactionSigner: Send + Sync {
+    async fn address(&self) -> Result<Address, TeeError>;
+    async fn sign_transaction(&self, tx_data: String) -> Result<SignedTransaction, TeeError>;
+
+    /// Have the user confirm `tx_data` before it is signed; signers that need no
+    /// confirmation return right away
+    async fn confirm(&self, _tx_data: &str, _events: &EventSink) -> Result<(), TeeError> {
+        Ok(())
+    }
+}
+
+/// Signer backed by the active TEE adapter; signing goes through the transaction policy and
+/// waits for a passkey step-up over the transaction
+pub struct TeeSigner;
+
+#[async_trait]
+impl TransactionSigner for TeeSigner {
+    async fn address(&self) -> Result<Address, TeeError> {
+        let data = tee_result_data(tee::perform_tee_operation(TeeOperation::GetPublicKey).await?)?;
+        serde_json::from_value(data["address"].clone())
+            .map_err(|e| TeeError::OperationFailed(format!("Invalid wallet address: {}", e)))
+    }
+
+    async fn sign_transaction(&self, tx_data: String) -> Result<SignedTransaction, TeeError> {
+        let data = tee_result_data(tee::perform_tee_operation(TeeOperation::SignTransaction(tx_data)).await?)?;
+        let field = |name: &str| data.get(name).cloned()
+            .ok_or_else(|| TeeError::OperationFailed(format!("Signing result has no {}", name)));
+        let invalid = |e: serde_json::Error| TeeError::OperationFailed(format!("Invalid signing result: {}", e));
+
+        Ok(SignedTransaction {
+            tx_hash: serde_json::from_value(field("tx_hash")?).map_err(invalid)?,
+            raw_transaction: serde_json::from_value(field("raw_transaction")?).map_err(invalid)?,
+        })
+    }
+
+    async fn confirm(&self, tx_data: &str, events: &EventSink) -> Result<(), TeeError> {
+        step_up::confirm(&TeeOperation::SignTransaction(tx_data.to_string()), events).await
+    }
+}
+
+pub(crate) fn tee_result_data(result: tee::TeeResult) -> Result<Value, TeeError> {
+    let data = result.data.ok_or(TeeError::OperationFailed(result.message))?;
+    serde_json::from_str(&data).map_err(|e| TeeError::OperationFailed(format!("Invalid TEE result: {}", e)))
+}
+
+/// Timing of the receipt watch after broadcast
+#[derive(Debug, Clone)]
+pub struct SendOptions {
+    pub poll_interval: Duration,
+    pub confirmation_timeout: Duration,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(2),
+            confirmation_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Broadcast transaction, returned to the caller and sent as tx-submitted
+#[derive(Debug, Clone, Serialize)]
+pub struct SubmittedTransaction {
+    pub tx_hash: B256,
+    pub chain_id: u64,
+    pub from: Address,
+    pub nonce: u64,
+    pub gas_limit: u64,
+    pub explorer_url: Option<String>,
+}
+
+// Failure before broadcast, with the step that failed
+struct SendError {
+    stage: &'static str,
+    message: String,
+}
+
+impl SendError {
+    fn new(stage: &'static str, message: impl ToString) -> Self {
+        Self { stage, message: message.to_string() }
+    }
+}
+
+/// Prepare, sign and broadcast a transaction, then watch for its receipt in the background
+///
+/// Missing nonce, gas and fee fields are filled in; fields already set on `tx` are kept.
+pub async fn send_transaction(
+    chain: &ChainConfig,
+    signer: &dyn TransactionSigner,
+    tx: TxRequest,
+    events: EventSink,
+    options: SendOptions,
+) -> Result<SubmittedTransaction, String> {
+    let client = Arc::new(RpcClient::new(chain.rpc_urls.clone()));
+    let result = submit(chain, client.clone(), signer, tx, &events).await;
+    report(chain, client, result, events, options)
+}
+
+// Emit the outcome of a submission and watch a broadcast transaction until its receipt
+fn report(
+    chain: &ChainConfig,
+    client: Arc<RpcClient>,
+    result: Result<SubmittedTransaction, SendError>,
+    events: EventSink,
+    options: SendOptions,
+) -> Result<SubmittedTransaction, String> {
+    match result {
+        Ok(submitted) => {
+            println!("COS72-Tauri: Transaction {} submitted on chain {}", submitted.tx_hash, chain.chain_id);
+            history::mark_submitted(submitted.tx_hash);
+            events(EVENT_TX_SUBMITTED, json!(submitted));
+            tokio::spawn(watch_receipt(client, submitted.clone(), events, options));
+            Ok(submitted)
+        },
+        Err(error) => {
+            println!("COS72-Tauri: Transaction failed at {}: {}", error.stage, error.message);
+            events(EVENT_TX_FAILED, json!({
+                "chain_id": chain.chain_id,
+                "stage": error.stage,
+                "error": error.message,
+            }));
+            Err(error.message)
+        }
+    }
+}
+
+async fn submit(
+    chain: &ChainConfig,
+    client: Arc<RpcClient>,
+    signer: &dyn TransactionSigner,
+    mut tx: TxRequest,
+    events: &EventSink,
+) -> Result<SubmittedTransaction, SendError> {
+    let chain_id = chain.chain_id;
+    if tx.chain_id.is_some_and(|id| id != chain_id) {
+        return Err(SendError::new("prepare", format!("Transaction is for chain {:?}, not {}", tx.chain_id, chain_id)));
+    }
+    tx.chain_id = Some(chain_id);
+
+    let from = signer.address().await.map_err(|e| SendError::new("sign", e))?;
+    if tx.from.is_some_and(|requested| requested != from) {
+        return Err(SendError::new("prepare", format!("Wallet address is {}, not {:?}", from, tx.from)));
+    }
+    tx.from = Some(from);
+
+    // Chain ID check and pending nonce in one round trip
+    let mut results = client.batch(&[
+        ("eth_chainId", json!([])),
+        ("eth_getTransactionCount", json!([from, "pending"])),
+    ]).await.map_err(|e| SendError::new("prepare", e))?.into_iter();
+    let remote_chain_id: U64 = batch_result(results.next())?;
+    let remote_pending: U64 = batch_result(results.next())?;
+    if remote_chain_id.to::<u64>() != chain_id {
+        return Err(SendError::new("prepare", format!(
+            "RPC endpoint reports chain {}, expected {}", remote_chain_id, chain_id
+        )));
+    }
+
+    let nonce = match tx.nonce {
+        Some(nonce) => nonce,
+        None => nonce::reserve(chain_id, from, remote_pending.to()),
+    };
+    let reserved = tx.nonce.is_none();
+    tx.nonce = Some(nonce);
+
+    let result = sign_and_broadcast(chain, &client, signer, tx, events).await;
+    if result.is_err() && reserved {
+        nonce::release(chain_id, from, nonce);
+    }
+    result
+}
+
+async fn sign_and_broadcast(
+    chain: &ChainConfig,
+    client: &RpcClient,
+    signer: &dyn TransactionSigner,
+    mut tx: TxRequest,
+    events: &EventSink,
+) -> Result<SubmittedTransaction, SendError> {
+    if tx.gas_limit.is_none() {
+        let call = CallRequest {
+            from: tx.from,
+            to: tx.to,
+            value: Some(tx.value),
+            data: Some(tx.data.clone()),
+            gas: None,
+        };
+        let estimate = client.estimate_gas(&call).await.map_err(|e| SendError::new("estimate_gas", describe_revert(e)))?;
+        tx.gas_limit = Some(if estimate == PLAIN_TRANSFER_GAS {
+            estimate
+        } else {
+            estimate.checked_mul(GAS_LIMIT_BUFFER_PERCENT)
+                .and_then(|buffer| estimate.checked_add(buffer / 100))
+                .ok_or_else(|| SendError::new("estimate_gas", RpcError::InvalidResponse(format!("Gas estimate {} is too large", estimate))))?
+        });
+    }
+
+    if tx.gas_price.is_none() && tx.max_fee_per_gas.is_none() {
+        match fees::estimate_fees(client).await.map_err(|e| SendError::new("fees", e))? {
+            TxFees::Legacy { gas_price } => tx.gas_price = Some(gas_price),
+            TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => {
+                tx.max_fee_per_gas = Some(max_fee_per_gas);
+                tx.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+            },
+        }
+    }
+
+    // A call that would revert is refused before the user is asked to sign
+    match simulate::simulate(client, &tx).await {
+        Ok(simulation) if !simulation.success => {
+            let reason = simulation.revert_reason.unwrap_or_else(|| "unknown reason".to_string());
+            return Err(SendError::new("simulation", format!("Transaction would revert: {}", reason)));
+        },
+        Ok(_) => {},
+        Err(e) => println!("COS72-Tauri: Transaction simulation failed, continuing: {}", e),
+    }
+
+    // The user confirms the final transaction, then the TEE checks the policy before signing
+    let tx_data = tx.to_json().to_string();
+    signer.confirm(&tx_data, events).await.map_err(|e| SendError::new("step_up", e))?;
+    let signed = signer.sign_transaction(tx_data).await.map_err(|e| {
+        let stage = match e {
+            TeeError::PolicyDenied(_) | TeeError::StepUpRequired(_) => "policy",
+            _ => "sign",
+        };
+        SendError::new(stage, e)
+    })?;
+
+    let tx_hash = client.send_raw_transaction(&signed.raw_transaction).await.map_err(|e| {
+        history::mark_failed(signed.tx_hash, &e.to_string());
+        SendError::new("broadcast", e)
+    })?;
+    if tx_hash != signed.tx_hash {
+        println!("COS72-Tauri: Node returned tx hash {} for signed transaction {}", tx_hash, signed.tx_hash);
+    }
+
+    let tx_hash_string = tx_hash.to_string();
+    Ok(SubmittedTransaction {
+        tx_hash,
+        chain_id: chain.chain_id,
+        from: tx.from.unwrap_or_default(),
+        nonce: tx.nonce.unwrap_or_default(),
+        gas_limit: tx.gas_limit.unwrap_or_default(),
+        explorer_url: chain.explorer_tx_url(&tx_hash_string),
+    })
+}
+
+fn batch_result<T: serde::de::DeserializeOwned>(result: Option<Result<Value, RpcError>>) -> Result<T, SendError> {
+    let value = result
+        .unwrap_or_else(|| Err(RpcError::InvalidResponse("missing batch response".to_string())))
+        .map_err(|e| SendError::new("prepare", e))?;
+    serde_json::from_value(value).map_err(|e| SendError::new("prepare", format!("Invalid RPC response: {}", e)))
+}
+
+// Gas estimation errors carry the revert data of the simulated call
+fn describe_revert(error: RpcError) -> String {
+    match simulate::revert_reason(&error) {
+        Some(reason) => format!("Transaction would revert: {}", reason),
+        None => error.to_string(),
+    }
+}
+
+// Poll for the receipt and report the outcome
+async fn watch_receipt(client: Arc<RpcClient>, submitted: SubmittedTransaction, events: EventSink, options: SendOptions) {
+    let started = Instant::now();
+
+    loop {
+        match client.get_transaction_receipt(submitted.tx_hash).await {
+            Ok(Some(receipt)) => {
+                let error = (!receipt.succeeded()).then(|| "Transaction reverted".to_string());
+                history::mark_included(submitted.tx_hash, receipt.transaction_hash, receipt.block_number.map(|block| block.to()), error);
+                let payload = json!({
+                    "tx_hash": submitted.tx_hash,
+                    "chain_id": submitted.chain_id,
+                    "block_number": receipt.block_number,
+                    "gas_used": receipt.gas_used,
+                    "effective_gas_price": receipt.effective_gas_price,
+                    "explorer_url": submitted.explorer_url,
+                });
+                if receipt.succeeded() {
+                    println!("COS72-Tauri: Transaction {} confirmed", submitted.tx_hash);
+                    events(EVENT_TX_CONFIRMED, payload);
+                } else {
+                    println!("COS72-Tauri: Transaction {} reverted", submitted.tx_hash);
+                    let mut payload = payload;
+                    payload["stage"] = json!("execution");
+                    payload["error"] = json!("Transaction reverted");
+                    events(EVENT_TX_FAILED, payload);
+                }
+                return;
+            },
+            Ok(None) => {},
+            // Transient RPC errors do not end the watch
+            Err(e) => println!("COS72-Tauri: Receipt poll for {} failed: {}", submitted.tx_hash, e),
+        }
+
+        if started.elapsed() >= options.confirmation_timeout {
+            events(EVENT_TX_FAILED, json!({
+                "tx_hash": submitted.tx_hash,
+                "chain_id": submitted.chain_id,
+                "stage": "confirmation",
+                "error": "Timed out waiting for the transaction receipt",
+            }));
+            return;
+        }
+        tokio::time::sleep(options.poll_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_node::{TestNode, REVERT_CALLDATA};
+    use crate::fido::test_authenticator::TestAuthenticator;
+    use crate::fido::webauthn;
+    use crate::policy;
+    use crate::tee::wallet::WalletKey;
+    use crate::tee::{TEEConnectionType, TEEType};
+    use alloy_primitives::keccak256;
+    use std::sync::Mutex;
+
+    #[async_trait]
+    impl TransactionSigner for WalletKey {
+        async fn address(&self) -> Result<Address, TeeError> {
+            Ok(WalletKey::address(self))
+        }
+
+        async fn sign_transaction(&self, tx_data: String) -> Result<SignedTransaction, TeeError> {
+            let signed = WalletKey::sign_transaction(self, &tx_data)?;
+            Ok(SignedTransaction {
+                tx_hash: serde_json::from_value(signed["tx_hash"].clone()).unwrap(),
+                raw_transaction: serde_json::from_value(signed["raw_transaction"].clone()).unwrap(),
+            })
+        }
+    }
+
+    type Recorded = Arc<Mutex<Vec<(String, Value)>>>;
+
+    // Collects emitted events for inspection
+    fn recorder() -> (EventSink, Recorded) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink_events = events.clone();
+        let sink: EventSink = Arc::new(move |event, payload| {
+            sink_events.lock().unwrap().push((event.to_string(), payload));
+        });
+        (sink, events)
+    }
+
+    fn fast_options() -> SendOptions {
+        SendOptions { poll_interval: Duration::from_millis(10), confirmation_timeout: Duration::from_millis(200) }
+    }
+
+    async fn wait_for_events(events: &Mutex<Vec<(String, Value)>>, count: usize) {
+        for _ in 0..100 {
+            if events.lock().unwrap().len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_transaction_signs_broadcasts_and_confirms() {
+        let node = TestNode::spawn().await;
+        let signer = WalletKey::from_private_key_hex(&format!("0x{}", "11".repeat(32))).unwrap();
+        node.set_nonce(signer.address(), 3);
+        let (sink, events) = recorder();
+
+        let tx = TxRequest::from_json(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266", "value": "1000"}"#).unwrap();
+        let submitted = send_transaction(&node.chain_config(), &signer, tx.clone(), sink.clone(), fast_options()).await.unwrap();
+        assert_eq!(submitted.nonce, 3);
+        assert_eq!(submitted.gas_limit, 21_000);
+        assert_eq!(submitted.from, signer.address());
+
+        let raw = node.raw_transactions();
+        assert_eq!(raw.len(), 1);
+        assert_eq!(keccak256(&raw[0]), submitted.tx_hash);
+        assert_eq!(raw[0][0], 0x02, "EIP-1559 transaction expected");
+
+        wait_for_events(&events, 2).await;
+        let events_seen = events.lock().unwrap().clone();
+        assert_eq!(events_seen[0].0, EVENT_TX_SUBMITTED);
+        assert_eq!(events_seen[1].0, EVENT_TX_CONFIRMED);
+        assert_eq!(events_seen[1].1["tx_hash"], json!(submitted.tx_hash));
+
+        // The node has not picked up the first transaction, so the local nonce is used
+        let second = send_transaction(&node.chain_config(), &signer, tx, sink, fast_options()).await.unwrap();
+        assert_eq!(second.nonce, 4);
+    }
+
+    #[tokio::test]
+    async fn test_send_transaction_reports_revert_and_releases_nonce() {
+        let node = TestNode::spawn().await;
+        let signer = WalletKey::from_private_key_hex(&format!("0x{}", "22".repeat(32))).unwrap();
+        let (sink, events) = recorder();
+
+        let mut tx = TxRequest::from_json(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"}"#).unwrap();
+        tx.data = Bytes::from_static(REVERT_CALLDATA);
+        let error = send_transaction(&node.chain_config(), &signer, tx, sink.clone(), fast_options()).await.unwrap_err();
+        assert!(error.contains("fail"), "{}", error);
+        assert!(node.raw_transactions().is_empty());
+
+        let failed = events.lock().unwrap()[0].clone();
+        assert_eq!(failed.0, EVENT_TX_FAILED);
+        assert_eq!(failed.1["stage"], json!("estimate_gas"));
+
+        // With the gas limit given, the simulation catches the revert before signing
+        let mut tx = TxRequest::from_json(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266", "gas": 60000}"#).unwrap();
+        tx.data = Bytes::from_static(REVERT_CALLDATA);
+        let error = send_transaction(&node.chain_config(), &signer, tx, sink.clone(), fast_options()).await.unwrap_err();
+        assert_eq!(error, "Transaction would revert: fail");
+        assert_eq!(events.lock().unwrap()[1].1["stage"], json!("simulation"));
+        assert!(node.raw_transactions().is_empty());
+
+        // The nonce reserved for the failed transaction is reused
+        let tx = TxRequest::from_json(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266", "value": "1"}"#).unwrap();
+        let submitted = send_transaction(&node.chain_config(), &signer, tx, sink.clone(), fast_options()).await.unwrap();
+        assert_eq!(submitted.nonce, 0);
+
+        // A gas estimate that overflows the buffer is refused
+        node.on("eth_estimateGas", |_| Ok(json!("0xffffffffffffffff")));
+        let mut tx = TxRequest::from_json(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"}"#).unwrap();
+        tx.data = Bytes::from_static(&[1, 2, 3, 4]);
+        let error = send_transaction(&node.chain_config(), &signer, tx, sink, fast_options()).await.unwrap_err();
+        assert!(error.contains("too large"), "{}", error);
+        assert_eq!(node.raw_transactions().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_transaction_reports_failed_receipt_and_wrong_chain() {
+        let node = TestNode::spawn().await;
+        node.on("eth_getTransactionReceipt", |params| Ok(json!({
+            "transactionHash": params[0],
+            "blockNumber": "0x2",
+            "status": "0x0",
+            "gasUsed": "0x5208",
+            "logs": [],
+        })));
+        let signer = WalletKey::from_private_key_hex(&format!("0x{}", "33".repeat(32))).unwrap();
+        let (sink, events) = recorder();
+
+        let tx = TxRequest::from_json(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266", "value": "1"}"#).unwrap();
+        send_transaction(&node.chain_config(), &signer, tx.clone(), sink.clone(), fast_options()).await.unwrap();
+        wait_for_events(&events, 2).await;
+        let failed = events.lock().unwrap()[1].clone();
+        assert_eq!(failed.0, EVENT_TX_FAILED);
+        assert_eq!(failed.1["stage"], json!("execution"));
+
+        // An endpoint serving another chain is refused before signing
+        let mut chain = node.chain_config();
+        chain.chain_id = 1;
+        let error = send_transaction(&chain, &signer, tx, sink, fast_options()).await.unwrap_err();
+        assert!(error.contains("expected 1"), "{}", error);
+        assert_eq!(node.raw_transactions().len(), 1);
+    }
+
+    #[test]
+    fn test_tee_signer_sends_after_passkey_step_up() {
+        // Passkeys live in the registry shared with the WebAuthn tests
+        let _guard = webauthn::tests::setup();
+        let _policy_guard = policy::tests::lock_policy();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            tee::configure_tee(TEEType::Teaclave, TEEConnectionType::Simulated).await.unwrap();
+            tee::perform_tee_operation(TeeOperation::CreateWallet).await.unwrap();
+            let node = TestNode::spawn().await;
+            let authenticator = Arc::new(Mutex::new(TestAuthenticator::new("tauri://localhost")));
+            webauthn::tests::register(&mut authenticator.lock().unwrap(), "alice");
+
+            // The window answers step-up-required with a passkey assertion over the transaction
+            let (recorded, events) = recorder();
+            let (passkey_events, _) = recorder();
+            let sink: EventSink = Arc::new(move |event, payload| {
+                if event == step_up::EVENT_STEP_UP_REQUIRED {
+                    let request: Value = serde_json::from_str(payload["operation"].as_str().unwrap()).unwrap();
+                    let op = TeeOperation::SignTransaction(request["txData"].as_str().unwrap().to_string());
+                    let ceremony = step_up::start(&op).unwrap();
+                    let response = authenticator.lock().unwrap().authenticate(&ceremony["challenge"]);
+                    step_up::finish(ceremony["ceremony_id"].as_str().unwrap(), &response, &passkey_events).unwrap();
+                }
+                recorded(event, payload);
+            });
+
+            let tx = TxRequest::from_json(r#"{"to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266", "value": "1000"}"#).unwrap();
+            let submitted = send_transaction(&node.chain_config(), &TeeSigner, tx, sink, fast_options()).await.unwrap();
+            let raw = node.raw_transactions();
+            assert_eq!(raw.len(), 1);
+            assert_eq!(keccak256(&raw[0]), submitted.tx_hash);
+            let events_seen = events.lock().unwrap().clone();
+            assert_eq!(events_seen[0].0, step_up::EVENT_STEP_UP_REQUIRED);
+            assert_eq!(events_seen[1].0, EVENT_TX_SUBMITTED);
+
+            // Without a step-up the TEE refuses to sign
+            let tx_data = r#"{"nonce": 0, "gas": 21000, "gasPrice": "1", "chainId": 1, "to": "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"}"#;
+            let error = tee::perform_tee_operation(TeeOperation::SignTransaction(tx_data.to_string())).await.unwrap_err();
+            assert!(matches!(error, TeeError::StepUpRequired(_)), "{}", error);
+        });
+    }
+}
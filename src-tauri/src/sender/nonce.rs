@@ -0,0 +1,59 @@
+// Pending Nonce Tracking
+// The node's pending nonce lags behind transactions that are signed but not yet
+// broadcast (or not yet seen by the node), so nonces handed out are tracked locally
+// per (chain, account)
+
+use alloy_primitives::Address;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Next nonce to hand out per (chain ID, account)
+static NEXT_NONCES: Lazy<Mutex<HashMap<(u64, Address), u64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn lock_nonces() -> std::sync::MutexGuard<'static, HashMap<(u64, Address), u64>> {
+    NEXT_NONCES.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Reserve the next nonce given the node's pending transaction count
+pub fn reserve(chain_id: u64, address: Address, remote_pending: u64) -> u64 {
+    let mut nonces = lock_nonces();
+    let next = nonces.entry((chain_id, address)).or_insert(0);
+    let nonce = remote_pending.max(*next);
+    *next = nonce + 1;
+    nonce
+}
+
+/// Return a reserved nonce that was never broadcast; only the latest reservation can be returned
+pub fn release(chain_id: u64, address: Address, nonce: u64) {
+    let mut nonces = lock_nonces();
+    if let Some(next) = nonces.get_mut(&(chain_id, address)) {
+        if *next == nonce + 1 {
+            *next = nonce;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_tracks_local_pending_nonces() {
+        let address = Address::repeat_byte(0x42);
+
+        assert_eq!(reserve(1, address, 5), 5);
+        // Node has not seen the first transaction yet
+        assert_eq!(reserve(1, address, 5), 6);
+        // Node is ahead of the local view
+        assert_eq!(reserve(1, address, 10), 10);
+        // Other chains are tracked separately
+        assert_eq!(reserve(10, address, 0), 0);
+
+        // Only the latest reservation can be released
+        release(1, address, 9);
+        assert_eq!(reserve(1, address, 0), 11);
+        release(1, address, 11);
+        assert_eq!(reserve(1, address, 0), 11);
+    }
+}
@@ -0,0 +1,246 @@
+// Transaction Replacement
+// Speed-up and cancellation of pending transactions: a new transaction with the same
+// nonce and fees bumped past the node's replacement threshold, signed through the TEE
+// and linked to the original in the history journal
+
+use alloy_primitives::{Bytes, U256};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::{fees, report, sign_and_broadcast, EventSink, SendError, SendOptions, SubmittedTransaction, TransactionSigner, PLAIN_TRANSFER_GAS};
+use crate::chain::{ChainConfig, RpcClient};
+use crate::eth::{TxFees, TxRequest};
+use crate::history::{self, EntryKind, EntryStatus, HistoryEntry};
+
+// Constants
+// Minimum fee increase nodes accept for a replacement, in percent
+const MIN_FEE_BUMP_PERCENT: u128 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplacementKind {
+    /// Same transaction with higher fees
+    SpeedUp,
+    /// 0-value transfer to self that takes the nonce
+    Cancel,
+}
+
+/// Fees for a replacement: at least the minimum bump over `original`, and no less
+/// than the current estimate so the replacement is not stuck as well
+pub fn bump_fees(original: TxFees, current: TxFees) -> TxFees {
+    let bump = |fee: u128| fee.saturating_add(fee.saturating_mul(MIN_FEE_BUMP_PERCENT).div_ceil(100));
+    match (original, current) {
+        (TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas }, current) => {
+            let (current_max_fee, current_priority_fee) = match current {
+                TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => (max_fee_per_gas, max_priority_fee_per_gas),
+                TxFees::Legacy { gas_price } => (gas_price, gas_price),
+            };
+            let max_priority_fee_per_gas = bump(max_priority_fee_per_gas).max(current_priority_fee);
+            TxFees::Eip1559 {
+                max_fee_per_gas: bump(max_fee_per_gas).max(current_max_fee).max(max_priority_fee_per_gas),
+                max_priority_fee_per_gas,
+            }
+        },
+        (TxFees::Legacy { gas_price }, TxFees::Legacy { gas_price: current }) => TxFees::Legacy { gas_price: bump(gas_price).max(current) },
+        (TxFees::Legacy { gas_price }, TxFees::Eip1559 { max_fee_per_gas, .. }) => TxFees::Legacy { gas_price: bump(gas_price).max(max_fee_per_gas) },
+    }
+}
+
+/// Transaction that replaces `original` with the given fees
+pub fn replacement_request(original: &TxRequest, kind: ReplacementKind, fees: TxFees) -> TxRequest {
+    let mut tx = match kind {
+        ReplacementKind::SpeedUp => original.clone(),
+        ReplacementKind::Cancel => TxRequest {
+            from: original.from,
+            to: original.from,
+            value: U256::ZERO,
+            data: Bytes::new(),
+            chain_id: original.chain_id,
+            nonce: original.nonce,
+            gas_limit: Some(PLAIN_TRANSFER_GAS),
+            ..Default::default()
+        },
+    };
+    match fees {
+        TxFees::Legacy { gas_price } => {
+            tx.gas_price = Some(gas_price);
+            tx.max_fee_per_gas = None;
+            tx.max_priority_fee_per_gas = None;
+        },
+        TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => {
+            tx.gas_price = None;
+            tx.max_fee_per_gas = Some(max_fee_per_gas);
+            tx.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+        },
+    }
+    tx
+}
+
+/// Speed up or cancel the pending transaction of a history entry
+///
+/// The replacement is broadcast and watched like a new transaction; the journal links
+/// both entries once it is broadcast.
+pub async fn replace_transaction(
+    chain: &ChainConfig,
+    signer: &dyn TransactionSigner,
+    entry: &HistoryEntry,
+    kind: ReplacementKind,
+    events: EventSink,
+    options: SendOptions,
+) -> Result<SubmittedTransaction, String> {
+    let client = Arc::new(RpcClient::new(chain.rpc_urls.clone()));
+    let result = submit_replacement(chain, &client, signer, entry, kind, &events).await;
+    report(chain, client, result, events, options)
+}
+
+async fn submit_replacement(
+    chain: &ChainConfig,
+    client: &RpcClient,
+    signer: &dyn TransactionSigner,
+    entry: &HistoryEntry,
+    kind: ReplacementKind,
+    events: &EventSink,
+) -> Result<SubmittedTransaction, SendError> {
+    if entry.kind != EntryKind::Transaction || entry.status != EntryStatus::Submitted {
+        return Err(SendError::new("prepare", format!("History entry {} is not a pending transaction", entry.id)));
+    }
+    let hash = entry.hash.ok_or_else(|| SendError::new("prepare", "Pending transaction has no hash"))?;
+    let original = TxRequest::from_json(&entry.request.to_string()).map_err(|e| SendError::new("prepare", e))?;
+    if original.chain_id != Some(chain.chain_id) {
+        return Err(SendError::new("prepare", format!("Transaction is for chain {:?}, not {}", original.chain_id, chain.chain_id)));
+    }
+    let (Some(from), Some(nonce)) = (original.from, original.nonce) else {
+        return Err(SendError::new("prepare", "Pending transaction has no sender or nonce"));
+    };
+    let original_fees = match (original.max_fee_per_gas, original.max_priority_fee_per_gas, original.gas_price) {
+        (Some(max_fee_per_gas), Some(max_priority_fee_per_gas), _) => TxFees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas },
+        (_, _, Some(gas_price)) => TxFees::Legacy { gas_price },
+        _ => return Err(SendError::new("prepare", "Pending transaction has no fees")),
+    };
+
+    let address = signer.address().await.map_err(|e| SendError::new("sign", e))?;
+    if address != from {
+        return Err(SendError::new("prepare", format!("Wallet address is {}, not {}", address, from)));
+    }
+
+    // Nothing to replace once the transaction or another one with its nonce is mined
+    if client.get_transaction_receipt(hash).await.map_err(|e| SendError::new("prepare", e))?.is_some() {
+        return Err(SendError::new("prepare", format!("Transaction {} is already included", hash)));
+    }
+    let mined_nonce = client.get_transaction_count(from, "latest").await.map_err(|e| SendError::new("prepare", e))?;
+    if mined_nonce > nonce {
+        return Err(SendError::new("prepare", format!("Nonce {} of {} is already used", nonce, from)));
+    }
+
+    let current_fees = fees::estimate_fees(client).await.map_err(|e| SendError::new("fees", e))?;
+    let tx = replacement_request(&original, kind, bump_fees(original_fees, current_fees));
+    let submitted = sign_and_broadcast(chain, client, signer, tx, events).await?;
+
+    println!("COS72-Tauri: Transaction {} replaced by {}", hash, submitted.tx_hash);
+    history::mark_replaced(hash, submitted.tx_hash);
+    Ok(submitted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_node::TestNode;
+    use crate::sender::EVENT_TX_SUBMITTED;
+    use crate::tee::wallet::WalletKey;
+    use crate::tee::{TeeOperation, TeeResult};
+    use alloy_primitives::{keccak256, Address, B256};
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_bump_fees_and_replacement_request() {
+        let original = TxFees::Eip1559 { max_fee_per_gas: 100, max_priority_fee_per_gas: 15 };
+        // Fees have not moved: exactly the minimum bump, rounded up
+        assert_eq!(
+            bump_fees(original, TxFees::Eip1559 { max_fee_per_gas: 50, max_priority_fee_per_gas: 1 }),
+            TxFees::Eip1559 { max_fee_per_gas: 110, max_priority_fee_per_gas: 17 }
+        );
+        // Fees have risen past the bump
+        assert_eq!(
+            bump_fees(original, TxFees::Eip1559 { max_fee_per_gas: 300, max_priority_fee_per_gas: 20 }),
+            TxFees::Eip1559 { max_fee_per_gas: 300, max_priority_fee_per_gas: 20 }
+        );
+        assert_eq!(bump_fees(TxFees::Legacy { gas_price: 10 }, TxFees::Legacy { gas_price: 5 }), TxFees::Legacy { gas_price: 11 });
+
+        let from = Address::repeat_byte(0x41);
+        let tx = TxRequest {
+            from: Some(from),
+            to: Some(Address::repeat_byte(0x42)),
+            value: U256::from(5),
+            data: Bytes::from_static(&[1, 2]),
+            chain_id: Some(1),
+            nonce: Some(9),
+            gas_limit: Some(60_000),
+            gas_price: Some(10),
+            ..Default::default()
+        };
+        let fees = TxFees::Eip1559 { max_fee_per_gas: 110, max_priority_fee_per_gas: 17 };
+        let speed_up = replacement_request(&tx, ReplacementKind::SpeedUp, fees);
+        assert_eq!(speed_up.data, tx.data);
+        assert_eq!((speed_up.gas_price, speed_up.max_fee_per_gas), (None, Some(110)));
+
+        let cancel = replacement_request(&tx, ReplacementKind::Cancel, fees);
+        assert_eq!((cancel.to, cancel.value, cancel.nonce, cancel.gas_limit), (Some(from), U256::ZERO, Some(9), Some(PLAIN_TRANSFER_GAS)));
+        assert!(cancel.data.is_empty());
+    }
+
+    // Journal a pending transaction as the TEE flow would
+    fn pending_entry(tx: &TxRequest, hash: B256) -> HistoryEntry {
+        let entry = HistoryEntry::for_operation(&TeeOperation::SignTransaction(tx.to_json().to_string()), "Test").unwrap();
+        let signed = TeeResult { success: true, message: String::new(), data: Some(json!({ "tx_hash": hash }).to_string()) };
+        history::record_signing(entry.clone(), &Ok(signed));
+        history::mark_submitted(hash);
+        history::get_entry(&entry.id).unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replace_pending_transaction() {
+        let node = TestNode::spawn().await;
+        let signer = WalletKey::from_private_key_hex(&format!("0x{}", "66".repeat(32))).unwrap();
+        node.set_nonce(signer.address(), 2);
+        let original = TxRequest {
+            from: Some(signer.address()),
+            to: Some(Address::repeat_byte(0x42)),
+            value: U256::from(1000),
+            chain_id: Some(31337),
+            nonce: Some(2),
+            gas_limit: Some(21_000),
+            max_fee_per_gas: Some(1_000),
+            max_priority_fee_per_gas: Some(100),
+            ..Default::default()
+        };
+        let original_hash = B256::repeat_byte(0x61);
+        let entry = pending_entry(&original, original_hash);
+
+        let events = Arc::new(Mutex::new(Vec::<(String, Value)>::new()));
+        let sink_events = events.clone();
+        let sink: EventSink = Arc::new(move |event, payload| sink_events.lock().unwrap().push((event.to_string(), payload)));
+
+        let submitted = replace_transaction(&node.chain_config(), &signer, &entry, ReplacementKind::Cancel, sink.clone(), SendOptions::default())
+            .await.unwrap();
+        assert_eq!(submitted.nonce, 2);
+        assert_eq!(keccak256(&node.raw_transactions()[0]), submitted.tx_hash);
+        assert_eq!(events.lock().unwrap()[0].0, EVENT_TX_SUBMITTED);
+
+        let replaced = history::get_entry(&entry.id).unwrap().unwrap();
+        assert_eq!(replaced.status, EntryStatus::Replaced);
+        assert_eq!(replaced.replaced_by, Some(submitted.tx_hash));
+
+        // The replaced entry is no longer pending
+        let error = replace_transaction(&node.chain_config(), &signer, &replaced, ReplacementKind::SpeedUp, sink.clone(), SendOptions::default())
+            .await.unwrap_err();
+        assert!(error.contains("not a pending transaction"), "{}", error);
+
+        // A nonce the chain has already used cannot be replaced
+        node.set_nonce(signer.address(), 3);
+        let stale = pending_entry(&original, B256::repeat_byte(0x62));
+        let error = replace_transaction(&node.chain_config(), &signer, &stale, ReplacementKind::SpeedUp, sink, SendOptions::default())
+            .await.unwrap_err();
+        assert!(error.contains("already used"), "{}", error);
+    }
+}
@@ -0,0 +1,287 @@
+// Transaction Simulation
+// Runs a transaction against the latest state before it is signed: success or the
+// decoded revert reason, gas used, and the native and token balance changes it would
+// cause. debug_traceCall with the call tracer gives nested value transfers and
+// Transfer logs; nodes without the debug namespace fall back to eth_call.
+
+use alloy_primitives::{Address, Bytes, B256, I256, U256, U64};
+use alloy_sol_types::{sol, SolEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::chain::rpc::{CallRequest, Log, RpcError};
+use crate::chain::RpcClient;
+use crate::eth::{self, TxRequest};
+
+sol! {
+    // ERC-20 and ERC-721 share the signature; ERC-721 indexes the token ID
+    event Transfer(address indexed from, address indexed to, uint256 value);
+}
+
+/// Outcome of a simulated transaction
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Simulation {
+    pub success: bool,
+    pub revert_reason: Option<String>,
+    pub gas_used: Option<u64>,
+    pub balance_changes: Vec<BalanceChange>,
+    /// Nested calls and logs were traced; otherwise only the top-level value is known
+    pub traced: bool,
+}
+
+/// Net change of one asset for one account; gas fees are not included
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BalanceChange {
+    pub address: Address,
+    /// Token contract, none for the native currency
+    pub token: Option<Address>,
+    /// ERC-721 token ID
+    pub token_id: Option<U256>,
+    /// Signed amount in base units
+    pub delta: String,
+}
+
+// Call frame returned by the call tracer
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallFrame {
+    #[serde(rename = "type")]
+    call_type: String,
+    from: Address,
+    #[serde(default)]
+    to: Option<Address>,
+    #[serde(default)]
+    value: Option<U256>,
+    #[serde(default)]
+    gas_used: Option<U64>,
+    #[serde(default)]
+    output: Option<Bytes>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    revert_reason: Option<String>,
+    #[serde(default)]
+    calls: Vec<CallFrame>,
+    #[serde(default)]
+    logs: Vec<Log>,
+}
+
+type Deltas = BTreeMap<(Address, Option<Address>, Option<U256>), I256>;
+
+/// Simulate `tx` on the latest block
+pub async fn simulate(client: &RpcClient, tx: &TxRequest) -> Result<Simulation, RpcError> {
+    let call = CallRequest {
+        from: tx.from,
+        to: tx.to,
+        value: Some(tx.value),
+        data: Some(tx.data.clone()),
+        gas: tx.gas_limit.map(U64::from),
+    };
+
+    let tracer = json!({ "tracer": "callTracer", "tracerConfig": { "withLog": true } });
+    match client.request::<CallFrame>("debug_traceCall", json!([call, "latest", tracer])).await {
+        Ok(frame) => Ok(from_trace(&frame)),
+        Err(e) => {
+            println!("COS72-Tauri: debug_traceCall unavailable, simulating with eth_call: {}", e);
+            simulate_with_call(client, &call).await
+        }
+    }
+}
+
+fn from_trace(frame: &CallFrame) -> Simulation {
+    let success = frame.error.is_none();
+    let mut deltas = Deltas::new();
+    if success {
+        collect_deltas(frame, &mut deltas);
+    }
+
+    Simulation {
+        success,
+        revert_reason: frame.error.as_ref().map(|error| {
+            frame.revert_reason.clone()
+                .or_else(|| frame.output.as_ref().filter(|output| !output.is_empty()).map(|output| decode_revert(output)))
+                .unwrap_or_else(|| error.clone())
+        }),
+        gas_used: frame.gas_used.map(|gas| gas.to()),
+        balance_changes: to_balance_changes(deltas),
+        traced: true,
+    }
+}
+
+// Value transfers and Transfer logs of the frame and its successful subcalls
+fn collect_deltas(frame: &CallFrame, deltas: &mut Deltas) {
+    // Delegate and static calls do not move value
+    let moves_value = matches!(frame.call_type.as_str(), "CALL" | "CREATE" | "CREATE2" | "SELFDESTRUCT");
+    if let (true, Some(to), Some(value)) = (moves_value, frame.to, frame.value.filter(|value| !value.is_zero())) {
+        add_delta(deltas, frame.from, None, None, value, false);
+        add_delta(deltas, to, None, None, value, true);
+    }
+
+    for log in &frame.logs {
+        apply_transfer_log(log, deltas);
+    }
+    for call in frame.calls.iter().filter(|call| call.error.is_none()) {
+        collect_deltas(call, deltas);
+    }
+}
+
+fn apply_transfer_log(log: &Log, deltas: &mut Deltas) {
+    if log.topics.first() != Some(&Transfer::SIGNATURE_HASH) {
+        return;
+    }
+    let topic_address = |topic: &B256| Address::from_word(*topic);
+    match log.topics.len() {
+        // ERC-20: amount in data
+        3 if log.data.len() == 32 => {
+            let amount = U256::from_be_slice(&log.data);
+            add_delta(deltas, topic_address(&log.topics[1]), Some(log.address), None, amount, false);
+            add_delta(deltas, topic_address(&log.topics[2]), Some(log.address), None, amount, true);
+        },
+        // ERC-721: token ID indexed
+        4 => {
+            let token_id = Some(U256::from_be_bytes(log.topics[3].0));
+            add_delta(deltas, topic_address(&log.topics[1]), Some(log.address), token_id, U256::from(1), false);
+            add_delta(deltas, topic_address(&log.topics[2]), Some(log.address), token_id, U256::from(1), true);
+        },
+        _ => {},
+    }
+}
+
+fn add_delta(deltas: &mut Deltas, address: Address, token: Option<Address>, token_id: Option<U256>, amount: U256, incoming: bool) {
+    let amount = I256::try_from(amount).unwrap_or(I256::MAX);
+    let delta = deltas.entry((address, token, token_id)).or_insert(I256::ZERO);
+    *delta = if incoming { delta.saturating_add(amount) } else { delta.saturating_sub(amount) };
+}
+
+fn to_balance_changes(deltas: Deltas) -> Vec<BalanceChange> {
+    deltas.into_iter()
+        .filter(|(_, delta)| !delta.is_zero())
+        .map(|((address, token, token_id), delta)| BalanceChange { address, token, token_id, delta: delta.to_string() })
+        .collect()
+}
+
+async fn simulate_with_call(client: &RpcClient, call: &CallRequest) -> Result<Simulation, RpcError> {
+    if let Err(e) = client.call(call, "latest").await {
+        return match revert_reason(&e) {
+            Some(reason) => Ok(Simulation {
+                success: false,
+                revert_reason: Some(reason),
+                gas_used: None,
+                balance_changes: Vec::new(),
+                traced: false,
+            }),
+            None => Err(e),
+        };
+    }
+
+    // The estimate is an upper bound of the gas used, good enough for a preview
+    let gas_used = client.estimate_gas(call).await.ok();
+    let mut deltas = Deltas::new();
+    if let (Some(from), Some(to), Some(value)) = (call.from, call.to, call.value.filter(|value| !value.is_zero())) {
+        add_delta(&mut deltas, from, None, None, value, false);
+        add_delta(&mut deltas, to, None, None, value, true);
+    }
+
+    Ok(Simulation {
+        success: true,
+        revert_reason: None,
+        gas_used,
+        balance_changes: to_balance_changes(deltas),
+        traced: false,
+    })
+}
+
+/// Revert reason of a failed eth_call or eth_estimateGas, None for other errors
+pub fn revert_reason(error: &RpcError) -> Option<String> {
+    match error {
+        RpcError::Rpc { data: Some(Value::String(data)), .. } => data.parse::<Bytes>().ok().map(|data| decode_revert(&data)),
+        // Some nodes report reverts without data
+        RpcError::Rpc { message, .. } if message.starts_with("execution reverted") => Some(message.clone()),
+        _ => None,
+    }
+}
+
+fn decode_revert(data: &[u8]) -> String {
+    let revert = eth::decode_revert_with(None, data);
+    match revert.get("message").and_then(Value::as_str) {
+        Some(reason) => reason.to_string(),
+        None => revert.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_node::{TestNode, REVERT_CALLDATA};
+
+    #[tokio::test]
+    async fn test_simulate_with_trace() {
+        let node = TestNode::spawn().await;
+        let sender = Address::repeat_byte(0x51);
+        let token = Address::repeat_byte(0x52);
+        let recipient = Address::repeat_byte(0x53);
+        let router = Address::repeat_byte(0x54);
+        let word = |address: Address| B256::left_padding_from(address.as_slice());
+        node.on("debug_traceCall", move |params| {
+            assert_eq!(params[2]["tracer"], json!("callTracer"));
+            Ok(json!({
+                "type": "CALL", "from": sender, "to": router, "value": "0x64", "gasUsed": "0xc350",
+                "calls": [
+                    {
+                        "type": "CALL", "from": router, "to": token, "value": "0x0", "gasUsed": "0x7530",
+                        "logs": [{ "address": token, "topics": [Transfer::SIGNATURE_HASH, word(sender), word(recipient)], "data": B256::from(U256::from(500)) }],
+                    },
+                    // Logs of reverted subcalls are discarded
+                    {
+                        "type": "CALL", "from": router, "to": token, "error": "execution reverted",
+                        "logs": [{ "address": token, "topics": [Transfer::SIGNATURE_HASH, word(sender), word(router)], "data": B256::from(U256::from(9)) }],
+                    },
+                    { "type": "DELEGATECALL", "from": router, "to": token, "value": "0x64" },
+                ],
+            }))
+        });
+
+        let client = RpcClient::new(vec![node.url()]);
+        let tx = TxRequest { from: Some(sender), to: Some(router), value: U256::from(100), ..Default::default() };
+        let simulation = simulate(&client, &tx).await.unwrap();
+        assert!(simulation.success && simulation.traced);
+        assert_eq!(simulation.gas_used, Some(50_000));
+        let change = |address, token| simulation.balance_changes.iter()
+            .find(|change| change.address == address && change.token == token)
+            .map(|change| change.delta.clone());
+        assert_eq!(change(sender, None).as_deref(), Some("-100"));
+        assert_eq!(change(router, None).as_deref(), Some("100"));
+        assert_eq!(change(sender, Some(token)).as_deref(), Some("-500"));
+        assert_eq!(change(recipient, Some(token)).as_deref(), Some("500"));
+        assert_eq!(simulation.balance_changes.len(), 4);
+
+        // Reverted top-level call
+        node.on("debug_traceCall", move |_| Ok(json!({
+            "type": "CALL", "from": sender, "to": token, "gasUsed": "0x5208",
+            "error": "execution reverted", "output": Bytes::from(REVERT_CALLDATA.to_vec()),
+        })));
+        let simulation = simulate(&client, &tx).await.unwrap();
+        assert!(!simulation.success);
+        assert!(simulation.balance_changes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_simulate_without_trace() {
+        let node = TestNode::spawn().await;
+        let client = RpcClient::new(vec![node.url()]);
+        let sender = Address::repeat_byte(0x51);
+        let recipient = Address::repeat_byte(0x53);
+
+        let tx = TxRequest { from: Some(sender), to: Some(recipient), value: U256::from(7), ..Default::default() };
+        let simulation = simulate(&client, &tx).await.unwrap();
+        assert!(simulation.success && !simulation.traced);
+        assert_eq!(simulation.gas_used, Some(21_000));
+        assert_eq!(simulation.balance_changes[0].delta, "-7");
+
+        let reverting = TxRequest { data: Bytes::from_static(REVERT_CALLDATA), ..tx };
+        let simulation = simulate(&client, &reverting).await.unwrap();
+        assert!(!simulation.success);
+        assert_eq!(simulation.revert_reason.as_deref(), Some("fail"));
+    }
+}
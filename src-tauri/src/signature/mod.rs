@@ -0,0 +1,239 @@
+// Signature Verification
+// Checks that an account signed a hash: ECDSA recovery for externally owned accounts,
+// isValidSignature (ERC-1271) for deployed smart accounts, and the ERC-6492 wrapper for
+// smart accounts that are not deployed yet, whose factory call is simulated ahead of the
+// ERC-1271 check with eth_simulateV1.
+
+use alloy_primitives::{b256, eip191_hash_message, Address, Bytes, Signature, B256, U64};
+use alloy_sol_types::{sol, SolCall, SolValue};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::chain::rpc::CallRequest;
+use crate::chain::RpcClient;
+use crate::sender::simulate::revert_reason;
+
+sol! {
+    interface IERC1271 {
+        function isValidSignature(bytes32 hash, bytes signature) external view returns (bytes4 magicValue);
+    }
+}
+
+// Constants
+/// Suffix that marks an ERC-6492 wrapped signature
+pub const ERC6492_MAGIC: B256 = b256!("6492649264926492649264926492649264926492649264926492649264926492");
+
+/// How a signature was verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureKind {
+    /// ECDSA signature of the account key
+    Eoa,
+    /// Accepted by the deployed smart account's isValidSignature
+    Erc1271,
+    /// Accepted by isValidSignature after simulating the account's deployment
+    Erc6492,
+}
+
+/// Signature of a counterfactual smart account with the call that deploys it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Erc6492Signature {
+    pub factory: Address,
+    pub factory_calldata: Bytes,
+    /// Signature checked by the account's isValidSignature once deployed
+    pub signature: Bytes,
+}
+
+impl Erc6492Signature {
+    /// Unwrap an ERC-6492 signature, None if `signature` is not wrapped
+    pub fn unwrap(signature: &[u8]) -> Option<Self> {
+        let wrapped = signature.strip_suffix(ERC6492_MAGIC.as_slice())?;
+        let (factory, factory_calldata, signature) = <(Address, Bytes, Bytes)>::abi_decode_params(wrapped).ok()?;
+        Some(Self { factory, factory_calldata, signature })
+    }
+
+    pub fn wrap(&self) -> Bytes {
+        let mut wrapped = (self.factory, self.factory_calldata.clone(), self.signature.clone()).abi_encode_params();
+        wrapped.extend_from_slice(ERC6492_MAGIC.as_slice());
+        wrapped.into()
+    }
+}
+
+/// EIP-191 hash of a personal message; 0x-prefixed hex is hashed as bytes
+pub fn personal_message_hash(message: &str) -> B256 {
+    let bytes = message.strip_prefix("0x")
+        .and_then(|data| hex::decode(data).ok())
+        .unwrap_or_else(|| message.as_bytes().to_vec());
+    eip191_hash_message(bytes)
+}
+
+/// Check that `account` signed `hash`
+///
+/// Returns None for an invalid signature. Smart account signatures need `client` on the
+/// account's chain; without it only ECDSA signatures can be accepted.
+pub async fn verify(client: Option<&RpcClient>, account: Address, hash: B256, signature: &[u8]) -> Result<Option<SignatureKind>, String> {
+    if let Some(wrapped) = Erc6492Signature::unwrap(signature) {
+        let client = client.ok_or_else(|| "Verifying an ERC-6492 signature needs an RPC connection".to_string())?;
+        let code = client.get_code(account, "latest").await.map_err(|e| format!("ERC-6492 check failed: {}", e))?;
+        // Once deployed, the wrapped signature is checked like any other ERC-1271 signature
+        if !code.is_empty() {
+            return Ok(is_valid_erc1271(client, account, hash, &wrapped.signature).await?.then_some(SignatureKind::Erc1271));
+        }
+        return Ok(is_valid_counterfactual(client, account, hash, &wrapped).await?.then_some(SignatureKind::Erc6492));
+    }
+
+    let recovered = Signature::try_from(signature).ok()
+        .and_then(|signature| signature.recover_address_from_prehash(&hash).ok());
+    if recovered == Some(account) {
+        return Ok(Some(SignatureKind::Eoa));
+    }
+
+    match client {
+        Some(client) => Ok(is_valid_erc1271(client, account, hash, signature).await?.then_some(SignatureKind::Erc1271)),
+        None => Ok(None),
+    }
+}
+
+// ERC-1271 check: the account returns the isValidSignature selector for valid signatures
+async fn is_valid_erc1271(client: &RpcClient, account: Address, hash: B256, signature: &[u8]) -> Result<bool, String> {
+    let call = IERC1271::isValidSignatureCall { hash, signature: Bytes::copy_from_slice(signature) };
+    let result = client.call(&CallRequest {
+        to: Some(account),
+        data: Some(call.abi_encode().into()),
+        ..Default::default()
+    }, "latest").await;
+
+    match result {
+        Ok(output) => Ok(is_magic_value(&output)),
+        // Accounts without code or without the function revert
+        Err(e) if revert_reason(&e).is_some() => Ok(false),
+        Err(e) => Err(format!("ERC-1271 check failed: {}", e)),
+    }
+}
+
+fn is_magic_value(output: &[u8]) -> bool {
+    IERC1271::isValidSignatureCall::abi_decode_returns(output)
+        .is_ok_and(|magic| magic == IERC1271::isValidSignatureCall::SELECTOR)
+}
+
+// Call result of eth_simulateV1
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SimulatedCall {
+    status: U64,
+    #[serde(default)]
+    return_data: Bytes,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimulatedBlock {
+    calls: Vec<SimulatedCall>,
+}
+
+// Deploy the account through its factory and call isValidSignature in the same simulated block
+async fn is_valid_counterfactual(client: &RpcClient, account: Address, hash: B256, wrapped: &Erc6492Signature) -> Result<bool, String> {
+    let validate = IERC1271::isValidSignatureCall { hash, signature: wrapped.signature.clone() };
+    let params = json!([{
+        "blockStateCalls": [{
+            "calls": [
+                { "to": wrapped.factory, "data": wrapped.factory_calldata },
+                { "to": account, "data": Bytes::from(validate.abi_encode()) },
+            ],
+        }],
+    }, "latest"]);
+    let blocks: Vec<SimulatedBlock> = client.request("eth_simulateV1", params).await
+        .map_err(|e| format!("ERC-6492 check failed: {}", e))?;
+
+    let calls = blocks.into_iter().next().map(|block| block.calls).unwrap_or_default();
+    let [deploy, check] = calls.as_slice() else {
+        return Err(format!("ERC-6492 check failed: expected 2 simulated calls, got {}", calls.len()));
+    };
+    if deploy.status.is_zero() {
+        println!("COS72-Tauri: Factory {} failed to deploy {}", wrapped.factory, account);
+        return Ok(false);
+    }
+    Ok(!check.status.is_zero() && is_magic_value(&check.return_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_node::TestNode;
+    use crate::tee::wallet::WalletKey;
+
+    fn magic_word() -> Bytes {
+        let mut magic = [0u8; 32];
+        magic[..4].copy_from_slice(&IERC1271::isValidSignatureCall::SELECTOR);
+        Bytes::from(magic.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_verify_eoa_and_erc1271() {
+        let key = WalletKey::from_private_key_hex(&format!("0x{}", "79".repeat(32))).unwrap();
+        let hash = personal_message_hash("hello");
+        let signature = key.sign_hash(&hash).unwrap().as_bytes();
+
+        assert_eq!(verify(None, key.address(), hash, &signature).await.unwrap(), Some(SignatureKind::Eoa));
+        assert_eq!(verify(None, Address::repeat_byte(0x81), hash, &signature).await.unwrap(), None);
+
+        let node = TestNode::spawn().await;
+        let client = RpcClient::new(vec![node.url()]);
+        let account = Address::repeat_byte(0x81);
+        node.on("eth_call", move |params| {
+            assert_eq!(params[0]["to"], json!(account));
+            Ok(json!(magic_word()))
+        });
+        assert_eq!(verify(Some(&client), account, hash, &signature).await.unwrap(), Some(SignatureKind::Erc1271));
+
+        // Accounts without isValidSignature return nothing
+        node.on("eth_call", |_| Ok(json!("0x")));
+        assert_eq!(verify(Some(&client), account, hash, &signature).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_verify_erc6492_counterfactual() {
+        let node = TestNode::spawn().await;
+        let client = RpcClient::new(vec![node.url()]);
+        let account = Address::repeat_byte(0x82);
+        let factory = Address::repeat_byte(0x83);
+        let hash = personal_message_hash("0x1234");
+        let wrapped = Erc6492Signature {
+            factory,
+            factory_calldata: Bytes::from_static(&[0xfa, 0xc7]),
+            signature: Bytes::from(vec![0xab; 65]),
+        };
+        let signature = wrapped.wrap();
+        assert_eq!(Erc6492Signature::unwrap(&signature), Some(wrapped.clone()));
+        assert_eq!(Erc6492Signature::unwrap(&[0xab; 65]), None);
+
+        node.on("eth_simulateV1", move |params| {
+            let calls = &params[0]["blockStateCalls"][0]["calls"];
+            assert_eq!(calls[0]["to"], json!(factory));
+            assert_eq!(calls[1]["to"], json!(account));
+            Ok(json!([{ "calls": [
+                { "status": "0x1", "returnData": "0x" },
+                { "status": "0x1", "returnData": magic_word() },
+            ] }]))
+        });
+        assert_eq!(verify(Some(&client), account, hash, &signature).await.unwrap(), Some(SignatureKind::Erc6492));
+        assert!(verify(None, account, hash, &signature).await.is_err());
+
+        // Failed deployment
+        node.on("eth_simulateV1", |_| Ok(json!([{ "calls": [
+            { "status": "0x0", "returnData": "0x" },
+            { "status": "0x0", "returnData": "0x" },
+        ] }])));
+        assert_eq!(verify(Some(&client), account, hash, &signature).await.unwrap(), None);
+
+        // Deployed since: the inner signature goes to isValidSignature directly
+        node.set_code(account, Bytes::from_static(&[0x60, 0x80]));
+        node.on("eth_call", move |params| {
+            let data: Bytes = serde_json::from_value(params[0]["data"].clone()).unwrap();
+            let call = IERC1271::isValidSignatureCall::abi_decode(&data).unwrap();
+            assert_eq!(call.signature, Bytes::from(vec![0xab; 65]));
+            Ok(json!(magic_word()))
+        });
+        node.on("eth_simulateV1", |_| Err((-32000, "deployed accounts are not simulated".to_string())));
+        assert_eq!(verify(Some(&client), account, hash, &signature).await.unwrap(), Some(SignatureKind::Erc1271));
+    }
+}
@@ -0,0 +1,325 @@
+// SIWE Message
+// EIP-4361 message format: parsing, rendering and validation of the fields a
+// relying party asks the wallet to sign
+
+use alloy_primitives::Address;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Constants
+const PREAMBLE_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+const SUPPORTED_VERSION: &str = "1";
+const MIN_NONCE_LENGTH: usize = 8;
+
+/// Sign-In With Ethereum message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SiweMessage {
+    /// Optional URI scheme in front of the domain, e.g. "https"
+    #[serde(default)]
+    pub scheme: Option<String>,
+    /// RFC 3986 authority requesting the sign-in
+    pub domain: String,
+    pub address: Address,
+    #[serde(default)]
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    /// RFC 3339 timestamps
+    pub issued_at: String,
+    #[serde(default)]
+    pub expiration_time: Option<String>,
+    #[serde(default)]
+    pub not_before: Option<String>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub resources: Vec<String>,
+}
+
+/// What the verifier expects of a message; unset fields are not checked
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SiweExpectations {
+    pub domain: Option<String>,
+    pub nonce: Option<String>,
+    pub chain_id: Option<u64>,
+}
+
+impl SiweMessage {
+    /// Message with the required fields, issued now
+    pub fn new(domain: &str, address: Address, uri: &str, chain_id: u64, nonce: &str) -> Self {
+        Self {
+            scheme: None,
+            domain: domain.to_string(),
+            address,
+            statement: None,
+            uri: uri.to_string(),
+            version: SUPPORTED_VERSION.to_string(),
+            chain_id,
+            nonce: nonce.to_string(),
+            issued_at: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            expiration_time: None,
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+    }
+
+    /// Parse the text form of a message
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.split('\n');
+        let mut next = |expected: &str| lines.next().ok_or_else(|| format!("SIWE message ends before {}", expected));
+
+        let preamble = next("the preamble")?;
+        let origin = preamble.strip_suffix(PREAMBLE_SUFFIX)
+            .ok_or_else(|| "Not a SIWE message: missing preamble".to_string())?;
+        let (scheme, domain) = match origin.split_once("://") {
+            Some((scheme, domain)) => (Some(scheme.to_string()), domain.to_string()),
+            None => (None, origin.to_string()),
+        };
+        if domain.is_empty() || domain.contains(char::is_whitespace) {
+            return Err(format!("Invalid SIWE domain: {}", origin));
+        }
+
+        let address_line = next("the address")?;
+        // EIP-4361 requires the EIP-55 checksum
+        let address = Address::parse_checksummed(address_line, None)
+            .map_err(|e| format!("Invalid SIWE address {}: {}", address_line, e))?;
+
+        if !next("the statement")?.is_empty() {
+            return Err("SIWE address must be followed by an empty line".to_string());
+        }
+        let statement = match next("the statement")? {
+            "" => None,
+            statement => {
+                if !next("the fields")?.is_empty() {
+                    return Err("SIWE statement must be a single line followed by an empty line".to_string());
+                }
+                Some(statement.to_string())
+            },
+        };
+
+        let mut fields: Vec<(&str, &str)> = Vec::new();
+        let mut resources = Vec::new();
+        let mut in_resources = false;
+        for line in lines {
+            if in_resources {
+                let resource = line.strip_prefix("- ")
+                    .ok_or_else(|| format!("Invalid SIWE resource line: {}", line))?;
+                resources.push(resource.to_string());
+            } else if line == "Resources:" {
+                in_resources = true;
+            } else {
+                let field = line.split_once(": ").ok_or_else(|| format!("Invalid SIWE field line: {}", line))?;
+                fields.push(field);
+            }
+        }
+
+        // Fields must appear in the order of the specification
+        const ORDER: [&str; 8] = ["URI", "Version", "Chain ID", "Nonce", "Issued At", "Expiration Time", "Not Before", "Request ID"];
+        let mut position = 0;
+        for (name, _) in &fields {
+            let index = ORDER.iter().position(|field| field == name)
+                .ok_or_else(|| format!("Unknown SIWE field: {}", name))?;
+            if index < position {
+                return Err(format!("SIWE field {} is out of order", name));
+            }
+            position = index + 1;
+        }
+        let field = |name: &str| fields.iter().find(|(field, _)| *field == name).map(|(_, value)| value.to_string());
+        let required = |name: &str| field(name).ok_or_else(|| format!("SIWE message has no {}", name));
+
+        let message = Self {
+            scheme,
+            domain,
+            address,
+            statement,
+            uri: required("URI")?,
+            version: required("Version")?,
+            chain_id: required("Chain ID")?.parse().map_err(|e| format!("Invalid SIWE Chain ID: {}", e))?,
+            nonce: required("Nonce")?,
+            issued_at: required("Issued At")?,
+            expiration_time: field("Expiration Time"),
+            not_before: field("Not Before"),
+            request_id: field("Request ID"),
+            resources,
+        };
+        message.check_format()?;
+        Ok(message)
+    }
+
+    // Field formats that do not depend on the verifier
+    fn check_format(&self) -> Result<(), String> {
+        if self.version != SUPPORTED_VERSION {
+            return Err(format!("Unsupported SIWE version {}", self.version));
+        }
+        if self.nonce.len() < MIN_NONCE_LENGTH || !self.nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err("SIWE nonce must be at least 8 alphanumeric characters".to_string());
+        }
+        url::Url::parse(&self.uri).map_err(|e| format!("Invalid SIWE URI {}: {}", self.uri, e))?;
+        if self.statement.as_deref().is_some_and(|statement| statement.contains('\n')) {
+            return Err("SIWE statement must be a single line".to_string());
+        }
+        parse_time("Issued At", &self.issued_at)?;
+        if let Some(expiration_time) = &self.expiration_time {
+            parse_time("Expiration Time", expiration_time)?;
+        }
+        if let Some(not_before) = &self.not_before {
+            parse_time("Not Before", not_before)?;
+        }
+        Ok(())
+    }
+
+    /// Check the message format, validity window and the verifier's expectations
+    pub fn validate(&self, expected: &SiweExpectations, now: DateTime<Utc>) -> Result<(), String> {
+        self.check_format()?;
+        if let Some(expiration_time) = &self.expiration_time {
+            if parse_time("Expiration Time", expiration_time)? <= now {
+                return Err(format!("SIWE message expired at {}", expiration_time));
+            }
+        }
+        if let Some(not_before) = &self.not_before {
+            if parse_time("Not Before", not_before)? > now {
+                return Err(format!("SIWE message is not valid before {}", not_before));
+            }
+        }
+
+        if let Some(domain) = &expected.domain {
+            if !self.domain.eq_ignore_ascii_case(domain) {
+                return Err(format!("SIWE message is for {}, expected {}", self.domain, domain));
+            }
+        }
+        if let Some(nonce) = &expected.nonce {
+            if &self.nonce != nonce {
+                return Err("SIWE nonce does not match".to_string());
+            }
+        }
+        if let Some(chain_id) = expected.chain_id {
+            if self.chain_id != chain_id {
+                return Err(format!("SIWE message is for chain {}, expected {}", self.chain_id, chain_id));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_time(name: &str, value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::<FixedOffset>::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("Invalid SIWE {} {}: {}", name, value, e))
+}
+
+impl fmt::Display for SiweMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.scheme {
+            Some(scheme) => write!(f, "{}://{}", scheme, self.domain)?,
+            None => write!(f, "{}", self.domain)?,
+        }
+        writeln!(f, "{}", PREAMBLE_SUFFIX)?;
+        writeln!(f, "{}", self.address.to_checksum(None))?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{}", statement)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "URI: {}", self.uri)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Chain ID: {}", self.chain_id)?;
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", self.issued_at)?;
+        if let Some(expiration_time) = &self.expiration_time {
+            write!(f, "\nExpiration Time: {}", expiration_time)?;
+        }
+        if let Some(not_before) = &self.not_before {
+            write!(f, "\nNot Before: {}", not_before)?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\nRequest ID: {}", request_id)?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\nResources:")?;
+            for resource in &self.resources {
+                write!(f, "\n- {}", resource)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Example message from EIP-4361
+    const EXAMPLE: &str = "service.invalid wants you to sign in with your Ethereum account:
+0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2
+
+I accept the ServiceOrg Terms of Service: https://service.invalid/tos
+
+URI: https://service.invalid/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json";
+
+    fn at(time: &str) -> DateTime<Utc> {
+        parse_time("now", time).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_render_example() {
+        let message = SiweMessage::parse(EXAMPLE).unwrap();
+        assert_eq!(message.domain, "service.invalid");
+        assert_eq!(message.statement.as_deref(), Some("I accept the ServiceOrg Terms of Service: https://service.invalid/tos"));
+        assert_eq!(message.nonce, "32891756");
+        assert_eq!(message.resources.len(), 2);
+        assert_eq!(message.to_string(), EXAMPLE);
+
+        // Without a statement, two empty lines follow the address
+        let mut bare = SiweMessage::new("example.com", message.address, "https://example.com", 8453, "abcdef123456");
+        bare.scheme = Some("https".to_string());
+        bare.expiration_time = Some("2030-01-01T00:00:00Z".to_string());
+        let text = bare.to_string();
+        assert!(text.starts_with("https://example.com wants you"));
+        assert!(text.contains("Cc2\n\n\nURI: https://example.com\n"));
+        assert_eq!(SiweMessage::parse(&text).unwrap(), bare);
+    }
+
+    #[test]
+    fn test_rejects_malformed_messages() {
+        assert!(SiweMessage::parse("hello").is_err());
+        // Address without checksum
+        assert!(SiweMessage::parse(&EXAMPLE.replace("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2")).is_err());
+        assert!(SiweMessage::parse(&EXAMPLE.replace("Nonce: 32891756", "Nonce: short")).is_err());
+        assert!(SiweMessage::parse(&EXAMPLE.replace("Version: 1", "Version: 2")).is_err());
+        // Fields out of order
+        assert!(SiweMessage::parse(&EXAMPLE.replace("Version: 1\nChain ID: 1", "Chain ID: 1\nVersion: 1")).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut message = SiweMessage::parse(EXAMPLE).unwrap();
+        message.expiration_time = Some("2021-10-01T00:00:00Z".to_string());
+        message.not_before = Some("2021-09-30T16:00:00Z".to_string());
+        let now = at("2021-09-30T17:00:00Z");
+
+        let expected = SiweExpectations { domain: Some("service.invalid".to_string()), nonce: Some("32891756".to_string()), chain_id: Some(1) };
+        assert!(message.validate(&expected, now).is_ok());
+        assert!(message.validate(&expected, at("2021-10-02T00:00:00Z")).unwrap_err().contains("expired"));
+        assert!(message.validate(&expected, at("2021-09-30T15:00:00Z")).unwrap_err().contains("not valid before"));
+
+        let phishing = SiweExpectations { domain: Some("service.example".to_string()), ..Default::default() };
+        assert!(message.validate(&phishing, now).is_err());
+        let other_nonce = SiweExpectations { nonce: Some("99999999".to_string()), ..Default::default() };
+        assert!(message.validate(&other_nonce, now).is_err());
+        let other_chain = SiweExpectations { chain_id: Some(10), ..Default::default() };
+        assert!(message.validate(&other_chain, now).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+// Sign-In With Ethereum Module
+// EIP-4361 sign-in for community web services with the TEE-held key: message
+// validation and a confirmation summary, signing through the TEE personal_sign path,
+// and signature verification for externally owned and smart accounts.
+
+pub mod message;
+
+pub use message::{SiweExpectations, SiweMessage};
+pub use crate::signature::SignatureKind;
+
+use alloy_primitives::{eip191_hash_message, Address, Bytes};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::chain::RpcClient;
+use crate::sender::{self, TeeSigner, TransactionSigner};
+use crate::signature;
+use crate::tee::{self, TeeError, TeeOperation};
+
+/// Signs EIP-191 personal messages for one account
+#[async_trait]
+pub trait MessageSigner: Send + Sync {
+    async fn address(&self) -> Result<Address, TeeError>;
+    async fn sign_message(&self, message: String) -> Result<Bytes, TeeError>;
+}
+
+#[async_trait]
+impl MessageSigner for TeeSigner {
+    async fn address(&self) -> Result<Address, TeeError> {
+        TransactionSigner::address(self).await
+    }
+
+    async fn sign_message(&self, message: String) -> Result<Bytes, TeeError> {
+        let data = sender::tee_result_data(tee::perform_tee_operation(TeeOperation::SignMessage(message)).await?)?;
+        serde_json::from_value(data["signature"].clone())
+            .map_err(|e| TeeError::OperationFailed(format!("Invalid signing result: {}", e)))
+    }
+}
+
+/// Fields of a sign-in message to create; the address defaults to the wallet and
+/// the nonce to a random one
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SiweRequest {
+    pub domain: String,
+    pub uri: String,
+    pub chain_id: u64,
+    #[serde(default)]
+    pub address: Option<Address>,
+    #[serde(default)]
+    pub statement: Option<String>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub expiration_time: Option<String>,
+    #[serde(default)]
+    pub resources: Vec<String>,
+}
+
+impl SiweRequest {
+    /// Text of the message, checked by parsing it back
+    pub fn build(self, wallet: Address) -> Result<String, String> {
+        let nonce = self.nonce.unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+        let mut message = SiweMessage::new(&self.domain, self.address.unwrap_or(wallet), &self.uri, self.chain_id, &nonce);
+        message.statement = self.statement;
+        message.expiration_time = self.expiration_time;
+        message.resources = self.resources;
+
+        let text = message.to_string();
+        SiweMessage::parse(&text)?;
+        Ok(text)
+    }
+}
+
+/// What the user is asked to confirm before signing in
+#[derive(Debug, Clone, Serialize)]
+pub struct SiweSummary {
+    pub message: SiweMessage,
+    pub description: String,
+    pub warnings: Vec<String>,
+}
+
+/// Parse and validate a sign-in request for confirmation
+///
+/// `origin` is the domain the request came from; a message for another domain is a
+/// phishing attempt and is refused.
+pub fn prepare(text: &str, origin: Option<&str>, wallet: Address) -> Result<SiweSummary, String> {
+    let message = SiweMessage::parse(text)?;
+    let expected = SiweExpectations { domain: origin.map(str::to_string), ..Default::default() };
+    message.validate(&expected, Utc::now())?;
+    if message.address != wallet {
+        return Err(format!("SIWE message is for {}, not the wallet address {}", message.address, wallet));
+    }
+
+    let mut warnings = Vec::new();
+    if origin.is_none() {
+        warnings.push(format!("The requesting site could not be checked against {}", message.domain));
+    }
+    if message.expiration_time.is_none() {
+        warnings.push("The sign-in does not expire".to_string());
+    }
+    if !message.uri.contains(&message.domain) {
+        warnings.push(format!("The sign-in URI {} is not on {}", message.uri, message.domain));
+    }
+
+    Ok(SiweSummary {
+        description: format!("Sign in to {} as {} on chain {}", message.domain, message.address, message.chain_id),
+        message,
+        warnings,
+    })
+}
+
+/// Signed sign-in message
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedSiwe {
+    pub message: String,
+    pub signature: Bytes,
+    pub address: Address,
+}
+
+/// Validate a sign-in request and sign it with the wallet
+pub async fn sign(text: &str, origin: Option<&str>, signer: &dyn MessageSigner) -> Result<SignedSiwe, String> {
+    let wallet = signer.address().await.map_err(|e| e.to_string())?;
+    let summary = prepare(text, origin, wallet)?;
+    let signature = signer.sign_message(text.to_string()).await.map_err(|e| e.to_string())?;
+    println!("COS72-Tauri: Signed in to {} as {}", summary.message.domain, wallet);
+    Ok(SignedSiwe { message: text.to_string(), signature, address: wallet })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifiedSiwe {
+    pub message: SiweMessage,
+    pub kind: SignatureKind,
+}
+
+/// Verify a signed sign-in message; smart accounts, deployed or not, are checked with
+/// `client` on the message's chain
+pub async fn verify(
+    text: &str,
+    signature: &[u8],
+    expected: &SiweExpectations,
+    client: Option<&RpcClient>,
+) -> Result<VerifiedSiwe, String> {
+    let message = SiweMessage::parse(text)?;
+    message.validate(expected, Utc::now())?;
+    let hash = eip191_hash_message(text);
+
+    match signature::verify(client, message.address, hash, signature).await? {
+        Some(kind) => Ok(VerifiedSiwe { message, kind }),
+        None => Err("Signature does not match the SIWE address".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_node::TestNode;
+    use crate::signature::IERC1271;
+    use alloy_sol_types::SolCall;
+    use crate::tee::wallet::WalletKey;
+    use serde_json::json;
+
+    #[async_trait]
+    impl MessageSigner for WalletKey {
+        async fn address(&self) -> Result<Address, TeeError> {
+            Ok(WalletKey::address(self))
+        }
+
+        async fn sign_message(&self, message: String) -> Result<Bytes, TeeError> {
+            let signed = WalletKey::sign_message(self, &message)?;
+            Ok(serde_json::from_value(signed["signature"].clone()).unwrap())
+        }
+    }
+
+    fn message_for(address: Address) -> String {
+        let mut message = SiweMessage::new("community.example", address, "https://community.example/login", 1, "a1b2c3d4e5f6");
+        message.statement = Some("Sign in to the community forum".to_string());
+        message.expiration_time = Some((Utc::now() + chrono::Duration::minutes(10)).to_rfc3339());
+        message.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_sign_and_verify_eoa() {
+        let key = WalletKey::from_private_key_hex(&format!("0x{}", "77".repeat(32))).unwrap();
+        let text = message_for(key.address());
+
+        let summary = prepare(&text, Some("community.example"), key.address()).unwrap();
+        assert!(summary.warnings.is_empty(), "{:?}", summary.warnings);
+        assert!(prepare(&text, Some("evil.example"), key.address()).is_err());
+        assert!(prepare(&text, None, Address::repeat_byte(1)).is_err());
+
+        let signed = sign(&text, Some("community.example"), &key).await.unwrap();
+        let expected = SiweExpectations { domain: Some("community.example".to_string()), nonce: Some("a1b2c3d4e5f6".to_string()), chain_id: Some(1) };
+        let verified = verify(&text, &signed.signature, &expected, None).await.unwrap();
+        assert_eq!(verified.kind, SignatureKind::Eoa);
+
+        // Signature over a different message
+        let other = WalletKey::from_private_key_hex(&format!("0x{}", "78".repeat(32))).unwrap();
+        let forged = sign(&message_for(other.address()), None, &other).await.unwrap();
+        assert!(verify(&text, &forged.signature, &expected, None).await.is_err());
+    }
+
+    #[test]
+    fn test_build_request() {
+        let wallet = Address::repeat_byte(0x72);
+        let request: SiweRequest = serde_json::from_value(json!({
+            "domain": "community.example",
+            "uri": "https://community.example",
+            "chainId": 10,
+        })).unwrap();
+        let message = SiweMessage::parse(&request.clone().build(wallet).unwrap()).unwrap();
+        assert_eq!(message.address, wallet);
+        assert_eq!(message.nonce.len(), 32);
+
+        let invalid = SiweRequest { uri: "not a uri".to_string(), ..request };
+        assert!(invalid.build(wallet).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_erc1271_smart_account() {
+        let node = TestNode::spawn().await;
+        let account = Address::repeat_byte(0x71);
+        let text = message_for(account);
+        let client = RpcClient::new(vec![node.url()]);
+
+        node.on("eth_call", move |params| {
+            assert_eq!(params[0]["to"], json!(account));
+            let mut magic = [0u8; 32];
+            magic[..4].copy_from_slice(&IERC1271::isValidSignatureCall::SELECTOR);
+            Ok(json!(Bytes::from(magic.to_vec())))
+        });
+        let verified = verify(&text, &[0xab; 70], &SiweExpectations::default(), Some(&client)).await.unwrap();
+        assert_eq!(verified.kind, SignatureKind::Erc1271);
+
+        node.on("eth_call", |_| Ok(json!(Bytes::from(vec![0u8; 32]))));
+        assert!(verify(&text, &[0xab; 70], &SiweExpectations::default(), Some(&client)).await.is_err());
+    }
+}
@@ -0,0 +1,104 @@
+// Application Data Storage
+// Locates the per-user data directory and persists small JSON state files in it
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Error as IoError, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+// Environment variable that overrides the data directory (tests, server deployments)
+const DATA_DIR_ENV: &str = "COS72_DATA_DIR";
+
+/// Get the application data directory, creating it if necessary
+pub fn data_dir() -> Result<PathBuf, IoError> {
+    let dir = match std::env::var(DATA_DIR_ENV) {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => default_data_dir()?,
+    };
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn default_data_dir() -> Result<PathBuf, IoError> {
+    #[cfg(target_os = "windows")]
+    {
+        let app_data = std::env::var("APPDATA")
+            .map_err(|_| IoError::new(ErrorKind::NotFound, "APPDATA env var not found"))?;
+        Ok(std::path::Path::new(&app_data).join("cos72-tauri").join("data"))
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let home = std::env::var("HOME")
+            .map_err(|_| IoError::new(ErrorKind::NotFound, "HOME env var not found"))?;
+        Ok(std::path::Path::new(&home).join("Library/Application Support/com.cos72.app/data"))
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let home = std::env::var("HOME")
+            .map_err(|_| IoError::new(ErrorKind::NotFound, "HOME env var not found"))?;
+        Ok(std::path::Path::new(&home).join(".local/share/cos72-tauri/data"))
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        Ok(std::env::temp_dir().join("cos72-tauri/data"))
+    }
+}
+
+/// Read a JSON file from the data directory, returning `None` if it does not exist
+pub fn load_json<T: DeserializeOwned>(file_name: &str) -> Result<Option<T>, IoError> {
+    let path = data_dir()?.join(file_name);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("Invalid JSON in {}: {}", path.display(), e)))
+}
+
+/// Write a JSON file to the data directory, replacing it atomically
+pub fn save_json<T: Serialize>(file_name: &str, value: &T) -> Result<(), IoError> {
+    save_json_at(&data_dir()?.join(file_name), value)
+}
+
+/// Write a JSON file to a temporary file and rename it into place, so a crash leaves
+/// either the old or the new content
+pub fn save_json_at<T: Serialize>(path: &Path, value: &T) -> Result<(), IoError> {
+    let tmp_path = path.with_extension("tmp");
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
+
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(content.as_bytes())?;
+    // Make sure the new file is on disk before it replaces the old one
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)
+}
+
+/// Read all lines of a log file in the data directory, empty if it does not exist
+pub fn read_lines(file_name: &str) -> Result<Vec<String>, IoError> {
+    let path = data_dir()?.join(file_name);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(std::fs::read_to_string(path)?.lines().map(str::to_string).collect())
+}
+
+/// Append one line to a log file in the data directory
+pub fn append_line(file_name: &str, line: &str) -> Result<(), IoError> {
+    let path = data_dir()?.join(file_name);
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+/// Point the data directory at a temporary directory shared by the test run
+#[cfg(test)]
+pub(crate) fn use_test_data_dir() {
+    static TEST_DATA_DIR: once_cell::sync::Lazy<tempfile::TempDir> =
+        once_cell::sync::Lazy::new(|| tempfile::tempdir().expect("create test data dir"));
+    std::env::set_var(DATA_DIR_ENV, TEST_DATA_DIR.path());
+}
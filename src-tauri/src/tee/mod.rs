@@ -8,12 +8,19 @@ use std::fmt;
 use tokio::sync::Mutex;
 use once_cell::sync::Lazy;
 use std::sync::Arc;
+use alloy_primitives::U256;
+
+use crate::eth::TxRequest;
+use crate::history;
+use crate::policy;
 
 // Submodules
 mod adapter_interface;
 mod teaclave_adapter;
 mod optee_adapter;
 mod adapter_factory;
+pub(crate) mod wallet;
+mod slip39;
 
 // Re-export key components
 pub use adapter_interface::{TEEAdapter, TEEConnectionType};
@@ -36,10 +43,14 @@ static TEE_ADAPTER: Lazy<Arc<Mutex<Box<dyn TEEAdapter>>>> = Lazy::new(|| {
 pub enum TeeOperation {
     CreateWallet,                      // Create new wallet
     SignTransaction(String),           // Sign transaction, parameter is transaction data
+    SignUserOperation(String),         // Sign ERC-4337 UserOperation, parameter is userOp, entryPoint and chainId JSON
+    SignMessage(String),               // Sign EIP-191 personal message (personal_sign), parameter is the message text
+    SignSafeTransaction(String),       // Sign Safe multisig transaction as an owner, parameter is safe, chainId and tx JSON
     VerifySignature(String, String),   // Verify signature, parameters are message and signature
     GetPublicKey,                      // Get public key
     ExportWallet(bool),                // Export wallet (boolean parameter indicates whether to export private key)
-    ImportWallet(String),              // Import wallet, parameter is wallet data
+    ImportWallet(String),              // Import wallet, parameter is wallet data (mnemonic, SLIP-39 shares, private key or v3 keystore JSON)
+    CreateBackupShares(u8, u8, String), // Split wallet seed into SLIP-39 shares, parameters are threshold, share count and passphrase
 }
 
 // TEE operation result
@@ -66,6 +77,8 @@ pub enum TeeError {
     NotSupported,                 // TEE not supported
     NotInitialized,               // TEE not initialized
     OperationFailed(String),      // Operation failed
+    PolicyDenied(String),         // Rejected by the transaction policy
+    StepUpRequired(String),       // Transaction policy requires a passkey step-up
     IoError(IoError),             // I/O error
 }
 
@@ -76,6 +89,8 @@ impl fmt::Display for TeeError {
             TeeError::NotSupported => write!(f, "TEE not supported on this device"),
             TeeError::NotInitialized => write!(f, "TEE environment not initialized"),
             TeeError::OperationFailed(msg) => write!(f, "TEE operation failed: {}", msg),
+            TeeError::PolicyDenied(reason) => write!(f, "Transaction rejected by policy: {}", reason),
+            TeeError::StepUpRequired(reason) => write!(f, "Transaction requires passkey step-up: {}", reason),
             TeeError::IoError(e) => write!(f, "I/O error: {}", e),
         }
     }
@@ -123,7 +138,34 @@ pub async fn configure_tee(tee_type: TEEType, connection_type: TEEConnectionType
 
 // Perform TEE operation
 pub async fn perform_tee_operation(op: TeeOperation) -> Result<TeeResult, TeeError> {
-    // Get adapter and perform operation
+    // Get adapter; holding the lock serializes policy checks with signing
     let mut adapter = TEE_ADAPTER.lock().await;
-    adapter.perform_operation(op).await
+    
+    // Signing requests are journaled with their outcome, including policy rejections
+    let history_entry = adapter.get_status().ok()
+        .and_then(|status| history::HistoryEntry::for_operation(&op, &status.type_name));
+    // A speed-up reuses the nonce of a pending transaction whose value was already counted
+    let replaced_value = match &op {
+        TeeOperation::SignTransaction(tx_data) => TxRequest::from_json(tx_data)
+            .map(|tx| history::replaced_value(&tx))
+            .unwrap_or_default(),
+        _ => U256::ZERO,
+    };
+
+    // Transactions must pass the signing policy before reaching the adapter
+    let result = match policy::authorize(&op) {
+        Ok(approved_value) => adapter.perform_operation(op).await.map(|result| (approved_value, result)),
+        Err(e) => Err(e),
+    };
+    let result = result.map(|(approved_value, result)| {
+        if let Some(value) = approved_value {
+            policy::record_spend(value.saturating_sub(replaced_value));
+        }
+        result
+    });
+
+    if let Some(entry) = history_entry {
+        history::record_signing(entry, &result);
+    }
+    result
 } 
\ No newline at end of file
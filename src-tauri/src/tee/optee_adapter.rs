@@ -3,6 +3,7 @@
 
 use crate::tee::{TeeError, TeeResult, TeeStatus, TeeOperation};
 use crate::tee::adapter_interface::{TEEAdapter, TEEConnectionType};
+use crate::tee::wallet::WalletKey;
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 use serde_json::{json, Value};
@@ -19,6 +20,7 @@ const REQUEST_TIMEOUT: u64 = 10; // seconds
 pub struct OpTeeAdapter {
     initialized: bool,
     wallet_id: Option<String>,
+    wallet_key: Option<WalletKey>,
     connection_type: TEEConnectionType,
     client: Client,
 }
@@ -50,6 +52,7 @@ impl TEEAdapter for OpTeeAdapter {
         Self {
             initialized: false,
             wallet_id: None,
+            wallet_key: None,
             // By default, use local connection if supported, otherwise remote
             connection_type: if Self::is_supported().unwrap_or(false) {
                 TEEConnectionType::Local
@@ -192,6 +195,17 @@ impl OpTeeAdapter {
                     .map_err(|e| TeeError::OperationFailed(format!("Invalid transaction data: {}", e)))?;
                 ("sign_transaction", Some(data))
             },
+            TeeOperation::SignUserOperation(user_op_data) => {
+                let data = serde_json::from_str(user_op_data)
+                    .map_err(|e| TeeError::OperationFailed(format!("Invalid UserOperation data: {}", e)))?;
+                ("sign_user_operation", Some(data))
+            },
+            TeeOperation::SignMessage(message) => ("sign_message", Some(json!({ "message": message }))),
+            TeeOperation::SignSafeTransaction(safe_tx_data) => {
+                let data = serde_json::from_str(safe_tx_data)
+                    .map_err(|e| TeeError::OperationFailed(format!("Invalid Safe transaction data: {}", e)))?;
+                ("sign_safe_transaction", Some(data))
+            },
             TeeOperation::GetPublicKey => ("get_public_key", None),
             TeeOperation::ExportWallet(include_private) => {
                 ("export_wallet", Some(json!({ "include_private": include_private })))
@@ -204,6 +218,13 @@ impl OpTeeAdapter {
             TeeOperation::VerifySignature(message, signature) => {
                 ("verify_signature", Some(json!({ "message": message, "signature": signature })))
             },
+            TeeOperation::CreateBackupShares(threshold, share_count, passphrase) => {
+                ("create_backup_shares", Some(json!({
+                    "threshold": threshold,
+                    "share_count": share_count,
+                    "passphrase": passphrase
+                })))
+            },
         };
         
         // Create API request
@@ -256,31 +277,47 @@ impl OpTeeAdapter {
         match op {
             TeeOperation::CreateWallet => self.simulated_create_wallet().await,
             TeeOperation::SignTransaction(tx_data) => self.simulated_sign_transaction(tx_data).await,
+            TeeOperation::SignUserOperation(user_op_data) => self.simulated_sign_user_operation(user_op_data).await,
+            TeeOperation::SignMessage(message) => self.simulated_sign_message(message).await,
+            TeeOperation::SignSafeTransaction(safe_tx_data) => self.simulated_sign_safe_transaction(safe_tx_data).await,
             TeeOperation::GetPublicKey => self.simulated_get_public_key().await,
             TeeOperation::ExportWallet(include_private) => self.simulated_export_wallet(include_private).await,
             TeeOperation::ImportWallet(wallet_data) => self.simulated_import_wallet(wallet_data).await,
             TeeOperation::VerifySignature(message, signature) => self.simulated_verify_signature(message, signature).await,
+            TeeOperation::CreateBackupShares(threshold, share_count, passphrase) => {
+                self.simulated_create_backup_shares(threshold, share_count, passphrase).await
+            },
         }
     }
     
     // Simulated implementations of TEE operations
     
+    // Get the simulated wallet ID and key
+    fn simulated_wallet(&self) -> Result<(&String, &WalletKey), TeeError> {
+        match (&self.wallet_id, &self.wallet_key) {
+            (Some(wallet_id), Some(key)) => Ok((wallet_id, key)),
+            _ => Err(TeeError::OperationFailed("Wallet not created".to_string())),
+        }
+    }
+    
     async fn simulated_create_wallet(&mut self) -> Result<TeeResult, TeeError> {
         println!("Simulating OP-TEE wallet creation");
         
-        // Generate a wallet ID
+        // Generate a wallet ID and a fresh mnemonic
         let wallet_id = format!("optee-sim-{}", uuid::Uuid::new_v4());
-        self.wallet_id = Some(wallet_id.clone());
+        let (key, mnemonic) = WalletKey::generate()?;
+        let address = key.address_string();
         
-        // Simulate mnemonic generation
-        let mnemonic = "army van defense carry jealous true garbage claim echo media make crunch";
+        self.wallet_id = Some(wallet_id.clone());
+        self.wallet_key = Some(key);
         
         Ok(TeeResult {
             success: true,
             message: "Wallet created successfully (simulation)".to_string(),
             data: Some(json!({
                 "wallet_id": wallet_id,
-                "mnemonic": mnemonic
+                "mnemonic": mnemonic,
+                "address": address
             }).to_string()),
         })
     }
@@ -289,35 +326,64 @@ impl OpTeeAdapter {
         println!("Simulating OP-TEE transaction signing");
         
         // Check for wallet
-        let wallet_id = self.wallet_id.as_ref()
-            .ok_or_else(|| TeeError::OperationFailed("Wallet not created".to_string()))?;
+        let (wallet_id, key) = self.simulated_wallet()?;
         
-        // Parse transaction data
-        let _tx_value: Value = serde_json::from_str(&tx_data)
-            .map_err(|e| TeeError::OperationFailed(format!("Invalid transaction data: {}", e)))?;
+        // Sign the complete transaction with the simulated key
+        let mut signed = key.sign_transaction(&tx_data)?;
+        signed["wallet_id"] = json!(wallet_id);
         
-        // Generate a deterministic signature based on transaction data
-        let mut signature = [0u8; 65];
+        Ok(TeeResult {
+            success: true,
+            message: "Transaction signed successfully (simulation)".to_string(),
+            data: Some(signed.to_string()),
+        })
+    }
+    
+    async fn simulated_sign_user_operation(&self, user_op_data: String) -> Result<TeeResult, TeeError> {
+        println!("Simulating OP-TEE UserOperation signing");
         
-        // Fill most of the signature with deterministic but pseudo-random data
-        for (i, byte) in tx_data.as_bytes().iter().enumerate().take(32) {
-            signature[i % 65] = *byte;
-        }
+        // Check for wallet
+        let (wallet_id, key) = self.simulated_wallet()?;
+        
+        let mut signed = key.sign_user_operation(&user_op_data)?;
+        signed["wallet_id"] = json!(wallet_id);
+        
+        Ok(TeeResult {
+            success: true,
+            message: "UserOperation signed successfully (simulation)".to_string(),
+            data: Some(signed.to_string()),
+        })
+    }
+    
+    async fn simulated_sign_safe_transaction(&self, safe_tx_data: String) -> Result<TeeResult, TeeError> {
+        println!("Simulating OP-TEE Safe transaction signing");
         
-        // Ensure the signature follows Ethereum format (r,s,v)
-        signature[64] = 27; // v value
+        // Check for wallet
+        let (wallet_id, key) = self.simulated_wallet()?;
         
-        let signature_hex = format!("0x{}", hex::encode(signature));
-        let tx_hash = format!("0x{}", hex::encode(&signature[0..32]));
+        let mut signed = key.sign_safe_transaction(&safe_tx_data)?;
+        signed["wallet_id"] = json!(wallet_id);
         
         Ok(TeeResult {
             success: true,
-            message: "Transaction signed successfully (simulation)".to_string(),
-            data: Some(json!({
-                "wallet_id": wallet_id,
-                "signature": signature_hex,
-                "tx_hash": tx_hash
-            }).to_string()),
+            message: "Safe transaction signed successfully (simulation)".to_string(),
+            data: Some(signed.to_string()),
+        })
+    }
+    
+    async fn simulated_sign_message(&self, message: String) -> Result<TeeResult, TeeError> {
+        println!("Simulating OP-TEE message signing");
+        
+        // Check for wallet
+        let (wallet_id, key) = self.simulated_wallet()?;
+        
+        let mut signed = key.sign_message(&message)?;
+        signed["wallet_id"] = json!(wallet_id);
+        
+        Ok(TeeResult {
+            success: true,
+            message: "Message signed successfully (simulation)".to_string(),
+            data: Some(signed.to_string()),
         })
     }
     
@@ -325,21 +391,15 @@ impl OpTeeAdapter {
         println!("Simulating OP-TEE public key retrieval");
         
         // Check for wallet
-        let wallet_id = self.wallet_id.as_ref()
-            .ok_or_else(|| TeeError::OperationFailed("Wallet not created".to_string()))?;
-        
-        // Deterministic key generation based on wallet ID
-        // In a real implementation, these would be derived cryptographically
-        let public_key = "0x04a88b3c5c4bf4ba8c18825611c1f0604bd3fedb82a8bdfefd1b9fc3b04a2bdf8f46a35c42c76fed6e910b0db5f4e71ac1e4cd4ee9fafaef5c3d201e1f34e9d0e1";
-        let address = "0x8e113078adf6888b7ba84967f299f29aece24c55";
+        let (wallet_id, key) = self.simulated_wallet()?;
         
         Ok(TeeResult {
             success: true,
             message: "Public key retrieved successfully (simulation)".to_string(),
             data: Some(json!({
                 "wallet_id": wallet_id,
-                "public_key": public_key,
-                "address": address
+                "public_key": key.public_key_hex(),
+                "address": key.address_string()
             }).to_string()),
         })
     }
@@ -348,19 +408,20 @@ impl OpTeeAdapter {
         println!("Simulating OP-TEE wallet export");
         
         // Check for wallet
-        let wallet_id = self.wallet_id.as_ref()
-            .ok_or_else(|| TeeError::OperationFailed("Wallet not created".to_string()))?;
+        let (wallet_id, key) = self.simulated_wallet()?;
         
         // Create export data
         let mut export_data = json!({
             "wallet_id": wallet_id,
-            "public_key": "0x04a88b3c5c4bf4ba8c18825611c1f0604bd3fedb82a8bdfefd1b9fc3b04a2bdf8f46a35c42c76fed6e910b0db5f4e71ac1e4cd4ee9fafaef5c3d201e1f34e9d0e1",
-            "address": "0x8e113078adf6888b7ba84967f299f29aece24c55",
+            "public_key": key.public_key_hex(),
+            "address": key.address_string(),
+            "source": key.source(),
+            "derivation_path": key.derivation_path(),
         });
         
         // Add private key if requested
         if include_private {
-            export_data["private_key"] = json!("0xf2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2f2");
+            export_data["private_key"] = json!(key.private_key_hex());
         }
         
         Ok(TeeResult {
@@ -373,51 +434,64 @@ impl OpTeeAdapter {
     async fn simulated_import_wallet(&mut self, wallet_data: String) -> Result<TeeResult, TeeError> {
         println!("Simulating OP-TEE wallet import");
         
-        // Parse wallet data
-        let wallet_value: Value = serde_json::from_str(&wallet_data)
-            .map_err(|e| TeeError::OperationFailed(format!("Invalid wallet data: {}", e)))?;
-        
-        // Verify required fields
-        if !wallet_value.is_object() || !wallet_value.as_object().unwrap().contains_key("private_key") {
-            return Err(TeeError::OperationFailed("Invalid wallet data format. Expected object with private_key".to_string()));
-        }
+        // Parse wallet data (mnemonic, private key or keystore) and derive the key
+        let key = WalletKey::from_import_data(&wallet_data)?;
         
         // Generate a wallet ID
         let wallet_id = format!("optee-imported-{}", uuid::Uuid::new_v4());
-        self.wallet_id = Some(wallet_id.clone());
+        let result = json!({
+            "wallet_id": wallet_id,
+            "address": key.address_string(),
+            "source": key.source(),
+            "derivation_path": key.derivation_path()
+        });
+        
+        self.wallet_id = Some(wallet_id);
+        self.wallet_key = Some(key);
         
         Ok(TeeResult {
             success: true,
             message: "Wallet imported successfully (simulation)".to_string(),
+            data: Some(result.to_string()),
+        })
+    }
+    
+    async fn simulated_create_backup_shares(&self, threshold: u8, share_count: u8, passphrase: String) -> Result<TeeResult, TeeError> {
+        println!("Simulating OP-TEE SLIP-39 backup ({}-of-{})", threshold, share_count);
+        
+        // Check for wallet
+        let (wallet_id, key) = self.simulated_wallet()?;
+        let shares = key.backup_shares(threshold, share_count, &passphrase)?;
+        
+        Ok(TeeResult {
+            success: true,
+            message: format!("Created {}-of-{} backup shares (simulation)", threshold, share_count),
             data: Some(json!({
                 "wallet_id": wallet_id,
-                "address": "0x8e113078adf6888b7ba84967f299f29aece24c55"
+                "address": key.address_string(),
+                "threshold": threshold,
+                "share_count": share_count,
+                "shares": shares
             }).to_string()),
         })
     }
     
-    async fn simulated_verify_signature(&self, _message: String, signature: String) -> Result<TeeResult, TeeError> {
+    async fn simulated_verify_signature(&self, message: String, signature: String) -> Result<TeeResult, TeeError> {
         println!("Simulating OP-TEE signature verification");
         
         // Check for wallet
-        let wallet_id = self.wallet_id.as_ref()
-            .ok_or_else(|| TeeError::OperationFailed("Wallet not created".to_string()))?;
-        
-        // Verify signature format
-        if !signature.starts_with("0x") || signature.len() != 132 {
-            return Err(TeeError::OperationFailed("Invalid signature format".to_string()));
-        }
+        let (wallet_id, key) = self.simulated_wallet()?;
         
-        // In a real implementation, we would cryptographically verify the signature
-        // For simulation, we always return true
+        // Recover the signer of the personal message and compare with the wallet address
+        let is_valid = key.verify_message(&message, &signature).await?;
         
         Ok(TeeResult {
             success: true,
-            message: "Signature verified successfully (simulation)".to_string(),
+            message: if is_valid { "Signature verified successfully (simulation)" } else { "Signature does not match the wallet (simulation)" }.to_string(),
             data: Some(json!({
                 "wallet_id": wallet_id,
-                "is_valid": true,
-                "address": "0x8e113078adf6888b7ba84967f299f29aece24c55"
+                "is_valid": is_valid,
+                "address": key.address_string()
             }).to_string()),
         })
     }
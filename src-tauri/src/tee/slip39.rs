@@ -0,0 +1,610 @@
+// SLIP-39 Shamir Backup
+// Splits the wallet seed into M-of-N mnemonic shares and recombines them
+// Follows https://github.com/satoshilabs/slips/blob/master/slip-0039.md using a single group
+
+use std::collections::BTreeMap;
+
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
+
+use crate::tee::TeeError;
+
+// Constants
+const RADIX_BITS: usize = 10;
+const WORD_MASK: u32 = (1 << RADIX_BITS) - 1;
+const CHECKSUM_LENGTH_WORDS: usize = 3;
+const METADATA_LENGTH_WORDS: usize = 4 + CHECKSUM_LENGTH_WORDS;
+const MIN_STRENGTH_BYTES: usize = 16;
+const MIN_MNEMONIC_LENGTH_WORDS: usize = METADATA_LENGTH_WORDS + (MIN_STRENGTH_BYTES * 8).div_ceil(RADIX_BITS);
+const DIGEST_LENGTH_BYTES: usize = 4;
+const CUSTOMIZATION_STRING: &[u8] = b"shamir";
+const CUSTOMIZATION_STRING_EXTENDABLE: &[u8] = b"shamir_extendable";
+const BASE_ITERATION_COUNT: u32 = 10000;
+const ROUND_COUNT: u8 = 4;
+const DEFAULT_ITERATION_EXPONENT: u8 = 1;
+const SECRET_INDEX: u8 = 255;
+const DIGEST_INDEX: u8 = 254;
+const MAX_SHARE_COUNT: u8 = 16;
+
+// GF(256) exp/log tables for the Rijndael polynomial x^8 + x^4 + x^3 + x + 1
+const GF_TABLES: ([u8; 255], [u8; 256]) = {
+    let mut exp = [0u8; 255];
+    let mut log = [0u8; 256];
+    let mut poly: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = poly as u8;
+        log[poly as usize] = i as u8;
+        // Multiply by the generator x + 1
+        poly = (poly << 1) ^ poly;
+        if poly & 0x100 != 0 {
+            poly ^= 0x11b;
+        }
+        i += 1;
+    }
+    (exp, log)
+};
+
+/// A single SLIP-39 share as encoded in one mnemonic
+#[derive(Debug, Clone, PartialEq)]
+struct Share {
+    identifier: u16,
+    extendable: bool,
+    iteration_exponent: u8,
+    group_index: u8,
+    group_threshold: u8,
+    group_count: u8,
+    member_index: u8,
+    member_threshold: u8,
+    value: Vec<u8>,
+}
+
+impl Share {
+    fn common_parameters(&self) -> (u16, bool, u8, u8, u8) {
+        (self.identifier, self.extendable, self.iteration_exponent, self.group_threshold, self.group_count)
+    }
+
+    fn to_mnemonic(&self) -> String {
+        let id_exp = (u32::from(self.identifier) << 5)
+            | (u32::from(self.extendable) << 4)
+            | u32::from(self.iteration_exponent);
+        let group_params = (u32::from(self.group_index) << 16)
+            | (u32::from(self.group_threshold - 1) << 12)
+            | (u32::from(self.group_count - 1) << 8)
+            | (u32::from(self.member_index) << 4)
+            | u32::from(self.member_threshold - 1);
+
+        let mut words = vec![
+            id_exp >> RADIX_BITS, id_exp & WORD_MASK,
+            group_params >> RADIX_BITS, group_params & WORD_MASK,
+        ];
+
+        // The share value is left-padded with zero bits to a multiple of the word size
+        let value_bits = self.value.len() * 8;
+        let padding_bits = (RADIX_BITS - value_bits % RADIX_BITS) % RADIX_BITS;
+        let mut accumulator: u32 = 0;
+        let mut pending_bits = padding_bits;
+        for byte in &self.value {
+            accumulator = (accumulator << 8) | u32::from(*byte);
+            pending_bits += 8;
+            if pending_bits >= RADIX_BITS {
+                pending_bits -= RADIX_BITS;
+                words.push((accumulator >> pending_bits) & WORD_MASK);
+                accumulator &= (1 << pending_bits) - 1;
+            }
+        }
+
+        let checksum = create_checksum(&words, self.extendable);
+        words.extend_from_slice(&checksum);
+
+        words.iter()
+            .map(|index| WORDLIST[*index as usize])
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn from_mnemonic(mnemonic: &str) -> Result<Self, TeeError> {
+        let words = mnemonic.split_whitespace()
+            .map(|word| {
+                let word = word.to_lowercase();
+                WORDLIST.binary_search(&word.as_str())
+                    .map(|index| index as u32)
+                    .map_err(|_| TeeError::OperationFailed(format!("Invalid SLIP-39 share: unknown word {}", word)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if words.len() < MIN_MNEMONIC_LENGTH_WORDS {
+            return Err(TeeError::OperationFailed(format!(
+                "Invalid SLIP-39 share: must be at least {} words, got {}", MIN_MNEMONIC_LENGTH_WORDS, words.len()
+            )));
+        }
+
+        let id_exp = (words[0] << RADIX_BITS) | words[1];
+        let extendable = (id_exp >> 4) & 1 == 1;
+        if polymod(customization_string(extendable), &words) != 1 {
+            return Err(TeeError::OperationFailed("Invalid SLIP-39 share: checksum mismatch".to_string()));
+        }
+
+        let group_params = (words[2] << RADIX_BITS) | words[3];
+        let group_threshold = ((group_params >> 12) & 0xf) as u8 + 1;
+        let group_count = ((group_params >> 8) & 0xf) as u8 + 1;
+        if group_threshold > group_count {
+            return Err(TeeError::OperationFailed("Invalid SLIP-39 share: group threshold exceeds group count".to_string()));
+        }
+
+        // Strip the left padding and decode the share value
+        let value_words = &words[4..words.len() - CHECKSUM_LENGTH_WORDS];
+        let padding_bits = (value_words.len() * RADIX_BITS) % 16;
+        if padding_bits > 8 {
+            return Err(TeeError::OperationFailed("Invalid SLIP-39 share: bad padding length".to_string()));
+        }
+
+        let mut value = Vec::with_capacity(value_words.len() * RADIX_BITS / 8);
+        let mut accumulator: u32 = 0;
+        let mut pending_bits: usize = 0;
+        let mut padding_left = padding_bits;
+        for word in value_words {
+            accumulator = (accumulator << RADIX_BITS) | word;
+            pending_bits += RADIX_BITS;
+            if padding_left > 0 {
+                pending_bits -= padding_left;
+                if accumulator >> pending_bits != 0 {
+                    return Err(TeeError::OperationFailed("Invalid SLIP-39 share: padding bits must be zero".to_string()));
+                }
+                accumulator &= (1 << pending_bits) - 1;
+                padding_left = 0;
+            }
+            while pending_bits >= 8 {
+                pending_bits -= 8;
+                value.push((accumulator >> pending_bits) as u8);
+                accumulator &= (1 << pending_bits) - 1;
+            }
+        }
+
+        Ok(Share {
+            identifier: (id_exp >> 5) as u16,
+            extendable,
+            iteration_exponent: (id_exp & 0xf) as u8,
+            group_index: (group_params >> 16) as u8,
+            group_threshold,
+            group_count,
+            member_index: ((group_params >> 4) & 0xf) as u8,
+            member_threshold: (group_params & 0xf) as u8 + 1,
+            value,
+        })
+    }
+}
+
+/// Split a master secret into `share_count` mnemonics, any `threshold` of which recover it
+pub fn split_master_secret(master_secret: &[u8], passphrase: &str, threshold: u8, share_count: u8) -> Result<Vec<String>, TeeError> {
+    validate_master_secret(master_secret)?;
+    validate_passphrase(passphrase)?;
+
+    if threshold == 0 || threshold > share_count {
+        return Err(TeeError::OperationFailed(format!(
+            "Invalid backup threshold {} for {} shares", threshold, share_count
+        )));
+    }
+    if share_count > MAX_SHARE_COUNT {
+        return Err(TeeError::OperationFailed(format!("At most {} shares are supported", MAX_SHARE_COUNT)));
+    }
+    if threshold == 1 && share_count > 1 {
+        return Err(TeeError::OperationFailed(
+            "A 1-of-N backup would make every share a full copy of the seed; use a threshold of at least 2".to_string()
+        ));
+    }
+
+    let mut id_bytes = [0u8; 2];
+    random_bytes(&mut id_bytes)?;
+    let identifier = u16::from_be_bytes(id_bytes) & 0x7fff;
+
+    let encrypted_secret = feistel(master_secret, passphrase, DEFAULT_ITERATION_EXPONENT, identifier, false, true);
+    let member_shares = split_secret(threshold, share_count, &encrypted_secret)?;
+
+    Ok(member_shares.into_iter()
+        .map(|(member_index, value)| Share {
+            identifier,
+            extendable: false,
+            iteration_exponent: DEFAULT_ITERATION_EXPONENT,
+            group_index: 0,
+            group_threshold: 1,
+            group_count: 1,
+            member_index,
+            member_threshold: threshold,
+            value,
+        }.to_mnemonic())
+        .collect())
+}
+
+/// Combine SLIP-39 mnemonics back into the master secret
+pub fn combine_mnemonics(mnemonics: &[String], passphrase: &str) -> Result<Vec<u8>, TeeError> {
+    validate_passphrase(passphrase)?;
+
+    let shares = mnemonics.iter()
+        .map(|mnemonic| Share::from_mnemonic(mnemonic))
+        .collect::<Result<Vec<_>, _>>()?;
+    let first = shares.first()
+        .ok_or_else(|| TeeError::OperationFailed("No SLIP-39 shares provided".to_string()))?;
+
+    if shares.iter().any(|share| share.common_parameters() != first.common_parameters()) {
+        return Err(TeeError::OperationFailed("SLIP-39 shares do not belong to the same backup".to_string()));
+    }
+
+    // Collect member shares per group
+    let mut groups: BTreeMap<u8, Vec<&Share>> = BTreeMap::new();
+    for share in &shares {
+        groups.entry(share.group_index).or_default().push(share);
+    }
+
+    let mut group_secrets = Vec::new();
+    for (group_index, members) in &groups {
+        let member_threshold = members[0].member_threshold;
+        if members.iter().any(|share| share.member_threshold != member_threshold) {
+            return Err(TeeError::OperationFailed(format!("Inconsistent member threshold in group {}", group_index)));
+        }
+        if members.len() < usize::from(member_threshold) {
+            continue;
+        }
+
+        let member_values = members.iter()
+            .take(usize::from(member_threshold))
+            .map(|share| (share.member_index, share.value.clone()))
+            .collect::<Vec<_>>();
+        group_secrets.push((*group_index, recover_secret(member_threshold, &member_values)?));
+    }
+
+    if group_secrets.len() < usize::from(first.group_threshold) {
+        let provided = groups.values().map(|members| members.len()).sum::<usize>();
+        return Err(TeeError::OperationFailed(format!(
+            "Not enough SLIP-39 shares: {} provided, {} required", provided, first.member_threshold
+        )));
+    }
+    group_secrets.truncate(usize::from(first.group_threshold));
+
+    let encrypted_secret = recover_secret(first.group_threshold, &group_secrets)?;
+    Ok(feistel(&encrypted_secret, passphrase, first.iteration_exponent, first.identifier, first.extendable, false))
+}
+
+fn validate_master_secret(master_secret: &[u8]) -> Result<(), TeeError> {
+    if master_secret.len() < MIN_STRENGTH_BYTES || !master_secret.len().is_multiple_of(2) {
+        return Err(TeeError::OperationFailed(format!(
+            "Master secret must be an even number of bytes and at least {} bytes long", MIN_STRENGTH_BYTES
+        )));
+    }
+    Ok(())
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), TeeError> {
+    if !passphrase.bytes().all(|b| (32..=126).contains(&b)) {
+        return Err(TeeError::OperationFailed("SLIP-39 passphrase must contain only printable ASCII characters".to_string()));
+    }
+    Ok(())
+}
+
+fn random_bytes(buf: &mut [u8]) -> Result<(), TeeError> {
+    getrandom::getrandom(buf)
+        .map_err(|e| TeeError::OperationFailed(format!("Failed to gather randomness: {}", e)))
+}
+
+fn customization_string(extendable: bool) -> &'static [u8] {
+    if extendable { CUSTOMIZATION_STRING_EXTENDABLE } else { CUSTOMIZATION_STRING }
+}
+
+// RS1024 checksum
+fn polymod(customization: &[u8], words: &[u32]) -> u32 {
+    const GENERATOR: [u32; 10] = [
+        0xE0E040, 0x1C1C080, 0x3838100, 0x7070200, 0xE0E0009,
+        0x1C0C2412, 0x38086C24, 0x3090FC48, 0x21B1F890, 0x3F3F120,
+    ];
+
+    let mut checksum: u32 = 1;
+    for value in customization.iter().map(|c| u32::from(*c)).chain(words.iter().copied()) {
+        let top = checksum >> 20;
+        checksum = ((checksum & 0xFFFFF) << 10) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn create_checksum(words: &[u32], extendable: bool) -> [u32; CHECKSUM_LENGTH_WORDS] {
+    let mut padded = words.to_vec();
+    padded.extend_from_slice(&[0; CHECKSUM_LENGTH_WORDS]);
+    let checksum = polymod(customization_string(extendable), &padded) ^ 1;
+    [(checksum >> 20) & WORD_MASK, (checksum >> 10) & WORD_MASK, checksum & WORD_MASK]
+}
+
+// Lagrange interpolation in GF(256) at point x
+fn interpolate(shares: &[(u8, Vec<u8>)], x: u8) -> Result<Vec<u8>, TeeError> {
+    let (exp, log) = &GF_TABLES;
+
+    if let Some((_, value)) = shares.iter().find(|(share_x, _)| *share_x == x) {
+        return Ok(value.clone());
+    }
+
+    let length = shares[0].1.len();
+    if shares.iter().any(|(_, value)| value.len() != length) {
+        return Err(TeeError::OperationFailed("SLIP-39 share values have different lengths".to_string()));
+    }
+    for (i, (xi, _)) in shares.iter().enumerate() {
+        if shares[i + 1..].iter().any(|(xj, _)| xj == xi) {
+            return Err(TeeError::OperationFailed("Duplicate SLIP-39 share index".to_string()));
+        }
+    }
+
+    let log_product: i32 = shares.iter().map(|(xi, _)| i32::from(log[(xi ^ x) as usize])).sum();
+    let mut result = vec![0u8; length];
+    for (xi, value) in shares {
+        let log_denominator: i32 = shares.iter()
+            .filter(|(xj, _)| xj != xi)
+            .map(|(xj, _)| i32::from(log[(xi ^ xj) as usize]))
+            .sum();
+        let log_basis = (log_product - i32::from(log[(xi ^ x) as usize]) - log_denominator).rem_euclid(255);
+
+        for (out, byte) in result.iter_mut().zip(value) {
+            if *byte != 0 {
+                *out ^= exp[((i32::from(log[*byte as usize]) + log_basis) % 255) as usize];
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn share_digest(random_part: &[u8], secret: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(random_part).expect("HMAC accepts any key length");
+    mac.update(secret);
+    mac.finalize().into_bytes()[..DIGEST_LENGTH_BYTES].to_vec()
+}
+
+fn split_secret(threshold: u8, share_count: u8, secret: &[u8]) -> Result<Vec<(u8, Vec<u8>)>, TeeError> {
+    if threshold == 1 {
+        return Ok((0..share_count).map(|i| (i, secret.to_vec())).collect());
+    }
+
+    let random_share_count = threshold - 2;
+    let mut shares = Vec::with_capacity(usize::from(share_count));
+    for i in 0..random_share_count {
+        let mut value = vec![0u8; secret.len()];
+        random_bytes(&mut value)?;
+        shares.push((i, value));
+    }
+
+    let mut random_part = vec![0u8; secret.len() - DIGEST_LENGTH_BYTES];
+    random_bytes(&mut random_part)?;
+    let mut digest_share = share_digest(&random_part, secret);
+    digest_share.extend_from_slice(&random_part);
+
+    let mut base_shares = shares.clone();
+    base_shares.push((DIGEST_INDEX, digest_share));
+    base_shares.push((SECRET_INDEX, secret.to_vec()));
+
+    for i in random_share_count..share_count {
+        shares.push((i, interpolate(&base_shares, i)?));
+    }
+    Ok(shares)
+}
+
+fn recover_secret(threshold: u8, shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>, TeeError> {
+    if threshold == 1 {
+        return Ok(shares[0].1.clone());
+    }
+
+    let secret = interpolate(shares, SECRET_INDEX)?;
+    let digest_share = interpolate(shares, DIGEST_INDEX)?;
+    let (digest, random_part) = digest_share.split_at(DIGEST_LENGTH_BYTES);
+    if share_digest(random_part, &secret) != digest {
+        return Err(TeeError::OperationFailed("Invalid SLIP-39 shares: digest verification failed".to_string()));
+    }
+    Ok(secret)
+}
+
+// Four round Feistel network keyed with PBKDF2 over the passphrase
+fn feistel(input: &[u8], passphrase: &str, iteration_exponent: u8, identifier: u16, extendable: bool, encrypt: bool) -> Vec<u8> {
+    let (left, right) = input.split_at(input.len() / 2);
+    let (mut left, mut right) = (left.to_vec(), right.to_vec());
+
+    let mut salt_prefix = Vec::new();
+    if !extendable {
+        salt_prefix.extend_from_slice(CUSTOMIZATION_STRING);
+        salt_prefix.extend_from_slice(&identifier.to_be_bytes());
+    }
+    let iterations = (BASE_ITERATION_COUNT << iteration_exponent) / u32::from(ROUND_COUNT);
+
+    let rounds: Vec<u8> = if encrypt { (0..ROUND_COUNT).collect() } else { (0..ROUND_COUNT).rev().collect() };
+    for round in rounds {
+        let mut password = vec![round];
+        password.extend_from_slice(passphrase.as_bytes());
+        let mut salt = salt_prefix.clone();
+        salt.extend_from_slice(&right);
+
+        let mut round_key = vec![0u8; right.len()];
+        pbkdf2_hmac::<Sha256>(&password, &salt, iterations, &mut round_key);
+
+        let next_right = left.iter().zip(&round_key).map(|(a, b)| a ^ b).collect::<Vec<_>>();
+        left = std::mem::replace(&mut right, next_right);
+    }
+
+    right.extend_from_slice(&left);
+    right
+}
+
+// SLIP-39 wordlist (1024 words, sorted)
+const WORDLIST: [&str; 1024] = [
+    "academic", "acid", "acne", "acquire", "acrobat", "activity", "actress", "adapt", "adequate",
+    "adjust", "admit", "adorn", "adult", "advance", "advocate", "afraid", "again", "agency",
+    "agree", "aide", "aircraft", "airline", "airport", "ajar", "alarm", "album", "alcohol",
+    "alien", "alive", "alpha", "already", "alto", "aluminum", "always", "amazing", "ambition",
+    "amount", "amuse", "analysis", "anatomy", "ancestor", "ancient", "angel", "angry", "animal",
+    "answer", "antenna", "anxiety", "apart", "aquatic", "arcade", "arena", "argue", "armed",
+    "artist", "artwork", "aspect", "auction", "august", "aunt", "average", "aviation", "avoid",
+    "award", "away", "axis", "axle", "beam", "beard", "beaver", "become", "bedroom", "behavior",
+    "being", "believe", "belong", "benefit", "best", "beyond", "bike", "biology", "birthday",
+    "bishop", "black", "blanket", "blessing", "blimp", "blind", "blue", "body", "bolt", "boring",
+    "born", "both", "boundary", "bracelet", "branch", "brave", "breathe", "briefing", "broken",
+    "brother", "browser", "bucket", "budget", "building", "bulb", "bulge", "bumpy", "bundle",
+    "burden", "burning", "busy", "buyer", "cage", "calcium", "camera", "campus", "canyon",
+    "capacity", "capital", "capture", "carbon", "cards", "careful", "cargo", "carpet", "carve",
+    "category", "cause", "ceiling", "center", "ceramic", "champion", "change", "charity", "check",
+    "chemical", "chest", "chew", "chubby", "cinema", "civil", "class", "clay", "cleanup", "client",
+    "climate", "clinic", "clock", "clogs", "closet", "clothes", "club", "cluster", "coal",
+    "coastal", "coding", "column", "company", "corner", "costume", "counter", "course", "cover",
+    "cowboy", "cradle", "craft", "crazy", "credit", "cricket", "criminal", "crisis", "critical",
+    "crowd", "crucial", "crunch", "crush", "crystal", "cubic", "cultural", "curious", "curly",
+    "custody", "cylinder", "daisy", "damage", "dance", "darkness", "database", "daughter",
+    "deadline", "deal", "debris", "debut", "decent", "decision", "declare", "decorate", "decrease",
+    "deliver", "demand", "density", "deny", "depart", "depend", "depict", "deploy", "describe",
+    "desert", "desire", "desktop", "destroy", "detailed", "detect", "device", "devote", "diagnose",
+    "dictate", "diet", "dilemma", "diminish", "dining", "diploma", "disaster", "discuss",
+    "disease", "dish", "dismiss", "display", "distance", "dive", "divorce", "document", "domain",
+    "domestic", "dominant", "dough", "downtown", "dragon", "dramatic", "dream", "dress", "drift",
+    "drink", "drove", "drug", "dryer", "duckling", "duke", "duration", "dwarf", "dynamic", "early",
+    "earth", "easel", "easy", "echo", "eclipse", "ecology", "edge", "editor", "educate", "either",
+    "elbow", "elder", "election", "elegant", "element", "elephant", "elevator", "elite", "else",
+    "email", "emerald", "emission", "emperor", "emphasis", "employer", "empty", "ending",
+    "endless", "endorse", "enemy", "energy", "enforce", "engage", "enjoy", "enlarge", "entrance",
+    "envelope", "envy", "epidemic", "episode", "equation", "equip", "eraser", "erode", "escape",
+    "estate", "estimate", "evaluate", "evening", "evidence", "evil", "evoke", "exact", "example",
+    "exceed", "exchange", "exclude", "excuse", "execute", "exercise", "exhaust", "exotic",
+    "expand", "expect", "explain", "express", "extend", "extra", "eyebrow", "facility", "fact",
+    "failure", "faint", "fake", "false", "family", "famous", "fancy", "fangs", "fantasy", "fatal",
+    "fatigue", "favorite", "fawn", "fiber", "fiction", "filter", "finance", "findings", "finger",
+    "firefly", "firm", "fiscal", "fishing", "fitness", "flame", "flash", "flavor", "flea",
+    "flexible", "flip", "float", "floral", "fluff", "focus", "forbid", "force", "forecast",
+    "forget", "formal", "fortune", "forward", "founder", "fraction", "fragment", "frequent",
+    "freshman", "friar", "fridge", "friendly", "frost", "froth", "frozen", "fumes", "funding",
+    "furl", "fused", "galaxy", "game", "garbage", "garden", "garlic", "gasoline", "gather",
+    "general", "genius", "genre", "genuine", "geology", "gesture", "glad", "glance", "glasses",
+    "glen", "glimpse", "goat", "golden", "graduate", "grant", "grasp", "gravity", "gray",
+    "greatest", "grief", "grill", "grin", "grocery", "gross", "group", "grownup", "grumpy",
+    "guard", "guest", "guilt", "guitar", "gums", "hairy", "hamster", "hand", "hanger", "harvest",
+    "have", "havoc", "hawk", "hazard", "headset", "health", "hearing", "heat", "helpful", "herald",
+    "herd", "hesitate", "hobo", "holiday", "holy", "home", "hormone", "hospital", "hour", "huge",
+    "human", "humidity", "hunting", "husband", "hush", "husky", "hybrid", "idea", "identify",
+    "idle", "image", "impact", "imply", "improve", "impulse", "include", "income", "increase",
+    "index", "indicate", "industry", "infant", "inform", "inherit", "injury", "inmate", "insect",
+    "inside", "install", "intend", "intimate", "invasion", "involve", "iris", "island", "isolate",
+    "item", "ivory", "jacket", "jerky", "jewelry", "join", "judicial", "juice", "jump", "junction",
+    "junior", "junk", "jury", "justice", "kernel", "keyboard", "kidney", "kind", "kitchen",
+    "knife", "knit", "laden", "ladle", "ladybug", "lair", "lamp", "language", "large", "laser",
+    "laundry", "lawsuit", "leader", "leaf", "learn", "leaves", "lecture", "legal", "legend",
+    "legs", "lend", "length", "level", "liberty", "library", "license", "lift", "likely", "lilac",
+    "lily", "lips", "liquid", "listen", "literary", "living", "lizard", "loan", "lobe", "location",
+    "losing", "loud", "loyalty", "luck", "lunar", "lunch", "lungs", "luxury", "lying", "lyrics",
+    "machine", "magazine", "maiden", "mailman", "main", "makeup", "making", "mama", "manager",
+    "mandate", "mansion", "manual", "marathon", "march", "market", "marvel", "mason", "material",
+    "math", "maximum", "mayor", "meaning", "medal", "medical", "member", "memory", "mental",
+    "merchant", "merit", "method", "metric", "midst", "mild", "military", "mineral", "minister",
+    "miracle", "mixed", "mixture", "mobile", "modern", "modify", "moisture", "moment", "morning",
+    "mortgage", "mother", "mountain", "mouse", "move", "much", "mule", "multiple", "muscle",
+    "museum", "music", "mustang", "nail", "national", "necklace", "negative", "nervous", "network",
+    "news", "nuclear", "numb", "numerous", "nylon", "oasis", "obesity", "object", "observe",
+    "obtain", "ocean", "often", "olympic", "omit", "oral", "orange", "orbit", "order", "ordinary",
+    "organize", "ounce", "oven", "overall", "owner", "paces", "pacific", "package", "paid",
+    "painting", "pajamas", "pancake", "pants", "papa", "paper", "parcel", "parking", "party",
+    "patent", "patrol", "payment", "payroll", "peaceful", "peanut", "peasant", "pecan", "penalty",
+    "pencil", "percent", "perfect", "permit", "petition", "phantom", "pharmacy", "photo", "phrase",
+    "physics", "pickup", "picture", "piece", "pile", "pink", "pipeline", "pistol", "pitch",
+    "plains", "plan", "plastic", "platform", "playoff", "pleasure", "plot", "plunge", "practice",
+    "prayer", "preach", "predator", "pregnant", "premium", "prepare", "presence", "prevent",
+    "priest", "primary", "priority", "prisoner", "privacy", "prize", "problem", "process",
+    "profile", "program", "promise", "prospect", "provide", "prune", "public", "pulse", "pumps",
+    "punish", "puny", "pupal", "purchase", "purple", "python", "quantity", "quarter", "quick",
+    "quiet", "race", "racism", "radar", "railroad", "rainbow", "raisin", "random", "ranked",
+    "rapids", "raspy", "reaction", "realize", "rebound", "rebuild", "recall", "receiver",
+    "recover", "regret", "regular", "reject", "relate", "remember", "remind", "remove", "render",
+    "repair", "repeat", "replace", "require", "rescue", "research", "resident", "response",
+    "result", "retailer", "retreat", "reunion", "revenue", "review", "reward", "rhyme", "rhythm",
+    "rich", "rival", "river", "robin", "rocky", "romantic", "romp", "roster", "round", "royal",
+    "ruin", "ruler", "rumor", "sack", "safari", "salary", "salon", "salt", "satisfy", "satoshi",
+    "saver", "says", "scandal", "scared", "scatter", "scene", "scholar", "science", "scout",
+    "scramble", "screw", "script", "scroll", "seafood", "season", "secret", "security", "segment",
+    "senior", "shadow", "shaft", "shame", "shaped", "sharp", "shelter", "sheriff", "short",
+    "should", "shrimp", "sidewalk", "silent", "silver", "similar", "simple", "single", "sister",
+    "skin", "skunk", "slap", "slavery", "sled", "slice", "slim", "slow", "slush", "smart", "smear",
+    "smell", "smirk", "smith", "smoking", "smug", "snake", "snapshot", "sniff", "society",
+    "software", "soldier", "solution", "soul", "source", "space", "spark", "speak", "species",
+    "spelling", "spend", "spew", "spider", "spill", "spine", "spirit", "spit", "spray", "sprinkle",
+    "square", "squeeze", "stadium", "staff", "standard", "starting", "station", "stay", "steady",
+    "step", "stick", "stilt", "story", "strategy", "strike", "style", "subject", "submit", "sugar",
+    "suitable", "sunlight", "superior", "surface", "surprise", "survive", "sweater", "swimming",
+    "swing", "switch", "symbolic", "sympathy", "syndrome", "system", "tackle", "tactics",
+    "tadpole", "talent", "task", "taste", "taught", "taxi", "teacher", "teammate", "teaspoon",
+    "temple", "tenant", "tendency", "tension", "terminal", "testify", "texture", "thank", "that",
+    "theater", "theory", "therapy", "thorn", "threaten", "thumb", "thunder", "ticket", "tidy",
+    "timber", "timely", "ting", "tofu", "together", "tolerate", "total", "toxic", "tracks",
+    "traffic", "training", "transfer", "trash", "traveler", "treat", "trend", "trial", "tricycle",
+    "trip", "triumph", "trouble", "true", "trust", "twice", "twin", "type", "typical", "ugly",
+    "ultimate", "umbrella", "uncover", "undergo", "unfair", "unfold", "unhappy", "union",
+    "universe", "unkind", "unknown", "unusual", "unwrap", "upgrade", "upstairs", "username",
+    "usher", "usual", "valid", "valuable", "vampire", "vanish", "various", "vegan", "velvet",
+    "venture", "verdict", "verify", "very", "veteran", "vexed", "victim", "video", "view",
+    "vintage", "violence", "viral", "visitor", "visual", "vitamins", "vocal", "voice", "volume",
+    "voter", "voting", "walnut", "warmth", "warn", "watch", "wavy", "wealthy", "weapon", "webcam",
+    "welcome", "welfare", "western", "width", "wildlife", "window", "wine", "wireless", "wisdom",
+    "withdraw", "wits", "wolf", "woman", "work", "worthy", "wrap", "wrist", "writing", "wrote",
+    "year", "yelp", "yield", "yoga", "zero",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from the SLIP-39 specification, passphrase "TREZOR"
+    #[test]
+    fn test_specification_vectors() {
+        let single = vec![
+            "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard".to_string(),
+        ];
+        assert_eq!(hex::encode(combine_mnemonics(&single, "TREZOR").unwrap()), "bb54aac4b89dc868ba37d9cc21b2cece");
+
+        let two_of_three = vec![
+            "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed".to_string(),
+            "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking".to_string(),
+        ];
+        assert_eq!(hex::encode(combine_mnemonics(&two_of_three, "TREZOR").unwrap()), "b43ceb7e57a0ea8766221624d01b0864");
+
+        let bad_checksum = vec![
+            "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision kidney".to_string(),
+        ];
+        assert!(combine_mnemonics(&bad_checksum, "TREZOR").is_err());
+    }
+
+    #[test]
+    fn test_split_and_combine_seed() {
+        let seed: Vec<u8> = (0u8..64).collect();
+        let shares = split_master_secret(&seed, "", 2, 3).unwrap();
+        assert_eq!(shares.len(), 3);
+
+        // Any two shares recover the seed
+        for (a, b) in [(0, 1), (0, 2), (2, 1)] {
+            let subset = vec![shares[a].clone(), shares[b].clone()];
+            assert_eq!(combine_mnemonics(&subset, "").unwrap(), seed);
+        }
+
+        // One share is not enough
+        assert!(combine_mnemonics(&shares[..1], "").is_err());
+    }
+
+    #[test]
+    fn test_share_encoding_round_trip() {
+        let shares = split_master_secret(&[7u8; 16], "cos72", 3, 5).unwrap();
+        for mnemonic in &shares {
+            let share = Share::from_mnemonic(mnemonic).unwrap();
+            assert_eq!(&share.to_mnemonic(), mnemonic);
+            assert_eq!(share.member_threshold, 3);
+        }
+    }
+
+    #[test]
+    fn test_split_rejects_invalid_parameters() {
+        let seed = [1u8; 32];
+        assert!(split_master_secret(&seed, "", 3, 2).is_err());
+        assert!(split_master_secret(&seed, "", 1, 3).is_err());
+        assert!(split_master_secret(&seed, "", 2, 17).is_err());
+        assert!(split_master_secret(&[1u8; 15], "", 2, 3).is_err());
+    }
+}
@@ -3,8 +3,9 @@
 
 use crate::tee::{TeeError, TeeResult, TeeStatus, TeeOperation};
 use crate::tee::adapter_interface::{TEEAdapter, TEEConnectionType};
+use crate::tee::wallet::WalletKey;
 use async_trait::async_trait;
-use serde_json::{json, Value};
+use serde_json::json;
 use uuid::Uuid;
 use std::sync::OnceLock;
 
@@ -17,6 +18,7 @@ const TEE_WALLET_PATH: &str = "wallet_data";
 pub struct TeaclaveAdapter {
     initialized: bool,
     wallet_id: Option<String>,
+    wallet_key: Option<WalletKey>,
     connection_type: TEEConnectionType,
 }
 
@@ -30,6 +32,7 @@ impl TEEAdapter for TeaclaveAdapter {
         Self {
             initialized: false,
             wallet_id: None,
+            wallet_key: None,
             connection_type: if Self::is_supported().unwrap_or(false) {
                 TEEConnectionType::Local
             } else {
@@ -100,10 +103,16 @@ impl TEEAdapter for TeaclaveAdapter {
         match op {
             TeeOperation::CreateWallet => self.create_wallet().await,
             TeeOperation::SignTransaction(tx_data) => self.sign_transaction(tx_data).await,
+            TeeOperation::SignUserOperation(user_op_data) => self.sign_user_operation(user_op_data).await,
+            TeeOperation::SignMessage(message) => self.sign_message(message).await,
+            TeeOperation::SignSafeTransaction(safe_tx_data) => self.sign_safe_transaction(safe_tx_data).await,
             TeeOperation::GetPublicKey => self.get_public_key().await,
             TeeOperation::ExportWallet(include_private) => self.export_wallet(include_private).await,
             TeeOperation::ImportWallet(wallet_data) => self.import_wallet(wallet_data).await,
             TeeOperation::VerifySignature(message, signature) => self.verify_signature(message, signature).await,
+            TeeOperation::CreateBackupShares(threshold, share_count, passphrase) => {
+                self.create_backup_shares(threshold, share_count, passphrase).await
+            },
         }
     }
 }
@@ -139,19 +148,27 @@ impl TeaclaveAdapter {
         }
     }
 
+    // Get the loaded wallet ID and key
+    fn loaded_wallet(&self) -> Result<(&String, &WalletKey), TeeError> {
+        match (&self.wallet_id, &self.wallet_key) {
+            (Some(wallet_id), Some(key)) => Ok((wallet_id, key)),
+            _ => Err(TeeError::OperationFailed("Wallet not created".to_string())),
+        }
+    }
+
     // Create new wallet
     async fn create_wallet(&mut self) -> Result<TeeResult, TeeError> {
         // In a real implementation, would call eth_wallet's create_wallet function
-        // Currently using a simulation
+        // Currently the key is generated in-process (real implementation would generate this inside TEE)
+        let (key, mnemonic) = WalletKey::generate()?;
         
         // Generate a random UUID as wallet ID
         let wallet_id = Uuid::new_v4().to_string();
+        let address = key.address_string();
         
-        // Mock mnemonic (real implementation would generate this inside TEE)
-        let mock_mnemonic = "mock test wallet phrase just for development not for production use".to_string();
-        
-        // Store wallet ID
+        // Store wallet
         self.wallet_id = Some(wallet_id.clone());
+        self.wallet_key = Some(key);
         
         // Return result
         Ok(TeeResult {
@@ -159,7 +176,8 @@ impl TeaclaveAdapter {
             message: "Wallet created successfully".to_string(),
             data: Some(json!({
                 "wallet_id": wallet_id,
-                "mnemonic": mock_mnemonic
+                "mnemonic": mnemonic,
+                "address": address
             }).to_string()),
         })
     }
@@ -167,61 +185,81 @@ impl TeaclaveAdapter {
     // Sign transaction
     async fn sign_transaction(&self, tx_data: String) -> Result<TeeResult, TeeError> {
         // Check if wallet is created
-        let wallet_id = self.wallet_id.as_ref()
-            .ok_or_else(|| TeeError::OperationFailed("Wallet not created".to_string()))?;
-            
-        // Parse transaction data
-        let _tx_value: Value = serde_json::from_str(&tx_data)
-            .map_err(|e| TeeError::OperationFailed(format!("Invalid transaction data: {}", e)))?;
-            
-        // Generate a pseudo random signature, not a full 0 signature
-        let mut mock_sig = [0u8; 65];
-        getrandom::getrandom(&mut mock_sig).unwrap_or_default();
+        let (wallet_id, key) = self.loaded_wallet()?;
         
-        // Ensure format matches Ethereum signature requirements (r, s, v)
-        mock_sig[64] = 27; // v value set to 27 (parity check bit)
+        // Sign the complete transaction (real implementation would sign inside TEE)
+        let mut signed = key.sign_transaction(&tx_data)?;
+        signed["wallet_id"] = json!(wallet_id);
         
-        // To make the signature more realistic, use the first few bytes of tx_data as part of the signature
-        if !tx_data.is_empty() {
-            let tx_bytes = tx_data.as_bytes();
-            for i in 0..std::cmp::min(32, tx_bytes.len()) {
-                mock_sig[i] = tx_bytes[i];
-            }
-        }
+        println!("Signed ETH transaction - wallet_id: {}, tx_hash: {}", wallet_id, signed["tx_hash"]);
         
-        // Ethereum signature is 65 bytes: r(32) + s(32) + v(1)
-        let mock_signature = format!("0x{}", hex::encode(mock_sig));
+        // Return result
+        Ok(TeeResult {
+            success: true,
+            message: "Transaction signed successfully".to_string(),
+            data: Some(signed.to_string()),
+        })
+    }
+
+    // Sign ERC-4337 UserOperation
+    async fn sign_user_operation(&self, user_op_data: String) -> Result<TeeResult, TeeError> {
+        // Check if wallet is created
+        let (wallet_id, key) = self.loaded_wallet()?;
         
-        // Generate mock transaction hash (first 20 bytes from signature)
-        let tx_hash = format!("0x{}", hex::encode(&mock_sig[0..32]));
+        // Sign the userOpHash (real implementation would sign inside TEE)
+        let mut signed = key.sign_user_operation(&user_op_data)?;
+        signed["wallet_id"] = json!(wallet_id);
         
-        println!("Simulating ETH transaction signing - wallet_id: {}", wallet_id);
-        println!("Generated mock signature: {}...", &mock_signature[0..34]);
-        println!("Generated mock tx_hash: {}", tx_hash);
+        println!("Signed UserOperation - wallet_id: {}, user_op_hash: {}", wallet_id, signed["user_op_hash"]);
         
         // Return result
         Ok(TeeResult {
             success: true,
-            message: "Transaction signed successfully".to_string(),
-            data: Some(json!({
-                "wallet_id": wallet_id,
-                "signature": mock_signature,
-                "tx_hash": tx_hash
-            }).to_string()),
+            message: "UserOperation signed successfully".to_string(),
+            data: Some(signed.to_string()),
+        })
+    }
+    
+    // Sign Safe multisig transaction
+    async fn sign_safe_transaction(&self, safe_tx_data: String) -> Result<TeeResult, TeeError> {
+        // Check if wallet is created
+        let (wallet_id, key) = self.loaded_wallet()?;
+        
+        // Sign the SafeTx hash (real implementation would sign inside TEE)
+        let mut signed = key.sign_safe_transaction(&safe_tx_data)?;
+        signed["wallet_id"] = json!(wallet_id);
+        
+        println!("Signed Safe transaction - wallet_id: {}, safe_tx_hash: {}", wallet_id, signed["safe_tx_hash"]);
+        
+        Ok(TeeResult {
+            success: true,
+            message: "Safe transaction signed successfully".to_string(),
+            data: Some(signed.to_string()),
+        })
+    }
+    
+    // Sign personal message
+    async fn sign_message(&self, message: String) -> Result<TeeResult, TeeError> {
+        // Check if wallet is created
+        let (wallet_id, key) = self.loaded_wallet()?;
+        
+        // Sign the EIP-191 message hash (real implementation would sign inside TEE)
+        let mut signed = key.sign_message(&message)?;
+        signed["wallet_id"] = json!(wallet_id);
+        
+        println!("Signed message - wallet_id: {}, message_hash: {}", wallet_id, signed["message_hash"]);
+        
+        Ok(TeeResult {
+            success: true,
+            message: "Message signed successfully".to_string(),
+            data: Some(signed.to_string()),
         })
     }
 
     // Get public key
     async fn get_public_key(&self) -> Result<TeeResult, TeeError> {
         // Check if wallet is created
-        let wallet_id = self.wallet_id.as_ref()
-            .ok_or_else(|| TeeError::OperationFailed("Wallet not created".to_string()))?;
-            
-        // Generate mock Ethereum public key (65 bytes)
-        let mock_pub_key = format!("0x{}", hex::encode([1u8; 65]));
-        
-        // Generate mock Ethereum address (20 bytes)
-        let mock_address = format!("0x{}", hex::encode([2u8; 20]));
+        let (wallet_id, key) = self.loaded_wallet()?;
         
         // Return result
         Ok(TeeResult {
@@ -229,8 +267,8 @@ impl TeaclaveAdapter {
             message: "Public key retrieved successfully".to_string(),
             data: Some(json!({
                 "wallet_id": wallet_id,
-                "public_key": mock_pub_key,
-                "address": mock_address
+                "public_key": key.public_key_hex(),
+                "address": key.address_string()
             }).to_string()),
         })
     }
@@ -238,24 +276,20 @@ impl TeaclaveAdapter {
     // Export wallet
     async fn export_wallet(&self, include_private: bool) -> Result<TeeResult, TeeError> {
         // Check if wallet is created
-        let wallet_id = self.wallet_id.as_ref()
-            .ok_or_else(|| TeeError::OperationFailed("Wallet not created".to_string()))?;
-            
-        // Generate mock data
-        let mock_public_key = format!("0x{}", hex::encode([1u8; 65]));
-        let mock_address = format!("0x{}", hex::encode([2u8; 20]));
+        let (wallet_id, key) = self.loaded_wallet()?;
         
         // Create export data
         let mut export_data = json!({
             "wallet_id": wallet_id,
-            "public_key": mock_public_key,
-            "address": mock_address
+            "public_key": key.public_key_hex(),
+            "address": key.address_string(),
+            "source": key.source(),
+            "derivation_path": key.derivation_path()
         });
         
         // Include private key if requested
         if include_private {
-            let mock_private_key = format!("0x{}", hex::encode([3u8; 32]));
-            export_data["private_key"] = json!(mock_private_key);
+            export_data["private_key"] = json!(key.private_key_hex());
         }
         
         // Return result
@@ -268,58 +302,69 @@ impl TeaclaveAdapter {
 
     // Import wallet
     async fn import_wallet(&mut self, wallet_data: String) -> Result<TeeResult, TeeError> {
-        // Parse wallet data
-        let wallet_value: Value = serde_json::from_str(&wallet_data)
-            .map_err(|e| TeeError::OperationFailed(format!("Invalid wallet data: {}", e)))?;
-            
-        // Check for required fields
-        if !wallet_value.is_object() || !wallet_value.as_object().unwrap().contains_key("private_key") {
-            return Err(TeeError::OperationFailed("Invalid wallet data format. Expected object with private_key".to_string()));
-        }
+        // Parse wallet data (mnemonic, private key or keystore) and derive the key
+        let key = WalletKey::from_import_data(&wallet_data)?;
         
         // Generate a random UUID as wallet ID
         let wallet_id = Uuid::new_v4().to_string();
+        let result = json!({
+            "wallet_id": wallet_id,
+            "address": key.address_string(),
+            "source": key.source(),
+            "derivation_path": key.derivation_path()
+        });
         
-        // Store wallet ID
-        self.wallet_id = Some(wallet_id.clone());
-        
+        // Store wallet
         // In a real implementation, would store the wallet data securely in the TEE
+        self.wallet_id = Some(wallet_id);
+        self.wallet_key = Some(key);
         
         // Return result
         Ok(TeeResult {
             success: true,
             message: "Wallet imported successfully".to_string(),
+            data: Some(result.to_string()),
+        })
+    }
+
+    // Create SLIP-39 backup shares
+    async fn create_backup_shares(&self, threshold: u8, share_count: u8, passphrase: String) -> Result<TeeResult, TeeError> {
+        // Check if wallet is created
+        let (wallet_id, key) = self.loaded_wallet()?;
+        
+        // In a real implementation, the seed would be split inside the TEE
+        let shares = key.backup_shares(threshold, share_count, &passphrase)?;
+        
+        // Return result
+        Ok(TeeResult {
+            success: true,
+            message: format!("Created {}-of-{} backup shares", threshold, share_count),
             data: Some(json!({
                 "wallet_id": wallet_id,
-                "address": format!("0x{}", hex::encode([2u8; 20]))
+                "address": key.address_string(),
+                "threshold": threshold,
+                "share_count": share_count,
+                "shares": shares
             }).to_string()),
         })
     }
 
     // Verify signature
-    async fn verify_signature(&self, _message: String, signature: String) -> Result<TeeResult, TeeError> {
-        // Simple mock implementation - in real version would do cryptographic verification
-        
+    async fn verify_signature(&self, message: String, signature: String) -> Result<TeeResult, TeeError> {
         // Check if wallet is created
-        let wallet_id = self.wallet_id.as_ref()
-            .ok_or_else(|| TeeError::OperationFailed("Wallet not created".to_string()))?;
+        let (wallet_id, key) = self.loaded_wallet()?;
             
-        // Check signature format
-        if !signature.starts_with("0x") || signature.len() != 132 {
-            return Err(TeeError::OperationFailed("Invalid signature format".to_string()));
-        }
-        
-        // Mock verification (always returns true for mock implementation)
-        // In real implementation, would recover address from signature and compare with wallet address
+        // Recover the signer of the personal message and compare with the wallet address
+        let is_valid = key.verify_message(&message, &signature).await?;
         
         // Return result
         Ok(TeeResult {
             success: true,
-            message: "Signature verified successfully".to_string(),
+            message: if is_valid { "Signature verified successfully" } else { "Signature does not match the wallet" }.to_string(),
             data: Some(json!({
                 "wallet_id": wallet_id,
-                "is_valid": true,
-                "address": format!("0x{}", hex::encode([2u8; 20]))
+                "is_valid": is_valid,
+                "address": key.address_string()
             }).to_string()),
         })
     }
@@ -0,0 +1,559 @@
+// Wallet Key Material
+// Shared by the TEE adapters when running in simulation mode: generates wallets,
+// restores them from a BIP39 mnemonic, SLIP-39 shares, a raw hex key or a v3 keystore, and derives
+// the Ethereum address that belongs to the key
+
+use aes::Aes128;
+use alloy_primitives::{keccak256, Address, Signature, B256};
+use bip32::{DerivationPath, XPrv};
+use bip39::{Language, Mnemonic};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use k256::ecdsa::SigningKey;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::eth::{SafeTransactionRequest, TxRequest, UserOperationRequest};
+use crate::signature;
+use crate::tee::slip39;
+use crate::tee::TeeError;
+
+// Constants
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+const SUPPORTED_MNEMONIC_WORDS: [usize; 2] = [12, 24];
+// Largest scrypt parameters accepted from a keystore (geth's "standard" is n = 2^18, r = 8,
+// p = 1); an imported file must not make the app spend gigabytes of memory
+const MAX_SCRYPT_N: u64 = 1 << 20;
+const MAX_SCRYPT_R: u64 = 8;
+const MAX_SCRYPT_P: u64 = 16;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+/// Where the key material held by an adapter came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletSource {
+    Generated,
+    Mnemonic,
+    Slip39,
+    PrivateKey,
+    Keystore,
+}
+
+/// Secp256k1 wallet key held by a (simulated) TEE adapter
+pub struct WalletKey {
+    signing_key: SigningKey,
+    // BIP32 seed, kept so the wallet can be backed up as SLIP-39 shares
+    seed: Option<Vec<u8>>,
+    derivation_path: Option<String>,
+    source: WalletSource,
+}
+
+impl WalletKey {
+    /// Generate a new wallet from fresh entropy, returning the key and its mnemonic
+    pub fn generate() -> Result<(Self, String), TeeError> {
+        // 128 bits of entropy gives a 12 word mnemonic
+        let mut entropy = [0u8; 16];
+        getrandom::getrandom(&mut entropy)
+            .map_err(|e| TeeError::OperationFailed(format!("Failed to gather entropy: {}", e)))?;
+
+        let mnemonic = Mnemonic::from_entropy(&entropy)
+            .map_err(|e| TeeError::OperationFailed(format!("Failed to create mnemonic: {}", e)))?;
+        let mut key = Self::from_seed(&mnemonic.to_seed(""), DEFAULT_DERIVATION_PATH)?;
+        key.source = WalletSource::Generated;
+
+        Ok((key, mnemonic.to_string()))
+    }
+
+    /// Restore a wallet from a BIP39 mnemonic, validating the wordlist and checksum
+    pub fn from_mnemonic(phrase: &str, passphrase: &str, derivation_path: &str) -> Result<Self, TeeError> {
+        let normalized = phrase.split_whitespace()
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<_>>();
+
+        if !SUPPORTED_MNEMONIC_WORDS.contains(&normalized.len()) {
+            return Err(TeeError::OperationFailed(format!(
+                "Mnemonic must have 12 or 24 words, got {}", normalized.len()
+            )));
+        }
+
+        let mnemonic = Mnemonic::parse_in(Language::English, normalized.join(" "))
+            .map_err(|e| TeeError::OperationFailed(format!("Invalid mnemonic: {}", e)))?;
+
+        let mut key = Self::from_seed(&mnemonic.to_seed(passphrase), derivation_path)?;
+        key.source = WalletSource::Mnemonic;
+        Ok(key)
+    }
+
+    /// Derive the wallet key from a BIP32 seed along the given derivation path
+    pub fn from_seed(seed: &[u8], derivation_path: &str) -> Result<Self, TeeError> {
+        let path: DerivationPath = derivation_path.parse()
+            .map_err(|e| TeeError::OperationFailed(format!("Invalid derivation path {}: {}", derivation_path, e)))?;
+        let xprv = XPrv::derive_from_path(seed, &path)
+            .map_err(|e| TeeError::OperationFailed(format!("Key derivation failed: {}", e)))?;
+
+        Ok(Self {
+            signing_key: xprv.private_key().clone(),
+            seed: Some(seed.to_vec()),
+            derivation_path: Some(derivation_path.to_string()),
+            source: WalletSource::Mnemonic,
+        })
+    }
+
+    /// Restore a wallet from SLIP-39 shares that together encode its BIP32 seed
+    pub fn from_slip39_shares(shares: &[String], passphrase: &str, derivation_path: &str) -> Result<Self, TeeError> {
+        let seed = slip39::combine_mnemonics(shares, passphrase)?;
+        let mut key = Self::from_seed(&seed, derivation_path)?;
+        key.source = WalletSource::Slip39;
+        Ok(key)
+    }
+
+    /// Restore a wallet from a raw 32-byte hex private key
+    pub fn from_private_key_hex(private_key: &str) -> Result<Self, TeeError> {
+        let bytes = decode_hex(private_key, "private key")?;
+        if bytes.len() != 32 {
+            return Err(TeeError::OperationFailed(format!(
+                "Private key must be 32 bytes, got {}", bytes.len()
+            )));
+        }
+
+        Self::from_private_key_bytes(&bytes, WalletSource::PrivateKey)
+    }
+
+    /// Restore a wallet from an Ethereum v3 keystore (Web3 Secret Storage)
+    pub fn from_keystore(keystore: &Value, password: &str) -> Result<Self, TeeError> {
+        let private_key = decrypt_keystore(keystore, password)?;
+        Self::from_private_key_bytes(&private_key, WalletSource::Keystore)
+    }
+
+    /// Parse `ImportWallet` data and restore the wallet it describes
+    ///
+    /// Accepted JSON objects:
+    /// - `{ "mnemonic": "...", "passphrase": "...", "derivation_path": "m/44'/60'/0'/0/0" }`
+    /// - `{ "slip39_shares": ["...", "..."], "passphrase": "...", "derivation_path": "m/44'/60'/0'/0/0" }`
+    /// - `{ "private_key": "0x..." }`
+    /// - `{ "keystore": { ...v3 keystore... }, "password": "..." }`
+    pub fn from_import_data(wallet_data: &str) -> Result<Self, TeeError> {
+        let wallet_value: Value = serde_json::from_str(wallet_data)
+            .map_err(|e| TeeError::OperationFailed(format!("Invalid wallet data: {}", e)))?;
+
+        let fields = wallet_value.as_object()
+            .ok_or_else(|| TeeError::OperationFailed("Invalid wallet data format. Expected a JSON object".to_string()))?;
+        let string_field = |name: &str| fields.get(name).and_then(|v| v.as_str());
+
+        if let Some(phrase) = string_field("mnemonic") {
+            let passphrase = string_field("passphrase").unwrap_or("");
+            let path = string_field("derivation_path").unwrap_or(DEFAULT_DERIVATION_PATH);
+            Self::from_mnemonic(phrase, passphrase, path)
+        } else if let Some(shares) = fields.get("slip39_shares").and_then(|v| v.as_array()) {
+            let shares = shares.iter()
+                .map(|share| share.as_str().map(str::to_string)
+                    .ok_or_else(|| TeeError::OperationFailed("SLIP-39 shares must be strings".to_string())))
+                .collect::<Result<Vec<_>, _>>()?;
+            let passphrase = string_field("passphrase").unwrap_or("");
+            let path = string_field("derivation_path").unwrap_or(DEFAULT_DERIVATION_PATH);
+            Self::from_slip39_shares(&shares, passphrase, path)
+        } else if let Some(private_key) = string_field("private_key") {
+            Self::from_private_key_hex(private_key)
+        } else if let Some(keystore) = fields.get("keystore") {
+            let password = string_field("password")
+                .ok_or_else(|| TeeError::OperationFailed("Missing password for keystore import".to_string()))?;
+            // The keystore may be passed either as an object or as its JSON text
+            let keystore = match keystore {
+                Value::String(text) => serde_json::from_str(text)
+                    .map_err(|e| TeeError::OperationFailed(format!("Invalid keystore JSON: {}", e)))?,
+                other => other.clone(),
+            };
+            Self::from_keystore(&keystore, password)
+        } else {
+            Err(TeeError::OperationFailed(
+                "Invalid wallet data format. Expected mnemonic, slip39_shares, private_key or keystore".to_string()
+            ))
+        }
+    }
+
+    fn from_private_key_bytes(bytes: &[u8], source: WalletSource) -> Result<Self, TeeError> {
+        let signing_key = SigningKey::from_slice(bytes)
+            .map_err(|_| TeeError::OperationFailed("Private key is not a valid secp256k1 scalar".to_string()))?;
+
+        Ok(Self {
+            signing_key,
+            seed: None,
+            derivation_path: None,
+            source,
+        })
+    }
+
+    /// Split the wallet seed into SLIP-39 shares, any `threshold` of which restore the wallet
+    pub fn backup_shares(&self, threshold: u8, share_count: u8, passphrase: &str) -> Result<Vec<String>, TeeError> {
+        let seed = self.seed.as_ref()
+            .ok_or_else(|| TeeError::OperationFailed(
+                "Wallet was imported from a private key and has no seed to back up".to_string()
+            ))?;
+        slip39::split_master_secret(seed, passphrase, threshold, share_count)
+    }
+
+    /// Sign a 32-byte digest, returning a recoverable signature
+    pub fn sign_hash(&self, hash: &B256) -> Result<Signature, TeeError> {
+        self.signing_key.sign_prehash_recoverable(hash.as_slice())
+            .map(Signature::from)
+            .map_err(|e| TeeError::OperationFailed(format!("Signing failed: {}", e)))
+    }
+
+    /// Sign complete transaction JSON, returning the signature and raw signed transaction
+    pub fn sign_transaction(&self, tx_data: &str) -> Result<Value, TeeError> {
+        let tx = TxRequest::from_json(tx_data).map_err(TeeError::OperationFailed)?;
+        if let Some(from) = tx.from {
+            if from != self.address() {
+                return Err(TeeError::OperationFailed(format!(
+                    "Transaction sender {} does not match wallet address {}", from, self.address_string()
+                )));
+            }
+        }
+
+        let unsigned = tx.to_unsigned().map_err(TeeError::OperationFailed)?;
+        let signature = self.sign_hash(&unsigned.signature_hash())?;
+        let raw_transaction = unsigned.encode_signed(&signature);
+
+        Ok(json!({
+            "from": self.address_string(),
+            "signature": format!("0x{}", hex::encode(signature.as_bytes())),
+            "tx_hash": keccak256(&raw_transaction),
+            "raw_transaction": raw_transaction,
+        }))
+    }
+
+    /// Sign an ERC-4337 UserOperation as the owner of its smart account
+    pub fn sign_user_operation(&self, user_op_data: &str) -> Result<Value, TeeError> {
+        let request = UserOperationRequest::from_json(user_op_data).map_err(TeeError::OperationFailed)?;
+        let signature = self.sign_hash(&request.signing_hash())?;
+
+        Ok(json!({
+            "signer": self.address_string(),
+            "sender": request.user_op.sender,
+            "user_op_hash": request.user_op_hash(),
+            "signature": format!("0x{}", hex::encode(signature.as_bytes())),
+        }))
+    }
+
+    /// Sign a Safe transaction's SafeTx hash as one of the Safe's owners
+    pub fn sign_safe_transaction(&self, safe_tx_data: &str) -> Result<Value, TeeError> {
+        let request = SafeTransactionRequest::from_json(safe_tx_data).map_err(TeeError::OperationFailed)?;
+        let safe_tx_hash = request.safe_tx_hash();
+        let signature = self.sign_hash(&safe_tx_hash)?;
+
+        Ok(json!({
+            "signer": self.address_string(),
+            "safe": request.safe,
+            "safe_tx_hash": safe_tx_hash,
+            "signature": format!("0x{}", hex::encode(signature.as_bytes())),
+        }))
+    }
+
+    /// Sign a personal message (EIP-191 `personal_sign`); 0x-prefixed hex is signed as bytes
+    pub fn sign_message(&self, message: &str) -> Result<Value, TeeError> {
+        let message_hash = signature::personal_message_hash(message);
+        let signature = self.sign_hash(&message_hash)?;
+
+        Ok(json!({
+            "signer": self.address_string(),
+            "message_hash": message_hash,
+            "signature": format!("0x{}", hex::encode(signature.as_bytes())),
+        }))
+    }
+
+    /// Whether `signature` is this wallet's `personal_sign` signature of `message`
+    pub async fn verify_message(&self, message: &str, signature: &str) -> Result<bool, TeeError> {
+        let bytes = hex::decode(signature.trim_start_matches("0x"))
+            .map_err(|_| TeeError::OperationFailed("Invalid signature format".to_string()))?;
+        let hash = signature::personal_message_hash(message);
+        let kind = signature::verify(None, self.address(), hash, &bytes).await
+            .map_err(TeeError::OperationFailed)?;
+        Ok(kind.is_some())
+    }
+
+    pub fn address(&self) -> Address {
+        Address::from_private_key(&self.signing_key)
+    }
+
+    /// EIP-55 checksummed address
+    pub fn address_string(&self) -> String {
+        self.address().to_checksum(None)
+    }
+
+    /// Uncompressed SEC1 public key (0x04 || X || Y)
+    pub fn public_key_hex(&self) -> String {
+        let point = self.signing_key.verifying_key().to_encoded_point(false);
+        format!("0x{}", hex::encode(point.as_bytes()))
+    }
+
+    pub fn private_key_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.signing_key.to_bytes()))
+    }
+
+    pub fn derivation_path(&self) -> Option<&str> {
+        self.derivation_path.as_deref()
+    }
+
+    pub fn source(&self) -> WalletSource {
+        self.source
+    }
+}
+
+// Decode a hex string with optional 0x prefix
+fn decode_hex(value: &str, what: &str) -> Result<Vec<u8>, TeeError> {
+    let trimmed = value.trim();
+    let stripped = trimmed.strip_prefix("0x").unwrap_or(trimmed);
+    hex::decode(stripped)
+        .map_err(|e| TeeError::OperationFailed(format!("Invalid hex in {}: {}", what, e)))
+}
+
+// Decrypt the private key stored in a v3 keystore
+fn decrypt_keystore(keystore: &Value, password: &str) -> Result<Vec<u8>, TeeError> {
+    let invalid = |msg: &str| TeeError::OperationFailed(format!("Invalid keystore: {}", msg));
+
+    if keystore.get("version").and_then(|v| v.as_u64()) != Some(3) {
+        return Err(invalid("only version 3 keystores are supported"));
+    }
+
+    // Some wallets write "Crypto" instead of "crypto"
+    let crypto = keystore.get("crypto").or_else(|| keystore.get("Crypto"))
+        .ok_or_else(|| invalid("missing crypto section"))?;
+    let field = |name: &str| crypto.get(name).and_then(|v| v.as_str())
+        .ok_or_else(|| invalid(&format!("missing {}", name)));
+
+    if field("cipher")? != "aes-128-ctr" {
+        return Err(invalid("unsupported cipher, expected aes-128-ctr"));
+    }
+
+    let ciphertext = decode_hex(field("ciphertext")?, "keystore ciphertext")?;
+    let mac = decode_hex(field("mac")?, "keystore mac")?;
+    let iv = crypto.get("cipherparams").and_then(|p| p.get("iv")).and_then(|v| v.as_str())
+        .ok_or_else(|| invalid("missing cipherparams.iv"))?;
+    let iv = decode_hex(iv, "keystore iv")?;
+    if iv.len() != 16 {
+        return Err(invalid("iv must be 16 bytes"));
+    }
+
+    let kdf_params = crypto.get("kdfparams").ok_or_else(|| invalid("missing kdfparams"))?;
+    let param = |name: &str| kdf_params.get(name).and_then(|v| v.as_u64())
+        .ok_or_else(|| invalid(&format!("missing kdfparams.{}", name)));
+    let salt = kdf_params.get("salt").and_then(|v| v.as_str())
+        .ok_or_else(|| invalid("missing kdfparams.salt"))?;
+    let salt = decode_hex(salt, "keystore salt")?;
+    let dklen = param("dklen")? as usize;
+    if dklen < 32 {
+        return Err(invalid("dklen must be at least 32"));
+    }
+
+    let mut derived_key = vec![0u8; dklen];
+    match field("kdf")? {
+        "scrypt" => {
+            let (n, r, p) = (param("n")?, param("r")?, param("p")?);
+            if !n.is_power_of_two() || n < 2 {
+                return Err(invalid("scrypt n must be a power of two"));
+            }
+            if n > MAX_SCRYPT_N || r > MAX_SCRYPT_R || p > MAX_SCRYPT_P {
+                return Err(invalid(&format!(
+                    "scrypt parameters exceed n = {}, r = {}, p = {}", MAX_SCRYPT_N, MAX_SCRYPT_R, MAX_SCRYPT_P
+                )));
+            }
+            let params = scrypt::Params::new(n.trailing_zeros() as u8, r as u32, p as u32, dklen)
+                .map_err(|e| invalid(&format!("bad scrypt parameters: {}", e)))?;
+            scrypt::scrypt(password.as_bytes(), &salt, &params, &mut derived_key)
+                .map_err(|e| invalid(&format!("scrypt failed: {}", e)))?;
+        },
+        "pbkdf2" => {
+            let prf = kdf_params.get("prf").and_then(|v| v.as_str()).unwrap_or("hmac-sha256");
+            if prf != "hmac-sha256" {
+                return Err(invalid("unsupported pbkdf2 prf, expected hmac-sha256"));
+            }
+            pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, param("c")? as u32, &mut derived_key);
+        },
+        other => return Err(invalid(&format!("unsupported kdf {}", other))),
+    }
+
+    // MAC = keccak256(derived_key[16..32] || ciphertext)
+    let mut mac_input = derived_key[16..32].to_vec();
+    mac_input.extend_from_slice(&ciphertext);
+    if keccak256(&mac_input).as_slice() != mac.as_slice() {
+        return Err(TeeError::OperationFailed("Keystore MAC mismatch: wrong password or corrupted keystore".to_string()));
+    }
+
+    let mut private_key = ciphertext;
+    let mut cipher = Aes128Ctr::new(derived_key[..16].into(), iv.as_slice().into());
+    cipher.apply_keystream(&mut private_key);
+
+    Ok(private_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Well-known development mnemonic (Hardhat / Anvil account #0)
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+    const TEST_ADDRESS: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+    const TEST_PRIVATE_KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    #[test]
+    fn test_import_mnemonic_derives_address() {
+        let key = WalletKey::from_mnemonic(TEST_MNEMONIC, "", DEFAULT_DERIVATION_PATH).unwrap();
+        assert_eq!(key.address_string(), TEST_ADDRESS);
+        assert_eq!(key.private_key_hex(), TEST_PRIVATE_KEY);
+        assert_eq!(key.source(), WalletSource::Mnemonic);
+    }
+
+    #[test]
+    fn test_import_mnemonic_rejects_bad_input() {
+        // Wrong word count
+        assert!(WalletKey::from_mnemonic("test test test", "", DEFAULT_DERIVATION_PATH).is_err());
+        // Word not in the BIP39 list
+        let unknown = TEST_MNEMONIC.replace("junk", "cos72");
+        assert!(WalletKey::from_mnemonic(&unknown, "", DEFAULT_DERIVATION_PATH).is_err());
+        // Valid words but failing checksum
+        let bad_checksum = TEST_MNEMONIC.replace("junk", "test");
+        assert!(WalletKey::from_mnemonic(&bad_checksum, "", DEFAULT_DERIVATION_PATH).is_err());
+    }
+
+    #[test]
+    fn test_passphrase_changes_address() {
+        let key = WalletKey::from_mnemonic(TEST_MNEMONIC, "cos72", DEFAULT_DERIVATION_PATH).unwrap();
+        assert_ne!(key.address_string(), TEST_ADDRESS);
+    }
+
+    #[test]
+    fn test_import_data_formats() {
+        let from_mnemonic = WalletKey::from_import_data(&json!({ "mnemonic": TEST_MNEMONIC }).to_string()).unwrap();
+        let from_hex = WalletKey::from_import_data(&json!({ "private_key": TEST_PRIVATE_KEY }).to_string()).unwrap();
+        assert_eq!(from_mnemonic.address(), from_hex.address());
+        assert_eq!(from_hex.source(), WalletSource::PrivateKey);
+
+        assert!(WalletKey::from_import_data("{}").is_err());
+        assert!(WalletKey::from_import_data("not json").is_err());
+    }
+
+    #[test]
+    fn test_generated_mnemonic_round_trips() {
+        let (generated, mnemonic) = WalletKey::generate().unwrap();
+        assert_eq!(mnemonic.split_whitespace().count(), 12);
+
+        let restored = WalletKey::from_mnemonic(&mnemonic, "", DEFAULT_DERIVATION_PATH).unwrap();
+        assert_eq!(generated.address(), restored.address());
+    }
+
+    #[test]
+    fn test_slip39_backup_restores_wallet() {
+        let key = WalletKey::from_mnemonic(TEST_MNEMONIC, "", DEFAULT_DERIVATION_PATH).unwrap();
+        let shares = key.backup_shares(2, 3, "").unwrap();
+
+        let import_data = json!({ "slip39_shares": [shares[2], shares[0]] }).to_string();
+        let restored = WalletKey::from_import_data(&import_data).unwrap();
+        assert_eq!(restored.address_string(), TEST_ADDRESS);
+        assert_eq!(restored.source(), WalletSource::Slip39);
+
+        // Keys imported without a seed cannot be backed up
+        let from_hex = WalletKey::from_private_key_hex(TEST_PRIVATE_KEY).unwrap();
+        assert!(from_hex.backup_shares(2, 3, "").is_err());
+    }
+
+    #[test]
+    fn test_sign_transaction_recovers_wallet_address() {
+        let key = WalletKey::from_private_key_hex(TEST_PRIVATE_KEY).unwrap();
+        let tx = json!({
+            "from": TEST_ADDRESS, "to": TEST_ADDRESS, "value": "1", "chainId": 31337,
+            "nonce": 0, "gas": 21000, "maxFeePerGas": "2000000000", "maxPriorityFeePerGas": "1000000"
+        });
+
+        let signed = key.sign_transaction(&tx.to_string()).unwrap();
+        let raw: alloy_primitives::Bytes = serde_json::from_value(signed["raw_transaction"].clone()).unwrap();
+        assert_eq!(signed["tx_hash"], json!(keccak256(&raw)));
+
+        let signature: Signature = signed["signature"].as_str().unwrap().parse().unwrap();
+        let hash = TxRequest::from_json(&tx.to_string()).unwrap().to_unsigned().unwrap().signature_hash();
+        assert_eq!(signature.recover_address_from_prehash(&hash).unwrap(), key.address());
+
+        // Wrong sender and incomplete transactions are refused
+        let mut other_sender = tx.clone();
+        other_sender["from"] = json!("0x0000000000000000000000000000000000000001");
+        assert!(key.sign_transaction(&other_sender.to_string()).is_err());
+        assert!(key.sign_transaction(&json!({ "to": TEST_ADDRESS }).to_string()).is_err());
+    }
+
+    #[test]
+    fn test_sign_message_personal_sign() {
+        let key = WalletKey::from_private_key_hex(TEST_PRIVATE_KEY).unwrap();
+        let signed = key.sign_message("hello").unwrap();
+        let signature: Signature = signed["signature"].as_str().unwrap().parse().unwrap();
+        assert_eq!(signature.recover_address_from_msg("hello").unwrap(), key.address());
+
+        // Hex input is signed as the bytes it encodes
+        assert_eq!(key.sign_message("0x68656c6c6f").unwrap(), signed);
+    }
+
+    #[tokio::test]
+    async fn test_verify_message_checks_the_signer() {
+        let key = WalletKey::from_private_key_hex(TEST_PRIVATE_KEY).unwrap();
+        let signature = key.sign_message("hello").unwrap()["signature"].as_str().unwrap().to_string();
+        assert!(key.verify_message("hello", &signature).await.unwrap());
+        assert!(!key.verify_message("goodbye", &signature).await.unwrap());
+
+        // Another key's signature is well-formed but not the wallet's
+        let other = WalletKey::from_private_key_hex(&format!("0x{}", "11".repeat(32))).unwrap();
+        let other_signature = other.sign_message("hello").unwrap()["signature"].as_str().unwrap().to_string();
+        assert!(!key.verify_message("hello", &other_signature).await.unwrap());
+        assert!(key.verify_message("hello", "0xzz").await.is_err());
+    }
+
+    #[test]
+    fn test_keystore_pbkdf2_vector() {
+        // Test vector from the Web3 Secret Storage definition, password "testpassword"
+        let keystore = json!({
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+                "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                "kdf": "pbkdf2",
+                "kdfparams": {
+                    "c": 262144,
+                    "dklen": 32,
+                    "prf": "hmac-sha256",
+                    "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                },
+                "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+            },
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3
+        });
+
+        let key = WalletKey::from_keystore(&keystore, "testpassword").unwrap();
+        assert_eq!(key.private_key_hex(), "0x7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d");
+        assert_eq!(key.source(), WalletSource::Keystore);
+
+        assert!(WalletKey::from_keystore(&keystore, "wrongpassword").is_err());
+    }
+
+    #[test]
+    fn test_keystore_scrypt_parameters_are_capped() {
+        let keystore = |n: u64, r: u64, p: u64| json!({
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+                "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+                "kdf": "scrypt",
+                "kdfparams": {
+                    "dklen": 32, "n": n, "r": r, "p": p,
+                    "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+                },
+                "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+            },
+            "version": 3
+        });
+
+        for (n, r, p) in [(1 << 21, 8, 1), (1 << 18, 9, 1), (1 << 18, 8, 17), (1 << 40, 1, 1)] {
+            let error = WalletKey::from_keystore(&keystore(n, r, p), "testpassword").err().unwrap();
+            assert!(error.to_string().contains("exceed"), "{}", error);
+        }
+    }
+}
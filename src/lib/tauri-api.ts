@@ -449,11 +449,12 @@ export async function getPasskeyCredentials(user_id: string): Promise<any> {
 
 /**
  * 完成Passkey认证
+ * @param ceremony_id webauthn_start_authentication 返回的仪式ID
  * @param response 认证响应
  */
-export async function finishPasskeyAuthentication(response: string): Promise<any> {
+export async function finishPasskeyAuthentication(ceremony_id: string, response: string): Promise<any> {
   try {
-    return await invoke<any>('webauthn_finish_authentication', { response });
+    return await invoke<any>('webauthn_finish_authentication', { ceremonyId: ceremony_id, response });
   } catch (error) {
     console.error('[TAURI-API-0.4.7] - 完成Passkey认证失败', error);
     throw error;
@@ -144,6 +144,19 @@ export async function mockInvoke(command: string, args?: Record<string, any>): P
         data: null
       };
     
+    case 'decode_transaction': {
+      // 模拟环境下不解析calldata，只返回基本信息
+      const tx = JSON.parse(args?.txData || '{}');
+      return {
+        action: tx.data && tx.data !== '0x' ? 'contract_call' : 'native_transfer',
+        description: `Transaction to ${tx.to || 'new contract'} (mock decoding)`,
+        value: tx.value || '0',
+        warnings: ['Running in browser mock mode; calldata was not decoded'],
+        calls: [],
+        simulation: null
+      };
+    }
+
     case 'verify_passkey':
       console.log('[MOCK] 处理verify_passkey命令，参数:', args);
       
@@ -20,7 +20,9 @@ export interface RegistrationResult {
 }
 
 export interface AuthenticationChallenge {
-  publicKey: any;
+  ceremony_id: string;
+  challenge: { publicKey: any };
+  expires_at: string;
 }
 
 export interface AuthenticationResult {
@@ -95,13 +97,13 @@ export async function startAuthentication(userId: string): Promise<Authenticatio
   }
 }
 
-// 完成验证
-export async function finishAuthentication(userId: string, response: any): Promise<AuthenticationResult> {
+// 完成验证，ceremonyId 为 startAuthentication 返回的 ceremony_id
+export async function finishAuthentication(ceremonyId: string, response: any): Promise<AuthenticationResult> {
   try {
     console.log('[WebAuthn] 完成验证流程');
     const responseJson = typeof response === 'string' ? response : JSON.stringify(response);
     const result = await invoke('webauthn_finish_authentication', { 
-      userId, 
+      ceremonyId, 
       response: responseJson 
     });
     console.log('[WebAuthn] 验证结果:', result);
@@ -136,4 +138,110 @@ export async function verifyChallenge(challenge: string): Promise<any> {
     console.error('[WebAuthn] 验证失败:', error);
     throw new Error(`验证失败: ${error}`);
   }
-} 
\ No newline at end of file
+} 
+// Passkey二次验证（交易签名和钱包导出）
+
+// 后端 step-up-required 事件内容
+export interface StepUpRequest {
+  operation: string;
+  operation_digest: string;
+  expires_at: string;
+}
+
+export const STEP_UP_REQUIRED_EVENT = 'step-up-required';
+
+function base64UrlToBuffer(value: string): ArrayBuffer {
+  const base64 = value.replace(/-/g, '+').replace(/_/g, '/');
+  const binary = atob(base64 + '='.repeat((4 - (base64.length % 4)) % 4));
+  const bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i);
+  }
+  return bytes.buffer;
+}
+
+function bufferToBase64Url(buffer: ArrayBuffer): string {
+  const bytes = new Uint8Array(buffer);
+  let binary = '';
+  for (let i = 0; i < bytes.byteLength; i++) {
+    binary += String.fromCharCode(bytes[i]);
+  }
+  return btoa(binary).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+}
+
+// 用钱包所有者的Passkey确认操作: request_step_up 返回与操作摘要绑定的挑战,
+// 平台Passkey或传入securityKey时的USB安全密钥签名后交给 finish_step_up, 通过后该操作可在短时间内执行一次
+export async function confirmWithPasskey(operation: string, securityKey?: { pin?: string }): Promise<any> {
+  try {
+    console.log('[WebAuthn] 开始Passkey二次验证');
+    const ceremony = await invoke<any>('request_step_up', { operation });
+    const options = ceremony.challenge.publicKey;
+    const response = securityKey
+      ? await signWithSecurityKey(options.challenge, securityKey.pin)
+      : await signWithPlatformPasskey(options);
+    const result = await invoke('finish_step_up', { ceremonyId: ceremony.ceremony_id, response });
+    console.log('[WebAuthn] 二次验证结果:', result);
+    return result;
+  } catch (error) {
+    console.error('[WebAuthn] 二次验证失败:', error);
+    throw new Error(`Passkey二次验证失败: ${error}`);
+  }
+}
+
+// 通过USB安全密钥签名仪式的挑战，返回断言JSON
+async function signWithSecurityKey(challenge: string, pin?: string): Promise<string> {
+  const signed = await invoke<{ response: string }>('security_key_sign_challenge', { challenge, pin: pin ?? null });
+  return signed.response;
+}
+
+// 通过浏览器WebAuthn API签名仪式的挑战，返回断言JSON
+async function signWithPlatformPasskey(options: any): Promise<string> {
+  const credential = await navigator.credentials.get({
+    publicKey: {
+      ...options,
+      challenge: base64UrlToBuffer(options.challenge),
+      allowCredentials: (options.allowCredentials || []).map((allowed: any) => ({
+        ...allowed,
+        id: base64UrlToBuffer(allowed.id)
+      }))
+    }
+  }) as PublicKeyCredential | null;
+  if (!credential) {
+    throw new Error('未获取到Passkey签名');
+  }
+
+  const assertion = credential.response as AuthenticatorAssertionResponse;
+  return JSON.stringify({
+    id: credential.id,
+    rawId: bufferToBase64Url(credential.rawId),
+    type: credential.type,
+    extensions: credential.getClientExtensionResults(),
+    response: {
+      authenticatorData: bufferToBase64Url(assertion.authenticatorData),
+      clientDataJSON: bufferToBase64Url(assertion.clientDataJSON),
+      signature: bufferToBase64Url(assertion.signature),
+      userHandle: assertion.userHandle ? bufferToBase64Url(assertion.userHandle) : null
+    }
+  });
+}
+
+// 监听后端发出的 step-up-required 事件（发送、加速、取消交易及发送UserOperation时）并用Passkey确认
+// 返回取消监听的函数
+export async function listenForStepUp(
+  onRequest: (request: StepUpRequest) => void,
+  onResult: (error: Error | null) => void
+): Promise<() => void> {
+  if (typeof window === 'undefined' || !window.__TAURI__ || !window.__TAURI__.event) {
+    return () => {};
+  }
+  return await window.__TAURI__.event.listen(STEP_UP_REQUIRED_EVENT, async (event: any) => {
+    const request = event.payload as StepUpRequest;
+    onRequest(request);
+    try {
+      await confirmWithPasskey(request.operation);
+      onResult(null);
+    } catch (error) {
+      onResult(error instanceof Error ? error : new Error(String(error)));
+    }
+  });
+}
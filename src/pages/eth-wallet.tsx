@@ -2,6 +2,7 @@ import React, { useState, useEffect } from 'react';
 import Head from 'next/head';
 import Link from 'next/link';
 import { invoke as invokeCommand, getTeeStatus, initializeTee, performTeeOperation, isTauriEnvironment } from '../lib/tauri-api';
+import { confirmWithPasskey, listenForStepUp } from '../lib/webauthn-api';
 import Layout from '../components/Layout';
 
 // 钱包信息接口
@@ -12,6 +13,16 @@ interface WalletInfo {
   mnemonic?: string;
 }
 
+// 待确认交易 (历史记录条目)
+interface PendingTransaction {
+  id: string;
+  chain_id: number | null;
+  to: string | null;
+  value: string;
+  nonce: string | null;
+  hash: string | null;
+}
+
 // TEE状态接口
 interface TeeStatus {
   available: boolean;
@@ -32,6 +43,7 @@ export default function EthWallet() {
   const [txData, setTxData] = useState<string>('');
   const [signatureResult, setSignatureResult] = useState<string | null>(null);
   const [isTauriEnv, setIsTauriEnv] = useState<boolean>(false);
+  const [pendingTxs, setPendingTxs] = useState<PendingTransaction[]>([]);
 
   // 添加日志
   const addLog = (message: string) => {
@@ -67,6 +79,30 @@ export default function EthWallet() {
     checkEnvironment();
   }, []);
 
+  // 发送、加速和取消交易时后端等待Passkey二次验证, 收到事件后用Passkey确认
+  useEffect(() => {
+    let unlisten: (() => void) | null = null;
+    listenForStepUp(
+      request => addLog(`交易等待Passkey确认, 摘要: ${request.operation_digest}`),
+      error => addLog(error ? `Passkey确认失败: ${error.message}` : 'Passkey确认成功, 继续签名')
+    ).then(stop => { unlisten = stop; });
+    return () => {
+      if (unlisten) unlisten();
+    };
+  }, []);
+
+  // 刷新待确认交易
+  const loadPendingTransactions = async () => {
+    try {
+      const entries = await invokeCommand<PendingTransaction[]>('get_history', {
+        filter: { kind: 'transaction', status: 'submitted', limit: 10 }
+      });
+      setPendingTxs(entries);
+    } catch (error: any) {
+      addLog(`获取待确认交易失败: ${error.message || error}`);
+    }
+  };
+
   // 初始化TEE
   const handleInitializeTee = async () => {
     try {
@@ -200,16 +236,55 @@ export default function EthWallet() {
       } catch (e) {
         throw new Error('交易数据不是有效的JSON格式');
       }
-      
+
+      // 签名前解析交易内容并模拟执行，让用户确认具体操作
+      const summary = await invokeCommand<{
+        description: string;
+        warnings: string[];
+        simulation: {
+          success: boolean;
+          revert_reason: string | null;
+          gas_used: number | null;
+          balance_changes: { address: string; token: string | null; token_id: string | null; delta: string }[];
+        } | null;
+      }>('decode_transaction', {
+        txData: JSON.stringify(parsedTxData)
+      });
+      addLog(`交易内容: ${summary.description}`);
+      summary.warnings.forEach(warning => addLog(`警告: ${warning}`));
+
+      const simulation = summary.simulation;
+      if (simulation && !simulation.success) {
+        addLog(`模拟执行失败: ${simulation.revert_reason}`);
+        if (!window.confirm(`交易模拟执行会失败: ${simulation.revert_reason}\n\n仍要签名吗?`)) {
+          addLog('用户取消了交易签名');
+          return;
+        }
+      }
+      const simulationText = simulation && simulation.success
+        ? [
+            simulation.gas_used !== null ? `预计 gas: ${simulation.gas_used}` : '',
+            ...simulation.balance_changes.map(change =>
+              `${change.address}: ${change.delta} ${change.token ? `(代币 ${change.token}${change.token_id ? ` #${change.token_id}` : ''})` : '(原生币)'}`)
+          ].filter(Boolean).join('\n')
+        : '';
+
+      const warningText = summary.warnings.map(warning => `⚠️ ${warning}`).join('\n');
+      if (!window.confirm(`${summary.description}\n\n${simulationText}\n\n${warningText}\n\n确认签名此交易?`)) {
+        addLog('用户取消了交易签名');
+        return;
+      }
+
+      // TEE只在Passkey确认过这笔交易后签名
+      const operation = JSON.stringify({ type: 'SignTransaction', txData: JSON.stringify(parsedTxData) });
+      addLog('请用Passkey确认交易...');
+      await confirmWithPasskey(operation);
+
       const result = await invokeCommand<{
         success: boolean;
         message: string;
         data?: string;
-      }>('perform_tee_operation', { 
-        operation: {
-          SignTransaction: JSON.stringify(parsedTxData)
-        }
-      });
+      }>('perform_tee_operation', { operation });
       
       if (result.success) {
         addLog('交易签名成功');
@@ -233,6 +308,64 @@ export default function EthWallet() {
     }
   };
 
+  // 发送交易: 后端填充 nonce、gas 和费用, 签名前通过 step-up-required 事件请求Passkey确认
+  const handleSendTransaction = async () => {
+    try {
+      setIsProcessing(true);
+      setError(null);
+      let parsedTxData;
+      try {
+        parsedTxData = JSON.parse(txData);
+      } catch (e) {
+        throw new Error('交易数据不是有效的JSON格式');
+      }
+      if (!parsedTxData.chainId) {
+        throw new Error('发送交易需要 chainId');
+      }
+      addLog('开始发送交易...');
+
+      const submitted = await invokeCommand<{ tx_hash: string; nonce: number; explorer_url: string | null }>('send_transaction', {
+        chainId: Number(parsedTxData.chainId),
+        from: parsedTxData.from ?? null,
+        to: parsedTxData.to ?? null,
+        value: parsedTxData.value ?? null,
+        data: parsedTxData.data ?? null
+      });
+      addLog(`交易已广播: ${submitted.tx_hash} (nonce ${submitted.nonce})`);
+      if (submitted.explorer_url) {
+        addLog(`浏览器: ${submitted.explorer_url}`);
+      }
+      await loadPendingTransactions();
+    } catch (error: any) {
+      addLog(`交易发送失败: ${error.message || error}`);
+      setError(`交易发送失败: ${error.message || error}`);
+    } finally {
+      setIsProcessing(false);
+    }
+  };
+
+  // 加速或取消待确认交易, 同样需要Passkey确认替换交易
+  const handleReplaceTransaction = async (id: string, kind: 'speed_up' | 'cancel') => {
+    const label = kind === 'speed_up' ? '加速' : '取消';
+    try {
+      setIsProcessing(true);
+      setError(null);
+      addLog(`开始${label}交易 ${id}...`);
+
+      const submitted = await invokeCommand<{ tx_hash: string }>(
+        kind === 'speed_up' ? 'speed_up_transaction' : 'cancel_transaction',
+        { id }
+      );
+      addLog(`${label}交易已广播: ${submitted.tx_hash}`);
+      await loadPendingTransactions();
+    } catch (error: any) {
+      addLog(`${label}交易失败: ${error.message || error}`);
+      setError(`${label}交易失败: ${error.message || error}`);
+    } finally {
+      setIsProcessing(false);
+    }
+  };
+
   // 加载示例交易数据
   const handleLoadExampleTx = () => {
     const exampleTx = {
@@ -427,7 +560,7 @@ export default function EthWallet() {
                   onChange={(e) => setTxData(e.target.value)}
                   className="w-full p-2 border border-gray-300 rounded font-mono text-sm"
                   rows={8}
-                  placeholder='{"to": "0x...", "value": "0x...", ...}'
+                  placeholder='{"to": "0x...", "value": "0x...", "chainId": 11155111, "nonce": 0, "gas": 21000, "maxFeePerGas": "0x...", "maxPriorityFeePerGas": "0x..."}'
                   disabled={isProcessing}
                 />
               </div>
@@ -455,6 +588,19 @@ export default function EthWallet() {
             >
               {isProcessing ? '签名中...' : '签名交易'}
             </button>
+
+            <button
+              onClick={handleSendTransaction}
+              disabled={isProcessing || !txData.trim()}
+              className={`
+                ${isProcessing || !txData.trim()
+                  ? 'bg-gray-300 cursor-not-allowed'
+                  : 'bg-green-500 hover:bg-green-600'
+                } text-white py-2 px-4 rounded w-full mt-2
+              `}
+            >
+              {isProcessing ? '发送中...' : '发送交易'}
+            </button>
             
             {signatureResult && (
               <div className="mt-4">
@@ -467,6 +613,49 @@ export default function EthWallet() {
           </div>
         )}
         
+        {/* 待确认交易 */}
+        {walletInfo && walletInfo.address && (
+          <div className="bg-white rounded-lg shadow-md p-6 mb-6">
+            <div className="flex justify-between items-center mb-4">
+              <h2 className="text-xl font-semibold">待确认交易</h2>
+              <button
+                onClick={loadPendingTransactions}
+                className="text-xs px-2 py-1 bg-gray-200 hover:bg-gray-300 rounded"
+              >
+                刷新
+              </button>
+            </div>
+            {pendingTxs.length > 0 ? (
+              <div className="space-y-2">
+                {pendingTxs.map(tx => (
+                  <div key={tx.id} className="bg-gray-50 p-3 rounded border border-gray-200 text-sm">
+                    <p className="font-mono break-all">{tx.hash}</p>
+                    <p className="text-gray-600">链 {tx.chain_id} · nonce {tx.nonce} · 发往 {tx.to} · 金额 {tx.value}</p>
+                    <div className="flex gap-2 mt-2">
+                      <button
+                        onClick={() => handleReplaceTransaction(tx.id, 'speed_up')}
+                        disabled={isProcessing}
+                        className="text-xs px-2 py-1 bg-blue-500 hover:bg-blue-600 text-white rounded"
+                      >
+                        加速
+                      </button>
+                      <button
+                        onClick={() => handleReplaceTransaction(tx.id, 'cancel')}
+                        disabled={isProcessing}
+                        className="text-xs px-2 py-1 bg-red-500 hover:bg-red-600 text-white rounded"
+                      >
+                        取消
+                      </button>
+                    </div>
+                  </div>
+                ))}
+              </div>
+            ) : (
+              <p className="text-gray-500 italic text-sm">暂无待确认交易</p>
+            )}
+          </div>
+        )}
+
         {/* 日志区域 */}
         <div className="bg-white rounded-lg shadow-md p-6 mb-6">
           <h2 className="text-xl font-semibold mb-4">操作日志</h2>
@@ -203,7 +203,7 @@ export default function PasskeyServer() {
       // 请求验证凭证
       console.log('调用navigator.credentials.get');
       const credential = await navigator.credentials.get({
-        publicKey: challenge as unknown as PublicKeyCredentialRequestOptions
+        publicKey: challenge.challenge.publicKey as PublicKeyCredentialRequestOptions
       });
       console.log('验证结果:', credential);
 
@@ -214,7 +214,7 @@ export default function PasskeyServer() {
         addLog('验证成功，正在完成验证流程...');
 
         const authResult = await finishAuthentication(
-          userId,
+          challenge.ceremony_id,
           credentialJson
         );
 